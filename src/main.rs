@@ -1,5 +1,5 @@
 use std::collections::{BTreeMap, HashSet};
-use std::io::{IsTerminal, Write};
+use std::io::{IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -43,10 +43,20 @@ enum Command {
     Init(InitArgs),
     /// Simulate pane output for trigger testing.
     Simulate(SimulateArgs),
+    /// Replay recorded pane captures through the rules without tmux.
+    TestRules(TestRulesArgs),
+    /// Re-run the rule engine over a session recorded with `run --record`.
+    Replay(ReplayArgs),
     /// Manage active local loopmux runs.
     Runs(RunsArgs),
     /// Inspect and validate workspace startup profiles.
     Config(ConfigArgs),
+    /// Print the JSON Schema for run or workspace YAML configs.
+    Schema(SchemaArgs),
+    /// Render the rule flow of a config as a Mermaid or Graphviz diagram.
+    Graph(GraphArgs),
+    /// Browse and edit the named prompt library.
+    Prompts(PromptsArgs),
 }
 
 #[derive(Debug, Parser)]
@@ -86,7 +96,7 @@ struct RunArgs {
     /// Inline executable command to run on each poll tick (mutually exclusive with trigger mode).
     #[arg(long, conflicts_with = "config")]
     exec: Option<String>,
-    /// tmux target scope (session, session:window, or session:window.pane), overrides config.
+    /// tmux target scope (session, session:window, session:window.pane, or a pane selector), overrides config.
     #[arg(long, short = 't')]
     target: Vec<String>,
     /// File containing tmux targets (one per line, '#' comments ignored).
@@ -146,10 +156,23 @@ struct RunArgs {
     /// Optional run codename (auto-generated when omitted).
     #[arg(long)]
     name: Option<String>,
+    /// Record captures, decisions, and sends to a JSONL session file for `loopmux replay`.
+    #[arg(long)]
+    record: Option<PathBuf>,
+    /// Seed for delay randomness (printed at start when omitted), overrides config.
+    #[arg(long)]
+    seed: Option<u64>,
 }
 
 const DEFAULT_HISTORY_LIMIT: usize = 50;
 const DEFAULT_TRIGGER_CONFIRM_SECONDS: u64 = 5;
+const DEFAULT_SUBMIT_KEY: &str = "Enter";
+const SNAPSHOT_DELIMITER: &str = "--- snapshot";
+const TEST_RULES_TARGET: &str = "input";
+const PREVIEW_CAPTURE_LINES: usize = 200;
+/// Lines above a `since_last_send` mark kept to re-locate it after history trimming.
+const MARK_ANCHOR_LINES: usize = 3;
+const MANUAL_HISTORY_CHOICES: usize = 5;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct RunHistory {
@@ -178,6 +201,9 @@ struct HistoryEntry {
     recheck_before_send: Option<bool>,
     fanout: Option<FanoutMode>,
     duration: Option<String>,
+    /// Pinned entries are listed first and never evicted by `--history-limit`.
+    #[serde(default)]
+    pinned: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -185,7 +211,7 @@ struct ValidateArgs {
     /// Path to the YAML config file.
     #[arg(long, short = 'c')]
     config: Option<PathBuf>,
-    /// tmux target scope (session, session:window, or session:window.pane), overrides config.
+    /// tmux target scope (session, session:window, session:window.pane, or a pane selector), overrides config.
     #[arg(long, short = 't')]
     target: Vec<String>,
     /// File containing tmux targets (one per line, '#' comments ignored).
@@ -203,6 +229,12 @@ struct ValidateArgs {
     /// Validate config without checking tmux target.
     #[arg(long)]
     skip_tmux: bool,
+    /// Fail on unknown config keys instead of printing warnings.
+    #[arg(long)]
+    strict: bool,
+    /// Also print the rule flow diagram in this format.
+    #[arg(long, value_enum)]
+    graph: Option<GraphFormat>,
 }
 
 #[derive(Debug, Parser)]
@@ -210,6 +242,24 @@ struct InitArgs {
     /// Path to write the YAML config file. If omitted, prints to stdout.
     #[arg(long, short = 'o')]
     output: Option<PathBuf>,
+    /// Generate a config tuned for a specific assistant or an exec watcher.
+    #[arg(long, value_enum)]
+    preset: Option<InitPreset>,
+    /// Pick a live tmux pane, detect its assistant, and write a validated config (default: loop.yaml).
+    #[arg(long)]
+    interactive: bool,
+    /// Target pane written into preset configs (default: ai:5.0).
+    #[arg(long)]
+    target: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum InitPreset {
+    ClaudeCode,
+    Codex,
+    #[value(name = "opencode")]
+    OpenCode,
+    ExecWatcher,
 }
 
 #[derive(Debug, Parser)]
@@ -225,6 +275,84 @@ struct SimulateArgs {
     repeat: Option<u32>,
 }
 
+#[derive(Debug, Parser)]
+#[command(
+    after_help = "Input format:\n  Snapshots are separated by lines starting with `--- snapshot` (for example `--- snapshot 2 ---`).\n  Snapshot N is evaluated at t = (N-1) * poll seconds.\n\nExamples:\n  loopmux test-rules --config loop.yaml --input capture.txt\n  loopmux test-rules --config loop.yaml --input capture.txt --golden capture.golden\n"
+)]
+struct TestRulesArgs {
+    /// Path to YAML config file.
+    #[arg(long)]
+    config: PathBuf,
+    /// Recorded pane captures to evaluate.
+    #[arg(long)]
+    input: PathBuf,
+    /// Override the scan window per snapshot: tail:N or head:N.
+    #[arg(long, value_parser = parse_capture_window)]
+    capture_window: Option<CaptureWindow>,
+    /// Compare the report with this file and fail on differences.
+    #[arg(long)]
+    golden: Option<PathBuf>,
+    /// Rewrite the golden file with the current report.
+    #[arg(long, requires = "golden")]
+    update_golden: bool,
+    /// Seed for delay randomness, overrides config (default 0).
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    after_help = "Examples:\n  loopmux run --config loop.yaml --record session.jsonl\n  loopmux replay session.jsonl --config new.yaml\n"
+)]
+struct ReplayArgs {
+    /// Session file written by `loopmux run --record`.
+    session: PathBuf,
+    /// Config to evaluate the recorded captures with.
+    #[arg(long)]
+    config: PathBuf,
+    /// Narrow the recorded capture per snapshot: tail:N or head:N.
+    #[arg(long, value_parser = parse_capture_window)]
+    capture_window: Option<CaptureWindow>,
+    /// Seed for delay randomness, overrides config (default 0).
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    after_help = "Editor setup:\n  loopmux schema > loopmux.schema.json\n  # yaml-language-server: $schema=./loopmux.schema.json\n"
+)]
+struct SchemaArgs {
+    /// Config shape to describe.
+    #[arg(long, value_enum, default_value = "run")]
+    kind: SchemaKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SchemaKind {
+    Run,
+    Workspace,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    after_help = "Examples:\n  loopmux graph --config loop.yaml > flow.mmd\n  loopmux graph --config loop.yaml --format dot | dot -Tsvg -o flow.svg\n"
+)]
+struct GraphArgs {
+    /// Path to YAML config file.
+    #[arg(long, short = 'c')]
+    config: PathBuf,
+    /// Diagram syntax to print.
+    #[arg(long, value_enum, default_value = "mermaid")]
+    format: GraphFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum GraphFormat {
+    Mermaid,
+    Dot,
+}
+
 #[derive(Debug, Parser)]
 #[command(
     after_help = concat!(
@@ -273,6 +401,9 @@ enum ConfigAction {
         /// Validate all profiles (including disabled and non-matching cwd).
         #[arg(long)]
         all: bool,
+        /// Fail on unknown config keys instead of printing warnings.
+        #[arg(long)]
+        strict: bool,
     },
     /// Diagnose workspace profile setup and suggest fixes.
     Doctor {
@@ -288,6 +419,32 @@ enum ConfigAction {
     },
 }
 
+#[derive(Debug, Parser)]
+#[command(after_help = concat!(
+    "Library sources:\n",
+    "  ~/.config/loopmux/prompts/<name>.md\n",
+    "  prompts: map in ~/.config/loopmux/config.yaml (wins on name clashes)\n\n",
+    "Use from an action:\n",
+    "  prompt: { ref: commit-small, vars: { scope: api } }\n\n",
+    "Version: ",
+    env!("CARGO_PKG_VERSION"),
+    "\n"
+))]
+struct PromptsArgs {
+    #[command(subcommand)]
+    action: Option<PromptsAction>,
+}
+
+#[derive(Debug, Subcommand)]
+enum PromptsAction {
+    /// List library prompts with their source.
+    Ls,
+    /// Print one prompt.
+    Show { name: String },
+    /// Open a prompt file in $EDITOR (created if missing).
+    Edit { name: String },
+}
+
 #[derive(Debug, Subcommand)]
 enum RunsAction {
     /// List active local loopmux runs.
@@ -320,7 +477,9 @@ struct Config {
     trigger_edge: Option<bool>,
     recheck_before_send: Option<bool>,
     fanout: Option<FanoutMode>,
+    submit_keys: Option<Vec<String>>,
     duration: Option<String>,
+    seed: Option<u64>,
     rule_eval: Option<RuleEval>,
     default_action: Option<Action>,
     delay: Option<DelayConfig>,
@@ -332,6 +491,82 @@ struct Config {
     single_line: Option<bool>,
     tui: Option<bool>,
     name: Option<String>,
+    ui: Option<UiConfig>,
+    done_when: Option<DoneWhen>,
+    capture: Option<CaptureMode>,
+    /// Capture panes with `-e` and parse SGR styling (implied by `style` criteria).
+    capture_ansi: Option<bool>,
+    /// Capture panes with `-J` so wrapped lines are joined.
+    capture_join: Option<bool>,
+    states: Option<Vec<RuleState>>,
+    /// Workspace file the config came from; its `prompts:` map (and imports) back `ref:` prompts.
+    #[serde(skip)]
+    workspace_path: Option<PathBuf>,
+}
+
+/// `done_when:` goal check; reaching it ends the run with reason `goal_reached`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DoneWhen {
+    #[serde(rename = "match")]
+    match_: Option<MatchCriteria>,
+    /// Shell command that must exit 0: run when `match` holds, or before each send without it.
+    exec: Option<String>,
+}
+
+/// How `run_loop` ended; decides the process exit code when `done_when` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunOutcome {
+    Finished,
+    GoalReached,
+}
+
+const DEFAULT_PROMPT_SOURCE_MAX_BYTES: usize = 16 * 1024;
+const DEFAULT_PROMPT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_FORWARD_MAX_BYTES: usize = 4000;
+const FORWARD_TRUNCATED_MARKER: &str = "[forwarded output truncated]";
+/// Exit code when a run with `done_when` stops without reaching its goal.
+const EXIT_GOAL_NOT_REACHED: i32 = 3;
+
+/// `ui:` section of a run config, or the whole of `~/.config/loopmux/ui.yaml`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+struct UiConfig {
+    colors: Option<UiColors>,
+    icons: Option<UiIconSet>,
+    layout: Option<UiLayout>,
+    keys: Option<UiKeys>,
+}
+
+/// 256-color palette indexes for the status bar state label.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+struct UiColors {
+    running: Option<u8>,
+    holding: Option<u8>,
+    waiting: Option<u8>,
+    delay: Option<u8>,
+    sending: Option<u8>,
+    error: Option<u8>,
+    stopped: Option<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum UiIconSet {
+    Auto,
+    Nerd,
+    Ascii,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+struct UiLayout {
+    compact_max: Option<u16>,
+    standard_max: Option<u16>,
+}
+
+/// Action name -> key, per TUI (see `RUN_KEY_ACTIONS` / `FLEET_KEY_ACTIONS`).
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+struct UiKeys {
+    run: Option<BTreeMap<String, String>>,
+    fleet: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -391,8 +626,34 @@ enum FanoutMode {
 enum TargetScope {
     All,
     Session(String),
-    Window { session: String, window: String },
+    Window {
+        session: String,
+        window: String,
+    },
     Pane(String),
+    /// Panes matching every selector, re-evaluated against `tmux list-panes` each cycle.
+    Select {
+        selectors: Vec<PaneSelector>,
+        label: String,
+    },
+}
+
+/// One condition of a pane selector target such as `pane_title=~claude`.
+#[derive(Debug, Clone)]
+enum PaneSelector {
+    Session(SelectorValue),
+    Title(SelectorValue),
+    Command(SelectorValue),
+    Cwd(SelectorValue),
+    Tag(SelectorValue),
+    Contains(String),
+}
+
+/// `key=glob` matches with `*` wildcards; `key=~regex` matches a regex.
+#[derive(Debug, Clone)]
+enum SelectorValue {
+    Glob(String),
+    Regex(Regex),
 }
 
 #[derive(Debug, Clone)]
@@ -400,6 +661,10 @@ struct TmuxPane {
     target: String,
     session: String,
     window: String,
+    current_command: String,
+    cwd: String,
+    tag: String,
+    title: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -438,8 +703,52 @@ struct Rule {
     action: Option<Action>,
     delay: Option<DelayConfig>,
     confirm_seconds: Option<u64>,
-    next: Option<String>,
+    next: Option<NextStep>,
     priority: Option<i32>,
+    /// Candidate in every state and after any `next`, e.g. an error rule.
+    escape: Option<bool>,
+    /// Fires allowed over the whole run before the rule is exhausted.
+    max_fires: Option<u32>,
+    /// Minimum time between two fires, e.g. `10m`.
+    cooldown: Option<String>,
+    budget: Option<RuleBudget>,
+    /// Sources this rule is evaluated for (target patterns); every source when unset.
+    targets: Option<Vec<String>>,
+    /// Target pattern of the pane(s) the action is sent to instead of the matched source.
+    send_to: Option<String>,
+    /// Step taken instead of firing once `max_fires` or `budget` is used up; the rule is
+    /// held when unset.
+    on_exhausted: Option<NextStep>,
+}
+
+/// `budget: {fires: 3, per: 1h}`: at most `fires` fires in any rolling `per` window.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct RuleBudget {
+    fires: u32,
+    per: String,
+}
+
+/// Where a flow goes after a rule fires: a rule or state id, `stop`, or a list of alternatives.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+enum NextStep {
+    One(String),
+    Any(Vec<String>),
+}
+
+/// A flow state: the rules it listens for, transitions per matched rule, and an optional
+/// timeout with a fallback step. The first state is the initial one.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct RuleState {
+    id: String,
+    #[serde(default)]
+    rules: Vec<String>,
+    /// Matched rule id -> next step, overriding that rule's own `next` in this state.
+    #[serde(default)]
+    transitions: BTreeMap<String, NextStep>,
+    /// How long the state may go without a send before `on_timeout` is taken.
+    timeout: Option<String>,
+    on_timeout: Option<NextStep>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -449,33 +758,97 @@ struct MatchCriteria {
     exact_line: Option<String>,
     contains: Option<String>,
     starts_with: Option<String>,
+    /// Duration (`45s`, `2m`) the captured window must stay unchanged before this matches.
+    idle_for: Option<String>,
+    /// Regex the last non-blank line must match, e.g. an agent's input prompt.
+    prompt_ready: Option<String>,
+    /// Text drawn in a given color or attribute; needs an ANSI capture.
+    style: Option<StyleMatch>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+struct StyleMatch {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: Option<bool>,
+    dim: Option<bool>,
+    italic: Option<bool>,
+    underline: Option<bool>,
+    /// Matched against each span of fitting text; any non-blank span matches when unset.
+    regex: Option<String>,
+}
+
+/// What criteria can see about a capture besides its text.
+#[derive(Debug, Clone, Copy, Default)]
+struct MatchContext<'a> {
+    /// How long the captured window has kept the same hash.
+    idle: Duration,
+    /// SGR style runs of the capture; empty unless `capture_ansi` is on.
+    styles: &'a [StyledRun],
 }
 
 #[derive(Debug)]
 struct TriggerExpr {
     ast: TriggerExprNode,
-    terms: Vec<Regex>,
+    terms: Vec<TriggerAtom>,
 }
 
 #[derive(Debug)]
 enum TriggerExprNode {
     Term(usize),
+    Not(Box<TriggerExprNode>),
     And(Box<TriggerExprNode>, Box<TriggerExprNode>),
     Or(Box<TriggerExprNode>, Box<TriggerExprNode>),
+    Then(Box<TriggerExprNode>, Box<TriggerExprNode>),
+}
+
+/// Part of the capture a trigger expression atom looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AtomScope {
+    /// Whole capture: raw regex terms and `contains:`.
+    Anywhere,
+    /// Any single line: `line:`.
+    Line,
+    /// Last non-blank line: `last_line:`.
+    LastLine,
+}
+
+#[derive(Debug)]
+enum AtomMatcher {
+    /// Quoted text: a substring for `contains:`, the whole trimmed line for `line:`/`last_line:`.
+    Text(String),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+struct TriggerAtom {
+    scope: AtomScope,
+    matcher: AtomMatcher,
+}
+
+#[derive(Debug, Clone)]
+struct RawTriggerAtom {
+    scope: AtomScope,
+    pattern: String,
+    regex: bool,
 }
 
 #[derive(Debug)]
 enum TriggerExprRawNode {
-    Term { pattern: String, pos: usize },
+    Term { atom: RawTriggerAtom, pos: usize },
+    Not(Box<TriggerExprRawNode>),
     And(Box<TriggerExprRawNode>, Box<TriggerExprRawNode>),
     Or(Box<TriggerExprRawNode>, Box<TriggerExprRawNode>),
+    Then(Box<TriggerExprRawNode>, Box<TriggerExprRawNode>),
 }
 
 #[derive(Debug)]
 enum TriggerExprToken {
-    Term { pattern: String, pos: usize },
+    Term { atom: RawTriggerAtom, pos: usize },
     And { pos: usize },
     Or { pos: usize },
+    Then { pos: usize },
+    Not { pos: usize },
     LParen { pos: usize },
     RParen { pos: usize },
 }
@@ -486,6 +859,8 @@ impl TriggerExprToken {
             Self::Term { pos, .. }
             | Self::And { pos }
             | Self::Or { pos }
+            | Self::Then { pos }
+            | Self::Not { pos }
             | Self::LParen { pos }
             | Self::RParen { pos } => *pos,
         }
@@ -522,6 +897,7 @@ impl<'a> TriggerExprParser<'a> {
                     next,
                     TriggerExprToken::And { .. }
                         | TriggerExprToken::Or { .. }
+                        | TriggerExprToken::Then { .. }
                         | TriggerExprToken::RParen { .. }
                 ) {
                     bail!("invalid trigger expression at pos {pos}: expected term after '{op}'");
@@ -533,6 +909,7 @@ impl<'a> TriggerExprParser<'a> {
             left = match op {
                 "&&" => TriggerExprRawNode::And(Box::new(left), Box::new(right)),
                 "||" => TriggerExprRawNode::Or(Box::new(left), Box::new(right)),
+                "then" => TriggerExprRawNode::Then(Box::new(left), Box::new(right)),
                 _ => unreachable!(),
             };
         }
@@ -547,10 +924,24 @@ impl<'a> TriggerExprParser<'a> {
             );
         };
         match token {
-            TriggerExprToken::Term { pattern, pos } => Ok(TriggerExprRawNode::Term {
-                pattern: pattern.clone(),
+            TriggerExprToken::Term { atom, pos } => Ok(TriggerExprRawNode::Term {
+                atom: atom.clone(),
                 pos: *pos,
             }),
+            TriggerExprToken::Not { pos } => {
+                if matches!(
+                    self.peek(),
+                    None | Some(
+                        TriggerExprToken::And { .. }
+                            | TriggerExprToken::Or { .. }
+                            | TriggerExprToken::Then { .. }
+                            | TriggerExprToken::RParen { .. }
+                    )
+                ) {
+                    bail!("invalid trigger expression at pos {pos}: expected term after '!'");
+                }
+                Ok(TriggerExprRawNode::Not(Box::new(self.parse_primary()?)))
+            }
             TriggerExprToken::LParen { .. } => {
                 let expr = self.parse_expr(0)?;
                 match self.next() {
@@ -571,6 +962,9 @@ impl<'a> TriggerExprParser<'a> {
             TriggerExprToken::Or { pos } => {
                 bail!("invalid trigger expression at pos {pos}: expected term after '||'")
             }
+            TriggerExprToken::Then { pos } => {
+                bail!("invalid trigger expression at pos {pos}: expected term before 'then'")
+            }
             TriggerExprToken::RParen { pos } => {
                 bail!("invalid trigger expression at pos {pos}: unexpected token")
             }
@@ -579,6 +973,7 @@ impl<'a> TriggerExprParser<'a> {
 
     fn peek_operator(&self) -> Option<(&'static str, usize, u8)> {
         match self.peek() {
+            Some(TriggerExprToken::Then { pos }) => Some(("then", *pos, 3)),
             Some(TriggerExprToken::And { pos }) => Some(("&&", *pos, 2)),
             Some(TriggerExprToken::Or { pos }) => Some(("||", *pos, 1)),
             _ => None,
@@ -642,6 +1037,143 @@ enum LogFormat {
 enum PromptBlock {
     Single(String),
     Multi(Vec<String>),
+    Ref(PromptRef),
+    Rotate(PromptRotation),
+    // Before `Source`, whose fields are all optional and would accept any map.
+    Forward(PromptForward),
+    Source(PromptSource),
+}
+
+/// `prompt: {forward: {between: [...]}, template: "..."}`: a region of the matched source's
+/// output, e.g. another agent's review, sent as the prompt.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct PromptForward {
+    forward: ForwardRegion,
+    /// `{{output}}` is replaced by the region; the region alone when unset.
+    template: Option<String>,
+    /// Longer regions are cut at the last line break before the limit (default 4000).
+    max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct ForwardRegion {
+    /// Start and end marker; the lines strictly between the last complete pair.
+    between: Option<[String; 2]>,
+    /// Regex; the lines after its last matching line.
+    after: Option<String>,
+    /// Keep only the last N lines of the region.
+    lines: Option<usize>,
+}
+
+/// `prompt: {file: ./NEXT.md}` or `prompt: {command: "..."}`, read fresh for every send.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct PromptSource {
+    file: Option<String>,
+    command: Option<String>,
+    /// How long `command` may run before the send is skipped (default 30s).
+    timeout: Option<String>,
+    /// Markdown heading whose body is sent (up to the next heading of the same level).
+    section: Option<String>,
+    /// 1-based inclusive line range, e.g. `10-40`.
+    lines: Option<String>,
+    /// Longer text is cut at the last line break before the limit.
+    max_bytes: Option<usize>,
+}
+
+/// A prompt whose file/command sources are read only when it is about to be sent.
+#[derive(Debug, Clone)]
+struct PendingPrompt {
+    /// Prompt blocks in order; each source's slot is empty until resolved.
+    parts: Vec<String>,
+    /// `(index into parts, "scope.slot", source)`.
+    sources: Vec<(usize, String, PromptSource)>,
+}
+
+impl PendingPrompt {
+    /// Reads every source now; `Err` carries the reason to log instead of sending.
+    fn resolve(self) -> std::result::Result<String, String> {
+        let mut parts = self.parts;
+        for (index, label, source) in &self.sources {
+            parts[*index] =
+                read_prompt_source(source).map_err(|reason| format!("{label}: {reason}"))?;
+        }
+        Ok(parts.join("\n"))
+    }
+}
+
+#[derive(Debug, Default)]
+struct BuiltPrompt {
+    text: String,
+    /// Set when the prompt has file/command sources; `text` is then incomplete.
+    pending: Option<PendingPrompt>,
+    /// Rotating blocks this prompt advances once it is sent.
+    rotation_keys: Vec<String>,
+    rotation_label: Option<String>,
+    /// Why the send is skipped (missing `forward` region).
+    skip: Option<String>,
+}
+
+/// `prompt: {rotate: sequence, items: [...]}`: a different text per send of this block.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct PromptRotation {
+    rotate: RotationMode,
+    items: Vec<RotationItem>,
+    /// Sent after every item went out once (`rotate: once`).
+    fallback: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RotationMode {
+    /// Items in order, then the last one repeats.
+    Sequence,
+    RoundRobin,
+    Weighted,
+    /// Items in order, then `fallback` repeats.
+    Once,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+enum RotationItem {
+    Text(String),
+    Weighted { text: String, weight: u32 },
+}
+
+impl RotationItem {
+    fn text(&self) -> &str {
+        match self {
+            RotationItem::Text(text) | RotationItem::Weighted { text, .. } => text,
+        }
+    }
+
+    fn weight(&self) -> u32 {
+        match self {
+            RotationItem::Text(_) => 1,
+            RotationItem::Weighted { weight, .. } => *weight,
+        }
+    }
+}
+
+/// `prompt: {ref: name, vars: {...}}` pointing into the prompt library.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct PromptRef {
+    #[serde(rename = "ref")]
+    name: String,
+    vars: Option<TemplateVars>,
+}
+
+/// Only the `prompts:` map and `imports` of a workspace config; everything else is ignored.
+#[derive(Debug, Deserialize)]
+struct WorkspacePrompts {
+    prompts: Option<BTreeMap<String, String>>,
+    imports: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PromptSnippet {
+    text: String,
+    source: PathBuf,
 }
 
 #[derive(Debug)]
@@ -655,13 +1187,30 @@ struct SendPlan {
     source_target: String,
     rule_id: Option<String>,
     rule_index: usize,
-    next_rule: Option<String>,
+    next_rule: Option<NextStep>,
     edge_key: String,
     prompt: String,
     trigger_preview: String,
     trigger_preview_lines: usize,
     stop_after: bool,
     delay_seconds: Option<u64>,
+    /// Panes matched by the rule's `send_to`; replaces the fanout recipients.
+    send_to: Option<Vec<String>>,
+    /// Rotating blocks this prompt advances once it is sent.
+    rotation_keys: Vec<String>,
+    rotation_label: Option<String>,
+    /// File/command sources still to read; `prompt` is filled in by `resolve_prompt`.
+    pending: Option<PendingPrompt>,
+}
+
+impl SendPlan {
+    /// Reads the prompt's file/command sources right before sending, after any delay.
+    fn resolve_prompt(&mut self) -> std::result::Result<(), String> {
+        if let Some(pending) = self.pending.take() {
+            self.prompt = pending.resolve()?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -688,6 +1237,12 @@ struct FleetRunRecord {
     version: String,
     #[serde(default)]
     events: Vec<FleetRunEvent>,
+    /// Sends so far per rotating prompt block (`default_action.prompt`, `<rule>.post`, ...).
+    #[serde(default)]
+    prompt_positions: BTreeMap<String, u32>,
+    /// Fire counters of rules with `max_fires`, `cooldown` or `budget`, e.g. `2/3`.
+    #[serde(default)]
+    rule_fires: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -720,6 +1275,8 @@ struct FleetRunRegistry {
     state_path: PathBuf,
     control_path: PathBuf,
     last_control_token: Option<String>,
+    prompt_positions: BTreeMap<String, u32>,
+    rule_fires: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
@@ -849,12 +1406,328 @@ fn main() -> Result<()> {
         Some(Command::Validate(args)) => validate(args),
         Some(Command::Init(args)) => init(args),
         Some(Command::Simulate(args)) => simulate(args),
+        Some(Command::TestRules(args)) => test_rules(args),
+        Some(Command::Replay(args)) => replay(args),
         Some(Command::Runs(args)) => runs(args),
         Some(Command::Config(args)) => config_command(args),
+        Some(Command::Schema(args)) => schema(args),
+        Some(Command::Graph(args)) => graph(args),
+        Some(Command::Prompts(args)) => prompts_command(args),
         None => run_default_workspace_profiles(),
     }
 }
 
+fn test_rules(args: TestRulesArgs) -> Result<()> {
+    let mut config = load_config(Some(&args.config))?;
+    if let Some(seed) = args.seed {
+        config.seed = Some(seed);
+    }
+    let resolved = resolve_config(
+        config, None, None, true, None, None, false, false, false, None, None, None,
+    )?;
+    if resolved.exec_command.is_some() {
+        bail!("test-rules does not support exec configs");
+    }
+    let input = std::fs::read_to_string(&args.input)
+        .with_context(|| format!("failed to read {}", args.input.display()))?;
+    let snapshots = split_snapshots(&input);
+    let window = args.capture_window.unwrap_or(resolved.capture_window);
+    let report = test_rules_report(&resolved, &snapshots, window)?;
+
+    let Some(golden) = args.golden else {
+        print!("{report}");
+        return Ok(());
+    };
+    if args.update_golden {
+        std::fs::write(&golden, &report)
+            .with_context(|| format!("failed to write {}", golden.display()))?;
+        println!("Updated golden {}", golden.display());
+        return Ok(());
+    }
+    let expected = std::fs::read_to_string(&golden)
+        .with_context(|| format!("failed to read {}", golden.display()))?;
+    if expected != report {
+        let expected_lines = expected.lines().collect::<Vec<_>>();
+        let actual_lines = report.lines().collect::<Vec<_>>();
+        for idx in 0..expected_lines.len().max(actual_lines.len()) {
+            let want = expected_lines.get(idx);
+            let got = actual_lines.get(idx);
+            if want != got {
+                if let Some(want) = want {
+                    println!("- {want}");
+                }
+                if let Some(got) = got {
+                    println!("+ {got}");
+                }
+            }
+        }
+        bail!(
+            "test-rules output differs from golden {} (rerun with --update-golden to accept)",
+            golden.display()
+        );
+    }
+    println!("Golden OK: {}", golden.display());
+    Ok(())
+}
+
+fn split_snapshots(input: &str) -> Vec<String> {
+    let mut snapshots = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut seen_delimiter = false;
+    for line in input.lines() {
+        if line.trim_start().starts_with(SNAPSHOT_DELIMITER) {
+            if seen_delimiter || current.iter().any(|line| !line.trim().is_empty()) {
+                snapshots.push(current.join("\n"));
+            }
+            current.clear();
+            seen_delimiter = true;
+            continue;
+        }
+        current.push(line);
+    }
+    if !seen_delimiter || !current.is_empty() {
+        snapshots.push(current.join("\n"));
+    }
+    snapshots
+}
+
+/// Evaluates snapshots with the run loop's rule, edge and confirm semantics on virtual time.
+fn test_rules_report(
+    config: &ResolvedConfig,
+    snapshots: &[String],
+    window: CaptureWindow,
+) -> Result<String> {
+    let captures = snapshots
+        .iter()
+        .enumerate()
+        .map(|(index, snapshot)| ReplayCapture {
+            target: TEST_RULES_TARGET.to_string(),
+            at: Duration::from_secs(index as u64 * config.poll),
+            output: snapshot.clone(),
+        })
+        .collect::<Vec<_>>();
+    let (mut lines, sends) = replay_captures(config, &captures, &BTreeMap::new(), window, false)?;
+    lines.push(format!(
+        "summary snapshots={} sends={sends}",
+        snapshots.len()
+    ));
+    let mut report = lines.join("\n");
+    report.push('\n');
+    Ok(report)
+}
+
+#[derive(Debug, Clone)]
+struct ReplayCapture {
+    target: String,
+    at: Duration,
+    output: String,
+}
+
+/// Feeds captures through a fresh `TriggerTracker`, treating every plan as sent.
+/// `titles` are the recorded pane titles, which may name panes that were never captured.
+/// Returns one report line per capture plus the number of simulated sends.
+fn replay_captures(
+    config: &ResolvedConfig,
+    captures: &[ReplayCapture],
+    titles: &BTreeMap<String, String>,
+    window: CaptureWindow,
+    show_target: bool,
+) -> Result<(Vec<String>, u32)> {
+    let mut tracker = TriggerTracker::with_seed(config.seed.unwrap_or(0));
+    let mut clock = Clock::virtual_now();
+    let max_sends = config.iterations.unwrap_or(u32::MAX);
+    let mut sends = 0u32;
+    let mut lines = Vec::new();
+    let mut stopped = None;
+    tracker.set_pane_titles(
+        captures
+            .iter()
+            .map(|capture| (capture.target.clone(), String::new()))
+            .chain(titles.clone()),
+    );
+    for (index, capture) in captures.iter().enumerate() {
+        clock.advance_to(capture.at);
+        let header = if show_target {
+            format!(
+                "#{} t={} target={}",
+                index + 1,
+                format_virtual_time(capture.at),
+                capture.target
+            )
+        } else {
+            format!("#{} t={}", index + 1, format_virtual_time(capture.at))
+        };
+        let output = scan_text(apply_capture_window(&capture.output, window), window);
+        let scanned = split_capture_styles(output, config.capture_ansi);
+        let scan = tracker.scan_target(
+            config,
+            &capture.target,
+            &scanned,
+            clock.now(),
+            &mut HashSet::new(),
+            false,
+        )?;
+        for detail in &scan.flow_events {
+            lines.push(format!("{header} {detail}"));
+        }
+        if let Some(reason) = scan.flow_stop {
+            lines.push(format!("{header} stopped reason={reason}"));
+            break;
+        }
+        if scan.skipped_unchanged {
+            lines.push(format!("{header} unchanged"));
+            continue;
+        }
+        if scan.matched_rule_ids.is_empty() {
+            lines.push(format!("{header} no match"));
+            continue;
+        }
+        for (rule_id, reason) in &scan.suppressed {
+            lines.push(format!(
+                "{header} held rule={} reason={reason}",
+                rule_id.as_deref().unwrap_or("<unnamed>")
+            ));
+        }
+        for mut plan in scan.plans {
+            if let Err(reason) = plan.resolve_prompt() {
+                lines.push(format!(
+                    "{header} held rule={} reason=prompt-source ({reason})",
+                    plan.rule_id.as_deref().unwrap_or("<unnamed>")
+                ));
+                tracker.retry_target(&plan.source_target);
+                continue;
+            }
+            let plan = &plan;
+            tracker.record_send(plan);
+            tracker.record_fire(plan, clock.now());
+            tracker.arm_edge(plan, config.trigger_edge);
+            sends = sends.saturating_add(1);
+            let delay = config
+                .rules
+                .get(plan.rule_index)
+                .and_then(|rule| rule.delay.as_ref())
+                .or(config.delay.as_ref())
+                .map(|delay| {
+                    format!(
+                        "{} ({}s)",
+                        delay_summary(delay),
+                        plan.delay_seconds.unwrap_or(0)
+                    )
+                })
+                .unwrap_or_else(|| "none".to_string());
+            let send_to = plan
+                .send_to
+                .as_ref()
+                .map(|targets| format!(" to={}", targets.join(",")))
+                .unwrap_or_default();
+            lines.push(format!(
+                "{header} fire rule={}{send_to} next={} delay={} prompt=\"{}\"",
+                plan.rule_id.as_deref().unwrap_or("<unnamed>"),
+                plan.next_rule
+                    .as_ref()
+                    .map(NextStep::label)
+                    .unwrap_or_else(|| "-".to_string()),
+                delay,
+                plan.prompt.replace('\n', "\\n")
+            ));
+            if plan.stop_after {
+                stopped = Some("rule_stop");
+            } else if config.once {
+                stopped = Some("once");
+            } else if !config.infinite && sends >= max_sends {
+                stopped = Some("iterations");
+            }
+            if stopped.is_some() {
+                break;
+            }
+        }
+        if let Some(reason) = stopped {
+            lines.push(format!("{header} stopped reason={reason}"));
+            break;
+        }
+    }
+    Ok((lines, sends))
+}
+
+fn format_virtual_time(at: Duration) -> String {
+    if at.subsec_millis() == 0 {
+        format!("{}s", at.as_secs())
+    } else {
+        format!("{:.3}s", at.as_secs_f64())
+    }
+}
+
+fn replay(args: ReplayArgs) -> Result<()> {
+    let mut config = load_config(Some(&args.config))?;
+    if let Some(seed) = args.seed {
+        config.seed = Some(seed);
+    }
+    let resolved = resolve_config(
+        config, None, None, true, None, None, false, false, false, None, None, None,
+    )?;
+    if resolved.exec_command.is_some() {
+        bail!("replay does not support exec configs");
+    }
+    let records = read_session_records(&args.session)?;
+    let mut captures = Vec::new();
+    let mut recorded_sends = 0usize;
+    let mut manual_sends = 0usize;
+    let mut titles = BTreeMap::new();
+    for record in &records {
+        match record {
+            SessionRecord::Capture {
+                t_ms,
+                target,
+                output,
+                ..
+            } => captures.push(ReplayCapture {
+                target: target.clone(),
+                at: Duration::from_millis(*t_ms),
+                output: output.clone(),
+            }),
+            SessionRecord::Send { source, .. } if source.is_some() => manual_sends += 1,
+            SessionRecord::Send { .. } => recorded_sends += 1,
+            SessionRecord::Panes {
+                titles: recorded, ..
+            } => titles.extend(recorded.clone()),
+            SessionRecord::Session { .. } | SessionRecord::Decision { .. } => {}
+        }
+    }
+    if captures.is_empty() {
+        bail!("no captures recorded in {}", args.session.display());
+    }
+    let window = args.capture_window.unwrap_or(resolved.capture_window);
+    let (lines, sends) = replay_captures(&resolved, &captures, &titles, window, true)?;
+    for line in lines {
+        println!("{line}");
+    }
+    println!(
+        "summary captures={} recorded_sends={} replayed_sends={} manual_sends={}",
+        captures.len(),
+        recorded_sends,
+        sends,
+        manual_sends
+    );
+    Ok(())
+}
+
+fn parse_capture_window(value: &str) -> Result<CaptureWindow> {
+    let (kind, count) = value
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("capture window must be tail:N or head:N"))?;
+    let count = count
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| anyhow::anyhow!("capture window line count must be > 0"))?;
+    match kind.trim() {
+        "tail" => Ok(CaptureWindow::Tail(count)),
+        "head" => Ok(CaptureWindow::Head(count)),
+        other => bail!("unknown capture window '{other}' (expected tail or head)"),
+    }
+}
+
 fn simulate(args: SimulateArgs) -> Result<()> {
     let delay = std::time::Duration::from_secs(args.sleep);
     match args.repeat {
@@ -895,9 +1768,12 @@ fn run(args: RunArgs) -> Result<()> {
     if !sources.file_paths.is_empty() {
         config.files = Some(sources.file_paths);
     }
+    if let Some(seed) = args.seed {
+        config.seed = Some(seed);
+    }
     let run_name = args.name.clone().or_else(|| config.name.clone());
     let identity = resolve_run_identity(run_name.as_deref());
-    let resolved = resolve_config(
+    let mut resolved = resolve_config(
         config,
         None,
         args.iterations,
@@ -911,6 +1787,7 @@ fn run(args: RunArgs) -> Result<()> {
         args.no_recheck_before_send.then_some(false),
         None,
     )?;
+    resolved.source_path = args.config.clone();
 
     if args.dry_run {
         print_validation(&resolved);
@@ -919,11 +1796,18 @@ fn run(args: RunArgs) -> Result<()> {
         return Ok(());
     }
 
-    let run_result = run_loop(resolved, identity);
-    if run_result.is_ok() {
-        store_run_history(&args)?;
+    let recorder = args
+        .record
+        .as_deref()
+        .map(|path| SessionRecorder::create(path, &resolved, &identity))
+        .transpose()?;
+    let expects_goal = resolved.done_when.is_some();
+    let outcome = run_loop(resolved, identity, recorder, &mut Clock::System, TMUX_IO)?;
+    store_run_history(&args)?;
+    if expects_goal && outcome != RunOutcome::GoalReached {
+        std::process::exit(EXIT_GOAL_NOT_REACHED);
     }
-    run_result
+    Ok(())
 }
 
 fn runs(args: RunsArgs) -> Result<()> {
@@ -943,7 +1827,9 @@ fn config_command(args: ConfigArgs) -> Result<()> {
     let action = args.action.unwrap_or(ConfigAction::List { all: false });
     match action {
         ConfigAction::List { all } => config_list(args.config.as_ref(), all),
-        ConfigAction::Validate { all } => config_validate(args.config.as_ref(), all),
+        ConfigAction::Validate { all, strict } => {
+            config_validate(args.config.as_ref(), all, strict)
+        }
         ConfigAction::Doctor { all } => config_doctor(args.config.as_ref(), all),
         ConfigAction::Test { profile } => config_test(args.config.as_ref(), &profile),
     }
@@ -991,13 +1877,14 @@ fn config_test(path_override: Option<&PathBuf>, profile_id: &str) -> Result<()>
     println!("Target: {}", resolved.target_label);
     println!("Rules: {}", resolved.rules.len());
     println!("Mode: {}", if resolved.tui { "tui" } else { "plain" });
-    println!(
-        "Capture: {}",
-        match resolved.capture_window {
-            CaptureWindow::Tail(lines) => format!("tail({lines})"),
-            CaptureWindow::Head(lines) => format!("head({lines})"),
-        }
-    );
+    let window = match resolved.capture_window {
+        CaptureWindow::Tail(lines) => format!("tail({lines})"),
+        CaptureWindow::Head(lines) => format!("head({lines})"),
+    };
+    match resolved.capture {
+        CaptureMode::Window => println!("Capture: {window}"),
+        mode => println!("Capture: {} (window {window})", mode.label()),
+    }
     println!("Dry-run OK: profile is valid and ready.");
     Ok(())
 }
@@ -1134,8 +2021,23 @@ fn config_list(path_override: Option<&PathBuf>, all: bool) -> Result<()> {
     Ok(())
 }
 
-fn config_validate(path_override: Option<&PathBuf>, all: bool) -> Result<()> {
+fn config_validate(path_override: Option<&PathBuf>, all: bool, strict: bool) -> Result<()> {
     let (config_path, profiles, cwd) = load_workspace_profile_context(path_override)?;
+    let key_issues = lint_workspace_config_keys(&config_path)?;
+    if strict && !key_issues.is_empty() {
+        bail!(
+            "unknown config keys in {}:\n- {}",
+            config_path.display(),
+            key_issues
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n- ")
+        );
+    }
+    for issue in &key_issues {
+        eprintln!("warning: {issue}");
+    }
     let selected = selected_workspace_profiles(&profiles, &cwd, all);
     if selected.is_empty() {
         println!(
@@ -1383,8 +2285,7 @@ fn load_workspace_profiles_from_path(
 
     let contents = std::fs::read_to_string(&normalized)
         .with_context(|| format!("failed to read {}", normalized.display()))?;
-    let workspace: WorkspaceConfig = serde_yaml::from_str(&contents)
-        .with_context(|| format!("failed to parse {}", normalized.display()))?;
+    let workspace: WorkspaceConfig = parse_yaml_source(&contents, &normalized)?;
 
     let mut profiles = Vec::new();
     let mut index = 0usize;
@@ -1402,7 +2303,10 @@ fn load_workspace_profiles_from_path(
                 sanitize_run_name(&id)
             },
             source_path: normalized.clone(),
-            config: workspace.config.clone(),
+            config: Config {
+                workspace_path: Some(normalized.clone()),
+                ..workspace.config.clone()
+            },
             enabled: workspace.enabled.unwrap_or(true),
             when: workspace.when.clone().unwrap_or_default(),
         });
@@ -1426,7 +2330,10 @@ fn load_workspace_profiles_from_path(
         profiles.push(ResolvedRunProfile {
             id,
             source_path: normalized.clone(),
-            config: run.config,
+            config: Config {
+                workspace_path: Some(normalized.clone()),
+                ..run.config
+            },
             enabled: run.enabled.unwrap_or(true),
             when: run.when.unwrap_or_default(),
         });
@@ -1513,6 +2420,11 @@ fn write_runtime_profile_config(profile: &ResolvedRunProfile) -> Result<PathBuf>
     if config.name.is_none() {
         config.name = Some(profile.id.clone());
     }
+    // The runtime file lives elsewhere, so resolve `ref:` prompts against the workspace now.
+    if config_has_prompt_refs(&config) {
+        let library = load_prompt_library(Some(&profile.source_path))?;
+        expand_config_prompt_refs(&mut config, &library)?;
+    }
     let serialized = serde_yaml::to_string(&config)
         .with_context(|| format!("failed to serialize profile config: {}", profile.id))?;
     std::fs::write(&path, serialized)
@@ -1520,7 +2432,7 @@ fn write_runtime_profile_config(profile: &ResolvedRunProfile) -> Result<PathBuf>
     Ok(path)
 }
 
-fn hydrate_run_args_from_history(mut args: RunArgs) -> Result<RunArgs> {
+fn hydrate_run_args_from_history(args: RunArgs) -> Result<RunArgs> {
     let needs_history = args.tui
         && args.config.is_none()
         && args.prompt.is_none()
@@ -1531,6 +2443,10 @@ fn hydrate_run_args_from_history(mut args: RunArgs) -> Result<RunArgs> {
     }
 
     let entry = select_history_entry(args.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT))?;
+    Ok(apply_history_entry(args, entry))
+}
+
+fn apply_history_entry(mut args: RunArgs, entry: HistoryEntry) -> RunArgs {
     if args.target.is_empty() {
         args.target = vec![entry.target];
     }
@@ -1584,7 +2500,7 @@ fn hydrate_run_args_from_history(mut args: RunArgs) -> Result<RunArgs> {
     if args.duration.is_none() {
         args.duration = entry.duration;
     }
-    Ok(args)
+    args
 }
 
 fn history_dir() -> Result<PathBuf> {
@@ -1675,9 +2591,36 @@ impl FleetRunRegistry {
             identity,
             profile_id,
             last_control_token: None,
+            prompt_positions: BTreeMap::new(),
+            rule_fires: BTreeMap::new(),
         })
     }
 
+    fn set_prompt_positions(&mut self, positions: &BTreeMap<String, u32>) {
+        self.prompt_positions = positions.clone();
+    }
+
+    /// Rotation positions left by the latest crashed run with this name and profile, so a
+    /// restarted run continues its prompt sequence. Cleanly stopped runs remove their record.
+    fn previous_prompt_positions(&self) -> Option<(String, BTreeMap<String, u32>)> {
+        load_fleet_runs()
+            .ok()?
+            .into_iter()
+            .filter(|run| {
+                run.stale
+                    && run.record.id != self.identity.id
+                    && run.record.name == self.identity.name
+                    && run.record.profile_id == self.profile_id
+                    && !run.record.prompt_positions.is_empty()
+            })
+            .max_by(|left, right| left.record.last_seen.cmp(&right.record.last_seen))
+            .map(|run| (run.record.id, run.record.prompt_positions))
+    }
+
+    fn set_rule_fires(&mut self, fires: BTreeMap<String, String>) {
+        self.rule_fires = fires;
+    }
+
     fn update(&self, target: &str, state: LoopState, sends: u32, poll_seconds: u64) -> Result<()> {
         let now = timestamp_now();
         let host = std::env::var("HOSTNAME")
@@ -1698,6 +2641,8 @@ impl FleetRunRegistry {
             last_seen: now.clone(),
             version: LOOPMUX_VERSION.to_string(),
             events: Vec::new(),
+            prompt_positions: self.prompt_positions.clone(),
+            rule_fires: self.rule_fires.clone(),
         };
 
         let mut record = if self.state_path.exists() {
@@ -1848,6 +2793,15 @@ fn load_fleet_runs() -> Result<Vec<FleetListedRun>> {
     Ok(runs)
 }
 
+/// Sends per rotating block, e.g. `default_action.prompt=2 review.post=1`.
+fn format_prompt_positions(positions: &BTreeMap<String, u32>) -> String {
+    positions
+        .iter()
+        .map(|(key, sends)| format!("{key}={sends}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn is_version_mismatch(run_version: &str) -> bool {
     run_version.trim().is_empty() || run_version.trim() != LOOPMUX_VERSION
 }
@@ -2100,6 +3054,23 @@ fn fleet_detail_lines(
         lines.push(format!("state: {}", run.record.state));
         lines.push(format!("target: {}", run.record.target));
         lines.push(format!("sends: {}", run.record.sends));
+        if !run.record.prompt_positions.is_empty() {
+            lines.push(format!(
+                "prompts: {}",
+                format_prompt_positions(&run.record.prompt_positions)
+            ));
+        }
+        if !run.record.rule_fires.is_empty() {
+            lines.push(format!(
+                "fires: {}",
+                run.record
+                    .rule_fires
+                    .iter()
+                    .map(|(rule, count)| format!("{rule}={count}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
         lines.push(format!(
             "version: {} ({})",
             version,
@@ -2374,41 +3345,42 @@ fn apply_external_control(
     hold_started: &mut Option<std::time::Instant>,
     held_total: &mut std::time::Duration,
     send_count: &mut u32,
-    last_hash_by_target: &mut std::collections::HashMap<String, String>,
+    tracker: &mut TriggerTracker,
     active_rule: &mut Option<String>,
-    active_rule_by_target: &mut std::collections::HashMap<String, Option<String>>,
+    now: std::time::Instant,
 ) -> bool {
     match command {
         FleetControlCommand::Stop => true,
         FleetControlCommand::Hold => {
             if hold_started.is_none() {
-                *hold_started = Some(std::time::Instant::now());
+                *hold_started = Some(now);
             }
             *loop_state = LoopState::Holding;
             false
         }
         FleetControlCommand::Resume => {
             if let Some(started_at) = hold_started.take() {
-                *held_total += started_at.elapsed();
+                *held_total += now.saturating_duration_since(started_at);
             }
             *loop_state = LoopState::Running;
             false
         }
         FleetControlCommand::Next => {
-            last_hash_by_target.clear();
+            tracker.clear_hashes();
             false
         }
         FleetControlCommand::Renew => {
             *send_count = 0;
-            last_hash_by_target.clear();
+            tracker.clear_flow();
+            tracker.reset_rotation();
             *active_rule = None;
-            active_rule_by_target.clear();
             false
         }
     }
 }
 
 fn sleep_with_heartbeat(
+    clock: &mut Clock,
     registry: &FleetRunRegistry,
     target: &str,
     state: LoopState,
@@ -2420,7 +3392,7 @@ fn sleep_with_heartbeat(
         return Ok(());
     }
     for _ in 0..seconds {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        clock.sleep(Duration::from_secs(1));
         registry.update(target, state, sends, poll_seconds)?;
     }
     Ok(())
@@ -2453,16 +3425,22 @@ fn summarize_exec_stream(bytes: &[u8], use_unicode: bool) -> String {
 
 fn run_fleet_manager_tui(profile_filter: Option<&str>) -> Result<()> {
     enable_raw_mode().context("failed to enable raw mode for fleet manager")?;
-    let result = run_fleet_manager_tui_inner(false, profile_filter);
+    let result = load_ui_settings(None)
+        .and_then(|ui| run_fleet_manager_tui_inner(false, profile_filter, ui));
     let _ = disable_raw_mode();
     result
 }
 
-fn run_fleet_manager_tui_embedded() -> Result<()> {
-    run_fleet_manager_tui_inner(true, None)
+fn run_fleet_manager_tui_embedded(ui: &UiConfig) -> Result<()> {
+    run_fleet_manager_tui_inner(true, None, load_ui_settings(Some(ui))?)
 }
 
-fn run_fleet_manager_tui_inner(embedded: bool, profile_filter: Option<&str>) -> Result<()> {
+fn run_fleet_manager_tui_inner(
+    embedded: bool,
+    profile_filter: Option<&str>,
+    ui: UiSettings,
+) -> Result<()> {
+    let mut show_help = false;
     let mut selected: usize = 0;
     let mut selected_run_id: Option<String> = None;
     let mut message = String::from("fleet manager ready");
@@ -2568,8 +3546,15 @@ fn run_fleet_manager_tui_inner(embedded: bool, profile_filter: Option<&str>) ->
             pending_action.as_ref(),
         );
 
+        let (lines, details) = if show_help {
+            (ui.fleet_keys.help_lines(), Vec::new())
+        } else {
+            (lines, details)
+        };
+
         let footer = format!(
-            "nav <-/-> · mark space · clear a · presets p/1-4 · sort o · filters x/v/f · search / · single h/r/n/R/s · bulk S/H/P/N/U · enter confirm · c cancel · i id · y stop-cmd · q/esc {} · {}",
+            "{}/esc {} · {}",
+            fleet_footer_hints(&ui.fleet_keys),
             if embedded {
                 "return to run"
             } else {
@@ -2676,7 +3661,16 @@ fn run_fleet_manager_tui_inner(embedded: bool, profile_filter: Option<&str>) ->
                         continue;
                     }
 
+                    let Some(code) = ui.fleet_keys.translate(code) else {
+                        continue;
+                    };
                     match code {
+                        KeyCode::Char('?') => {
+                            show_help = !show_help;
+                        }
+                        KeyCode::Esc if show_help => {
+                            show_help = false;
+                        }
                         KeyCode::Esc | KeyCode::Char('q') => break,
                         KeyCode::Enter => {
                             if let Some(action) = pending_action.take() {
@@ -3258,6 +4252,9 @@ fn store_run_history(args: &RunArgs) -> Result<()> {
 
     let mut history = load_run_history()?;
     let limit = args.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT).max(1);
+    let pinned = history.entries.iter().any(|entry| {
+        entry.pinned && history_entry_signature(entry).as_deref() == Some(signature.as_str())
+    });
     history.entries.retain(|entry| {
         history_entry_signature(entry)
             .map(|existing| existing != signature)
@@ -3287,14 +4284,23 @@ fn store_run_history(args: &RunArgs) -> Result<()> {
             recheck_before_send: Some(!args.no_recheck_before_send),
             fanout: Some(args.fanout),
             duration: args.duration.clone(),
+            pinned,
         },
     );
-    if history.entries.len() > limit {
-        history.entries.truncate(limit);
-    }
+    evict_history(&mut history.entries, limit);
     save_run_history(&history)
 }
 
+/// Drops the oldest unpinned entries until `limit` is met (pinned ones always stay).
+fn evict_history(entries: &mut Vec<HistoryEntry>, limit: usize) {
+    while entries.len() > limit {
+        let Some(oldest) = entries.iter().rposition(|entry| !entry.pinned) else {
+            break;
+        };
+        entries.remove(oldest);
+    }
+}
+
 fn history_entry_signature(entry: &HistoryEntry) -> Option<String> {
     Some(format!(
         "target={}|prompt={}|trigger={}|trigger_expr={}|trigger_exact_line={}|exclude={}|pre={}|post={}|iterations={}|tail={}|head={}|once={}|poll={}|trigger_confirm_seconds={}|log_preview_lines={}|trigger_edge={}|recheck_before_send={}|fanout={}|duration={}",
@@ -3331,7 +4337,365 @@ fn select_history_entry(limit: usize) -> Result<HistoryEntry> {
     if history.entries.is_empty() {
         bail!("no run history found; run a command once before using --tui history picker");
     }
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return select_history_entry_numbered(&history, limit);
+    }
+
+    enable_raw_mode().context("failed to enable raw mode for history picker")?;
+    let result = run_history_picker(history, limit);
+    let _ = disable_raw_mode();
+    let mut out = std::io::stdout();
+    let _ = out.queue(MoveTo(0, 0));
+    let _ = out.queue(Clear(ClearType::All));
+    let _ = out.flush();
+    match result? {
+        Some(entry) => Ok(entry),
+        None => bail!("history selection cancelled"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HistoryPickerMode {
+    Search,
+    ConfirmDelete,
+    Export(String),
+}
+
+fn run_history_picker(mut history: RunHistory, limit: usize) -> Result<Option<HistoryEntry>> {
+    let mut query = String::new();
+    let mut selected: usize = 0;
+    let mut mode = HistoryPickerMode::Search;
+    let mut message = String::new();
+    let use_unicode = supports_unicode();
+
+    loop {
+        let candidates = history_candidates(&history.entries, limit);
+        let matches = history_matches(&history.entries, &candidates, &query);
+        selected = selected.min(matches.len().saturating_sub(1));
+        let current = matches.get(selected).copied();
+
+        let (width, height) = crossterm::terminal::size().unwrap_or((100, 30));
+        let width = width as usize;
+        let rows = (height as usize).saturating_sub(3);
+        let split = width >= 100;
+        let list_width = if split { width * 45 / 100 } else { width };
+        let list_rows = if split { rows } else { rows / 2 };
+        let offset = selected.saturating_sub(list_rows.saturating_sub(1));
+        let list = matches
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(list_rows)
+            .map(|(position, index)| {
+                let entry = &history.entries[*index];
+                let marker = if position == selected { '>' } else { ' ' };
+                let pin = if entry.pinned { '*' } else { ' ' };
+                fit_line(
+                    &format!(
+                        "{marker}{pin} {} {}",
+                        entry.target,
+                        entry.prompt.lines().next().unwrap_or("")
+                    ),
+                    list_width,
+                    use_unicode,
+                )
+            })
+            .collect::<Vec<_>>();
+        let preview = current
+            .map(|index| history_entry_preview_lines(&history.entries[index]))
+            .unwrap_or_default();
+
+        let mut screen = Vec::with_capacity(height as usize);
+        screen.push(fit_line(
+            &match &mode {
+                HistoryPickerMode::Search => format!(
+                    "history> {query}   ({}/{})",
+                    matches.len(),
+                    candidates.len()
+                ),
+                HistoryPickerMode::ConfirmDelete => "delete selected entry? y/n".to_string(),
+                HistoryPickerMode::Export(path) => format!("export to> {path}"),
+            },
+            width,
+            use_unicode,
+        ));
+        for row in 0..rows {
+            if split {
+                let left = list.get(row).map(String::as_str).unwrap_or("");
+                let right = preview.get(row).map(String::as_str).unwrap_or("");
+                screen.push(fit_line(
+                    &format!("{} | {right}", pad_to_width(left, list_width)),
+                    width,
+                    use_unicode,
+                ));
+            } else if row < list_rows {
+                screen.push(list.get(row).cloned().unwrap_or_default());
+            } else {
+                let line = preview
+                    .get(row - list_rows)
+                    .map(String::as_str)
+                    .unwrap_or("");
+                screen.push(fit_line(line, width, use_unicode));
+            }
+        }
+        screen.push(fit_line(&message, width, use_unicode));
+        screen.push(fit_line(
+            "type to search · up/down move · enter run · ^p pin · ^d delete · ^e export yaml · esc cancel",
+            width,
+            use_unicode,
+        ));
+
+        let mut out = std::io::stdout();
+        let _ = out.queue(MoveTo(0, 0));
+        let _ = out.queue(Clear(ClearType::All));
+        for (row, line) in screen.iter().enumerate() {
+            let _ = out.queue(MoveTo(0, row as u16));
+            let _ = write!(out, "{line}");
+        }
+        let _ = out.flush();
+
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event::read().context("history picker input failed")?
+        else {
+            continue;
+        };
+        let control = modifiers.contains(KeyModifiers::CONTROL);
+        match mode.clone() {
+            HistoryPickerMode::ConfirmDelete => {
+                if let (KeyCode::Char('y'), Some(index)) = (code, current) {
+                    let removed = history.entries.remove(index);
+                    save_run_history(&history)?;
+                    message = format!("deleted {}", removed.last_run);
+                } else {
+                    message = "delete cancelled".to_string();
+                }
+                mode = HistoryPickerMode::Search;
+            }
+            HistoryPickerMode::Export(mut path) => match code {
+                KeyCode::Esc => {
+                    message = "export cancelled".to_string();
+                    mode = HistoryPickerMode::Search;
+                }
+                KeyCode::Enter => {
+                    if let Some(index) = current {
+                        message =
+                            match export_history_entry(&history.entries[index], Path::new(&path)) {
+                                Ok(()) => format!("exported {path}"),
+                                Err(err) => format!("export failed: {err:#}"),
+                            };
+                    }
+                    mode = HistoryPickerMode::Search;
+                }
+                KeyCode::Backspace => {
+                    path.pop();
+                    mode = HistoryPickerMode::Export(path);
+                }
+                KeyCode::Char(value) => {
+                    path.push(value);
+                    mode = HistoryPickerMode::Export(path);
+                }
+                _ => {}
+            },
+            HistoryPickerMode::Search => match code {
+                KeyCode::Char('c') if control => return Ok(None),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Enter if current.is_some() => {
+                    return Ok(current.map(|index| history.entries[index].clone()));
+                }
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => selected = selected.saturating_add(1),
+                KeyCode::Char('p') if control && current.is_some() => {
+                    let entry = &mut history.entries[current.unwrap_or_default()];
+                    entry.pinned = !entry.pinned;
+                    message = if entry.pinned { "pinned" } else { "unpinned" }.to_string();
+                    save_run_history(&history)?;
+                }
+                KeyCode::Char('d') if control && current.is_some() => {
+                    mode = HistoryPickerMode::ConfirmDelete;
+                }
+                KeyCode::Char('e') if control && current.is_some() => {
+                    mode = HistoryPickerMode::Export("loopmux-history.yaml".to_string());
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                    selected = 0;
+                }
+                KeyCode::Char(value) if !control => {
+                    query.push(value);
+                    selected = 0;
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Pinned entries plus the `limit` most recent ones, pinned first.
+fn history_candidates(entries: &[HistoryEntry], limit: usize) -> Vec<usize> {
+    let mut candidates = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.pinned)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    candidates.extend(
+        entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.pinned)
+            .take(limit.max(1))
+            .map(|(index, _)| index),
+    );
+    candidates
+}
+
+/// Candidates whose target, prompt or trigger fuzzy-match `query`, best score first.
+fn history_matches(entries: &[HistoryEntry], candidates: &[usize], query: &str) -> Vec<usize> {
+    if query.trim().is_empty() {
+        return candidates.to_vec();
+    }
+    let mut scored = candidates
+        .iter()
+        .filter_map(|index| {
+            let entry = &entries[*index];
+            let haystack = format!(
+                "{} {} {} {}",
+                entry.target,
+                entry.prompt,
+                entry.trigger,
+                entry.trigger_expr.as_deref().unwrap_or("")
+            );
+            fuzzy_score(query, &haystack).map(|score| (score, *index))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, index)| index).collect()
+}
+
+/// Case-insensitive subsequence match; consecutive and word-start hits score higher.
+fn fuzzy_score(query: &str, haystack: &str) -> Option<i64> {
+    let haystack = haystack.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0i64;
+    let mut position = 0usize;
+    let mut previous: Option<usize> = None;
+    for wanted in query
+        .to_lowercase()
+        .chars()
+        .filter(|value| !value.is_whitespace())
+    {
+        let found = (position..haystack.len()).find(|index| haystack[*index] == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !haystack[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= (found - position).min(10) as i64;
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+fn history_entry_preview_lines(entry: &HistoryEntry) -> Vec<String> {
+    let mut lines = vec![
+        format!("last run: {}", entry.last_run),
+        format!("target: {}", entry.target),
+    ];
+    if let Some(expr) = entry.trigger_expr.as_deref() {
+        lines.push(format!("trigger_expr: {expr}"));
+    } else {
+        let kind = if entry.trigger_exact_line.unwrap_or(false) {
+            "exact_line"
+        } else {
+            "trigger"
+        };
+        lines.push(format!("{kind}: {}", entry.trigger));
+    }
+    for (label, value) in [
+        ("exclude", entry.exclude.as_deref()),
+        ("pre", entry.pre.as_deref()),
+    ] {
+        if let Some(value) = value {
+            lines.push(format!("{label}: {value}"));
+        }
+    }
+    lines.push("prompt:".to_string());
+    lines.extend(entry.prompt.lines().map(|line| format!("  {line}")));
+    if let Some(post) = entry.post.as_deref() {
+        lines.push(format!("post: {post}"));
+    }
+    let mut settings = Vec::new();
+    if let Some(iterations) = entry.iterations {
+        settings.push(format!("iterations={iterations}"));
+    }
+    if let Some(tail) = entry.tail {
+        settings.push(format!("tail={tail}"));
+    }
+    if let Some(head) = entry.head {
+        settings.push(format!("head={head}"));
+    }
+    if let Some(poll) = entry.poll {
+        settings.push(format!("poll={poll}s"));
+    }
+    if let Some(seconds) = entry.trigger_confirm_seconds {
+        settings.push(format!("confirm={seconds}s"));
+    }
+    if let Some(duration) = entry.duration.as_deref() {
+        settings.push(format!("duration={duration}"));
+    }
+    if entry.once {
+        settings.push("once".to_string());
+    }
+    if let Some(fanout) = entry.fanout {
+        settings.push(format!("fanout={}", fanout_label(fanout)));
+    }
+    if !settings.is_empty() {
+        lines.push(settings.join(" "));
+    }
+    if entry.pinned {
+        lines.push("pinned".to_string());
+    }
+    lines
+}
+
+/// Standalone run config equivalent to replaying `entry` with `loopmux run`.
+fn history_entry_config(entry: &HistoryEntry) -> Result<Config> {
+    let args = RunArgs::try_parse_from(["run"]).context("failed to build run args")?;
+    resolve_run_config(&apply_history_entry(args, entry.clone()))
+}
+
+fn export_history_entry(entry: &HistoryEntry, path: &Path) -> Result<()> {
+    let config = history_entry_config(entry)?;
+    let mut value = serde_yaml::to_value(&config).context("failed to serialize config")?;
+    strip_yaml_nulls(&mut value);
+    let yaml = serde_yaml::to_string(&value).context("failed to serialize config")?;
+    std::fs::write(
+        path,
+        format!(
+            "# Exported from loopmux history ({})\n{yaml}",
+            entry.last_run
+        ),
+    )
+    .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn strip_yaml_nulls(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            mapping.retain(|_, child| !child.is_null());
+            for (_, child) in mapping.iter_mut() {
+                strip_yaml_nulls(child);
+            }
+        }
+        serde_yaml::Value::Sequence(items) => items.iter_mut().for_each(strip_yaml_nulls),
+        _ => {}
+    }
+}
 
+fn select_history_entry_numbered(history: &RunHistory, limit: usize) -> Result<HistoryEntry> {
     println!("loopmux history (most recent first):");
     let visible = history
         .entries
@@ -3378,19 +4742,19 @@ fn select_history_entry(limit: usize) -> Result<HistoryEntry> {
     }
 }
 
-fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
+fn run_loop(
+    mut config: ResolvedConfig,
+    identity: RunIdentity,
+    mut recorder: Option<SessionRecorder>,
+    clock: &mut Clock,
+    io: PaneIo,
+) -> Result<RunOutcome> {
     let mut send_count: u32 = 0;
     let max_sends = config.iterations.unwrap_or(u32::MAX);
-    let mut last_hash_by_target: std::collections::HashMap<String, String> =
-        std::collections::HashMap::new();
-    let mut trigger_edge_active: HashSet<String> = HashSet::new();
-    let mut trigger_confirm_pending_since: std::collections::HashMap<String, std::time::Instant> =
-        std::collections::HashMap::new();
-    let mut active_rule_by_target: std::collections::HashMap<String, Option<String>> =
-        std::collections::HashMap::new();
+    let seed = config.seed.unwrap_or_else(entropy_seed);
+    let mut tracker = TriggerTracker::with_seed(seed);
+    let mut panes = PaneTable::default();
     let mut active_rule: Option<String> = None;
-    let mut backoff_state: std::collections::HashMap<String, BackoffState> =
-        std::collections::HashMap::new();
     let mut exec_in_flight: Option<ExecInFlight> = None;
     let mut exec_running_ticks: u32 = 0;
     let mut logger = Logger::new(config.logging.clone())?;
@@ -3403,11 +4767,12 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
     } else {
         UiMode::Plain
     };
-    let log_icon_mode = detect_icon_mode();
+    let ui_settings = load_ui_settings(Some(&config.ui))?;
+    let log_icon_mode = ui_settings.icon_mode;
     let log_use_unicode = supports_unicode();
     let mut loop_state = LoopState::Running;
     let mut tui = if ui_mode == UiMode::Tui {
-        Some(TuiState::new(&config)?)
+        Some(TuiState::new(ui_settings.clone())?)
     } else {
         None
     };
@@ -3429,6 +4794,7 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
         } else {
             println!("loopmux: iterations = {max_sends}");
         }
+        println!("loopmux: seed {seed}");
         println!("loopmux: started at {start_timestamp}");
     } else if ui_mode == UiMode::Tui {
         if let Some(tui_state) = tui.as_mut() {
@@ -3439,15 +4805,34 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
         }
     }
     logger.log(LogEvent::started(&config, start_timestamp.clone()))?;
-    let run_started = std::time::Instant::now();
+    logger.log(LogEvent::status(&config, format!("seed={seed}")))?;
+    if let Some((previous, positions)) = fleet_registry.previous_prompt_positions() {
+        logger.log(LogEvent::status(
+            &config,
+            format!(
+                "prompt rotation resumed from {previous}: {}",
+                format_prompt_positions(&positions)
+            ),
+        ))?;
+        tracker.rotation = positions;
+        fleet_registry.set_prompt_positions(&tracker.rotation);
+    }
+    let run_started = clock.now();
     let mut held_total = std::time::Duration::from_secs(0);
     let mut hold_started: Option<std::time::Instant> = None;
+    // Evidence for `done_when` once the goal is reached; ends the loop.
+    let mut goal: Option<String> = None;
+    // Capture hash each source's `done_when` was last checked against.
+    let mut done_checked: std::collections::HashMap<String, String> =
+        std::collections::HashMap::new();
+    let mut flow_stop = None;
     fleet_registry.update(&config.target_label, loop_state, send_count, config.poll)?;
 
     while config.infinite || send_count < max_sends {
         fleet_registry.update(&config.target_label, loop_state, send_count, config.poll)?;
         let mut force_rescan = false;
-        let active_elapsed = effective_elapsed(run_started, held_total, hold_started);
+        let active_elapsed =
+            effective_elapsed_at(clock.now(), run_started, held_total, hold_started);
         if let Some(limit) = config.duration {
             if active_elapsed >= limit {
                 if ui_mode == UiMode::Tui {
@@ -3482,10 +4867,11 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                 &mut hold_started,
                 &mut held_total,
                 &mut send_count,
-                &mut last_hash_by_target,
+                &mut tracker,
                 &mut active_rule,
-                &mut active_rule_by_target,
+                clock.now(),
             );
+            fleet_registry.set_prompt_positions(&tracker.rotation);
             if let Some(tui_state) = tui.as_mut() {
                 tui_state.push_log(format!(
                     "[{}] control command={} source=fleet-manager",
@@ -3510,16 +4896,16 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                         TuiAction::Pause => {}
                         TuiAction::Resume => {
                             if let Some(started_at) = hold_started.take() {
-                                held_total += started_at.elapsed();
+                                held_total += clock.elapsed(started_at);
                             }
                             loop_state = LoopState::Running;
                         }
                         TuiAction::HoldToggle => {
                             if let Some(started_at) = hold_started.take() {
-                                held_total += started_at.elapsed();
+                                held_total += clock.elapsed(started_at);
                                 loop_state = LoopState::Running;
                             } else {
-                                hold_started = Some(std::time::Instant::now());
+                                hold_started = Some(clock.now());
                                 loop_state = LoopState::Holding;
                             }
                         }
@@ -3536,7 +4922,12 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                 send_count,
                                 max_sends,
                                 active_rule.as_deref(),
-                                effective_elapsed(run_started, held_total, hold_started),
+                                effective_elapsed_at(
+                                    clock.now(),
+                                    run_started,
+                                    held_total,
+                                    hold_started,
+                                ),
                                 "",
                             )?;
                             break;
@@ -3548,43 +4939,59 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                             break;
                         }
                         TuiAction::Next => {
-                            last_hash_by_target.clear();
-                            trigger_edge_active.clear();
-                            trigger_confirm_pending_since.clear();
+                            tracker.reset();
                             active_rule = None;
-                            active_rule_by_target.clear();
-                            backoff_state.clear();
                             loop_state = LoopState::Running;
                             force_rescan = true;
                         }
                         TuiAction::Renew => {
                             send_count = 0;
-                            last_hash_by_target.clear();
-                            trigger_edge_active.clear();
-                            trigger_confirm_pending_since.clear();
+                            tracker.reset();
+                            tracker.reset_rotation();
                             active_rule = None;
-                            active_rule_by_target.clear();
-                            backoff_state.clear();
                             tui_state.push_log(format!(
                                 "[{}] renewed counter reason=manual",
                                 timestamp_now()
                             ));
                         }
                         TuiAction::Redraw => {}
+                        TuiAction::ManualSend => tui_manual_send(
+                            tui_state,
+                            &config,
+                            &mut panes,
+                            &mut logger,
+                            recorder.as_mut(),
+                            &mut send_count,
+                        )?,
+                        TuiAction::EditPrompt => tui_edit_prompt(
+                            tui_state,
+                            &mut config,
+                            &mut logger,
+                            active_rule.as_deref(),
+                        )?,
+                        TuiAction::PaneControl(command) => tui_pane_control(
+                            tui_state,
+                            command,
+                            &config,
+                            &mut panes,
+                            &mut tracker,
+                            &mut logger,
+                        )?,
                     }
                 }
+                tui_state.set_pane_rows(panes.rows(&tracker, &config, clock.now()));
                 tui_state.update(
                     loop_state,
                     &config,
                     send_count,
                     max_sends,
                     active_rule.as_deref(),
-                    effective_elapsed(run_started, held_total, hold_started),
+                    effective_elapsed_at(clock.now(), run_started, held_total, hold_started),
                     "",
                 )?;
             }
             if open_fleet_manager {
-                if let Err(err) = run_fleet_manager_tui_embedded() {
+                if let Err(err) = run_fleet_manager_tui_embedded(&config.ui) {
                     if let Some(tui_state) = tui.as_mut() {
                         tui_state.push_log(format!(
                             "[{}] fleet manager error=\"{}\"",
@@ -3602,7 +5009,7 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
             if force_rescan {
                 continue;
             }
-            std::thread::sleep(std::time::Duration::from_millis(100));
+            clock.sleep(Duration::from_millis(100));
             continue;
         }
 
@@ -3757,8 +5164,12 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
             }
 
             if ui_mode == UiMode::SingleLine {
-                let elapsed =
-                    format_std_duration(effective_elapsed(run_started, held_total, hold_started));
+                let elapsed = format_std_duration(effective_elapsed_at(
+                    clock.now(),
+                    run_started,
+                    held_total,
+                    hold_started,
+                ));
                 let status = status_line(
                     &config,
                     send_count,
@@ -3771,6 +5182,7 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
             }
 
             sleep_with_heartbeat(
+                clock,
                 &fleet_registry,
                 &config.target_label,
                 loop_state,
@@ -3785,25 +5197,48 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
         let mut matched_sources: HashSet<String> = HashSet::new();
         let mut tmux_recipients: Vec<String> = Vec::new();
         if loop_state != LoopState::Holding {
-            tmux_recipients = if let Some(explicit) = &config.explicit_targets {
-                explicit.clone()
-            } else {
-                let panes = match list_tmux_panes() {
-                    Ok(value) => value,
-                    Err(err) => {
-                        let detail = err.to_string();
-                        logger.log(LogEvent::error(&config, detail))?;
-                        return Err(err);
+            let tmux_panes =
+                if config.explicit_targets.is_none() || rules_address_panes(&config.rules) {
+                    match (io.list)() {
+                        Ok(value) => value,
+                        Err(err) => {
+                            let detail = err.to_string();
+                            logger.log(LogEvent::error(&config, detail))?;
+                            return Err(err);
+                        }
                     }
+                } else {
+                    Vec::new()
                 };
-                select_targets_for_scope(&config.target_scope, &panes)
+            tracker.set_pane_titles(
+                tmux_panes
+                    .iter()
+                    .map(|pane| (pane.target.clone(), pane.title.clone())),
+            );
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record_panes(&tmux_panes)?;
+            }
+            tmux_recipients = match &config.explicit_targets {
+                Some(explicit) => explicit.clone(),
+                None => select_targets_for_scope(&config.target_scope, &tmux_panes),
             };
             let mut poll_targets = tmux_recipients.clone();
             poll_targets.extend(config.file_sources.iter().map(|path| file_source_key(path)));
+            panes.sync(&poll_targets);
+            if let Some(tui_state) = tui.as_mut() {
+                tui_state.set_preview_targets(&poll_targets);
+            }
             let mut broadcast_plan_keys: HashSet<String> = HashSet::new();
 
             for target in &poll_targets {
-                let output = match capture_source(target, config.capture_window) {
+                if goal.is_some() {
+                    break;
+                }
+                if panes.is_held(target) {
+                    continue;
+                }
+                let mark = panes.capture_mark(target);
+                let output = match (io.capture)(target, &config, mark.as_ref()) {
                     Ok(output) => output,
                     Err(err) => {
                         let detail = err.to_string();
@@ -3811,116 +5246,123 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                         return Err(err);
                     }
                 };
-                let output =
-                    if config.capture_window.lines() == 1 && config.capture_window.is_tail() {
-                        last_non_empty_line(&output)
-                    } else {
-                        output
-                    };
-                let hash = hash_output(&output);
-                let last_hash = last_hash_by_target.get(target).cloned().unwrap_or_default();
-                let has_pending_confirm =
-                    has_pending_confirm_for_target(&trigger_confirm_pending_since, target);
-                if should_skip_scan_by_hash(
-                    config.trigger_edge,
-                    &hash,
-                    &last_hash,
-                    has_pending_confirm,
-                ) {
-                    continue;
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record_capture(target, &output)?;
                 }
-
-                let active = active_rule_by_target
-                    .get(target)
-                    .and_then(|value| value.as_deref());
-                let rule_matches = evaluate_rules(&config, &mut logger, &output, active)?;
-
-                let matched_edge_keys = rule_matches
-                    .iter()
-                    .map(|rule_match| trigger_edge_key(target, rule_match))
-                    .collect::<HashSet<_>>();
-                refresh_trigger_edges_for_target(
-                    &mut trigger_edge_active,
-                    target,
-                    &matched_edge_keys,
-                    config.trigger_edge,
-                );
-                refresh_trigger_confirm_for_target(
-                    &mut trigger_confirm_pending_since,
-                    target,
-                    &matched_edge_keys,
-                );
-
-                if rule_matches.is_empty() {
-                    continue;
+                let output = match effective_capture_window(&config, mark.as_ref()) {
+                    Some(window) => scan_text(output, window),
+                    None => output,
+                };
+                let scanned = split_capture_styles(output, config.capture_ansi);
+                let output = &scanned.text;
+                // Screens from before the first send are stale; unchanged output was already
+                // checked, unless `idle_for` needs time to pass on it.
+                let done_hash = hash_capture(output, &scanned.styles);
+                let fresh = done_checked.get(target) != Some(&done_hash);
+                let done = config.done_when.as_ref().filter(|done| {
+                    send_count > 0
+                        && (fresh
+                            || done
+                                .match_
+                                .as_ref()
+                                .is_some_and(|criteria| has_text(&criteria.idle_for)))
+                });
+                if done.is_some() {
+                    done_checked.insert(target.clone(), done_hash);
                 }
-
-                matched_sources.insert(target.clone());
-                for rule_match in rule_matches {
-                    let edge_key = trigger_edge_key(target, &rule_match);
-                    if !edge_guard_allows(&trigger_edge_active, &edge_key, config.trigger_edge) {
-                        continue;
+                let evidence = match done {
+                    Some(done) => {
+                        let ctx = MatchContext {
+                            styles: &scanned.styles,
+                            ..tracker.observe_output(target, output, clock.now())
+                        };
+                        done_when_match_evidence(done, target, output, &ctx)?
                     }
-                    if !confirm_window_elapsed(
-                        config.trigger_confirm_seconds,
-                        rule_match.rule.confirm_seconds,
-                        &edge_key,
-                        &mut trigger_confirm_pending_since,
-                        std::time::Instant::now(),
-                    ) {
-                        continue;
+                    None => None,
+                };
+                // A failing `exec` is re-run only once the output changes.
+                let done = done.filter(|done| fresh || done.exec.is_none());
+                if let (Some(done), Some(evidence)) = (done, evidence) {
+                    goal = confirm_done_exec(done, evidence)?;
+                    if goal.is_some() {
+                        break;
                     }
-
-                    let (trigger_preview_lines, trigger_preview) =
-                        extract_trigger_preview(&output, config.log_preview_lines, log_use_unicode);
-
-                    let action = rule_match
-                        .rule
-                        .action
-                        .as_ref()
-                        .unwrap_or(&config.default_action);
-                    let prompt = build_prompt(action);
-                    if config.fanout == FanoutMode::Broadcast {
-                        let key = format!(
-                            "{}|{}",
-                            rule_match.rule.id.as_deref().unwrap_or("<unnamed>"),
-                            prompt
-                        );
-                        if !broadcast_plan_keys.insert(key) {
-                            continue;
-                        }
+                }
+                let scan = tracker.scan_target(
+                    &config,
+                    target,
+                    &scanned,
+                    clock.now(),
+                    &mut broadcast_plan_keys,
+                    log_use_unicode,
+                )?;
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record_decision(target, &scan)?;
+                }
+                for detail in &scan.flow_events {
+                    logger.log(LogEvent::status(
+                        &config,
+                        format!("{detail} target={target}"),
+                    ))?;
+                    if let Some(tui_state) = tui.as_mut() {
+                        tui_state
+                            .push_log(format!("[{}] {detail} target={target}", timestamp_now()));
                     }
-                    let delay = rule_match.rule.delay.as_ref().or(config.delay.as_ref());
-                    let delay_seconds = if let Some(delay) = delay {
-                        Some(compute_delay_seconds(
-                            delay,
-                            &rule_match,
-                            &mut backoff_state,
-                        )?)
-                    } else {
-                        None
-                    };
-                    plans.push(SendPlan {
-                        source_target: target.clone(),
-                        rule_id: rule_match.rule.id.clone(),
-                        rule_index: rule_match.index,
-                        next_rule: rule_match.rule.next.clone(),
-                        edge_key,
-                        prompt,
-                        trigger_preview,
-                        trigger_preview_lines,
-                        stop_after: rule_match.rule.next.as_deref() == Some("stop"),
-                        delay_seconds,
-                    });
                 }
-                if config.trigger_edge {
-                    last_hash_by_target.insert(target.clone(), hash);
+                if !scan.flow_events.is_empty() {
+                    active_rule = tracker.active_step(target).map(NextStep::label);
                 }
-
-                if matches!(config.rule_eval, RuleEval::MultiMatch) {
-                    active_rule_by_target.insert(target.clone(), None);
+                if scan.flow_stop.is_some() {
+                    flow_stop = scan.flow_stop;
+                    break;
+                }
+                for detail in &scan.skipped_prompts {
+                    logger.log(LogEvent::status(
+                        &config,
+                        format!("{detail} target={target}"),
+                    ))?;
+                    if let Some(tui_state) = tui.as_mut() {
+                        tui_state
+                            .push_log(format!("[{}] {detail} target={target}", timestamp_now()));
+                    }
+                }
+                for rule_id in &scan.matched_rule_ids {
+                    logger.log(LogEvent::matched(&config, rule_id.as_deref()))?;
+                }
+                if let Some(rule_id) = scan.matched_rule_ids.first() {
+                    panes.record_match(target, rule_id.as_deref());
+                }
+                if !scan.matched_rule_ids.is_empty() {
+                    matched_sources.insert(target.clone());
                 }
+                plans.extend(scan.plans);
             }
+            // Without `match`, the exec check runs whenever a prompt is about to go out.
+            if let Some(done) = config
+                .done_when
+                .as_ref()
+                .filter(|done| done.match_.is_none() && goal.is_none() && !plans.is_empty())
+            {
+                goal = confirm_done_exec(done, String::new())?;
+            }
+        }
+
+        if let Some(evidence) = goal.as_deref() {
+            if ui_mode == UiMode::Plain {
+                println!("loopmux: goal reached ({evidence})");
+            }
+            break;
+        }
+        if let Some(reason) = flow_stop {
+            let detail = reason.replace('_', " ");
+            if let Some(tui_state) = tui.as_mut() {
+                tui_state.push_log(format!("[{}] stopped reason={reason}", timestamp_now()));
+            }
+            if ui_mode == UiMode::Plain {
+                println!("loopmux: stopping due to {detail}");
+            }
+            logger.log(LogEvent::stopped(&config, &detail, send_count))?;
+            break;
         }
 
         if plans.is_empty() {
@@ -3929,7 +5371,7 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
             }
         } else {
             let mut stop_after = false;
-            for plan in plans {
+            for mut plan in plans {
                 if loop_state == LoopState::Holding {
                     break;
                 }
@@ -3945,6 +5387,10 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                             plan.rule_id.as_deref(),
                             detail,
                         ))?;
+                        panes.set_delay(
+                            &plan.source_target,
+                            Some(clock.now() + Duration::from_secs(delay_seconds)),
+                        );
                         if let Some(tui_state) = tui.as_mut() {
                             tui_state.push_log(format!(
                                 "[{}] delay rule={} detail=\"delay {}s\"",
@@ -3952,17 +5398,24 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                 plan.rule_id.as_deref().unwrap_or("<unnamed>"),
                                 delay_seconds
                             ));
+                            tui_state.set_pane_rows(panes.rows(&tracker, &config, clock.now()));
                             tui_state.update(
                                 loop_state,
                                 &config,
                                 send_count,
                                 max_sends,
                                 plan.rule_id.as_deref(),
-                                effective_elapsed(run_started, held_total, hold_started),
+                                effective_elapsed_at(
+                                    clock.now(),
+                                    run_started,
+                                    held_total,
+                                    hold_started,
+                                ),
                                 "",
                             )?;
                         }
                         sleep_with_heartbeat(
+                            clock,
                             &fleet_registry,
                             &config.target_label,
                             loop_state,
@@ -3970,18 +5423,34 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                             config.poll,
                             delay_seconds,
                         )?;
+                        panes.set_delay(&plan.source_target, None);
                     }
                 }
 
-                let recipients = match config.fanout {
-                    FanoutMode::Matched => {
-                        if file_source_path(&plan.source_target).is_some() {
-                            tmux_recipients.clone()
-                        } else {
+                if let Err(reason) = plan.resolve_prompt() {
+                    let detail = format!(
+                        "prompt skipped rule={} reason=\"{reason}\" target={}",
+                        plan.rule_id.as_deref().unwrap_or("<unnamed>"),
+                        plan.source_target
+                    );
+                    logger.log(LogEvent::status(&config, detail.clone()))?;
+                    if let Some(tui_state) = tui.as_mut() {
+                        tui_state.push_log(format!("[{}] {detail}", timestamp_now()));
+                    }
+                    tracker.retry_target(&plan.source_target);
+                    continue;
+                }
+
+                let recipients = match (&plan.send_to, config.fanout) {
+                    (Some(send_to), _) => send_to.clone(),
+                    (None, FanoutMode::Matched) => {
+                        if file_source_path(&plan.source_target).is_some() {
+                            tmux_recipients.clone()
+                        } else {
                             vec![plan.source_target.clone()]
                         }
                     }
-                    FanoutMode::Broadcast => tmux_recipients.clone(),
+                    (None, FanoutMode::Broadcast) => tmux_recipients.clone(),
                 };
                 if recipients.is_empty() {
                     continue;
@@ -3989,27 +5458,52 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
 
                 let mut sent_any_for_plan = false;
                 for target in recipients {
+                    if panes.is_held(&target) {
+                        continue;
+                    }
+                    if panes.take_skip(&target) {
+                        let detail = format!(
+                            "pane skipped send target={} rule={}",
+                            target,
+                            plan.rule_id.as_deref().unwrap_or("<unnamed>")
+                        );
+                        logger.log(LogEvent::status(&config, detail.clone()))?;
+                        if let Some(tui_state) = tui.as_mut() {
+                            tui_state.push_log(format!("[{}] {detail}", timestamp_now()));
+                        }
+                        continue;
+                    }
                     if config.recheck_before_send {
-                        let output = capture_source(&target, config.capture_window)?;
-                        let output = if config.capture_window.lines() == 1
-                            && config.capture_window.is_tail()
-                        {
-                            last_non_empty_line(&output)
+                        // A `send_to` rule is rechecked on the pane that matched, not the recipient.
+                        let checked = if plan.send_to.is_some() {
+                            plan.source_target.clone()
                         } else {
-                            output
+                            target.clone()
                         };
+                        let mark = panes.capture_mark(&checked);
+                        let output = (io.capture)(&checked, &config, mark.as_ref())?;
+                        let output = match effective_capture_window(&config, mark.as_ref()) {
+                            Some(window) => scan_text(output, window),
+                            None => output,
+                        };
+                        let scanned = split_capture_styles(output, config.capture_ansi);
+                        let output = &scanned.text;
                         let Some(rule) = config.rules.get(plan.rule_index) else {
                             continue;
                         };
-                        if !matches_rule(rule, &output)? {
+                        let ctx = MatchContext {
+                            styles: &scanned.styles,
+                            ..tracker.observe_output(&checked, output, clock.now())
+                        };
+                        if !matches_rule_at(rule, output, &ctx)? {
                             let (recheck_preview_lines, recheck_preview) = extract_trigger_preview(
-                                &output,
+                                output,
                                 config.log_preview_lines,
                                 log_use_unicode,
                             );
                             let detail = format!(
                                 "suppressed stale trigger target={} rule={} preview={}L {}",
-                                target,
+                                checked,
                                 plan.rule_id.as_deref().unwrap_or("<unnamed>"),
                                 recheck_preview_lines,
                                 truncate_text(&recheck_preview, 70, log_use_unicode)
@@ -4028,7 +5522,7 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                     if ui_mode == UiMode::Tui {
                         loop_state = LoopState::Sending;
                     }
-                    if let Err(err) = send_prompt(&target, &plan.prompt) {
+                    if let Err(err) = (io.send)(&target, &plan.prompt, &config.submit_keys) {
                         let detail = err.to_string();
                         logger.log(LogEvent::error(&config, detail.clone()))?;
                         if ui_mode == UiMode::Tui {
@@ -4045,7 +5539,12 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                     send_count,
                                     max_sends,
                                     plan.rule_id.as_deref(),
-                                    effective_elapsed(run_started, held_total, hold_started),
+                                    effective_elapsed_at(
+                                        clock.now(),
+                                        run_started,
+                                        held_total,
+                                        hold_started,
+                                    ),
                                     "",
                                 )?;
                             }
@@ -4057,14 +5556,24 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                     }
                     send_count = send_count.saturating_add(1);
                     sent_any_for_plan = true;
-                    active_rule = plan.next_rule.clone();
-                    active_rule_by_target
-                        .insert(plan.source_target.clone(), plan.next_rule.clone());
+                    active_rule = plan.next_rule.as_ref().map(NextStep::label);
+                    tracker.record_send(&plan);
+                    fleet_registry.set_prompt_positions(&tracker.rotation);
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.record_send(&target, &plan)?;
+                    }
                     let now = OffsetDateTime::now_utc();
                     let timestamp = now
                         .format(&time::format_description::well_known::Rfc3339)
                         .unwrap_or_else(|_| "unknown".into());
-                    let elapsed = format_std_duration(effective_elapsed(
+                    panes.record_send(&target, &timestamp);
+                    if config.capture == CaptureMode::SinceLastSend {
+                        for source in [&target, &plan.source_target] {
+                            update_capture_mark(&config, &mut panes, &mut logger, source)?;
+                        }
+                    }
+                    let elapsed = format_std_duration(effective_elapsed_at(
+                        clock.now(),
                         run_started,
                         held_total,
                         hold_started,
@@ -4090,13 +5599,20 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                 log_use_unicode,
                                 log_icon_mode,
                             ));
+                            tui_state.set_pane_rows(panes.rows(&tracker, &config, clock.now()));
+                            tui_state.set_rotation(plan.rotation_label.clone());
                             tui_state.update(
                                 loop_state,
                                 &config,
                                 send_count,
                                 max_sends,
                                 plan.rule_id.as_deref(),
-                                effective_elapsed(run_started, held_total, hold_started),
+                                effective_elapsed_at(
+                                    clock.now(),
+                                    run_started,
+                                    held_total,
+                                    hold_started,
+                                ),
                                 &status,
                             )?;
                         }
@@ -4122,8 +5638,18 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                         break;
                     }
                 }
-                if config.trigger_edge && sent_any_for_plan {
-                    trigger_edge_active.insert(plan.edge_key.clone());
+                if sent_any_for_plan {
+                    tracker.record_fire(&plan, clock.now());
+                    tracker.arm_edge(&plan, config.trigger_edge);
+                    fleet_registry.set_rule_fires(tracker.rule_fire_counts(&config));
+                    if let Some(tui_state) = tui.as_mut() {
+                        tui_state.set_fires(
+                            plan.rule_id
+                                .as_ref()
+                                .and_then(|id| tracker.rule_fire_counts(&config).remove(id))
+                                .map(|count| format!("fires {count}")),
+                        );
+                    }
                 }
                 if plan.stop_after {
                     stop_after = true;
@@ -4144,7 +5670,12 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                             send_count,
                             max_sends,
                             active_rule.as_deref(),
-                            effective_elapsed(run_started, held_total, hold_started),
+                            effective_elapsed_at(
+                                clock.now(),
+                                run_started,
+                                held_total,
+                                hold_started,
+                            ),
                             "",
                         )?;
                     }
@@ -4165,7 +5696,12 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                             send_count,
                             max_sends,
                             active_rule.as_deref(),
-                            effective_elapsed(run_started, held_total, hold_started),
+                            effective_elapsed_at(
+                                clock.now(),
+                                run_started,
+                                held_total,
+                                hold_started,
+                            ),
                             "",
                         )?;
                     }
@@ -4188,22 +5724,22 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                     match action {
                         TuiAction::Pause => {
                             if hold_started.is_none() {
-                                hold_started = Some(std::time::Instant::now());
+                                hold_started = Some(clock.now());
                             }
                             loop_state = LoopState::Holding;
                         }
                         TuiAction::Resume => {
                             if let Some(started_at) = hold_started.take() {
-                                held_total += started_at.elapsed();
+                                held_total += clock.elapsed(started_at);
                             }
                             loop_state = LoopState::Running;
                         }
                         TuiAction::HoldToggle => {
                             if let Some(started_at) = hold_started.take() {
-                                held_total += started_at.elapsed();
+                                held_total += clock.elapsed(started_at);
                                 loop_state = LoopState::Running;
                             } else {
-                                hold_started = Some(std::time::Instant::now());
+                                hold_started = Some(clock.now());
                                 loop_state = LoopState::Holding;
                             }
                         }
@@ -4219,36 +5755,56 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                 send_count,
                                 max_sends,
                                 active_rule.as_deref(),
-                                effective_elapsed(run_started, held_total, hold_started),
+                                effective_elapsed_at(
+                                    clock.now(),
+                                    run_started,
+                                    held_total,
+                                    hold_started,
+                                ),
                                 "",
                             )?;
                             logger.log(LogEvent::stopped(&config, "manual", send_count))?;
                             break;
                         }
                         TuiAction::Next => {
-                            last_hash_by_target.clear();
-                            trigger_edge_active.clear();
-                            trigger_confirm_pending_since.clear();
+                            tracker.reset();
                             active_rule = None;
-                            active_rule_by_target.clear();
-                            backoff_state.clear();
                             loop_state = LoopState::Running;
                             force_rescan = true;
                         }
                         TuiAction::Renew => {
                             send_count = 0;
-                            last_hash_by_target.clear();
-                            trigger_edge_active.clear();
-                            trigger_confirm_pending_since.clear();
+                            tracker.reset();
+                            tracker.reset_rotation();
                             active_rule = None;
-                            active_rule_by_target.clear();
-                            backoff_state.clear();
                             tui_state.push_log(format!(
                                 "[{}] renewed counter reason=manual",
                                 timestamp_now()
                             ));
                         }
                         TuiAction::Redraw => {}
+                        TuiAction::ManualSend => tui_manual_send(
+                            tui_state,
+                            &config,
+                            &mut panes,
+                            &mut logger,
+                            recorder.as_mut(),
+                            &mut send_count,
+                        )?,
+                        TuiAction::EditPrompt => tui_edit_prompt(
+                            tui_state,
+                            &mut config,
+                            &mut logger,
+                            active_rule.as_deref(),
+                        )?,
+                        TuiAction::PaneControl(command) => tui_pane_control(
+                            tui_state,
+                            command,
+                            &config,
+                            &mut panes,
+                            &mut tracker,
+                            &mut logger,
+                        )?,
                         TuiAction::Quit => {
                             tui_state
                                 .push_log(format!("[{}] stopped reason=quit", timestamp_now()));
@@ -4257,18 +5813,19 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                         }
                     }
                 }
+                tui_state.set_pane_rows(panes.rows(&tracker, &config, clock.now()));
                 tui_state.update(
                     loop_state,
                     &config,
                     send_count,
                     max_sends,
                     active_rule.as_deref(),
-                    effective_elapsed(run_started, held_total, hold_started),
+                    effective_elapsed_at(clock.now(), run_started, held_total, hold_started),
                     "",
                 )?;
             }
             if open_fleet_manager {
-                if let Err(err) = run_fleet_manager_tui_embedded() {
+                if let Err(err) = run_fleet_manager_tui_embedded(&config.ui) {
                     if let Some(tui_state) = tui.as_mut() {
                         tui_state.push_log(format!(
                             "[{}] fleet manager error=\"{}\"",
@@ -4289,36 +5846,35 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
         }
 
         if ui_mode == UiMode::Tui {
-            let sleep_until =
-                std::time::Instant::now() + std::time::Duration::from_secs(config.poll);
+            let sleep_until = clock.now() + std::time::Duration::from_secs(config.poll);
             let mut should_exit_loop = false;
-            while std::time::Instant::now() < sleep_until {
+            while clock.now() < sleep_until {
                 if let Some(tui_state) = tui.as_mut() {
                     if let Some(action) = tui_state.poll_input()? {
                         match action {
                             TuiAction::Pause => {
                                 if hold_started.is_none() {
-                                    hold_started = Some(std::time::Instant::now());
+                                    hold_started = Some(clock.now());
                                 }
                                 loop_state = LoopState::Holding;
                             }
                             TuiAction::Resume => {
                                 if let Some(started_at) = hold_started.take() {
-                                    held_total += started_at.elapsed();
+                                    held_total += clock.elapsed(started_at);
                                 }
                                 loop_state = LoopState::Running;
                             }
                             TuiAction::HoldToggle => {
                                 if let Some(started_at) = hold_started.take() {
-                                    held_total += started_at.elapsed();
+                                    held_total += clock.elapsed(started_at);
                                     loop_state = LoopState::Running;
                                 } else {
-                                    hold_started = Some(std::time::Instant::now());
+                                    hold_started = Some(clock.now());
                                     loop_state = LoopState::Holding;
                                 }
                             }
                             TuiAction::Fleet => {
-                                if let Err(err) = run_fleet_manager_tui_embedded() {
+                                if let Err(err) = run_fleet_manager_tui_embedded(&config.ui) {
                                     tui_state.push_log(format!(
                                         "[{}] fleet manager error=\"{}\"",
                                         timestamp_now(),
@@ -4333,24 +5889,17 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                 break;
                             }
                             TuiAction::Next => {
-                                last_hash_by_target.clear();
-                                trigger_edge_active.clear();
-                                trigger_confirm_pending_since.clear();
+                                tracker.reset();
                                 active_rule = None;
-                                active_rule_by_target.clear();
-                                backoff_state.clear();
                                 loop_state = LoopState::Running;
                                 force_rescan = true;
                                 break;
                             }
                             TuiAction::Renew => {
                                 send_count = 0;
-                                last_hash_by_target.clear();
-                                trigger_edge_active.clear();
-                                trigger_confirm_pending_since.clear();
+                                tracker.reset();
+                                tracker.reset_rotation();
                                 active_rule = None;
-                                active_rule_by_target.clear();
-                                backoff_state.clear();
                                 tui_state.push_log(format!(
                                     "[{}] renewed counter reason=manual",
                                     timestamp_now()
@@ -4368,7 +5917,12 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                     send_count,
                                     max_sends,
                                     active_rule.as_deref(),
-                                    effective_elapsed(run_started, held_total, hold_started),
+                                    effective_elapsed_at(
+                                        clock.now(),
+                                        run_started,
+                                        held_total,
+                                        hold_started,
+                                    ),
                                     "",
                                 )?;
                                 should_exit_loop = true;
@@ -4382,19 +5936,42 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
                                 break;
                             }
                             TuiAction::Redraw => {}
+                            TuiAction::ManualSend => tui_manual_send(
+                                tui_state,
+                                &config,
+                                &mut panes,
+                                &mut logger,
+                                recorder.as_mut(),
+                                &mut send_count,
+                            )?,
+                            TuiAction::EditPrompt => tui_edit_prompt(
+                                tui_state,
+                                &mut config,
+                                &mut logger,
+                                active_rule.as_deref(),
+                            )?,
+                            TuiAction::PaneControl(command) => tui_pane_control(
+                                tui_state,
+                                command,
+                                &config,
+                                &mut panes,
+                                &mut tracker,
+                                &mut logger,
+                            )?,
                         }
                     }
+                    tui_state.set_pane_rows(panes.rows(&tracker, &config, clock.now()));
                     tui_state.update(
                         loop_state,
                         &config,
                         send_count,
                         max_sends,
                         active_rule.as_deref(),
-                        effective_elapsed(run_started, held_total, hold_started),
+                        effective_elapsed_at(clock.now(), run_started, held_total, hold_started),
                         "",
                     )?;
                 }
-                std::thread::sleep(std::time::Duration::from_millis(100));
+                clock.sleep(Duration::from_millis(100));
             }
             if should_exit_loop {
                 break;
@@ -4404,6 +5981,7 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
             }
         } else {
             sleep_with_heartbeat(
+                clock,
                 &fleet_registry,
                 &config.target_label,
                 loop_state,
@@ -4419,28 +5997,48 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
         let _ = in_flight.child.wait();
     }
 
-    let elapsed = format_std_duration(effective_elapsed(run_started, held_total, hold_started));
+    let elapsed = format_std_duration(effective_elapsed_at(
+        clock.now(),
+        run_started,
+        held_total,
+        hold_started,
+    ));
+    let outcome = if goal.is_some() {
+        RunOutcome::GoalReached
+    } else {
+        RunOutcome::Finished
+    };
+    let stop_reason = match outcome {
+        RunOutcome::GoalReached => "goal_reached",
+        RunOutcome::Finished => "completed",
+    };
     if ui_mode == UiMode::Tui {
         if let Some(tui_state) = tui.as_mut() {
             tui_state.push_log(format!(
-                "[{}] stopped reason=completed sends={} elapsed={}",
+                "[{}] stopped reason={stop_reason} sends={} elapsed={}",
                 timestamp_now(),
                 send_count,
                 elapsed
             ));
+            if let Some(evidence) = goal.as_deref() {
+                tui_state.push_log(format!("[{}] goal {evidence}", timestamp_now()));
+            }
             tui_state.update(
                 LoopState::Stopped,
                 &config,
                 send_count,
                 max_sends,
                 active_rule.as_deref(),
-                effective_elapsed(run_started, held_total, hold_started),
+                effective_elapsed_at(clock.now(), run_started, held_total, hold_started),
                 "",
             )?;
-            std::thread::sleep(std::time::Duration::from_secs(3));
+            clock.sleep(Duration::from_secs(3));
         }
     }
-    logger.log(LogEvent::stopped(&config, "completed", send_count))?;
+    match goal {
+        Some(evidence) => logger.log(LogEvent::goal_reached(&config, send_count, evidence))?,
+        None => logger.log(LogEvent::stopped(&config, "completed", send_count))?,
+    }
     if let Some(mut tui_state) = tui {
         tui_state.shutdown()?;
     }
@@ -4448,7 +6046,7 @@ fn run_loop(config: ResolvedConfig, identity: RunIdentity) -> Result<()> {
         println!();
     }
     println!("loopmux: stopped after {send_count} sends (elapsed {elapsed})");
-    Ok(())
+    Ok(outcome)
 }
 
 fn capture_source(source: &str, window: CaptureWindow) -> Result<String> {
@@ -4458,13 +6056,162 @@ fn capture_source(source: &str, window: CaptureWindow) -> Result<String> {
     capture_pane(source, window)
 }
 
+/// Captures `source` according to `config.capture`; `mark` is its position at the last send.
+fn capture_for_scan(
+    source: &str,
+    config: &ResolvedConfig,
+    mark: Option<&CaptureMark>,
+) -> Result<String> {
+    let file = file_source_path(source);
+    let flags = PaneCaptureFlags {
+        ansi: config.capture_ansi,
+        join: config.capture_join,
+    };
+    match (config.capture, mark, file) {
+        (CaptureMode::SinceLastSend, Some(mark), Some(path)) => {
+            let content = read_file_source(path)?;
+            Ok(content
+                .lines()
+                .skip(mark.position)
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        (CaptureMode::SinceLastSend, Some(mark), None) => {
+            let (history, cursor_y) = pane_scroll_position(source)?;
+            // Marks past the cursor mean the history was cleared; fall back to the screen.
+            if mark.position > history + cursor_y {
+                return capture_pane_range(source, "0", None, flags);
+            }
+            let start = mark.position as i64 - history as i64 - mark.anchor.len() as i64;
+            let output = capture_pane_range(source, &start.to_string(), None, flags)?;
+            let lines = output.lines().collect::<Vec<_>>();
+            if lines_match_anchor(&lines, &mark.anchor) {
+                return Ok(lines[mark.anchor.len()..].join("\n"));
+            }
+            // tmux may have trimmed history at `history-limit`, shifting lines up; find the
+            // anchor again, or keep the plain position when the lines were redrawn instead.
+            let full = capture_pane_range(source, "-", None, flags)?;
+            let full_lines = full.lines().collect::<Vec<_>>();
+            let expected = mark.position.saturating_sub(mark.anchor.len());
+            Ok(
+                match find_mark_anchor(&full_lines, expected, &mark.anchor) {
+                    Some(start) => full_lines[start..].join("\n"),
+                    None => lines[mark.anchor.len().min(lines.len())..].join("\n"),
+                },
+            )
+        }
+        (CaptureMode::VisibleScreen | CaptureMode::FullScrollback, _, Some(path)) => {
+            read_file_source(path)
+        }
+        (CaptureMode::VisibleScreen, _, None) => capture_pane_range(source, "0", None, flags),
+        (CaptureMode::FullScrollback, _, None) => capture_pane_range(source, "-", None, flags),
+        (_, _, Some(path)) => capture_file(path, config.capture_window),
+        (_, _, None) => capture_pane_window(source, config.capture_window, flags),
+    }
+}
+
+/// Window to scan a capture from `capture_for_scan` with; `None` when the mode replaces it.
+fn effective_capture_window(
+    config: &ResolvedConfig,
+    mark: Option<&CaptureMark>,
+) -> Option<CaptureWindow> {
+    match (config.capture, mark) {
+        (CaptureMode::Window, _) | (CaptureMode::SinceLastSend, None) => {
+            Some(config.capture_window)
+        }
+        _ => None,
+    }
+}
+
+/// Where a source's output stood at a send, for `capture: since_last_send`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CaptureMark {
+    /// Line count for files, scrollback + cursor row for panes.
+    position: usize,
+    /// Pane lines just above `position`, to find it again after tmux trims old history.
+    anchor: Vec<String>,
+}
+
+/// Current output position of `source`.
+fn output_mark(source: &str, flags: PaneCaptureFlags) -> Result<CaptureMark> {
+    if let Some(path) = file_source_path(source) {
+        return Ok(CaptureMark {
+            position: read_file_source(path)?.lines().count(),
+            anchor: Vec::new(),
+        });
+    }
+    let (history, cursor_y) = pane_scroll_position(source)?;
+    let start = cursor_y as i64 - MARK_ANCHOR_LINES as i64;
+    let end = cursor_y as i64 - 1;
+    let anchor = if history + cursor_y == 0 {
+        Vec::new()
+    } else {
+        capture_pane_range(source, &start.to_string(), Some(&end.to_string()), flags)?
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    };
+    Ok(CaptureMark {
+        position: history + cursor_y,
+        anchor,
+    })
+}
+
+fn lines_match_anchor(lines: &[&str], anchor: &[String]) -> bool {
+    lines.len() >= anchor.len()
+        && lines
+            .iter()
+            .zip(anchor)
+            .all(|(line, anchor)| line.trim_end() == anchor)
+}
+
+/// Index just past `anchor` in a full scrollback capture, searching up from `expected` (where
+/// it sat before trimming) since trimmed history only moves lines up.
+fn find_mark_anchor(lines: &[&str], expected: usize, anchor: &[String]) -> Option<usize> {
+    if anchor.is_empty() {
+        return None;
+    }
+    let last = expected.min(lines.len().checked_sub(anchor.len())?);
+    (0..=last)
+        .rev()
+        .find(|start| lines_match_anchor(&lines[*start..], anchor))
+        .map(|start| start + anchor.len())
+}
+
+fn pane_scroll_position(target: &str) -> Result<(usize, usize)> {
+    let output = std::process::Command::new("tmux")
+        .args([
+            "display-message",
+            "-p",
+            "-t",
+            target,
+            "#{history_size} #{cursor_y}",
+        ])
+        .output()
+        .context("failed to query tmux scroll position")?;
+    if !output.status.success() {
+        bail!("tmux display-message failed");
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut fields = text.split_whitespace().map(|value| value.parse::<usize>());
+    match (fields.next(), fields.next()) {
+        (Some(Ok(history)), Some(Ok(cursor_y))) => Ok((history, cursor_y)),
+        _ => bail!("unexpected tmux scroll position: {}", text.trim()),
+    }
+}
+
+fn read_file_source(path: &str) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("failed to read file source: {path}"))
+}
+
 fn capture_file(path: &str, window: CaptureWindow) -> Result<String> {
-    let path_buf = PathBuf::from(path);
-    let content = std::fs::read_to_string(&path_buf)
-        .with_context(|| format!("failed to read file source: {}", path_buf.display()))?;
+    Ok(apply_capture_window(&read_file_source(path)?, window))
+}
+
+fn apply_capture_window(content: &str, window: CaptureWindow) -> String {
     let lines = content.lines().collect::<Vec<_>>();
     if lines.is_empty() {
-        return Ok(String::new());
+        return String::new();
     }
     let selected = match window {
         CaptureWindow::Tail(count) => {
@@ -4476,20 +6223,66 @@ fn capture_file(path: &str, window: CaptureWindow) -> Result<String> {
             &lines[..end]
         }
     };
-    Ok(selected.join("\n"))
+    selected.join("\n")
+}
+
+/// `tail: 1` scans the last non-blank line so trailing prompt padding does not hide triggers.
+fn scan_text(output: String, window: CaptureWindow) -> String {
+    if window.lines() == 1 && window.is_tail() {
+        last_non_empty_line(&output)
+    } else {
+        output
+    }
+}
+
+/// `capture-pane` options beyond the line range.
+#[derive(Debug, Clone, Copy, Default)]
+struct PaneCaptureFlags {
+    /// `-e`: keep SGR escape sequences.
+    ansi: bool,
+    /// `-J`: join wrapped lines.
+    join: bool,
 }
 
 fn capture_pane(target: &str, window: CaptureWindow) -> Result<String> {
+    capture_pane_window(target, window, PaneCaptureFlags::default())
+}
+
+fn capture_pane_window(
+    target: &str,
+    window: CaptureWindow,
+    flags: PaneCaptureFlags,
+) -> Result<String> {
+    match window {
+        CaptureWindow::Tail(lines) => capture_pane_range(target, &format!("-{lines}"), None, flags),
+        CaptureWindow::Head(lines) => capture_pane_range(
+            target,
+            "0",
+            Some(&lines.saturating_sub(1).to_string()),
+            flags,
+        ),
+    }
+}
+
+/// `capture-pane` from line `start` (relative to the visible top, `-` for history start)
+/// to `end`, or the bottom of the pane.
+fn capture_pane_range(
+    target: &str,
+    start: &str,
+    end: Option<&str>,
+    flags: PaneCaptureFlags,
+) -> Result<String> {
     let mut command = std::process::Command::new("tmux");
     command.arg("capture-pane").arg("-p");
-    match window {
-        CaptureWindow::Tail(lines) => {
-            command.arg("-S").arg(format!("-{lines}"));
-        }
-        CaptureWindow::Head(lines) => {
-            let end = lines.saturating_sub(1);
-            command.arg("-S").arg("0").arg("-E").arg(end.to_string());
-        }
+    if flags.ansi {
+        command.arg("-e");
+    }
+    if flags.join {
+        command.arg("-J");
+    }
+    command.arg("-S").arg(start);
+    if let Some(end) = end {
+        command.arg("-E").arg(end);
     }
     let output = command
         .args(["-t", target])
@@ -4505,12 +6298,12 @@ fn last_non_empty_line(output: &str) -> String {
     output
         .lines()
         .rev()
-        .find(|line| !line.trim().is_empty())
+        .find(|line| !line.trim().is_empty() && !parse_sgr(line).0.trim().is_empty())
         .unwrap_or("")
         .to_string()
 }
 
-fn send_prompt(target: &str, prompt: &str) -> Result<()> {
+fn send_prompt(target: &str, prompt: &str, submit_keys: &[String]) -> Result<()> {
     let output = std::process::Command::new("tmux")
         .args(["send-keys", "-t", target, "-l", prompt])
         .output()
@@ -4519,16 +6312,269 @@ fn send_prompt(target: &str, prompt: &str) -> Result<()> {
         bail!("tmux send-keys failed");
     }
 
-    let output = std::process::Command::new("tmux")
-        .args(["send-keys", "-t", target, "Enter"])
-        .output()
-        .context("failed to submit tmux keys")?;
-    if !output.status.success() {
-        bail!("tmux send-keys submit failed");
+    for key in submit_keys {
+        let output = std::process::Command::new("tmux")
+            .args(["send-keys", "-t", target, key])
+            .output()
+            .context("failed to submit tmux keys")?;
+        if !output.status.success() {
+            bail!("tmux send-keys submit failed (key={key})");
+        }
     }
     Ok(())
 }
 
+/// A terminal color as emitted in SGR sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiColor {
+    /// Palette index: 0-7 standard, 8-15 bright, 16-255 extended.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct SgrStyle {
+    fg: Option<AnsiColor>,
+    bg: Option<AnsiColor>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+}
+
+/// Text on one capture line that shares a single style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StyledRun {
+    line: usize,
+    text: String,
+    style: SgrStyle,
+}
+
+const ANSI_COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Splits SGR styling out of a `capture-pane -e` capture; other escape sequences are dropped.
+fn parse_sgr(raw: &str) -> (String, Vec<StyledRun>) {
+    let mut text = String::new();
+    let mut runs: Vec<StyledRun> = Vec::new();
+    let mut style = SgrStyle::default();
+    let mut line = 0;
+    let mut chars = raw.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            match chars.next() {
+                Some('[') => {
+                    let mut params = String::new();
+                    for next in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&next) {
+                            if next == 'm' {
+                                apply_sgr(&mut style, &params);
+                            }
+                            break;
+                        }
+                        params.push(next);
+                    }
+                }
+                Some(']') => {
+                    let mut terminator = None;
+                    for next in chars.by_ref() {
+                        if next == '\x07' || next == '\x1b' {
+                            terminator = Some(next);
+                            break;
+                        }
+                    }
+                    if terminator == Some('\x1b') && chars.peek() == Some(&'\\') {
+                        chars.next();
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        text.push(ch);
+        if ch == '\n' {
+            line += 1;
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.line == line && run.style == style => run.text.push(ch),
+            _ => runs.push(StyledRun {
+                line,
+                text: ch.to_string(),
+                style,
+            }),
+        }
+    }
+    (text, runs)
+}
+
+fn apply_sgr(style: &mut SgrStyle, params: &str) {
+    let codes = params
+        .split(';')
+        .map(|code| code.parse::<u16>().unwrap_or(0))
+        .collect::<Vec<_>>();
+    let mut index = 0;
+    while index < codes.len() {
+        match codes[index] {
+            0 => *style = SgrStyle::default(),
+            1 => style.bold = true,
+            2 => style.dim = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            22 => {
+                style.bold = false;
+                style.dim = false;
+            }
+            23 => style.italic = false,
+            24 => style.underline = false,
+            code @ 30..=37 => style.fg = indexed_color(code - 30),
+            code @ 40..=47 => style.bg = indexed_color(code - 40),
+            code @ 90..=97 => style.fg = indexed_color(code - 90 + 8),
+            code @ 100..=107 => style.bg = indexed_color(code - 100 + 8),
+            39 => style.fg = None,
+            49 => style.bg = None,
+            code @ (38 | 48) => {
+                let (color, used) = match &codes[index + 1..] {
+                    [5, value, ..] => (indexed_color(*value), 2),
+                    [2, r, g, b, ..] => (
+                        match (u8::try_from(*r), u8::try_from(*g), u8::try_from(*b)) {
+                            (Ok(r), Ok(g), Ok(b)) => Some(AnsiColor::Rgb(r, g, b)),
+                            _ => None,
+                        },
+                        4,
+                    ),
+                    rest => (None, rest.len()),
+                };
+                if code == 38 {
+                    style.fg = color;
+                } else {
+                    style.bg = color;
+                }
+                index += used;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+}
+
+fn indexed_color(value: u16) -> Option<AnsiColor> {
+    u8::try_from(value).ok().map(AnsiColor::Indexed)
+}
+
+/// Whether `color` fits a `style.fg`/`style.bg` spec: a name (`red` also matches bright red),
+/// `bright_<name>`, a palette index, `#rrggbb`, or `default`.
+fn color_matches(spec: &str, color: Option<AnsiColor>) -> Result<bool> {
+    let spec = spec.trim().to_ascii_lowercase();
+    if spec == "default" {
+        return Ok(color.is_none());
+    }
+    let indexed = |index: u8| color == Some(AnsiColor::Indexed(index));
+    if let Some(name) = spec.strip_prefix("bright_") {
+        if let Some(index) = ANSI_COLOR_NAMES.iter().position(|known| *known == name) {
+            return Ok(indexed(index as u8 + 8));
+        }
+    } else if let Some(index) = ANSI_COLOR_NAMES.iter().position(|known| *known == spec) {
+        return Ok(indexed(index as u8) || indexed(index as u8 + 8));
+    }
+    if let Ok(index) = spec.parse::<u8>() {
+        return Ok(indexed(index));
+    }
+    if let Some(hex) = spec.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16);
+        if let (Ok(r), Ok(g), Ok(b)) = (channel(0), channel(2), channel(4)) {
+            return Ok(color == Some(AnsiColor::Rgb(r, g, b)));
+        }
+    }
+    bail!("unknown color '{spec}' (expected a name, bright_<name>, 0-255, #rrggbb or default)")
+}
+
+impl StyleMatch {
+    fn accepts(&self, style: &SgrStyle) -> Result<bool> {
+        let flags = [
+            (self.bold, style.bold),
+            (self.dim, style.dim),
+            (self.italic, style.italic),
+            (self.underline, style.underline),
+        ];
+        if flags
+            .iter()
+            .any(|(wanted, actual)| wanted.is_some_and(|wanted| wanted != *actual))
+        {
+            return Ok(false);
+        }
+        for (spec, color) in [(&self.fg, style.fg), (&self.bg, style.bg)] {
+            let Some(spec) = spec.as_deref() else {
+                continue;
+            };
+            if !color_matches(spec, color)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn has_filter(&self) -> bool {
+        self.fg.is_some()
+            || self.bg.is_some()
+            || self.bold.is_some()
+            || self.dim.is_some()
+            || self.italic.is_some()
+            || self.underline.is_some()
+    }
+}
+
+/// Joins neighbouring runs on a line that fit `style` and checks each span against its regex.
+fn style_matches(style: &StyleMatch, runs: &[StyledRun]) -> Result<bool> {
+    let regex = style
+        .regex
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .context("invalid style regex")?;
+    let span_matches = |span: &str| {
+        !span.trim().is_empty() && regex.as_ref().is_none_or(|regex| regex.is_match(span))
+    };
+    let mut span = String::new();
+    let mut span_line = None;
+    for run in runs {
+        let fits = style.accepts(&run.style)?;
+        if !fits || span_line != Some(run.line) {
+            if span_matches(&span) {
+                return Ok(true);
+            }
+            span.clear();
+            span_line = None;
+        }
+        if fits {
+            span.push_str(&run.text);
+            span_line = Some(run.line);
+        }
+    }
+    Ok(span_matches(&span))
+}
+
+/// A capture ready for rule evaluation: plain text plus its SGR style runs.
+#[derive(Debug, Default)]
+struct ScannedOutput {
+    text: String,
+    /// Empty unless `capture_ansi` is on.
+    styles: Vec<StyledRun>,
+}
+
+fn split_capture_styles(output: String, ansi: bool) -> ScannedOutput {
+    if ansi {
+        let (text, styles) = parse_sgr(&output);
+        ScannedOutput { text, styles }
+    } else {
+        ScannedOutput {
+            text: output,
+            styles: Vec::new(),
+        }
+    }
+}
+
 fn hash_output(output: &str) -> String {
     let mut hash: u64 = 14695981039346656037;
     for byte in output.as_bytes() {
@@ -4538,20 +6584,27 @@ fn hash_output(output: &str) -> String {
     format!("{hash:x}")
 }
 
+/// Like `hash_output`, but style-only changes (a banner turning red) also count.
+fn hash_capture(output: &str, styles: &[StyledRun]) -> String {
+    if styles.is_empty() {
+        return hash_output(output);
+    }
+    hash_output(&format!("{output}{styles:?}"))
+}
+
 fn select_rules<'a>(
     output: &str,
     rules: &'a [Rule],
     rule_eval: &RuleEval,
-    active_rule: Option<&str>,
+    allowed: Option<&[usize]>,
+    ctx: &MatchContext,
 ) -> Result<Vec<RuleMatch<'a>>> {
     let mut candidates = Vec::new();
     for (index, rule) in rules.iter().enumerate() {
-        if let Some(active) = active_rule {
-            if rule.id.as_deref() != Some(active) {
-                continue;
-            }
+        if allowed.is_some_and(|allowed| !allowed.contains(&index)) {
+            continue;
         }
-        if !matches_rule(rule, output)? {
+        if !matches_rule_at(rule, output, ctx)? {
             continue;
         }
         candidates.push(RuleMatch { rule, index });
@@ -4586,67 +6639,702 @@ fn select_rules<'a>(
     }
 }
 
-fn evaluate_rules<'a>(
-    config: &'a ResolvedConfig,
-    logger: &mut Logger,
-    output: &str,
-    active_rule: Option<&str>,
-) -> Result<Vec<RuleMatch<'a>>> {
-    let matches = select_rules(output, &config.rules, &config.rule_eval, active_rule)?;
-    for rule_match in &matches {
-        logger.log(LogEvent::matched(config, rule_match.rule.id.as_deref()))?;
+/// Per-target trigger bookkeeping shared by the run loop and `test-rules`.
+#[derive(Debug, Default)]
+struct TriggerTracker {
+    last_hash_by_target: std::collections::HashMap<String, String>,
+    edge_active: HashSet<String>,
+    confirm_pending_since: std::collections::HashMap<String, std::time::Instant>,
+    active_rule_by_target: std::collections::HashMap<String, Option<NextStep>>,
+    /// When each target's current state started waiting, for state timeouts.
+    state_entered_at: std::collections::HashMap<String, std::time::Instant>,
+    backoff_state: std::collections::HashMap<String, BackoffState>,
+    rng: DelayRng,
+    /// Separate stream for weighted rotation picks, so adding one keeps seeded delays.
+    rotation_rng: DelayRng,
+    rotation: BTreeMap<String, u32>,
+    /// Hash of each target's capture and when it last changed, for `idle_for`.
+    changed_at_by_target: std::collections::HashMap<String, (String, std::time::Instant)>,
+    /// Fire history per rule index, counted across all sources, for rule limits.
+    rule_fires: std::collections::HashMap<usize, RuleFires>,
+    /// Exhausted rules whose `on_exhausted` was already taken; left out of matching until a
+    /// `budget` window frees up again.
+    exhausted_rules: HashSet<usize>,
+    /// Title of every known pane, for rule `targets` and `send_to` patterns.
+    titles_by_target: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+struct RuleFires {
+    count: u32,
+    /// Fire times still inside the `budget`/`cooldown` window, oldest first.
+    recent: Vec<std::time::Instant>,
+}
+
+/// Why a matched rule may not fire now.
+#[derive(Debug, PartialEq, Eq)]
+enum RuleLimit {
+    Cooldown,
+    /// `max_fires` or `budget` is used up; the label names the limit.
+    Exhausted(String),
+}
+
+#[derive(Debug, Default)]
+struct TargetScan {
+    skipped_unchanged: bool,
+    matched_rule_ids: Vec<Option<String>>,
+    suppressed: Vec<(Option<String>, &'static str)>,
+    /// Log lines for matches whose prompt source came back missing or empty.
+    skipped_prompts: Vec<String>,
+    /// Log lines for state timeouts and exhausted rules that moved the target's flow.
+    flow_events: Vec<String>,
+    /// Such a step was `stop`: `state_timeout` or `rule_exhausted`.
+    flow_stop: Option<&'static str>,
+    plans: Vec<SendPlan>,
+}
+
+impl TriggerTracker {
+    fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: DelayRng::new(seed),
+            rotation_rng: DelayRng::new(seed ^ ROTATION_RNG_STREAM),
+            ..Self::default()
+        }
     }
-    Ok(matches)
-}
 
-fn trigger_edge_key(target: &str, rule_match: &RuleMatch<'_>) -> String {
-    let rule_id = rule_match.rule.id.as_deref().unwrap_or("<unnamed>");
-    format!("{target}|{rule_id}|{}", rule_match.index)
-}
+    fn reset(&mut self) {
+        self.last_hash_by_target.clear();
+        self.edge_active.clear();
+        self.confirm_pending_since.clear();
+        self.active_rule_by_target.clear();
+        self.state_entered_at.clear();
+        self.backoff_state.clear();
+        self.rule_fires.clear();
+    }
 
-fn refresh_trigger_edges_for_target(
-    active_edges: &mut HashSet<String>,
-    target: &str,
-    matched_keys: &HashSet<String>,
-    enabled: bool,
-) {
-    if !enabled {
-        return;
+    /// Records `output` for `target` and reports how long it has been unchanged.
+    fn observe_output(
+        &mut self,
+        target: &str,
+        output: &str,
+        now: std::time::Instant,
+    ) -> MatchContext<'static> {
+        let hash = hash_output(output);
+        let entry = self
+            .changed_at_by_target
+            .entry(target.to_string())
+            .or_insert_with(|| (hash.clone(), now));
+        if entry.0 != hash {
+            *entry = (hash, now);
+        }
+        MatchContext {
+            idle: now.saturating_duration_since(entry.1),
+            styles: &[],
+        }
     }
-    let prefix = format!("{target}|");
-    active_edges.retain(|key| !key.starts_with(&prefix) || matched_keys.contains(key));
-}
 
-fn edge_guard_allows(active_edges: &HashSet<String>, edge_key: &str, enabled: bool) -> bool {
-    !enabled || !active_edges.contains(edge_key)
-}
+    fn set_pane_titles(&mut self, titles: impl IntoIterator<Item = (String, String)>) {
+        self.titles_by_target = titles.into_iter().collect();
+    }
 
-fn refresh_trigger_confirm_for_target(
-    pending_since: &mut std::collections::HashMap<String, std::time::Instant>,
-    target: &str,
-    matched_keys: &HashSet<String>,
-) {
-    let prefix = format!("{target}|");
-    pending_since.retain(|key, _| !key.starts_with(&prefix) || matched_keys.contains(key));
-}
+    /// Known tmux panes matching a rule's `send_to` pattern.
+    fn send_to_targets(&self, pattern: &str) -> Vec<String> {
+        self.titles_by_target
+            .iter()
+            .filter(|(target, title)| {
+                file_source_path(target).is_none() && target_pattern_matches(pattern, target, title)
+            })
+            .map(|(target, _)| target.clone())
+            .collect()
+    }
 
-fn confirm_window_elapsed(
-    global_seconds: u64,
-    rule_override_seconds: Option<u64>,
-    edge_key: &str,
-    pending_since: &mut std::collections::HashMap<String, std::time::Instant>,
-    now: std::time::Instant,
-) -> bool {
-    let seconds = rule_override_seconds.unwrap_or(global_seconds);
-    if seconds == 0 {
-        pending_since.remove(edge_key);
-        return true;
+    fn clear_hashes(&mut self) {
+        self.last_hash_by_target.clear();
     }
 
-    let wait = std::time::Duration::from_secs(seconds);
-    let Some(first_seen) = pending_since.get(edge_key).copied() else {
-        pending_since.insert(edge_key.to_string(), now);
-        return false;
+    /// Forgets `target`'s capture hash so an unchanged pane is scanned again next tick.
+    fn retry_target(&mut self, target: &str) {
+        self.last_hash_by_target.remove(target);
+    }
+
+    fn reset_rotation(&mut self) {
+        self.rotation.clear();
+    }
+
+    /// Builds the prompt for `action`, picking the current item of rotating blocks and
+    /// cutting `forward` regions out of `output`. File/command sources are left pending
+    /// until the send.
+    fn build_send_prompt(
+        &mut self,
+        action: &Action,
+        scope: &str,
+        output: &str,
+    ) -> Result<BuiltPrompt> {
+        let mut parts = Vec::new();
+        let mut sources = Vec::new();
+        let mut built = BuiltPrompt::default();
+        for (slot, block) in [
+            ("pre", &action.pre),
+            ("prompt", &action.prompt),
+            ("post", &action.post),
+        ] {
+            match block {
+                Some(PromptBlock::Rotate(rotation)) => {
+                    let key = format!("{scope}.{slot}");
+                    let position = self.rotation.get(&key).copied().unwrap_or(0);
+                    let (text, step) =
+                        match rotation_pick(rotation, position, &mut self.rotation_rng) {
+                            Ok(picked) => picked,
+                            Err(err) => {
+                                built.skip = Some(format!("{scope}.{slot}: {err}"));
+                                return Ok(built);
+                            }
+                        };
+                    parts.push(text);
+                    built.rotation_keys.push(key);
+                    built.rotation_label = Some(step);
+                }
+                Some(PromptBlock::Source(source)) => {
+                    sources.push((parts.len(), format!("{scope}.{slot}"), source.clone()));
+                    parts.push(String::new());
+                }
+                Some(PromptBlock::Forward(forward)) => match forward_region(forward, output) {
+                    Ok(text) => parts.push(text),
+                    Err(reason) => {
+                        built.skip = Some(format!("{scope}.{slot}: {reason}"));
+                        return Ok(built);
+                    }
+                },
+                _ => push_block(&mut parts, block.as_ref()),
+            }
+        }
+        built.text = parts.join("\n");
+        if !sources.is_empty() {
+            built.pending = Some(PendingPrompt { parts, sources });
+        }
+        Ok(built)
+    }
+
+    fn clear_flow(&mut self) {
+        self.last_hash_by_target.clear();
+        self.active_rule_by_target.clear();
+        self.state_entered_at.clear();
+    }
+
+    /// Like `reset`, but only for one source; other panes keep their flow.
+    fn reset_target(&mut self, target: &str) {
+        let prefix = format!("{target}|");
+        self.last_hash_by_target.remove(target);
+        self.edge_active.retain(|key| !key.starts_with(&prefix));
+        self.confirm_pending_since
+            .retain(|key, _| !key.starts_with(&prefix));
+        self.active_rule_by_target.remove(target);
+        self.state_entered_at.remove(target);
+    }
+
+    /// Takes the current state's `on_timeout` step once it has waited `timeout` without a send.
+    fn check_state_timeout(
+        &mut self,
+        config: &ResolvedConfig,
+        target: &str,
+        now: std::time::Instant,
+        scan: &mut TargetScan,
+    ) -> Result<()> {
+        let step = self.active_rule_by_target.get(target).cloned().flatten();
+        let Some(state) = current_state(&config.states, step.as_ref()) else {
+            return Ok(());
+        };
+        let entered = *self
+            .state_entered_at
+            .entry(target.to_string())
+            .or_insert(now);
+        let Some(timeout) = state.timeout.as_deref() else {
+            return Ok(());
+        };
+        if now.saturating_duration_since(entered) < parse_duration(timeout)? {
+            return Ok(());
+        }
+        let next = state.on_timeout.clone();
+        scan.flow_events.push(format!(
+            "state timeout state={} after={timeout} next={}",
+            state.id,
+            next.as_ref()
+                .map(NextStep::label)
+                .unwrap_or_else(|| "-".to_string())
+        ));
+        if next.as_ref().is_some_and(NextStep::is_stop) {
+            scan.flow_stop = Some("state_timeout");
+        }
+        self.state_entered_at.insert(target.to_string(), now);
+        self.active_rule_by_target.insert(target.to_string(), next);
+        Ok(())
+    }
+
+    fn edge_state(&self, target: &str, enabled: bool) -> &'static str {
+        if !enabled {
+            return "off";
+        }
+        let prefix = format!("{target}|");
+        if self.edge_active.iter().any(|key| key.starts_with(&prefix)) {
+            "armed"
+        } else {
+            "ready"
+        }
+    }
+
+    /// Seconds left on the shortest pending confirm window for `target`.
+    fn confirm_remaining(
+        &self,
+        config: &ResolvedConfig,
+        target: &str,
+        now: std::time::Instant,
+    ) -> Option<u64> {
+        let prefix = format!("{target}|");
+        self.confirm_pending_since
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, since)| {
+                let seconds = key
+                    .rsplit('|')
+                    .next()
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .and_then(|index| config.rules.get(index))
+                    .and_then(|rule| rule.confirm_seconds)
+                    .unwrap_or(config.trigger_confirm_seconds);
+                seconds.saturating_sub(now.saturating_duration_since(*since).as_secs())
+            })
+            .min()
+    }
+
+    /// Runs rule selection, edge guard and confirm window for one captured source.
+    fn scan_target(
+        &mut self,
+        config: &ResolvedConfig,
+        target: &str,
+        scanned: &ScannedOutput,
+        now: std::time::Instant,
+        broadcast_plan_keys: &mut HashSet<String>,
+        use_unicode: bool,
+    ) -> Result<TargetScan> {
+        let mut scan = TargetScan::default();
+        let output = scanned.text.as_str();
+        let styles = scanned.styles.as_slice();
+        self.check_state_timeout(config, target, now, &mut scan)?;
+        if scan.flow_stop.is_some() {
+            return Ok(scan);
+        }
+        let hash = hash_capture(output, styles);
+        let last_hash = self
+            .last_hash_by_target
+            .get(target)
+            .cloned()
+            .unwrap_or_default();
+        let has_pending_confirm =
+            has_pending_confirm_for_target(&self.confirm_pending_since, target);
+        let ctx = MatchContext {
+            styles,
+            ..self.observe_output(target, output, now)
+        };
+        if !rules_watch_idle(&config.rules)
+            && should_skip_scan_by_hash(config.trigger_edge, &hash, &last_hash, has_pending_confirm)
+        {
+            scan.skipped_unchanged = true;
+            return Ok(scan);
+        }
+
+        let step = self.active_rule_by_target.get(target).cloned().flatten();
+        let mut allowed = scope_rules_to_target(
+            &config.rules,
+            candidate_rules(&config.rules, &config.states, step.as_ref()),
+            target,
+            self.titles_by_target.get(target).map_or("", String::as_str),
+        );
+        if !self.exhausted_rules.is_empty() {
+            for index in self.exhausted_rules.clone() {
+                let limit = self.rule_limit(&config.rules[index], index, now)?;
+                if !matches!(limit, Some(RuleLimit::Exhausted(_))) {
+                    self.exhausted_rules.remove(&index);
+                }
+            }
+            let exhausted = &self.exhausted_rules;
+            allowed = Some(
+                allowed
+                    .unwrap_or_else(|| (0..config.rules.len()).collect())
+                    .into_iter()
+                    .filter(|index| !exhausted.contains(index))
+                    .collect(),
+            );
+        }
+        let rule_matches = select_rules(
+            output,
+            &config.rules,
+            &config.rule_eval,
+            allowed.as_deref(),
+            &ctx,
+        )?;
+        scan.matched_rule_ids = rule_matches
+            .iter()
+            .map(|rule_match| rule_match.rule.id.clone())
+            .collect();
+
+        let matched_edge_keys = rule_matches
+            .iter()
+            .map(|rule_match| trigger_edge_key(target, rule_match))
+            .collect::<HashSet<_>>();
+        refresh_trigger_edges_for_target(
+            &mut self.edge_active,
+            target,
+            &matched_edge_keys,
+            config.trigger_edge,
+        );
+        refresh_trigger_confirm_for_target(
+            &mut self.confirm_pending_since,
+            target,
+            &matched_edge_keys,
+        );
+
+        if rule_matches.is_empty() {
+            return Ok(scan);
+        }
+
+        for rule_match in rule_matches {
+            let edge_key = trigger_edge_key(target, &rule_match);
+            if !edge_guard_allows(&self.edge_active, &edge_key, config.trigger_edge) {
+                scan.suppressed
+                    .push((rule_match.rule.id.clone(), "edge-guard"));
+                continue;
+            }
+            if !confirm_window_elapsed(
+                config.trigger_confirm_seconds,
+                rule_match.rule.confirm_seconds,
+                &edge_key,
+                &mut self.confirm_pending_since,
+                now,
+            ) {
+                scan.suppressed
+                    .push((rule_match.rule.id.clone(), "confirm-pending"));
+                continue;
+            }
+            match self.rule_limit(rule_match.rule, rule_match.index, now)? {
+                None => {}
+                Some(RuleLimit::Cooldown) => {
+                    scan.suppressed
+                        .push((rule_match.rule.id.clone(), "cooldown"));
+                    continue;
+                }
+                Some(RuleLimit::Exhausted(limit)) => {
+                    self.exhausted_rules.insert(rule_match.index);
+                    let next = rule_match
+                        .rule
+                        .on_exhausted
+                        .clone()
+                        .unwrap_or_else(|| NextStep::One("stop".to_string()));
+                    scan.flow_events.push(format!(
+                        "rule exhausted rule={} limit={limit} next={}",
+                        rule_match.rule.id.as_deref().unwrap_or("<unnamed>"),
+                        next.label()
+                    ));
+                    if next.is_stop() {
+                        scan.flow_stop = Some("rule_exhausted");
+                    }
+                    self.active_rule_by_target
+                        .insert(target.to_string(), Some(next));
+                    self.state_entered_at.remove(target);
+                    continue;
+                }
+            }
+            let send_to = rule_match
+                .rule
+                .send_to
+                .as_deref()
+                .map(|pattern| self.send_to_targets(pattern));
+            if send_to.as_ref().is_some_and(Vec::is_empty) {
+                scan.suppressed
+                    .push((rule_match.rule.id.clone(), "send-to-missing"));
+                continue;
+            }
+
+            let (trigger_preview_lines, trigger_preview) =
+                extract_trigger_preview(output, config.log_preview_lines, use_unicode);
+
+            let (action, scope) = match rule_match.rule.action.as_ref() {
+                Some(action) => (
+                    action,
+                    rule_match
+                        .rule
+                        .id
+                        .clone()
+                        .unwrap_or_else(|| format!("rules[{}]", rule_match.index)),
+                ),
+                None => (&config.default_action, "default_action".to_string()),
+            };
+            let built = self.build_send_prompt(action, &scope, output)?;
+            if let Some(reason) = built.skip {
+                scan.suppressed
+                    .push((rule_match.rule.id.clone(), "prompt-source"));
+                scan.skipped_prompts.push(format!(
+                    "prompt skipped rule={} reason=\"{reason}\"",
+                    rule_match.rule.id.as_deref().unwrap_or("<unnamed>")
+                ));
+                continue;
+            }
+            let prompt = built.text;
+            if config.fanout == FanoutMode::Broadcast {
+                let key = format!(
+                    "{}|{}",
+                    rule_match.rule.id.as_deref().unwrap_or("<unnamed>"),
+                    prompt
+                );
+                if !broadcast_plan_keys.insert(key) {
+                    continue;
+                }
+            }
+            let delay = rule_match.rule.delay.as_ref().or(config.delay.as_ref());
+            let delay_seconds = if let Some(delay) = delay {
+                Some(compute_delay_seconds(
+                    delay,
+                    &rule_match,
+                    &mut self.backoff_state,
+                    &mut self.rng,
+                )?)
+            } else {
+                None
+            };
+            let state = owning_state(&config.states, step.as_ref(), rule_match.rule);
+            let next_rule = next_step_after(state, rule_match.rule);
+            scan.plans.push(SendPlan {
+                source_target: target.to_string(),
+                rule_id: rule_match.rule.id.clone(),
+                rule_index: rule_match.index,
+                stop_after: next_rule.as_ref().is_some_and(NextStep::is_stop),
+                next_rule,
+                edge_key,
+                prompt,
+                trigger_preview,
+                trigger_preview_lines,
+                delay_seconds,
+                send_to,
+                rotation_keys: built.rotation_keys,
+                rotation_label: built.rotation_label,
+                pending: built.pending,
+            });
+        }
+        // When every match was skipped for its prompt, keep the hash so the next tick retries.
+        let retry = scan.plans.is_empty() && !scan.skipped_prompts.is_empty();
+        if config.trigger_edge && !retry {
+            self.last_hash_by_target.insert(target.to_string(), hash);
+        }
+
+        if matches!(config.rule_eval, RuleEval::MultiMatch) && config.states.is_empty() {
+            self.active_rule_by_target.insert(target.to_string(), None);
+        }
+        Ok(scan)
+    }
+
+    fn active_step(&self, target: &str) -> Option<&NextStep> {
+        self.active_rule_by_target
+            .get(target)
+            .and_then(Option::as_ref)
+    }
+
+    fn record_send(&mut self, plan: &SendPlan) {
+        self.active_rule_by_target
+            .insert(plan.source_target.clone(), plan.next_rule.clone());
+        self.state_entered_at.remove(&plan.source_target);
+        for key in &plan.rotation_keys {
+            *self.rotation.entry(key.clone()).or_default() += 1;
+        }
+    }
+
+    /// Counts one fire of the plan's rule; call once per plan that reached a recipient.
+    fn record_fire(&mut self, plan: &SendPlan, now: std::time::Instant) {
+        let fires = self.rule_fires.entry(plan.rule_index).or_default();
+        fires.count = fires.count.saturating_add(1);
+        fires.recent.push(now);
+    }
+
+    fn rule_limit(
+        &mut self,
+        rule: &Rule,
+        index: usize,
+        now: std::time::Instant,
+    ) -> Result<Option<RuleLimit>> {
+        let fires = self.rule_fires.entry(index).or_default();
+        if let Some(max) = rule.max_fires.filter(|max| fires.count >= *max) {
+            return Ok(Some(RuleLimit::Exhausted(format!("max_fires={max}"))));
+        }
+        let budget = rule
+            .budget
+            .as_ref()
+            .map(|budget| Ok::<_, anyhow::Error>((budget, parse_duration(&budget.per)?)))
+            .transpose()?;
+        let cooldown = rule.cooldown.as_deref().map(parse_duration).transpose()?;
+        let window = budget
+            .map(|(_, per)| per)
+            .into_iter()
+            .chain(cooldown)
+            .max()
+            .unwrap_or_default();
+        fires
+            .recent
+            .retain(|at| now.saturating_duration_since(*at) < window);
+        if let Some((budget, per)) = budget {
+            let used = fires
+                .recent
+                .iter()
+                .filter(|at| now.saturating_duration_since(**at) < per)
+                .count();
+            if used >= budget.fires as usize {
+                return Ok(Some(RuleLimit::Exhausted(format!(
+                    "budget={}/{}",
+                    budget.fires, budget.per
+                ))));
+            }
+        }
+        let cooling = cooldown.is_some_and(|cooldown| {
+            fires
+                .recent
+                .last()
+                .is_some_and(|at| now.saturating_duration_since(*at) < cooldown)
+        });
+        Ok(cooling.then_some(RuleLimit::Cooldown))
+    }
+
+    /// Fire counters of rules with limits, e.g. `failure-path=2/3`, for TUI and fleet details.
+    fn rule_fire_counts(&self, config: &ResolvedConfig) -> BTreeMap<String, String> {
+        config
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| {
+                rule.max_fires.is_some() || rule.budget.is_some() || rule.cooldown.is_some()
+            })
+            .map(|(index, rule)| {
+                let count = self.rule_fires.get(&index).map_or(0, |fires| fires.count);
+                let label = match rule.max_fires {
+                    Some(max) => format!("{count}/{max}"),
+                    None => count.to_string(),
+                };
+                (
+                    rule.id.clone().unwrap_or_else(|| format!("rules[{index}]")),
+                    label,
+                )
+            })
+            .collect()
+    }
+
+    fn arm_edge(&mut self, plan: &SendPlan, enabled: bool) {
+        if enabled {
+            self.edge_active.insert(plan.edge_key.clone());
+        }
+    }
+}
+
+/// Narrows `allowed` to rules whose `targets` cover `target`; unchanged when no rule sets
+/// `targets`.
+fn scope_rules_to_target(
+    rules: &[Rule],
+    allowed: Option<Vec<usize>>,
+    target: &str,
+    title: &str,
+) -> Option<Vec<usize>> {
+    if rules.iter().all(|rule| rule.targets.is_none()) {
+        return allowed;
+    }
+    Some(
+        (0..rules.len())
+            .filter(|index| {
+                allowed
+                    .as_ref()
+                    .is_none_or(|allowed| allowed.contains(index))
+            })
+            .filter(|index| {
+                rules[*index].targets.as_ref().is_none_or(|patterns| {
+                    patterns
+                        .iter()
+                        .any(|pattern| target_pattern_matches(pattern, target, title))
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Whether a rule target pattern names a source. `title:<name>` matches the pane title,
+/// `session:window.pane` and `file://path` match the source itself, `session:window` covers
+/// its panes, and a plain word matches the session name only. `*` is a wildcard throughout.
+fn target_pattern_matches(pattern: &str, target: &str, title: &str) -> bool {
+    let pattern = pattern.trim();
+    if let Some(title_pattern) = pattern.strip_prefix("title:") {
+        return wildcard_match(title_pattern.trim(), title);
+    }
+    if pattern.starts_with("file://") || (pattern.contains(':') && pattern.contains('.')) {
+        return wildcard_match(pattern, target);
+    }
+    if pattern.contains(':') {
+        return wildcard_match(&format!("{pattern}.*"), target);
+    }
+    if file_source_path(target).is_some() {
+        return false;
+    }
+    let session = target.split(':').next().unwrap_or("");
+    wildcard_match(pattern, session)
+}
+
+/// Rules address panes by pattern, so the run loop needs every pane's title.
+fn rules_address_panes(rules: &[Rule]) -> bool {
+    rules
+        .iter()
+        .any(|rule| rule.targets.is_some() || rule.send_to.is_some())
+}
+
+fn trigger_edge_key(target: &str, rule_match: &RuleMatch<'_>) -> String {
+    let rule_id = rule_match.rule.id.as_deref().unwrap_or("<unnamed>");
+    format!("{target}|{rule_id}|{}", rule_match.index)
+}
+
+fn refresh_trigger_edges_for_target(
+    active_edges: &mut HashSet<String>,
+    target: &str,
+    matched_keys: &HashSet<String>,
+    enabled: bool,
+) {
+    if !enabled {
+        return;
+    }
+    let prefix = format!("{target}|");
+    active_edges.retain(|key| !key.starts_with(&prefix) || matched_keys.contains(key));
+}
+
+fn edge_guard_allows(active_edges: &HashSet<String>, edge_key: &str, enabled: bool) -> bool {
+    !enabled || !active_edges.contains(edge_key)
+}
+
+fn refresh_trigger_confirm_for_target(
+    pending_since: &mut std::collections::HashMap<String, std::time::Instant>,
+    target: &str,
+    matched_keys: &HashSet<String>,
+) {
+    let prefix = format!("{target}|");
+    pending_since.retain(|key, _| !key.starts_with(&prefix) || matched_keys.contains(key));
+}
+
+fn confirm_window_elapsed(
+    global_seconds: u64,
+    rule_override_seconds: Option<u64>,
+    edge_key: &str,
+    pending_since: &mut std::collections::HashMap<String, std::time::Instant>,
+    now: std::time::Instant,
+) -> bool {
+    let seconds = rule_override_seconds.unwrap_or(global_seconds);
+    if seconds == 0 {
+        pending_since.remove(edge_key);
+        return true;
+    }
+
+    let wait = std::time::Duration::from_secs(seconds);
+    let Some(first_seen) = pending_since.get(edge_key).copied() else {
+        pending_since.insert(edge_key.to_string(), now);
+        return false;
     };
     if now.duration_since(first_seen) >= wait {
         pending_since.remove(edge_key);
@@ -4663,6 +7351,25 @@ fn has_pending_confirm_for_target(
     pending_since.keys().any(|key| key.starts_with(&prefix))
 }
 
+/// Whether any rule or the `done_when` match needs `capture-pane -e`.
+fn rules_use_style(rules: &[Rule], done_when: Option<&DoneWhen>) -> bool {
+    rules
+        .iter()
+        .flat_map(|rule| [rule.match_.as_ref(), rule.exclude.as_ref()])
+        .chain([done_when.and_then(|done| done.match_.as_ref())])
+        .flatten()
+        .any(|criteria| criteria.style.is_some())
+}
+
+/// Unchanged captures still need scanning while some rule waits for the pane to go idle.
+fn rules_watch_idle(rules: &[Rule]) -> bool {
+    rules.iter().any(|rule| {
+        rule.match_
+            .as_ref()
+            .is_some_and(|criteria| has_text(&criteria.idle_for))
+    })
+}
+
 fn should_skip_scan_by_hash(
     trigger_edge_enabled: bool,
     hash: &str,
@@ -4722,12 +7429,17 @@ fn compact_sent_log(
     )
 }
 
+#[cfg(test)]
 fn matches_rule(rule: &Rule, output: &str) -> Result<bool> {
+    matches_rule_at(rule, output, &MatchContext::default())
+}
+
+fn matches_rule_at(rule: &Rule, output: &str, ctx: &MatchContext) -> Result<bool> {
     let match_defined = rule.match_.as_ref().map(has_match).unwrap_or(false);
     let matches = if match_defined {
         rule.match_
             .as_ref()
-            .map(|criteria| matches_criteria(criteria, output))
+            .map(|criteria| matches_criteria_at(criteria, output, ctx))
             .unwrap_or(Ok(false))?
     } else {
         true
@@ -4736,14 +7448,55 @@ fn matches_rule(rule: &Rule, output: &str) -> Result<bool> {
         return Ok(false);
     }
     if let Some(exclude) = &rule.exclude {
-        if matches_criteria(exclude, output)? {
+        if matches_criteria_at(exclude, output, ctx)? {
             return Ok(false);
         }
     }
     Ok(true)
 }
 
+#[cfg(test)]
 fn matches_criteria(criteria: &MatchCriteria, output: &str) -> Result<bool> {
+    matches_criteria_at(criteria, output, &MatchContext::default())
+}
+
+/// Text criteria are alternatives; `idle_for` and `prompt_ready` must hold on top of them.
+fn matches_criteria_at(criteria: &MatchCriteria, output: &str, ctx: &MatchContext) -> Result<bool> {
+    let idle_for = criteria
+        .idle_for
+        .as_deref()
+        .filter(|value| !value.trim().is_empty())
+        .map(parse_duration)
+        .transpose()?;
+    if idle_for.is_some_and(|wanted| ctx.idle < wanted) {
+        return Ok(false);
+    }
+    if let Some(pattern) = criteria
+        .prompt_ready
+        .as_deref()
+        .filter(|value| !value.trim().is_empty())
+    {
+        let re = Regex::new(pattern).context("invalid prompt_ready regex")?;
+        let last_line = output
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("");
+        if !re.is_match(last_line) {
+            return Ok(false);
+        }
+    }
+    if !has_text_match(criteria) {
+        return Ok(true);
+    }
+    let style_hit = criteria
+        .style
+        .as_ref()
+        .map(|style| style_matches(style, ctx.styles))
+        .transpose()?;
+    if style_hit == Some(true) {
+        return Ok(true);
+    }
     if let Some(trigger_expr) = &criteria.trigger_expr {
         if eval_trigger_expr(&parse_trigger_expr(trigger_expr)?, output) {
             return Ok(true);
@@ -4774,6 +7527,13 @@ fn matches_criteria(criteria: &MatchCriteria, output: &str) -> Result<bool> {
     Ok(false)
 }
 
+/// Typed atom prefixes; anything else is a raw regex term.
+const TRIGGER_ATOM_PREFIXES: &[(&str, AtomScope)] = &[
+    ("last_line:", AtomScope::LastLine),
+    ("line:", AtomScope::Line),
+    ("contains:", AtomScope::Anywhere),
+];
+
 fn tokenize_trigger_expr(input: &str) -> Result<Vec<TriggerExprToken>> {
     let mut tokens = Vec::new();
     let mut idx = 0;
@@ -4796,6 +7556,11 @@ fn tokenize_trigger_expr(input: &str) -> Result<Vec<TriggerExprToken>> {
             idx += 2;
             continue;
         }
+        if ch == '!' {
+            tokens.push(TriggerExprToken::Not { pos: idx });
+            idx += 1;
+            continue;
+        }
         if ch == '(' {
             tokens.push(TriggerExprToken::LParen { pos: idx });
             idx += 1;
@@ -4806,6 +7571,36 @@ fn tokenize_trigger_expr(input: &str) -> Result<Vec<TriggerExprToken>> {
             idx += 1;
             continue;
         }
+        if rest.starts_with("then") && is_trigger_term_boundary(&rest[4..]) {
+            tokens.push(TriggerExprToken::Then { pos: idx });
+            idx += 4;
+            continue;
+        }
+        if let Some((prefix, scope)) = TRIGGER_ATOM_PREFIXES
+            .iter()
+            .find(|(prefix, _)| rest.starts_with(prefix))
+            .filter(|(prefix, _)| rest[prefix.len()..].starts_with(['"', '/']))
+        {
+            let start = idx;
+            let literal_start = idx + prefix.len();
+            let (pattern, regex, end) = read_trigger_literal(input, literal_start, prefix)?;
+            if pattern.is_empty() {
+                bail!("invalid trigger expression at pos {start}: empty '{prefix}' atom");
+            }
+            if !is_trigger_term_boundary(&input[end..]) {
+                bail!("invalid trigger expression at pos {end}: unexpected token");
+            }
+            tokens.push(TriggerExprToken::Term {
+                atom: RawTriggerAtom {
+                    scope: *scope,
+                    pattern,
+                    regex,
+                },
+                pos: start,
+            });
+            idx = end;
+            continue;
+        }
 
         let start = idx;
         while idx < input.len() {
@@ -4826,28 +7621,88 @@ fn tokenize_trigger_expr(input: &str) -> Result<Vec<TriggerExprToken>> {
             bail!("invalid trigger expression at pos {start}: unexpected token");
         }
         tokens.push(TriggerExprToken::Term {
-            pattern: term.to_string(),
+            atom: RawTriggerAtom {
+                scope: AtomScope::Anywhere,
+                pattern: term.to_string(),
+                regex: true,
+            },
             pos: start,
         });
     }
     Ok(tokens)
 }
 
+fn is_trigger_term_boundary(rest: &str) -> bool {
+    rest.is_empty()
+        || rest.starts_with(|ch: char| ch.is_whitespace() || ch == '(' || ch == ')')
+        || rest.starts_with("&&")
+        || rest.starts_with("||")
+}
+
+/// Reads `"text"` or `/regex/` at `start`; returns the unescaped body, whether it is a regex,
+/// and the byte offset after the closing delimiter.
+fn read_trigger_literal(input: &str, start: usize, prefix: &str) -> Result<(String, bool, usize)> {
+    let delimiter = if input[start..].starts_with('/') {
+        '/'
+    } else {
+        '"'
+    };
+    let mut body = String::new();
+    let mut chars = input[start + 1..].char_indices();
+    while let Some((offset, ch)) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some((_, escaped))
+                    if escaped == delimiter || (delimiter == '"' && escaped == '\\') =>
+                {
+                    body.push(escaped)
+                }
+                Some((_, escaped)) => {
+                    body.push('\\');
+                    body.push(escaped);
+                }
+                None => break,
+            }
+            continue;
+        }
+        if ch == delimiter {
+            return Ok((body, delimiter == '/', start + 1 + offset + 1));
+        }
+        body.push(ch);
+    }
+    let kind = if delimiter == '/' { "regex" } else { "string" };
+    bail!(
+        "invalid trigger expression at pos {}: unterminated {kind} in '{prefix}' atom",
+        start - prefix.len()
+    )
+}
+
 fn compile_trigger_expr(
     node: TriggerExprRawNode,
-    compiled_terms: &mut Vec<Regex>,
+    compiled_terms: &mut Vec<TriggerAtom>,
 ) -> Result<TriggerExprNode> {
     match node {
-        TriggerExprRawNode::Term { pattern, pos } => {
-            let regex = Regex::new(&pattern).map_err(|err| {
-                anyhow::anyhow!(
-                    "invalid trigger expression at pos {pos}: invalid regex term: {err}"
-                )
-            })?;
+        TriggerExprRawNode::Term { atom, pos } => {
+            let matcher = if atom.regex {
+                AtomMatcher::Regex(Regex::new(&atom.pattern).map_err(|err| {
+                    anyhow::anyhow!(
+                        "invalid trigger expression at pos {pos}: invalid regex term: {err}"
+                    )
+                })?)
+            } else {
+                AtomMatcher::Text(atom.pattern)
+            };
             let idx = compiled_terms.len();
-            compiled_terms.push(regex);
+            compiled_terms.push(TriggerAtom {
+                scope: atom.scope,
+                matcher,
+            });
             Ok(TriggerExprNode::Term(idx))
         }
+        TriggerExprRawNode::Not(inner) => Ok(TriggerExprNode::Not(Box::new(compile_trigger_expr(
+            *inner,
+            compiled_terms,
+        )?))),
         TriggerExprRawNode::And(left, right) => Ok(TriggerExprNode::And(
             Box::new(compile_trigger_expr(*left, compiled_terms)?),
             Box::new(compile_trigger_expr(*right, compiled_terms)?),
@@ -4856,6 +7711,10 @@ fn compile_trigger_expr(
             Box::new(compile_trigger_expr(*left, compiled_terms)?),
             Box::new(compile_trigger_expr(*right, compiled_terms)?),
         )),
+        TriggerExprRawNode::Then(left, right) => Ok(TriggerExprNode::Then(
+            Box::new(compile_trigger_expr(*left, compiled_terms)?),
+            Box::new(compile_trigger_expr(*right, compiled_terms)?),
+        )),
     }
 }
 
@@ -4875,20 +7734,88 @@ fn parse_trigger_expr(input: &str) -> Result<TriggerExpr> {
     Ok(TriggerExpr { ast, terms })
 }
 
-fn eval_trigger_expr(expr: &TriggerExpr, output: &str) -> bool {
-    fn eval_node(node: &TriggerExprNode, terms: &[Regex], output: &str) -> bool {
-        match node {
-            TriggerExprNode::Term(idx) => terms[*idx].is_match(output),
-            TriggerExprNode::And(left, right) => {
-                eval_node(left, terms, output) && eval_node(right, terms, output)
-            }
-            TriggerExprNode::Or(left, right) => {
-                eval_node(left, terms, output) || eval_node(right, terms, output)
-            }
-        }
-    }
+/// Lines of `text` with the byte offset just past each (excluding the newline).
+fn line_ends(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0usize, |offset, raw| {
+        let start = *offset;
+        *offset += raw.len();
+        let line = raw.trim_end_matches(['\n', '\r']);
+        Some((start + line.len(), line))
+    })
+}
+
+impl TriggerAtom {
+    fn matches_line(&self, line: &str) -> bool {
+        match &self.matcher {
+            AtomMatcher::Text(expected) => line.trim() == expected.trim(),
+            AtomMatcher::Regex(regex) => regex.is_match(line),
+        }
+    }
+
+    /// Byte offset just past the first match in `text`.
+    fn match_end(&self, text: &str) -> Option<usize> {
+        match (self.scope, &self.matcher) {
+            (AtomScope::Anywhere, AtomMatcher::Text(needle)) => {
+                text.find(needle.as_str()).map(|start| start + needle.len())
+            }
+            (AtomScope::Anywhere, AtomMatcher::Regex(regex)) => {
+                regex.find(text).map(|found| found.end())
+            }
+            (AtomScope::Line, _) => line_ends(text)
+                .find(|(_, line)| self.matches_line(line))
+                .map(|(end, _)| end),
+            (AtomScope::LastLine, _) => line_ends(text)
+                .filter(|(_, line)| !line.trim().is_empty())
+                .last()
+                .filter(|(_, line)| self.matches_line(line))
+                .map(|(end, _)| end),
+        }
+    }
+
+    /// Byte ranges in one preview line worth highlighting.
+    fn highlight_ranges(&self, line: &str) -> Vec<(usize, usize)> {
+        match (self.scope, &self.matcher) {
+            (AtomScope::Anywhere, AtomMatcher::Text(needle)) => line
+                .match_indices(needle.as_str())
+                .map(|(start, found)| (start, start + found.len()))
+                .collect(),
+            (_, AtomMatcher::Regex(regex)) => regex
+                .find_iter(line)
+                .map(|found| (found.start(), found.end()))
+                .collect(),
+            (_, AtomMatcher::Text(_)) if self.matches_line(line) => {
+                let start = line.len() - line.trim_start().len();
+                vec![(start, start + line.trim().len())]
+            }
+            (_, AtomMatcher::Text(_)) => Vec::new(),
+        }
+    }
+}
+
+fn eval_trigger_expr(expr: &TriggerExpr, output: &str) -> bool {
+    /// Offset just past where `node` is satisfied; `then` resumes the search from there.
+    fn match_end(node: &TriggerExprNode, terms: &[TriggerAtom], text: &str) -> Option<usize> {
+        match node {
+            TriggerExprNode::Term(idx) => terms[*idx].match_end(text),
+            TriggerExprNode::Not(inner) => match_end(inner, terms, text).is_none().then_some(0),
+            TriggerExprNode::And(left, right) => {
+                let left = match_end(left, terms, text)?;
+                Some(left.max(match_end(right, terms, text)?))
+            }
+            TriggerExprNode::Or(left, right) => {
+                match (match_end(left, terms, text), match_end(right, terms, text)) {
+                    (Some(left), Some(right)) => Some(left.min(right)),
+                    (left, right) => left.or(right),
+                }
+            }
+            TriggerExprNode::Then(left, right) => {
+                let left = match_end(left, terms, text)?;
+                Some(left + match_end(right, terms, &text[left..])?)
+            }
+        }
+    }
 
-    eval_node(&expr.ast, &expr.terms, output)
+    match_end(&expr.ast, &expr.terms, output).is_some()
 }
 
 #[cfg(test)]
@@ -4905,6 +7832,300 @@ fn build_prompt(action: &Action) -> String {
     parts.join("\n")
 }
 
+/// Text for the `position`-th send of a rotating block, plus a short step label.
+fn rotation_pick(
+    rotation: &PromptRotation,
+    position: u32,
+    rng: &mut DelayRng,
+) -> Result<(String, String)> {
+    let count = rotation.items.len();
+    if count == 0 {
+        bail!("prompt rotation needs at least one item");
+    }
+    let position = position as usize;
+    let index = match rotation.rotate {
+        RotationMode::Sequence => position.min(count - 1),
+        RotationMode::RoundRobin => position % count,
+        RotationMode::Once => {
+            if position >= count {
+                let fallback = rotation
+                    .fallback
+                    .clone()
+                    .context("rotate: once needs a fallback")?;
+                return Ok((fallback, "fallback".to_string()));
+            }
+            position
+        }
+        RotationMode::Weighted => {
+            let total = rotation
+                .items
+                .iter()
+                .map(|item| u64::from(item.weight()))
+                .sum::<u64>();
+            if total == 0 {
+                bail!("weighted prompt rotation needs a positive weight");
+            }
+            let mut roll = rng.between(0, total - 1)?;
+            rotation
+                .items
+                .iter()
+                .position(|item| {
+                    let weight = u64::from(item.weight());
+                    if roll < weight {
+                        return true;
+                    }
+                    roll -= weight;
+                    false
+                })
+                .unwrap_or(count - 1)
+        }
+    };
+    Ok((
+        rotation.items[index].text().to_string(),
+        format!("step {}/{count}", index + 1),
+    ))
+}
+
+fn validate_prompt_blocks(action: &Action, label: &str) -> Result<()> {
+    for (slot, block) in [
+        ("pre", &action.pre),
+        ("prompt", &action.prompt),
+        ("post", &action.post),
+    ] {
+        if let Some(PromptBlock::Source(source)) = block {
+            validate_prompt_source(source).with_context(|| format!("{label}.{slot}"))?;
+            continue;
+        }
+        if let Some(PromptBlock::Forward(forward)) = block {
+            validate_prompt_forward(forward).with_context(|| format!("{label}.{slot}"))?;
+            continue;
+        }
+        let Some(PromptBlock::Rotate(rotation)) = block else {
+            continue;
+        };
+        if rotation.items.is_empty() {
+            bail!("{label}.{slot}: rotation needs at least one item");
+        }
+        if rotation.rotate == RotationMode::Once && rotation.fallback.is_none() {
+            bail!("{label}.{slot}: `rotate: once` needs a fallback");
+        }
+        if rotation.rotate == RotationMode::Weighted
+            && rotation.items.iter().all(|item| item.weight() == 0)
+        {
+            bail!("{label}.{slot}: weighted rotation needs a positive weight");
+        }
+    }
+    Ok(())
+}
+
+fn validate_prompt_forward(forward: &PromptForward) -> Result<()> {
+    let region = &forward.forward;
+    if region
+        .between
+        .as_ref()
+        .is_some_and(|markers| markers.iter().any(|marker| marker.trim().is_empty()))
+    {
+        bail!("forward.between markers cannot be empty");
+    }
+    if let Some(after) = &region.after {
+        Regex::new(after).context("invalid forward.after regex")?;
+    }
+    if region.lines == Some(0) || forward.max_bytes == Some(0) {
+        bail!("forward lines and max_bytes must be at least 1");
+    }
+    if forward
+        .template
+        .as_deref()
+        .is_some_and(|template| !template.contains("{{output}}"))
+    {
+        bail!("forward template needs an {{{{output}}}} placeholder");
+    }
+    Ok(())
+}
+
+fn validate_prompt_source(source: &PromptSource) -> Result<()> {
+    match (&source.file, &source.command) {
+        (Some(_), Some(_)) => bail!("prompt source takes `file` or `command`, not both"),
+        (None, None) => bail!("prompt source needs `file` or `command`"),
+        (Some(file), None) => {
+            if !Path::new(file).is_file() {
+                bail!("prompt file not found: {file}");
+            }
+        }
+        (None, Some(command)) => {
+            if command.trim().is_empty() {
+                bail!("prompt command cannot be empty");
+            }
+        }
+    }
+    if source.section.is_some() && source.lines.is_some() {
+        bail!("prompt source takes `section` or `lines`, not both");
+    }
+    if let Some(range) = &source.lines {
+        parse_line_range(range)?;
+    }
+    if source.max_bytes == Some(0) {
+        bail!("prompt source max_bytes must be > 0");
+    }
+    if let Some(timeout) = source.timeout.as_deref() {
+        if source.command.is_none() {
+            bail!("prompt source timeout only applies to `command`");
+        }
+        if parse_duration(timeout)?.is_zero() {
+            bail!("prompt source timeout must be > 0");
+        }
+    }
+    Ok(())
+}
+
+/// Reads a file/command prompt source; `Err` carries the reason to log instead of sending.
+fn read_prompt_source(source: &PromptSource) -> std::result::Result<String, String> {
+    let (label, raw) = if let Some(file) = &source.file {
+        let raw = std::fs::read_to_string(file)
+            .map_err(|err| format!("prompt file unreadable: {file} ({err})"))?;
+        (file.clone(), raw)
+    } else if let Some(command) = &source.command {
+        let timeout = source
+            .timeout
+            .as_deref()
+            .map(parse_duration)
+            .transpose()
+            .map_err(|err| err.to_string())?
+            .unwrap_or(DEFAULT_PROMPT_COMMAND_TIMEOUT);
+        (command.clone(), run_prompt_command(command, timeout)?)
+    } else {
+        return Err("prompt source needs `file` or `command`".to_string());
+    };
+
+    let selected = if let Some(heading) = &source.section {
+        markdown_section(&raw, heading)
+            .ok_or_else(|| format!("section `{heading}` not found in {label}"))?
+    } else if let Some(range) = &source.lines {
+        let (start, end) = parse_line_range(range).map_err(|err| err.to_string())?;
+        raw.lines()
+            .skip(start - 1)
+            .take(end + 1 - start)
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        raw
+    };
+    let text = truncate_prompt_bytes(
+        selected.trim(),
+        source.max_bytes.unwrap_or(DEFAULT_PROMPT_SOURCE_MAX_BYTES),
+    );
+    if text.is_empty() {
+        return Err(format!("prompt source empty: {label}"));
+    }
+    Ok(text)
+}
+
+/// Runs a prompt `command` and returns its stdout; a hung command is killed at `timeout`.
+fn run_prompt_command(command: &str, timeout: Duration) -> std::result::Result<String, String> {
+    let mut child = std::process::Command::new("sh")
+        .args(["-lc", command])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|err| format!("prompt command failed to start: {err}"))?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| "prompt command has no stdout".to_string())?;
+    // Drained on a thread so a chatty command cannot block on a full pipe.
+    let reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = stdout.read_to_end(&mut buffer);
+        buffer
+    });
+    let started = std::time::Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "prompt command timed out after {}",
+                    format_std_duration(timeout)
+                ));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(20)),
+            Err(err) => return Err(format!("prompt command failed: {err}")),
+        }
+    };
+    let stdout = reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(format!(
+            "prompt command exited with {}",
+            status
+                .code()
+                .map(|code| code.to_string())
+                .unwrap_or_else(|| "signal".to_string())
+        ));
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+fn parse_line_range(range: &str) -> Result<(usize, usize)> {
+    let (start, end) = range
+        .split_once('-')
+        .with_context(|| format!("invalid line range `{range}` (expected START-END)"))?;
+    let start = start
+        .trim()
+        .parse::<usize>()
+        .with_context(|| format!("invalid line range `{range}`"))?;
+    let end = end
+        .trim()
+        .parse::<usize>()
+        .with_context(|| format!("invalid line range `{range}`"))?;
+    if start == 0 || end < start {
+        bail!("invalid line range `{range}` (1-based, START <= END)");
+    }
+    Ok((start, end))
+}
+
+/// Body under the markdown heading `heading`, up to the next heading of the same or higher level.
+fn markdown_section(text: &str, heading: &str) -> Option<String> {
+    let level_of = |line: &str| {
+        let trimmed = line.trim_start();
+        let level = trimmed.chars().take_while(|value| *value == '#').count();
+        (level > 0 && trimmed[level..].starts_with([' ', '\t'])).then_some(level)
+    };
+    let wanted = heading.trim().trim_start_matches('#').trim();
+    let lines = text.lines().collect::<Vec<_>>();
+    let (start, level) = lines.iter().enumerate().find_map(|(index, line)| {
+        let level = level_of(line)?;
+        line.trim_start()[level..]
+            .trim()
+            .eq_ignore_ascii_case(wanted)
+            .then_some((index, level))
+    })?;
+    let body = lines[start + 1..]
+        .iter()
+        .take_while(|line| level_of(line).is_none_or(|next| next > level))
+        .copied()
+        .collect::<Vec<_>>();
+    Some(body.join("\n"))
+}
+
+fn truncate_prompt_bytes(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut cut = max_bytes;
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let head = &text[..cut];
+    head.rfind('\n')
+        .map(|newline| &head[..newline])
+        .unwrap_or(head)
+        .trim_end()
+        .to_string()
+}
+
 fn push_block(parts: &mut Vec<String>, block: Option<&PromptBlock>) {
     let Some(block) = block else {
         return;
@@ -4912,24 +8133,115 @@ fn push_block(parts: &mut Vec<String>, block: Option<&PromptBlock>) {
     match block {
         PromptBlock::Single(text) => parts.push(text.clone()),
         PromptBlock::Multi(items) => parts.extend(items.iter().cloned()),
+        // Expanded by `resolve_config`; an unexpanded ref contributes nothing.
+        PromptBlock::Ref(_) => {}
+        // Outside a run (validate, manual picks) a rotation reads as its first item.
+        PromptBlock::Rotate(rotation) => {
+            parts.extend(rotation.items.first().map(|item| item.text().to_string()))
+        }
+        PromptBlock::Source(source) => parts.extend(read_prompt_source(source).ok()),
+        // Needs a matched capture, so it only contributes during a run.
+        PromptBlock::Forward(_) => {}
+    }
+}
+
+/// Region of `output` picked by a `forward` block, sanitized, size-limited and wrapped in
+/// its template.
+fn forward_region(forward: &PromptForward, output: &str) -> std::result::Result<String, String> {
+    let lines = output.lines().collect::<Vec<_>>();
+    let mut selected = lines.as_slice();
+    if let Some([start, end]) = &forward.forward.between {
+        let end_at = selected
+            .iter()
+            .rposition(|line| line.contains(end.as_str()))
+            .ok_or_else(|| format!("forward end marker `{end}` not found"))?;
+        let start_at = selected[..end_at]
+            .iter()
+            .rposition(|line| line.contains(start.as_str()))
+            .ok_or_else(|| format!("forward start marker `{start}` not found before `{end}`"))?;
+        selected = &selected[start_at + 1..end_at];
+    }
+    if let Some(after) = &forward.forward.after {
+        let re = Regex::new(after).map_err(|err| format!("invalid forward.after regex: {err}"))?;
+        let at = selected
+            .iter()
+            .rposition(|line| re.is_match(line))
+            .ok_or_else(|| format!("no line matches forward.after `{after}`"))?;
+        selected = &selected[at + 1..];
+    }
+    if let Some(count) = forward.forward.lines {
+        selected = &selected[selected.len().saturating_sub(count)..];
+    }
+    let text = truncate_forwarded(
+        &sanitize_forwarded(&selected.join("\n")),
+        forward.max_bytes.unwrap_or(DEFAULT_FORWARD_MAX_BYTES),
+    );
+    if text.is_empty() {
+        return Err("forwarded region is empty".to_string());
+    }
+    Ok(match &forward.template {
+        Some(template) => template.replace("{{output}}", &text),
+        None => text,
+    })
+}
+
+/// Cuts forwarded text to `max_bytes` at a line end (or, when no whole line fits, a character
+/// boundary) and appends a marker so the receiving agent knows findings were dropped.
+fn truncate_forwarded(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
     }
+    let mut cut = max_bytes;
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let head = &text[..cut];
+    let head = match head.rfind('\n').map(|newline| head[..newline].trim_end()) {
+        Some(lines) if !lines.is_empty() => lines,
+        _ => head.trim_end(),
+    };
+    format!(
+        "{head}\n{FORWARD_TRUNCATED_MARKER} ({} of {} bytes)",
+        head.len(),
+        text.len()
+    )
+}
+
+/// Drops escape sequences, control characters, trailing whitespace and blank edge lines so
+/// text from one pane can be typed into another.
+fn sanitize_forwarded(text: &str) -> String {
+    let (plain, _) = parse_sgr(text);
+    plain
+        .lines()
+        .map(|line| {
+            line.chars()
+                .filter(|ch| *ch == '\t' || !ch.is_control())
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
 }
 
 fn compute_delay_seconds(
     delay: &DelayConfig,
     rule_match: &RuleMatch<'_>,
     backoff_state: &mut std::collections::HashMap<String, BackoffState>,
+    rng: &mut DelayRng,
 ) -> Result<u64> {
     match delay.mode {
         DelayMode::Fixed => Ok(delay.value.unwrap_or(0)),
-        DelayMode::Range => random_between(delay.min.unwrap_or(0), delay.max.unwrap_or(0)),
+        DelayMode::Range => rng.between(delay.min.unwrap_or(0), delay.max.unwrap_or(0)),
         DelayMode::Jitter => {
-            let base = random_between(delay.min.unwrap_or(0), delay.max.unwrap_or(0))? as f64;
+            let base = rng.between(delay.min.unwrap_or(0), delay.max.unwrap_or(0))? as f64;
             let jitter = delay.jitter.unwrap_or(0.0);
             let spread = base * jitter;
             let min = (base - spread).max(0.0);
             let max = base + spread;
-            let jittered = random_between(min as u64, max as u64)? as f64;
+            let jittered = rng.between(min as u64, max as u64)? as f64;
             Ok(jittered as u64)
         }
         DelayMode::Backoff => delay
@@ -4959,23 +8271,125 @@ fn compute_delay_seconds(
     }
 }
 
-fn random_between(min: u64, max: u64) -> Result<u64> {
-    if min > max {
-        bail!("invalid delay range: {min}-{max}");
+/// Seeded splitmix64 generator so delay sequences can be reproduced with `--seed`.
+#[derive(Debug, Clone, Default)]
+struct DelayRng {
+    state: u64,
+}
+
+impl DelayRng {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
     }
-    if min == max {
-        return Ok(min);
+
+    fn between(&mut self, min: u64, max: u64) -> Result<u64> {
+        if min > max {
+            bail!("invalid delay range: {min}-{max}");
+        }
+        if min == max {
+            return Ok(min);
+        }
+        let span = max - min + 1;
+        Ok(min + (self.next_u64() % span))
     }
-    let span = max - min + 1;
-    let nanos = std::time::SystemTime::now()
+}
+
+/// Mixed into the seed for the rotation stream.
+const ROTATION_RNG_STREAM: u64 = 0x5DEE_CE66_D1CE_4E5B;
+
+fn entropy_seed() -> u64 {
+    std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
-        .context("system time error")?
-        .subsec_nanos() as u64;
-    Ok(min + (nanos % span))
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// tmux calls made by `run_loop`; tests swap in fakes so a run needs no tmux server.
+#[derive(Clone, Copy)]
+struct PaneIo {
+    list: fn() -> Result<Vec<TmuxPane>>,
+    capture: fn(&str, &ResolvedConfig, Option<&CaptureMark>) -> Result<String>,
+    send: fn(&str, &str, &[String]) -> Result<()>,
+}
+
+const TMUX_IO: PaneIo = PaneIo {
+    list: list_tmux_panes,
+    capture: capture_for_scan,
+    send: send_prompt,
+};
+
+/// Time source for trigger bookkeeping; `Virtual` advances only when told to, so
+/// replays and tests can cover hours of loop time without sleeping.
+#[derive(Debug, Clone, Copy)]
+enum Clock {
+    System,
+    Virtual {
+        origin: std::time::Instant,
+        offset: Duration,
+    },
+}
+
+impl Clock {
+    fn virtual_now() -> Self {
+        Self::Virtual {
+            origin: std::time::Instant::now(),
+            offset: Duration::ZERO,
+        }
+    }
+
+    fn now(&self) -> std::time::Instant {
+        match self {
+            Self::System => std::time::Instant::now(),
+            Self::Virtual { origin, offset } => *origin + *offset,
+        }
+    }
+
+    fn advance_to(&mut self, at: Duration) {
+        if let Self::Virtual { offset, .. } = self {
+            *offset = (*offset).max(at);
+        }
+    }
+
+    fn elapsed(&self, since: std::time::Instant) -> Duration {
+        self.now().saturating_duration_since(since)
+    }
+
+    /// Waits `duration`; a virtual clock just moves forward.
+    fn sleep(&mut self, duration: Duration) {
+        match self {
+            Self::System => std::thread::sleep(duration),
+            Self::Virtual { offset, .. } => *offset += duration,
+        }
+    }
 }
 
 fn validate(args: ValidateArgs) -> Result<()> {
     let mut config = load_config(args.config.as_ref())?;
+    let key_issues = match args.config.as_ref() {
+        Some(path) => lint_config_keys(path, SchemaKind::Run)?,
+        None => Vec::new(),
+    };
+    if args.strict && !key_issues.is_empty() {
+        bail!(
+            "unknown config keys:\n- {}",
+            key_issues
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n- ")
+        );
+    }
+    for issue in &key_issues {
+        eprintln!("warning: {issue}");
+    }
     let sources = collect_source_inputs(
         &args.target,
         &args.targets_file,
@@ -5004,11 +8418,33 @@ fn validate(args: ValidateArgs) -> Result<()> {
         None,
     )?;
     print_validation(&resolved);
+    if let Some(format) = args.graph.filter(|_| resolved.exec_command.is_none()) {
+        println!();
+        print!("{}", rule_flow_graph(&resolved, format));
+    }
+    Ok(())
+}
+
+fn graph(args: GraphArgs) -> Result<()> {
+    let config = load_config(Some(&args.config))?;
+    let resolved = resolve_config(
+        config, None, None, true, None, None, false, false, false, None, None, None,
+    )?;
+    if resolved.exec_command.is_some() {
+        bail!("graph does not support exec configs");
+    }
+    print!("{}", rule_flow_graph(&resolved, args.format));
     Ok(())
 }
 
 fn init(args: InitArgs) -> Result<()> {
-    let template = default_template();
+    if args.interactive {
+        return init_interactive(&args);
+    }
+    let template = match args.preset {
+        Some(preset) => preset_template(preset, args.target.as_deref().unwrap_or("ai:5.0")),
+        None => default_template(),
+    };
     if let Some(path) = args.output {
         std::fs::write(&path, template)
             .with_context(|| format!("failed to write template to {}", path.display()))?;
@@ -5019,3556 +8455,9497 @@ fn init(args: InitArgs) -> Result<()> {
     Ok(())
 }
 
-fn load_config(path: Option<&PathBuf>) -> Result<Config> {
-    let Some(path) = path else {
-        bail!("--config is required");
+fn init_interactive(args: &InitArgs) -> Result<()> {
+    if args.preset == Some(InitPreset::ExecWatcher) {
+        bail!(
+            "exec-watcher preset does not target a tmux pane; use `loopmux init --preset exec-watcher`"
+        );
+    }
+    let path = args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("loop.yaml"));
+    if path.exists() {
+        bail!(
+            "{} already exists; pass --output to write elsewhere",
+            path.display()
+        );
+    }
+
+    let panes = list_tmux_panes()?;
+    if panes.is_empty() {
+        bail!("no tmux panes found; start your assistant inside tmux first");
+    }
+    println!("tmux panes:");
+    let mut detected = Vec::with_capacity(panes.len());
+    for (idx, pane) in panes.iter().enumerate() {
+        let content = capture_pane(&pane.target, CaptureWindow::Tail(40)).unwrap_or_default();
+        let assistant = detect_assistant(&pane.current_command, &content);
+        println!(
+            "{}. {} cmd={} assistant={} last={}",
+            idx + 1,
+            pane.target,
+            pane.current_command,
+            assistant.map(init_preset_label).unwrap_or("unknown"),
+            truncate_text(&last_non_empty_line(&content), 60, true)
+        );
+        detected.push(assistant);
+    }
+    let index = read_numbered_choice("Select pane", panes.len())?;
+    let pane = &panes[index];
+    let preset = match args.preset.or(detected[index]) {
+        Some(preset) => preset,
+        None => {
+            let choices = [
+                InitPreset::ClaudeCode,
+                InitPreset::Codex,
+                InitPreset::OpenCode,
+            ];
+            println!("Could not detect the assistant in {}:", pane.target);
+            for (idx, choice) in choices.iter().enumerate() {
+                println!("{}. {}", idx + 1, init_preset_label(*choice));
+            }
+            choices[read_numbered_choice("Select preset", choices.len())?]
+        }
     };
-    let contents = std::fs::read_to_string(path)
-        .with_context(|| format!("failed to read {}", path.display()))?;
-    let config: Config = serde_yaml::from_str(&contents)
-        .with_context(|| format!("failed to parse {}", path.display()))?;
-    Ok(config)
+
+    let template = preset_template(preset, &pane.target);
+    let config: Config = serde_yaml::from_str(&template).context("failed to parse preset")?;
+    resolve_config(
+        config, None, None, false, None, None, false, false, false, None, None, None,
+    )
+    .with_context(|| {
+        format!(
+            "generated {} config failed validation",
+            init_preset_label(preset)
+        )
+    })?;
+    std::fs::write(&path, template)
+        .with_context(|| format!("failed to write template to {}", path.display()))?;
+    println!(
+        "Wrote {} config for {} to {}",
+        init_preset_label(preset),
+        pane.target,
+        path.display()
+    );
+    println!("Next: loopmux run --config {}", path.display());
+    Ok(())
 }
 
-fn resolve_run_config(args: &RunArgs) -> Result<Config> {
-    if args.config.is_some() {
-        return load_config(args.config.as_ref());
+fn read_numbered_choice(label: &str, count: usize) -> Result<usize> {
+    loop {
+        print!("{label} (1-{count}, q to cancel): ");
+        let _ = std::io::stdout().flush();
+        let mut input = String::new();
+        let read = std::io::stdin()
+            .read_line(&mut input)
+            .context("failed to read selection")?;
+        if read == 0 {
+            bail!("selection cancelled: no input");
+        }
+        let trimmed = input.trim();
+        if trimmed.eq_ignore_ascii_case("q") {
+            bail!("selection cancelled");
+        }
+        let Ok(index) = trimmed.parse::<usize>() else {
+            println!("Invalid selection: {trimmed}");
+            continue;
+        };
+        if index == 0 || index > count {
+            println!("Selection out of range: {index}");
+            continue;
+        }
+        return Ok(index - 1);
     }
+}
 
-    if let Some(exec_command) = args.exec.as_ref() {
-        if args.prompt.is_some()
-            || args.trigger.is_some()
-            || args.trigger_expr.is_some()
-            || args.trigger_exact_line
-            || args.exclude.is_some()
-            || args.pre.is_some()
-            || args.post.is_some()
-        {
-            bail!(
-                "--exec cannot be combined with --prompt/--trigger/--trigger-expr/--exclude/--pre/--post"
-            );
+fn init_preset_label(preset: InitPreset) -> &'static str {
+    match preset {
+        InitPreset::ClaudeCode => "claude-code",
+        InitPreset::Codex => "codex",
+        InitPreset::OpenCode => "opencode",
+        InitPreset::ExecWatcher => "exec-watcher",
+    }
+}
+
+fn detect_assistant(current_command: &str, content: &str) -> Option<InitPreset> {
+    let command = current_command.to_ascii_lowercase();
+    for (needle, preset) in [
+        ("claude", InitPreset::ClaudeCode),
+        ("codex", InitPreset::Codex),
+        ("opencode", InitPreset::OpenCode),
+    ] {
+        if command.contains(needle) {
+            return Some(preset);
         }
-        if !args.target.is_empty()
-            || !args.targets_file.is_empty()
-            || !args.file.is_empty()
-            || !args.files_file.is_empty()
-        {
-            bail!("--exec cannot be combined with --target/--targets-file/--file/--files-file");
+    }
+    let content = content.to_ascii_lowercase();
+    for (needle, preset) in [
+        ("claude code", InitPreset::ClaudeCode),
+        ("openai codex", InitPreset::Codex),
+        ("opencode", InitPreset::OpenCode),
+    ] {
+        if content.contains(needle) {
+            return Some(preset);
         }
-
-        let command = exec_command.trim();
-        if command.is_empty() {
-            bail!("--exec command cannot be empty");
+    }
+    None
+}
+
+fn preset_template(preset: InitPreset, target: &str) -> String {
+    // (busy-indicator exclude regex, confirm seconds, delay min, delay max); all three
+    // assistants submit with Enter and share the sentinel prompt.
+    let (busy, confirm, min, max) = match preset {
+        InitPreset::ClaudeCode => ("(?i)(esc to interrupt|do you want to proceed)", 5, 5, 30),
+        InitPreset::Codex => ("(?i)(esc to interrupt|allow command\\?)", 5, 5, 30),
+        InitPreset::OpenCode => ("(?i)esc (to )?interrupt", 3, 3, 20),
+        InitPreset::ExecWatcher => {
+            return r#"# loopmux preset: exec-watcher
+exec:
+  command: "make test"
+poll: 10
+iterations: 3
+name: "exec-watch"
+"#
+            .to_string();
         }
+    };
+    format!(
+        r#"# loopmux preset: {label}
+target: "{target}"
+iterations: 10
+poll: 5
+tail: 40
+trigger_confirm_seconds: {confirm}
+trigger_edge: true
+recheck_before_send: true
+submit_keys: ["Enter"]
 
-        return Ok(Config {
-            target: None,
-            targets: None,
-            files: None,
-            exec: Some(ExecConfig {
-                command: command.to_string(),
-            }),
-            iterations: args.iterations,
-            infinite: None,
-            poll: args.poll,
-            trigger_confirm_seconds: args.trigger_confirm_seconds,
-            log_preview_lines: args.log_preview_lines,
-            trigger_edge: Some(!args.no_trigger_edge),
-            recheck_before_send: Some(!args.no_recheck_before_send),
-            fanout: Some(args.fanout),
-            duration: args.duration.clone(),
-            rule_eval: None,
-            default_action: None,
-            delay: None,
-            rules: None,
-            logging: None,
-            template_vars: None,
-            tail: args.tail,
-            once: Some(args.once),
-            single_line: Some(args.single_line),
-            tui: Some(args.tui),
-            name: args.name.clone(),
-        });
-    }
+default_action:
+  prompt: "Continue with the next step. When it is done, print <CONTINUE-LOOP> on its own line."
 
-    let Some(prompt) = args.prompt.as_ref() else {
-        bail!("--config or --prompt is required");
-    };
-    if args.trigger.is_none() && args.trigger_expr.is_none() {
-        bail!("--trigger or --trigger-expr is required when using --prompt");
-    };
+delay:
+  mode: range
+  min: {min}
+  max: {max}
 
-    let default_action = Action {
-        pre: args
-            .pre
-            .as_ref()
-            .map(|value| PromptBlock::Single(value.clone())),
-        prompt: Some(PromptBlock::Single(prompt.clone())),
-        post: args
-            .post
-            .as_ref()
-            .map(|value| PromptBlock::Single(value.clone())),
-    };
-    let rule = Rule {
-        id: Some("inline".to_string()),
-        match_: Some(MatchCriteria {
-            regex: if args.trigger_expr.is_some() || args.trigger_exact_line {
-                None
-            } else {
-                args.trigger.clone()
-            },
-            trigger_expr: args.trigger_expr.clone(),
-            exact_line: if args.trigger_expr.is_none() && args.trigger_exact_line {
-                args.trigger.clone()
-            } else {
-                None
-            },
-            contains: None,
-            starts_with: None,
-        }),
-        exclude: args.exclude.as_ref().map(|value| MatchCriteria {
-            regex: Some(value.clone()),
-            trigger_expr: None,
-            exact_line: None,
-            contains: None,
-            starts_with: None,
-        }),
-        action: None,
-        delay: None,
-        confirm_seconds: None,
-        next: None,
-        priority: None,
+rules:
+  - id: continue-loop
+    match:
+      exact_line: "<CONTINUE-LOOP>"
+    exclude:
+      regex: '{busy}'
+"#,
+        label = init_preset_label(preset),
+    )
+}
+
+fn load_config(path: Option<&PathBuf>) -> Result<Config> {
+    let Some(path) = path else {
+        bail!("--config is required");
     };
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let mut config: Config = parse_yaml_source(&contents, path)?;
+    config.workspace_path = Some(path.clone());
+    Ok(config)
+}
 
-    Ok(Config {
-        target: args.target.first().cloned(),
-        targets: if args.target.is_empty() {
-            None
-        } else {
-            Some(args.target.clone())
-        },
-        files: None,
-        exec: None,
-        iterations: args.iterations,
-        infinite: None,
-        poll: args.poll,
-        trigger_confirm_seconds: args.trigger_confirm_seconds,
-        log_preview_lines: args.log_preview_lines,
-        trigger_edge: Some(!args.no_trigger_edge),
-        recheck_before_send: Some(!args.no_recheck_before_send),
-        fanout: Some(args.fanout),
-        duration: args.duration.clone(),
-        rule_eval: Some(RuleEval::FirstMatch),
-        default_action: Some(default_action),
-        delay: None,
-        rules: Some(vec![rule]),
-        logging: None,
-        template_vars: None,
-        tail: args.tail,
-        once: Some(args.once),
-        single_line: Some(args.single_line),
-        tui: Some(args.tui),
-        name: args.name.clone(),
+fn parse_yaml_source<T: serde::de::DeserializeOwned>(contents: &str, path: &Path) -> Result<T> {
+    serde_yaml::from_str(contents).map_err(|err| {
+        let location = err
+            .location()
+            .map(|location| format!(":{}:{}", location.line(), location.column()))
+            .unwrap_or_default();
+        anyhow::Error::new(err).context(format!("failed to parse {}{location}", path.display()))
     })
 }
 
-fn read_list_file_entries(path: &PathBuf) -> Result<Vec<String>> {
-    let content = std::fs::read_to_string(path)
-        .with_context(|| format!("failed to read list file: {}", path.display()))?;
-    let mut values = Vec::new();
-    for (idx, line) in content.lines().enumerate() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
+fn prompts_command(args: PromptsArgs) -> Result<()> {
+    let library = load_prompt_library(None)?;
+    match args.action.unwrap_or(PromptsAction::Ls) {
+        PromptsAction::Ls => {
+            if library.is_empty() {
+                println!(
+                    "No prompts yet; add one with `loopmux prompts edit <name>` or a `prompts:` map in the workspace config."
+                );
+                return Ok(());
+            }
+            for (name, snippet) in &library {
+                let first = snippet.text.lines().next().unwrap_or("");
+                println!(
+                    "{name}\t{}\t{}",
+                    snippet.source.display(),
+                    truncate_text(first, 60, true)
+                );
+            }
+            Ok(())
         }
-        values.push(trimmed.to_string());
-        if values.last().is_some_and(|value| value.is_empty()) {
-            bail!(
-                "invalid empty entry in {} at line {}",
-                path.display(),
-                idx + 1
-            );
+        PromptsAction::Show { name } => {
+            let snippet = lookup_prompt(&library, &name)?;
+            println!("{}", snippet.text);
+            Ok(())
+        }
+        PromptsAction::Edit { name } => {
+            let dir = prompt_library_dir().context("HOME not set for prompt library")?;
+            let path = dir.join(format!("{name}.md"));
+            if let Some(snippet) = library.get(&name).filter(|snippet| snippet.source != path) {
+                bail!(
+                    "prompt `{name}` is defined in {}; edit it there",
+                    snippet.source.display()
+                );
+            }
+            if name.trim().is_empty() || name.contains(['/', '\\']) {
+                bail!("invalid prompt name `{name}`");
+            }
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+            if !path.exists() {
+                std::fs::write(&path, "")
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+            run_editor(&path)?;
+            println!("Saved {}", path.display());
+            Ok(())
         }
     }
-    Ok(values)
 }
 
-fn dedupe_preserve_order(values: Vec<String>) -> Vec<String> {
-    let mut seen = HashSet::new();
-    let mut deduped = Vec::new();
-    for value in values {
-        if seen.insert(value.clone()) {
-            deduped.push(value);
+fn prompt_library_dir() -> Option<PathBuf> {
+    let home = std::env::var("HOME").ok()?;
+    Some(
+        PathBuf::from(home)
+            .join(".config")
+            .join("loopmux")
+            .join("prompts"),
+    )
+}
+
+/// `~/.config/loopmux/prompts/*.md` plus the workspace config's `prompts:` map.
+/// Prompt snippets from the prompts dir, the default workspace config and, when given, the
+/// active `workspace` file with its imports (later sources win on name clashes).
+fn load_prompt_library(workspace: Option<&Path>) -> Result<BTreeMap<String, PromptSnippet>> {
+    let mut library = BTreeMap::new();
+    if let Some(dir) = prompt_library_dir().filter(|dir| dir.is_dir()) {
+        let entries =
+            std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("failed to read {}", dir.display()))?
+                .path();
+            if path.extension().and_then(|value| value.to_str()) != Some("md") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|value| value.to_str()) else {
+                continue;
+            };
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            library.insert(
+                name.to_string(),
+                PromptSnippet {
+                    text: text.trim_end().to_string(),
+                    source: path.clone(),
+                },
+            );
         }
     }
-    deduped
+    let mut visited = HashSet::new();
+    if let Some(path) = default_workspace_config_path()
+        .ok()
+        .filter(|path| path.exists())
+    {
+        load_workspace_prompts(&path, &mut library, &mut visited)?;
+    }
+    if let Some(path) = workspace.filter(|path| path.exists()) {
+        load_workspace_prompts(path, &mut library, &mut visited)?;
+    }
+    Ok(library)
 }
 
-fn collect_source_inputs(
-    targets: &[String],
-    targets_file: &[PathBuf],
-    files: &[PathBuf],
-    files_file: &[PathBuf],
-) -> Result<SourceInputs> {
-    let mut merged_targets = targets.to_vec();
-    for path in targets_file {
-        merged_targets.extend(read_list_file_entries(path)?);
+/// Adds the `prompts:` map of `path`, after those of its imports so the importing file wins.
+fn load_workspace_prompts(
+    path: &Path,
+    library: &mut BTreeMap<String, PromptSnippet>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    let normalized = path.canonicalize().unwrap_or(path.to_path_buf());
+    if !visited.insert(normalized.clone()) {
+        return Ok(());
     }
-
-    let mut merged_files = files
-        .iter()
-        .map(|value| value.display().to_string())
-        .collect::<Vec<_>>();
-    for path in files_file {
-        merged_files.extend(read_list_file_entries(path)?);
+    let contents = std::fs::read_to_string(&normalized)
+        .with_context(|| format!("failed to read {}", normalized.display()))?;
+    let workspace: WorkspacePrompts = parse_yaml_source(&contents, &normalized)?;
+    for import in workspace.imports.unwrap_or_default() {
+        let import_path = resolve_workspace_import_path(&normalized, &import)?;
+        load_workspace_prompts(&import_path, library, visited)?;
+    }
+    for (name, text) in workspace.prompts.unwrap_or_default() {
+        library.insert(
+            name,
+            PromptSnippet {
+                text: text.trim_end().to_string(),
+                source: normalized.clone(),
+            },
+        );
     }
+    Ok(())
+}
 
-    Ok(SourceInputs {
-        tmux_targets: dedupe_preserve_order(merged_targets),
-        file_paths: dedupe_preserve_order(merged_files),
+fn lookup_prompt<'a>(
+    library: &'a BTreeMap<String, PromptSnippet>,
+    name: &str,
+) -> Result<&'a PromptSnippet> {
+    library.get(name).ok_or_else(|| {
+        let known = library.keys().cloned().collect::<Vec<_>>();
+        let hint = suggest_key(name, &known)
+            .map(|suggestion| format!(" (did you mean `{suggestion}`?)"))
+            .unwrap_or_default();
+        anyhow::anyhow!("unknown prompt ref `{name}`{hint}; run `loopmux prompts ls`")
     })
 }
 
-#[derive(Debug)]
-struct ResolvedConfig {
-    profile_id: Option<String>,
-    exec_command: Option<String>,
-    target_scope: TargetScope,
-    target_label: String,
-    explicit_targets: Option<Vec<String>>,
-    file_sources: Vec<String>,
-    iterations: Option<u32>,
-    infinite: bool,
-    has_prompt: bool,
-    poll: u64,
-    trigger_confirm_seconds: u64,
-    log_preview_lines: usize,
-    trigger_edge: bool,
-    recheck_before_send: bool,
-    fanout: FanoutMode,
-    duration: Option<Duration>,
-    rule_eval: RuleEval,
-    rules: Vec<Rule>,
-    delay: Option<DelayConfig>,
-    prompt_placeholders: Vec<String>,
-    template_vars: Vec<String>,
-    default_action: Action,
-    logging: LoggingConfigResolved,
-    capture_window: CaptureWindow,
-    once: bool,
-    single_line: bool,
-    tui: bool,
+fn action_has_prompt_refs(action: &Action) -> bool {
+    [&action.pre, &action.prompt, &action.post]
+        .into_iter()
+        .any(|block| matches!(block, Some(PromptBlock::Ref(_))))
 }
 
-#[derive(Debug, Clone, Copy)]
-enum CaptureWindow {
-    Tail(usize),
-    Head(usize),
+fn config_has_prompt_refs(config: &Config) -> bool {
+    config
+        .default_action
+        .as_ref()
+        .is_some_and(action_has_prompt_refs)
+        || config
+            .rules
+            .iter()
+            .flatten()
+            .any(|rule| rule.action.as_ref().is_some_and(action_has_prompt_refs))
 }
 
-impl CaptureWindow {
-    fn from_overrides(tail: Option<usize>, head: Option<usize>) -> Self {
-        if let Some(lines) = head {
-            return Self::Head(lines.max(1));
+fn expand_config_prompt_refs(
+    config: &mut Config,
+    library: &BTreeMap<String, PromptSnippet>,
+) -> Result<()> {
+    if let Some(action) = config.default_action.as_mut() {
+        expand_action_refs(action, library).context("default_action")?;
+    }
+    for (index, rule) in config.rules.iter_mut().flatten().enumerate() {
+        let label = rule.id.clone().unwrap_or_else(|| format!("#{}", index + 1));
+        if let Some(action) = rule.action.as_mut() {
+            expand_action_refs(action, library).with_context(|| format!("rule {label}"))?;
         }
-        Self::Tail(tail.unwrap_or(1).max(1))
     }
+    Ok(())
+}
 
-    fn lines(self) -> usize {
-        match self {
-            Self::Tail(lines) | Self::Head(lines) => lines,
+/// Replaces `{ref: ...}` blocks with library text, filling the ref's `vars`.
+/// Placeholders not covered by `vars` are left for the `template_vars` check.
+fn expand_action_refs(
+    action: &mut Action,
+    library: &BTreeMap<String, PromptSnippet>,
+) -> Result<()> {
+    for block in [&mut action.pre, &mut action.prompt, &mut action.post] {
+        let Some(PromptBlock::Ref(reference)) = block.as_ref() else {
+            continue;
+        };
+        let mut text = lookup_prompt(library, &reference.name)?.text.clone();
+        for (key, value) in reference.vars.iter().flatten() {
+            let value = template_value_text(value);
+            text = Regex::new(&format!(r"\{{\{{\s*{}\s*\}}\}}", regex::escape(key)))
+                .context("invalid template var name")?
+                .replace_all(&text, regex::NoExpand(&value))
+                .into_owned();
         }
+        *block = Some(PromptBlock::Single(text));
     }
+    Ok(())
+}
 
-    fn is_tail(self) -> bool {
-        matches!(self, Self::Tail(_))
+fn template_value_text(value: &TemplateValue) -> String {
+    match value {
+        TemplateValue::String(text) => text.clone(),
+        TemplateValue::Number(number) => number.to_string(),
+        TemplateValue::Bool(flag) => flag.to_string(),
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum UiMode {
-    Plain,
-    SingleLine,
-    Tui,
+fn schema(args: SchemaArgs) -> Result<()> {
+    let schema = config_schema(args.kind);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&schema).context("failed to serialize schema")?
+    );
+    Ok(())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum LoopState {
-    Running,
-    Holding,
-    Waiting,
-    Delay,
-    Sending,
-    Error,
-    Stopped,
+fn config_schema(kind: SchemaKind) -> serde_json::Value {
+    let mut properties = run_config_schema_properties();
+    let (title, properties) = match kind {
+        SchemaKind::Run => ("loopmux run config", properties),
+        SchemaKind::Workspace => {
+            properties.extend(run_profile_schema_properties());
+            properties.insert(
+                "prompts".to_string(),
+                json!({
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "Named prompt library entries, used as `prompt: {ref: name}`."
+                }),
+            );
+            properties.insert(
+                "imports".to_string(),
+                json!({
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Extra workspace files whose runs/events are merged in."
+                }),
+            );
+            let mut profile = run_config_schema_properties();
+            profile.extend(run_profile_schema_properties());
+            let profile = json!({
+                "type": "object",
+                "properties": profile,
+                "additionalProperties": false
+            });
+            properties.insert(
+                "runs".to_string(),
+                json!({ "type": "array", "items": profile.clone() }),
+            );
+            properties.insert(
+                "events".to_string(),
+                json!({ "type": "array", "items": profile, "description": "Alias of `runs`." }),
+            );
+            ("loopmux workspace config", properties)
+        }
+    };
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+        "$defs": config_schema_defs()
+    })
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum LayoutMode {
-    Compact,
-    Standard,
-    Wide,
+fn run_config_schema_properties() -> serde_json::Map<String, serde_json::Value> {
+    let value = json!({
+        "target": { "type": "string", "description": "tmux scope: all, session, session:window, session:window.pane, or a pane selector (pane_title=~claude, current_command=node, cwd=..., contains:\"text\", @tag)." },
+        "targets": { "type": "array", "items": { "type": "string" } },
+        "files": { "type": "array", "items": { "type": "string" } },
+        "exec": {
+            "type": "object",
+            "properties": { "command": { "type": "string" } },
+            "required": ["command"],
+            "additionalProperties": false
+        },
+        "iterations": { "type": "integer", "minimum": 1 },
+        "infinite": { "type": "boolean" },
+        "poll": { "type": "integer", "minimum": 1 },
+        "trigger_confirm_seconds": { "type": "integer", "minimum": 0 },
+        "log_preview_lines": { "type": "integer", "minimum": 1 },
+        "trigger_edge": { "type": "boolean" },
+        "recheck_before_send": { "type": "boolean" },
+        "fanout": { "enum": ["matched", "broadcast"] },
+        "submit_keys": {
+            "type": "array",
+            "items": { "type": "string" },
+            "description": "tmux key names sent after the prompt text (default: [Enter])."
+        },
+        "duration": { "type": "string", "description": "e.g. 5m, 2h, 1d, 1w, 1mon, 1y." },
+        "seed": { "type": "integer", "minimum": 0, "description": "Seed for delay randomness." },
+        "rule_eval": { "enum": ["first_match", "multi_match", "priority"] },
+        "default_action": { "$ref": "#/$defs/action" },
+        "delay": { "$ref": "#/$defs/delay" },
+        "rules": { "type": "array", "items": { "$ref": "#/$defs/rule" } },
+        "states": { "type": "array", "items": { "$ref": "#/$defs/state" } },
+        "logging": {
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "format": { "enum": ["text", "jsonl"] }
+            },
+            "additionalProperties": false
+        },
+        "template_vars": {
+            "type": "object",
+            "additionalProperties": { "type": ["string", "number", "boolean"] }
+        },
+        "tail": { "type": "integer", "minimum": 1 },
+        "capture": { "enum": ["window", "since_last_send", "visible_screen", "full_scrollback"] },
+        "capture_ansi": { "type": "boolean" },
+        "capture_join": { "type": "boolean" },
+        "once": { "type": "boolean" },
+        "single_line": { "type": "boolean" },
+        "tui": { "type": "boolean" },
+        "name": { "type": "string" },
+        "ui": { "$ref": "#/$defs/ui" },
+        "done_when": {
+            "type": "object",
+            "properties": {
+                "match": { "$ref": "#/$defs/match" },
+                "exec": { "type": "string", "description": "Shell command that must exit 0 for the goal to count." }
+            },
+            "additionalProperties": false,
+            "description": "Goal check; reaching it stops the run with reason goal_reached (exit 0)."
+        }
+    });
+    match value {
+        serde_json::Value::Object(map) => map,
+        _ => unreachable!(),
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum IconMode {
-    Nerd,
-    Ascii,
+fn key_actions_schema(table: &[(&str, &str, &str)]) -> serde_json::Value {
+    let properties = table
+        .iter()
+        .map(|(action, default, help)| {
+            (
+                action.to_string(),
+                json!({ "type": "string", "description": format!("{help} (default `{default}`)") }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false
+    })
 }
 
-#[derive(Debug, Clone, Copy)]
-struct StyleConfig {
-    use_color: bool,
-    use_bg: bool,
-    use_unicode_ellipsis: bool,
-    dim_logs: bool,
+fn run_profile_schema_properties() -> serde_json::Map<String, serde_json::Value> {
+    let value = json!({
+        "id": { "type": "string" },
+        "enabled": { "type": "boolean" },
+        "when": {
+            "type": "object",
+            "properties": {
+                "cwd_matches": { "type": "array", "items": { "type": "string" } }
+            },
+            "additionalProperties": false
+        }
+    });
+    match value {
+        serde_json::Value::Object(map) => map,
+        _ => unreachable!(),
+    }
+}
+
+fn config_schema_defs() -> serde_json::Value {
+    json!({
+        "prompt_block": {
+            "oneOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } },
+                {
+                    "type": "object",
+                    "properties": {
+                        "ref": { "type": "string", "description": "Prompt library name (`loopmux prompts ls`)." },
+                        "vars": {
+                            "type": "object",
+                            "additionalProperties": { "type": ["string", "number", "boolean"] }
+                        }
+                    },
+                    "required": ["ref"],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "rotate": { "enum": ["sequence", "round_robin", "weighted", "once"] },
+                        "items": {
+                            "type": "array",
+                            "items": {
+                                "oneOf": [
+                                    { "type": "string" },
+                                    {
+                                        "type": "object",
+                                        "properties": {
+                                            "text": { "type": "string" },
+                                            "weight": { "type": "integer", "minimum": 0 }
+                                        },
+                                        "required": ["text", "weight"],
+                                        "additionalProperties": false
+                                    }
+                                ]
+                            }
+                        },
+                        "fallback": { "type": "string", "description": "Sent once every item went out (`rotate: once`)." }
+                    },
+                    "required": ["rotate", "items"],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "forward": {
+                            "type": "object",
+                            "properties": {
+                                "between": { "type": "array", "items": { "type": "string" }, "minItems": 2, "maxItems": 2, "description": "Start and end marker; the lines between the last pair." },
+                                "after": { "type": "string", "description": "Regex; the lines after its last match." },
+                                "lines": { "type": "integer", "minimum": 1, "description": "Keep only the last N lines." }
+                            },
+                            "additionalProperties": false
+                        },
+                        "template": { "type": "string", "description": "`{{output}}` is replaced by the forwarded region." },
+                        "max_bytes": { "type": "integer", "minimum": 1, "description": "Cut longer regions at a line break (default 4000)." }
+                    },
+                    "required": ["forward"],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "file": { "type": "string", "description": "Read fresh before every send (relative to the working directory)." },
+                        "command": { "type": "string", "description": "Shell command whose stdout is the prompt." },
+                        "timeout": { "type": "string", "description": "Skip the send when `command` runs longer (default 30s)." },
+                        "section": { "type": "string", "description": "Markdown heading to extract." },
+                        "lines": { "type": "string", "description": "1-based inclusive range, e.g. `10-40`." },
+                        "max_bytes": { "type": "integer", "minimum": 1, "description": "Cut longer text at a line break (default 16384)." }
+                    },
+                    "additionalProperties": false
+                }
+            ]
+        },
+        "action": {
+            "type": "object",
+            "properties": {
+                "pre": { "$ref": "#/$defs/prompt_block" },
+                "prompt": { "$ref": "#/$defs/prompt_block" },
+                "post": { "$ref": "#/$defs/prompt_block" }
+            },
+            "additionalProperties": false
+        },
+        "match": {
+            "type": "object",
+            "properties": {
+                "regex": { "type": "string" },
+                "trigger_expr": { "type": "string" },
+                "exact_line": { "type": "string" },
+                "contains": { "type": "string" },
+                "starts_with": { "type": "string" },
+                "idle_for": { "type": "string" },
+                "prompt_ready": { "type": "string" },
+                "style": {
+                    "type": "object",
+                    "properties": {
+                        "fg": { "type": "string" },
+                        "bg": { "type": "string" },
+                        "bold": { "type": "boolean" },
+                        "dim": { "type": "boolean" },
+                        "italic": { "type": "boolean" },
+                        "underline": { "type": "boolean" },
+                        "regex": { "type": "string" }
+                    },
+                    "additionalProperties": false
+                }
+            },
+            "additionalProperties": false
+        },
+        "delay": {
+            "type": "object",
+            "properties": {
+                "mode": { "enum": ["fixed", "range", "jitter", "backoff"] },
+                "value": { "type": "integer", "minimum": 0 },
+                "min": { "type": "integer", "minimum": 0 },
+                "max": { "type": "integer", "minimum": 0 },
+                "jitter": { "type": "number", "minimum": 0.0, "maximum": 1.0 },
+                "backoff": {
+                    "type": "object",
+                    "properties": {
+                        "base": { "type": "integer", "minimum": 1 },
+                        "factor": { "type": "number", "minimum": 1.0 },
+                        "max": { "type": "integer", "minimum": 1 }
+                    },
+                    "required": ["base", "factor"],
+                    "additionalProperties": false
+                }
+            },
+            "required": ["mode"],
+            "additionalProperties": false
+        },
+        "ui": {
+            "type": "object",
+            "properties": {
+                "colors": {
+                    "type": "object",
+                    "properties": {
+                        "running": { "$ref": "#/$defs/color" },
+                        "holding": { "$ref": "#/$defs/color" },
+                        "waiting": { "$ref": "#/$defs/color" },
+                        "delay": { "$ref": "#/$defs/color" },
+                        "sending": { "$ref": "#/$defs/color" },
+                        "error": { "$ref": "#/$defs/color" },
+                        "stopped": { "$ref": "#/$defs/color" }
+                    },
+                    "additionalProperties": false
+                },
+                "icons": { "enum": ["auto", "nerd", "ascii"] },
+                "layout": {
+                    "type": "object",
+                    "properties": {
+                        "compact_max": { "type": "integer", "minimum": 1, "description": "Widest terminal (columns) using the compact status bar (default 80)." },
+                        "standard_max": { "type": "integer", "minimum": 1, "description": "Widest terminal using the standard status bar (default 120)." }
+                    },
+                    "additionalProperties": false
+                },
+                "keys": {
+                    "type": "object",
+                    "properties": {
+                        "run": key_actions_schema(RUN_KEY_ACTIONS),
+                        "fleet": key_actions_schema(FLEET_KEY_ACTIONS)
+                    },
+                    "additionalProperties": false
+                }
+            },
+            "additionalProperties": false
+        },
+        "color": { "type": "integer", "minimum": 0, "maximum": 255, "description": "256-color palette index." },
+        "rule": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "match": { "$ref": "#/$defs/match" },
+                "exclude": { "$ref": "#/$defs/match" },
+                "action": { "$ref": "#/$defs/action" },
+                "delay": { "$ref": "#/$defs/delay" },
+                "confirm_seconds": { "type": "integer", "minimum": 0 },
+                "next": { "$ref": "#/$defs/next", "description": "Rule or state id to continue with, `stop`, or a list of alternatives." },
+                "priority": { "type": "integer" },
+                "escape": { "type": "boolean", "description": "Candidate in every state and after any `next`." },
+                "max_fires": { "type": "integer", "minimum": 0, "description": "Fires allowed over the whole run." },
+                "cooldown": { "type": "string", "description": "Minimum time between fires, e.g. `10m`." },
+                "budget": {
+                    "type": "object",
+                    "properties": {
+                        "fires": { "type": "integer", "minimum": 1 },
+                        "per": { "type": "string", "description": "Rolling window, e.g. `1h`." }
+                    },
+                    "required": ["fires", "per"],
+                    "additionalProperties": false
+                },
+                "on_exhausted": { "$ref": "#/$defs/next", "description": "Step taken once `max_fires` or `budget` is used up." },
+                "targets": { "type": "array", "items": { "type": "string" }, "minItems": 1, "description": "Sources the rule is evaluated for: `session`, `session:window`, `session:window.pane`, `file://path` or `title:<pane title>`; `*` wildcards." },
+                "send_to": { "type": "string", "description": "Pane pattern the action is sent to instead of the matched source." }
+            },
+            "additionalProperties": false
+        },
+        "next": {
+            "oneOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" }, "minItems": 1 }
+            ]
+        },
+        "state": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "rules": { "type": "array", "items": { "type": "string" } },
+                "transitions": { "type": "object", "additionalProperties": { "$ref": "#/$defs/next" } },
+                "timeout": { "type": "string", "description": "Duration without a send, e.g. `10m`." },
+                "on_timeout": { "$ref": "#/$defs/next" }
+            },
+            "required": ["id"],
+            "additionalProperties": false
+        }
+    })
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum TuiAction {
-    Pause,
-    Resume,
-    HoldToggle,
-    Fleet,
-    Stop,
-    Next,
-    Renew,
-    Redraw,
-    Quit,
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ConfigKeyIssue {
+    source: PathBuf,
+    path: String,
+    key: String,
+    line: Option<usize>,
+    column: Option<usize>,
+    suggestion: Option<String>,
 }
 
-struct TuiState {
-    width: u16,
-    height: u16,
-    icon_mode: IconMode,
-    style: StyleConfig,
-    logs: Vec<String>,
-    max_logs: usize,
+impl std::fmt::Display for ConfigKeyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source.display())?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": unknown key `{}`", self.key)?;
+        if self.path != self.key {
+            write!(f, " at `{}`", self.path)?;
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        Ok(())
+    }
 }
 
-impl TuiState {
-    fn new(_config: &ResolvedConfig) -> Result<Self> {
-        enable_raw_mode().context("failed to enable raw mode")?;
-        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
-        let style = detect_style();
-        Ok(Self {
-            width,
-            height,
-            icon_mode: detect_icon_mode(),
-            style,
-            logs: Vec::new(),
-            max_logs: height.saturating_sub(3) as usize,
+fn lint_config_keys(path: &Path, kind: SchemaKind) -> Result<Vec<ConfigKeyIssue>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let value: serde_yaml::Value = parse_yaml_source(&contents, path)?;
+    Ok(lint_config_value_keys(&contents, &value, kind, path))
+}
+
+fn lint_workspace_config_keys(path: &Path) -> Result<Vec<ConfigKeyIssue>> {
+    let mut visited = HashSet::new();
+    let mut pending = vec![path.to_path_buf()];
+    let mut issues = Vec::new();
+    while let Some(next) = pending.pop() {
+        let normalized = next.canonicalize().unwrap_or(next.clone());
+        if !visited.insert(normalized.clone()) {
+            continue;
+        }
+        let contents = std::fs::read_to_string(&normalized)
+            .with_context(|| format!("failed to read {}", normalized.display()))?;
+        let value: serde_yaml::Value = parse_yaml_source(&contents, &normalized)?;
+        issues.extend(lint_config_value_keys(
+            &contents,
+            &value,
+            SchemaKind::Workspace,
+            &normalized,
+        ));
+        if let Some(imports) = value.get("imports").and_then(|value| value.as_sequence()) {
+            for import in imports.iter().filter_map(|value| value.as_str()) {
+                pending.push(resolve_workspace_import_path(&normalized, import)?);
+            }
+        }
+    }
+    Ok(issues)
+}
+
+fn lint_config_value_keys(
+    source: &str,
+    value: &serde_yaml::Value,
+    kind: SchemaKind,
+    path: &Path,
+) -> Vec<ConfigKeyIssue> {
+    let schema = config_schema(kind);
+    let locations = yaml_key_locations(source);
+    let mut unknown = Vec::new();
+    collect_unknown_keys(value, &schema, &schema, "", &mut unknown);
+    unknown
+        .into_iter()
+        .map(|(key_path, key, known)| {
+            let location = locations.get(&key_path).copied();
+            ConfigKeyIssue {
+                source: path.to_path_buf(),
+                suggestion: suggest_key(&key, &known),
+                path: key_path,
+                key,
+                line: location.map(|(line, _)| line),
+                column: location.map(|(_, column)| column),
+            }
         })
+        .collect()
+}
+
+fn resolve_schema_ref<'a>(
+    node: &'a serde_json::Value,
+    root: &'a serde_json::Value,
+) -> &'a serde_json::Value {
+    let Some(reference) = node.get("$ref").and_then(|value| value.as_str()) else {
+        return node;
+    };
+    reference
+        .strip_prefix("#/")
+        .and_then(|pointer| root.pointer(&format!("/{pointer}")))
+        .unwrap_or(node)
+}
+
+fn collect_unknown_keys(
+    value: &serde_yaml::Value,
+    node: &serde_json::Value,
+    root: &serde_json::Value,
+    path: &str,
+    out: &mut Vec<(String, String, Vec<String>)>,
+) {
+    let node = resolve_schema_ref(node, root);
+    if let Some(variants) = node.get("oneOf").and_then(|value| value.as_array()) {
+        let wanted = match value {
+            serde_yaml::Value::Mapping(_) => "object",
+            serde_yaml::Value::Sequence(_) => "array",
+            _ => return,
+        };
+        let candidates = variants
+            .iter()
+            .filter(|variant| {
+                resolve_schema_ref(variant, root)
+                    .get("type")
+                    .and_then(|value| value.as_str())
+                    == Some(wanted)
+            })
+            .collect::<Vec<_>>();
+        // Several object shapes: lint against the one that knows most of the keys used.
+        let known_keys = |variant: &serde_json::Value| {
+            let properties = resolve_schema_ref(variant, root).get("properties");
+            value.as_mapping().map_or(0, |mapping| {
+                mapping
+                    .keys()
+                    .filter_map(|key| key.as_str())
+                    .filter(|key| properties.and_then(|props| props.get(key)).is_some())
+                    .count()
+            })
+        };
+        let variant = candidates
+            .iter()
+            .rev()
+            .max_by_key(|variant| known_keys(variant))
+            .copied();
+        if let Some(variant) = variant {
+            collect_unknown_keys(value, variant, root, path, out);
+        }
+        return;
+    }
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            let properties = node.get("properties").and_then(|value| value.as_object());
+            let additional = node.get("additionalProperties");
+            for (key, child) in mapping {
+                let Some(key) = key.as_str() else {
+                    continue;
+                };
+                let child_path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{path}.{key}")
+                };
+                if let Some(child_node) = properties.and_then(|properties| properties.get(key)) {
+                    collect_unknown_keys(child, child_node, root, &child_path, out);
+                } else if let Some(additional) = additional.filter(|value| value.is_object()) {
+                    collect_unknown_keys(child, additional, root, &child_path, out);
+                } else if additional.and_then(|value| value.as_bool()) == Some(false) {
+                    let known = properties
+                        .map(|properties| properties.keys().cloned().collect())
+                        .unwrap_or_default();
+                    out.push((child_path, key.to_string(), known));
+                }
+            }
+        }
+        serde_yaml::Value::Sequence(items) => {
+            if let Some(item_node) = node.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    collect_unknown_keys(item, item_node, root, &format!("{path}[{index}]"), out);
+                }
+            }
+        }
+        _ => {}
     }
+}
 
-    fn update(
-        &mut self,
-        state: LoopState,
-        config: &ResolvedConfig,
-        current: u32,
-        total: u32,
-        rule_id: Option<&str>,
-        active_elapsed: std::time::Duration,
-        _last_status: &str,
-    ) -> Result<()> {
-        let elapsed = format_std_duration(active_elapsed);
-        let remaining_duration = config
-            .duration
-            .map(|limit| format_std_duration(limit.saturating_sub(active_elapsed)));
-        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
-        self.width = width;
-        self.height = height;
-        self.max_logs = height.saturating_sub(3) as usize;
+fn suggest_key(key: &str, known: &[String]) -> Option<String> {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 2.max(candidate.len() / 4))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone())
+}
+
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right_chars = right.chars().collect::<Vec<_>>();
+    let mut previous = (0..=right_chars.len()).collect::<Vec<_>>();
+    for (i, left_char) in left.chars().enumerate() {
+        let mut current = vec![i + 1; right_chars.len() + 1];
+        for (j, right_char) in right_chars.iter().enumerate() {
+            let cost = usize::from(left_char != *right_char);
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[right_chars.len()]
+}
 
-        let layout = layout_mode(width);
-        let bar = render_status_bar(
-            state,
-            layout,
-            self.icon_mode,
-            self.style,
-            width,
-            config,
-            current,
-            total,
-            rule_id,
-            &elapsed,
-            remaining_duration.as_deref(),
-        );
+/// Maps block-style YAML key paths (`rules[0].match.regex`) to 1-based line/column.
+/// Flow-style mappings are not indexed; callers fall back to location-less messages.
+fn yaml_key_locations(source: &str) -> std::collections::HashMap<String, (usize, usize)> {
+    struct Frame {
+        indent: usize,
+        path: String,
+        item: bool,
+    }
 
-        let log_height = if width < 60 { 0 } else { self.max_logs };
+    let mut locations = std::collections::HashMap::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut item_counts: std::collections::HashMap<String, usize> =
+        std::collections::HashMap::new();
+    let mut block_scalar_indent: Option<usize> = None;
 
-        let mut out = std::io::stdout();
-        let _ = out.queue(MoveTo(0, 0));
-        let _ = out.queue(Clear(ClearType::All));
-        let _ = write!(out, "{bar}");
+    for (line_index, raw) in source.lines().enumerate() {
+        let trimmed = raw.trim_start();
+        let mut indent = raw.len() - trimmed.len();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(scalar_indent) = block_scalar_indent {
+            if indent > scalar_indent {
+                continue;
+            }
+            block_scalar_indent = None;
+        }
+        if trimmed == "---" || trimmed == "..." {
+            stack.clear();
+            continue;
+        }
 
-        for idx in 0..log_height {
-            let raw_line = self
-                .logs
-                .iter()
-                .rev()
-                .take(log_height)
-                .rev()
-                .nth(idx)
-                .map(|value| value.to_string())
-                .unwrap_or_else(|| "".to_string());
-            let mut line = fit_line(&raw_line, width as usize, self.style.use_unicode_ellipsis);
-            if self.style.use_color && self.style.dim_logs && !line.is_empty() {
-                let log_prefix = style_prefix(Some(log_line_color(&raw_line)), None, false);
-                line = format!("{log_prefix}{line}\x1B[0m");
+        let mut content = trimmed;
+        while content == "-" || content.starts_with("- ") {
+            while stack.last().is_some_and(|frame| {
+                frame.indent > indent || (frame.item && frame.indent == indent)
+            }) {
+                stack.pop();
             }
-            let _ = out.queue(MoveTo(0, (idx + 1) as u16));
-            let _ = out.queue(Clear(ClearType::CurrentLine));
-            let _ = write!(out, "{line}");
+            let parent = stack
+                .last()
+                .map(|frame| frame.path.clone())
+                .unwrap_or_default();
+            let count = item_counts.entry(parent.clone()).or_insert(0);
+            let path = format!("{parent}[{count}]");
+            *count += 1;
+            stack.push(Frame {
+                indent,
+                path,
+                item: true,
+            });
+            let rest = content[1..].trim_start();
+            indent += content.len() - rest.len();
+            content = rest;
+        }
+        if content.is_empty() {
+            continue;
         }
 
-        let footer_row = self.height.saturating_sub(1);
-        let footer_summary = if state == LoopState::Stopped {
-            Some(render_footer_summary(config, current, total, &elapsed))
-        } else {
-            None
+        let Some(key) = yaml_line_key(content) else {
+            continue;
         };
-        let footer = render_footer(self.style, width, footer_summary.as_deref());
-        let _ = out.queue(MoveTo(0, footer_row));
-        let _ = out.queue(Clear(ClearType::CurrentLine));
-        let _ = write!(out, "{footer}");
-        let _ = out.flush();
-        Ok(())
-    }
-
-    fn push_log(&mut self, line: String) {
-        self.logs.push(line);
-        if self.logs.len() > 500 {
-            self.logs.drain(0..self.logs.len().saturating_sub(500));
+        while stack
+            .last()
+            .is_some_and(|frame| frame.indent > indent || (!frame.item && frame.indent == indent))
+        {
+            stack.pop();
         }
-    }
-
-    fn poll_input(&self) -> Result<Option<TuiAction>> {
-        if event::poll(Duration::from_millis(10)).context("poll input failed")? {
-            let ev = event::read()?;
-            return Ok(match ev {
-                Event::Resize(_, _) => Some(TuiAction::Redraw),
-                Event::Key(KeyEvent {
-                    code, modifiers, ..
-                }) => match code {
-                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
-                        Some(TuiAction::Stop)
-                    }
-                    KeyCode::Char('p') => Some(TuiAction::Pause),
-                    KeyCode::Char('r') => Some(TuiAction::Resume),
-                    KeyCode::Char('h') => Some(TuiAction::HoldToggle),
-                    KeyCode::Char('f') => Some(TuiAction::Fleet),
-                    KeyCode::Char('R') => Some(TuiAction::Renew),
-                    KeyCode::Char('s') => Some(TuiAction::Stop),
-                    KeyCode::Char('n') => Some(TuiAction::Next),
-                    KeyCode::Char('q') => Some(TuiAction::Quit),
-                    _ => None,
-                },
-                _ => None,
-            });
+        let path = match stack.last() {
+            Some(frame) => format!("{}.{key}", frame.path),
+            None => key.to_string(),
+        };
+        item_counts.remove(&path);
+        locations
+            .entry(path.clone())
+            .or_insert((line_index + 1, indent + 1));
+        let value = content[content.find(':').map(|idx| idx + 1).unwrap_or(0)..].trim();
+        if value.starts_with('|') || value.starts_with('>') {
+            block_scalar_indent = Some(indent);
         }
-        Ok(None)
-    }
-
-    fn shutdown(&mut self) -> Result<()> {
-        disable_raw_mode().context("failed to disable raw mode")?;
-        Ok(())
+        stack.push(Frame {
+            indent,
+            path,
+            item: false,
+        });
     }
+    locations
 }
 
-fn layout_mode(width: u16) -> LayoutMode {
-    if width <= 80 {
-        LayoutMode::Compact
-    } else if width <= 120 {
-        LayoutMode::Standard
-    } else {
-        LayoutMode::Wide
+fn yaml_line_key(content: &str) -> Option<&str> {
+    let (key, _) = content.split_once(':')?;
+    let key = key.trim();
+    let key = key
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .or_else(|| {
+            key.strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+        })
+        .unwrap_or(key);
+    if key.is_empty() || key.contains(' ') {
+        return None;
     }
+    Some(key)
 }
 
-fn detect_icon_mode() -> IconMode {
-    if std::env::var("LOOPMUX_NO_NERD_FONT").is_ok() {
-        return IconMode::Ascii;
+fn resolve_run_config(args: &RunArgs) -> Result<Config> {
+    if args.config.is_some() {
+        return load_config(args.config.as_ref());
     }
-    IconMode::Nerd
-}
 
-fn detect_style() -> StyleConfig {
-    let no_color = std::env::var("NO_COLOR").is_ok();
-    let term = std::env::var("TERM").unwrap_or_default();
-    let color_term = std::env::var("COLORTERM").unwrap_or_default();
-    let use_color = !no_color && term != "dumb";
-    let use_bg = use_color && (term.contains("256color") || !color_term.is_empty());
-    let use_unicode_ellipsis = supports_unicode();
-    let dim_logs = std::env::var("LOOPMUX_TUI_BRIGHT_LOGS").is_err();
-    StyleConfig {
-        use_color,
-        use_bg,
-        use_unicode_ellipsis,
-        dim_logs,
+    if let Some(exec_command) = args.exec.as_ref() {
+        if args.prompt.is_some()
+            || args.trigger.is_some()
+            || args.trigger_expr.is_some()
+            || args.trigger_exact_line
+            || args.exclude.is_some()
+            || args.pre.is_some()
+            || args.post.is_some()
+        {
+            bail!(
+                "--exec cannot be combined with --prompt/--trigger/--trigger-expr/--exclude/--pre/--post"
+            );
+        }
+        if !args.target.is_empty()
+            || !args.targets_file.is_empty()
+            || !args.file.is_empty()
+            || !args.files_file.is_empty()
+        {
+            bail!("--exec cannot be combined with --target/--targets-file/--file/--files-file");
+        }
+
+        let command = exec_command.trim();
+        if command.is_empty() {
+            bail!("--exec command cannot be empty");
+        }
+
+        return Ok(Config {
+            target: None,
+            targets: None,
+            files: None,
+            exec: Some(ExecConfig {
+                command: command.to_string(),
+            }),
+            iterations: args.iterations,
+            infinite: None,
+            poll: args.poll,
+            trigger_confirm_seconds: args.trigger_confirm_seconds,
+            log_preview_lines: args.log_preview_lines,
+            trigger_edge: Some(!args.no_trigger_edge),
+            recheck_before_send: Some(!args.no_recheck_before_send),
+            fanout: Some(args.fanout),
+            submit_keys: None,
+            duration: args.duration.clone(),
+            seed: None,
+            rule_eval: None,
+            default_action: None,
+            delay: None,
+            rules: None,
+            logging: None,
+            template_vars: None,
+            tail: args.tail,
+            once: Some(args.once),
+            single_line: Some(args.single_line),
+            tui: Some(args.tui),
+            name: args.name.clone(),
+            ui: None,
+            done_when: None,
+            capture: None,
+            capture_ansi: None,
+            capture_join: None,
+            states: None,
+            workspace_path: None,
+        });
     }
-}
 
-fn supports_unicode() -> bool {
-    let locale = std::env::var("LC_ALL")
-        .or_else(|_| std::env::var("LC_CTYPE"))
-        .or_else(|_| std::env::var("LANG"))
-        .unwrap_or_default();
-    let locale = locale.to_lowercase();
-    locale.contains("utf-8") || locale.contains("utf8")
-}
+    let Some(prompt) = args.prompt.as_ref() else {
+        bail!("--config or --prompt is required");
+    };
+    if args.trigger.is_none() && args.trigger_expr.is_none() {
+        bail!("--trigger or --trigger-expr is required when using --prompt");
+    };
 
-fn render_footer(style: StyleConfig, width: u16, summary: Option<&str>) -> String {
-    let sep_text = if style.use_unicode_ellipsis {
-        " · "
-    } else {
-        " . "
+    let default_action = Action {
+        pre: args
+            .pre
+            .as_ref()
+            .map(|value| PromptBlock::Single(value.clone())),
+        prompt: Some(PromptBlock::Single(prompt.clone())),
+        post: args
+            .post
+            .as_ref()
+            .map(|value| PromptBlock::Single(value.clone())),
     };
-    let text = if let Some(summary) = summary {
-        format!("stopped{sep_text}{summary}{sep_text}q quit")
-    } else {
-        format!(
-            "h hold/resume (p/r){sep_text}f fleet{sep_text}R renew{sep_text}n next{sep_text}s/^C stop{sep_text}q quit"
-        )
+    let rule = Rule {
+        id: Some("inline".to_string()),
+        match_: Some(MatchCriteria {
+            regex: if args.trigger_expr.is_some() || args.trigger_exact_line {
+                None
+            } else {
+                args.trigger.clone()
+            },
+            trigger_expr: args.trigger_expr.clone(),
+            exact_line: if args.trigger_expr.is_none() && args.trigger_exact_line {
+                args.trigger.clone()
+            } else {
+                None
+            },
+            contains: None,
+            starts_with: None,
+            idle_for: None,
+            prompt_ready: None,
+            style: None,
+        }),
+        exclude: args.exclude.as_ref().map(|value| MatchCriteria {
+            regex: Some(value.clone()),
+            trigger_expr: None,
+            exact_line: None,
+            contains: None,
+            starts_with: None,
+            idle_for: None,
+            prompt_ready: None,
+            style: None,
+        }),
+        action: None,
+        delay: None,
+        confirm_seconds: None,
+        next: None,
+        priority: None,
+        escape: None,
+        max_fires: None,
+        cooldown: None,
+        budget: None,
+        targets: None,
+        send_to: None,
+        on_exhausted: None,
     };
-    let line = pad_to_width(&text, width as usize);
-    if style.use_color {
-        let prefix = style_prefix(Some(240), style.use_bg.then_some(235), false);
-        format!("{prefix}{line}\x1B[0m")
-    } else {
-        line
+
+    Ok(Config {
+        target: args.target.first().cloned(),
+        targets: if args.target.is_empty() {
+            None
+        } else {
+            Some(args.target.clone())
+        },
+        files: None,
+        exec: None,
+        iterations: args.iterations,
+        infinite: None,
+        poll: args.poll,
+        trigger_confirm_seconds: args.trigger_confirm_seconds,
+        log_preview_lines: args.log_preview_lines,
+        trigger_edge: Some(!args.no_trigger_edge),
+        recheck_before_send: Some(!args.no_recheck_before_send),
+        fanout: Some(args.fanout),
+        submit_keys: None,
+        duration: args.duration.clone(),
+        seed: None,
+        rule_eval: Some(RuleEval::FirstMatch),
+        default_action: Some(default_action),
+        delay: None,
+        rules: Some(vec![rule]),
+        logging: None,
+        template_vars: None,
+        tail: args.tail,
+        once: Some(args.once),
+        single_line: Some(args.single_line),
+        tui: Some(args.tui),
+        name: args.name.clone(),
+        ui: None,
+        done_when: None,
+        capture: None,
+        capture_ansi: None,
+        capture_join: None,
+        states: None,
+        workspace_path: None,
+    })
+}
+
+fn read_list_file_entries(path: &PathBuf) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read list file: {}", path.display()))?;
+    let mut values = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        values.push(trimmed.to_string());
+        if values.last().is_some_and(|value| value.is_empty()) {
+            bail!(
+                "invalid empty entry in {} at line {}",
+                path.display(),
+                idx + 1
+            );
+        }
     }
+    Ok(values)
 }
 
-fn render_footer_summary(
-    config: &ResolvedConfig,
-    current: u32,
-    total: u32,
-    elapsed: &str,
-) -> String {
-    if config.infinite || total == 0 || total == u32::MAX {
-        format!("sends {current} elapsed {elapsed}")
-    } else {
-        format!("iter {current}/{total} elapsed {elapsed}")
+fn dedupe_preserve_order(values: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut deduped = Vec::new();
+    for value in values {
+        if seen.insert(value.clone()) {
+            deduped.push(value);
+        }
     }
+    deduped
 }
 
-fn render_status_bar(
-    state: LoopState,
-    layout: LayoutMode,
-    icon_mode: IconMode,
-    style: StyleConfig,
-    width: u16,
-    config: &ResolvedConfig,
-    current: u32,
-    total: u32,
-    rule_id: Option<&str>,
-    elapsed: &str,
-    remaining_duration: Option<&str>,
-) -> String {
-    let (icon, label) = state_label(state, icon_mode);
-    let progress = if config.infinite {
-        "inf".to_string()
-    } else {
-        format!("{}/{}", current, total)
-    };
-    let percent = if config.infinite || total == 0 {
-        "--".to_string()
-    } else {
-        format!("{}%", (current * 100 / total))
-    };
-    let bar = render_progress_bar(current, total, layout, style.use_unicode_ellipsis);
-    let trigger = rule_id.unwrap_or("-");
-    let profile = config.profile_id.as_deref().unwrap_or("-");
-
-    let icon_glyph = if style.use_unicode_ellipsis {
-        icon
-    } else {
-        ascii_icon(icon)
-    };
-    let state_text = format!("{icon_glyph} {label}");
-    let iter_text = if config.infinite {
-        "iter ∞".to_string()
-    } else {
-        format!("iter {progress}")
-    };
-    let trigger_text = truncate_text(
-        trigger,
-        match layout {
-            LayoutMode::Compact => 16,
-            LayoutMode::Standard => 28,
-            LayoutMode::Wide => 44,
-        },
-        style.use_unicode_ellipsis,
-    );
-    let trigger_prefix = if config.exec_command.is_some() {
-        "evt"
-    } else {
-        "trg"
-    };
+fn collect_source_inputs(
+    targets: &[String],
+    targets_file: &[PathBuf],
+    files: &[PathBuf],
+    files_file: &[PathBuf],
+) -> Result<SourceInputs> {
+    let mut merged_targets = targets.to_vec();
+    for path in targets_file {
+        merged_targets.extend(read_list_file_entries(path)?);
+    }
 
-    let sep_text = if style.use_unicode_ellipsis {
-        " · "
-    } else {
-        " . "
-    };
+    let mut merged_files = files
+        .iter()
+        .map(|value| value.display().to_string())
+        .collect::<Vec<_>>();
+    for path in files_file {
+        merged_files.extend(read_list_file_entries(path)?);
+    }
 
-    let mut left_parts = Vec::new();
-    left_parts.push(state_text.clone());
-    left_parts.push(iter_text);
-    left_parts.push(format!("{bar} {percent}"));
+    Ok(SourceInputs {
+        tmux_targets: dedupe_preserve_order(merged_targets),
+        file_paths: dedupe_preserve_order(merged_files),
+    })
+}
 
-    let mut right_parts = Vec::new();
-    match layout {
-        LayoutMode::Compact => {
-            if let Some(remaining) = remaining_duration {
-                right_parts.push(format!("rem {remaining}"));
-            }
-            right_parts.push(format!("run {profile}"));
-            right_parts.push(format!("{trigger_prefix} {trigger_text}"));
-            right_parts.push(format!("v{}", LOOPMUX_VERSION));
-            right_parts.push(config.target_label.clone());
-        }
-        LayoutMode::Standard => {
-            if let Some(remaining) = remaining_duration {
-                right_parts.push(format!("rem {remaining}"));
-            }
-            right_parts.push(format!("run {profile}"));
-            right_parts.push(format!("{trigger_prefix} {trigger_text}"));
-            right_parts.push(format!("last {elapsed}"));
-            right_parts.push(format!("v{}", LOOPMUX_VERSION));
-            right_parts.push(config.target_label.clone());
-        }
-        LayoutMode::Wide => {
-            if let Some(remaining) = remaining_duration {
-                right_parts.push(format!("rem {remaining}"));
-            }
-            right_parts.push(format!("run {profile}"));
-            right_parts.push(format!("{trigger_prefix} {trigger_text}"));
-            right_parts.push(format!("last {elapsed}"));
-            right_parts.push(format!("v{}", LOOPMUX_VERSION));
-            right_parts.push(format!("target {}", config.target_label));
+#[derive(Debug)]
+struct ResolvedConfig {
+    profile_id: Option<String>,
+    exec_command: Option<String>,
+    target_scope: TargetScope,
+    target_label: String,
+    explicit_targets: Option<Vec<String>>,
+    file_sources: Vec<String>,
+    iterations: Option<u32>,
+    infinite: bool,
+    has_prompt: bool,
+    poll: u64,
+    trigger_confirm_seconds: u64,
+    log_preview_lines: usize,
+    trigger_edge: bool,
+    recheck_before_send: bool,
+    fanout: FanoutMode,
+    submit_keys: Vec<String>,
+    duration: Option<Duration>,
+    seed: Option<u64>,
+    rule_eval: RuleEval,
+    rules: Vec<Rule>,
+    delay: Option<DelayConfig>,
+    prompt_placeholders: Vec<String>,
+    template_vars: Vec<String>,
+    default_action: Action,
+    logging: LoggingConfigResolved,
+    capture_window: CaptureWindow,
+    once: bool,
+    single_line: bool,
+    tui: bool,
+    /// YAML file the run was loaded from; enables TUI prompt write-back.
+    source_path: Option<PathBuf>,
+    ui: UiConfig,
+    done_when: Option<DoneWhen>,
+    capture: CaptureMode,
+    capture_ansi: bool,
+    capture_join: bool,
+    states: Vec<RuleState>,
+}
+
+/// Which part of a source's output is scanned each poll.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum CaptureMode {
+    /// The `tail`/`head` line window.
+    #[default]
+    Window,
+    /// Only output produced after loopmux's last send; the window until the first send.
+    SinceLastSend,
+    /// The pane's visible screen without scrollback.
+    VisibleScreen,
+    /// The pane's whole scrollback history.
+    FullScrollback,
+}
+
+impl CaptureMode {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Window => "window",
+            Self::SinceLastSend => "since_last_send",
+            Self::VisibleScreen => "visible_screen",
+            Self::FullScrollback => "full_scrollback",
         }
     }
+}
 
-    let left_sep_text = if matches!(layout, LayoutMode::Compact) {
-        " "
-    } else {
-        sep_text
-    };
-    let left_text = left_parts.join(left_sep_text);
-    let right_sep_text = if matches!(layout, LayoutMode::Compact) {
-        " "
-    } else {
-        sep_text
-    };
-    let mut right_text = right_parts.join(right_sep_text);
-    let mut line = if right_text.is_empty() {
-        left_text.clone()
-    } else {
-        let width_usize = width as usize;
-        let left_len = left_text.chars().count();
-        let right_len = right_text.chars().count();
-        let gap = 1;
-        if left_len + gap + right_len > width_usize {
-            let available = width_usize.saturating_sub(left_len + gap);
-            if available > 0 {
-                right_text = truncate_text(&right_text, available, style.use_unicode_ellipsis);
-                format!("{left_text}{}{}", " ".repeat(gap), right_text)
-            } else {
-                left_text.clone()
-            }
-        } else {
-            let padding = width_usize.saturating_sub(left_len + gap + right_len);
-            format!(
-                "{left_text}{}{}{}",
-                " ".repeat(gap),
-                " ".repeat(padding),
-                right_text
-            )
+#[derive(Debug, Clone, Copy)]
+enum CaptureWindow {
+    Tail(usize),
+    Head(usize),
+}
+
+impl CaptureWindow {
+    fn from_overrides(tail: Option<usize>, head: Option<usize>) -> Self {
+        if let Some(lines) = head {
+            return Self::Head(lines.max(1));
         }
-    };
-    line = pad_to_width(&line, width as usize);
+        Self::Tail(tail.unwrap_or(1).max(1))
+    }
 
-    if style.use_color {
-        let label_color = state_color(state);
-        let base_prefix = style_prefix(Some(248), style.use_bg.then_some(236), false);
-        let state_prefix = format!("\x1B[38;5;{label_color}m");
-        let sep_prefix = style_prefix(Some(240), style.use_bg.then_some(236), false);
-        let colored_state = format!("{state_prefix}{state_text}{base_prefix}");
-        let mut colored_line = line.replacen(&state_text, &colored_state, 1);
-        colored_line =
-            colored_line.replace(sep_text, &format!("{sep_prefix}{sep_text}{base_prefix}"));
-        format!("{base_prefix}{colored_line}\x1B[0m")
-    } else {
-        line
+    fn lines(self) -> usize {
+        match self {
+            Self::Tail(lines) | Self::Head(lines) => lines,
+        }
     }
-}
 
-fn state_label(state: LoopState, icon_mode: IconMode) -> (&'static str, &'static str) {
-    match (state, icon_mode) {
-        (LoopState::Running, IconMode::Nerd) => ("󰐊", "RUN"),
-        (LoopState::Holding, IconMode::Nerd) => ("󰏤", "HOLD"),
-        (LoopState::Delay, IconMode::Nerd) => ("󰔟", "DELAY"),
-        (LoopState::Error, IconMode::Nerd) => ("󰅚", "ERROR"),
-        (LoopState::Stopped, IconMode::Nerd) => ("󰩈", "STOP"),
-        (LoopState::Waiting, IconMode::Nerd) => ("󰔟", "WAIT"),
-        (LoopState::Sending, IconMode::Nerd) => ("󰐊", "SEND"),
-        (LoopState::Running, IconMode::Ascii) => (">", "RUN"),
-        (LoopState::Holding, IconMode::Ascii) => ("||", "HOLD"),
-        (LoopState::Delay, IconMode::Ascii) => ("...", "DELAY"),
-        (LoopState::Error, IconMode::Ascii) => ("!", "ERROR"),
-        (LoopState::Stopped, IconMode::Ascii) => ("x", "STOP"),
-        (LoopState::Waiting, IconMode::Ascii) => ("...", "WAIT"),
-        (LoopState::Sending, IconMode::Ascii) => (">", "SEND"),
+    fn is_tail(self) -> bool {
+        matches!(self, Self::Tail(_))
     }
 }
 
-fn render_progress_bar(current: u32, total: u32, layout: LayoutMode, unicode: bool) -> String {
-    let width = match layout {
-        LayoutMode::Compact => 6,
-        LayoutMode::Standard => 10,
-        LayoutMode::Wide => 14,
-    };
-    if total == 0 {
-        return if unicode {
-            "░".repeat(width)
-        } else {
-            ".".repeat(width)
-        };
-    }
-    let filled = ((current as f64 / total as f64) * width as f64).round() as usize;
-    let filled = filled.min(width);
-    let filled_char = if unicode { "▰" } else { "=" };
-    let empty_char = if unicode { "▱" } else { "." };
-    format!(
-        "{}{}",
-        filled_char.repeat(filled),
-        empty_char.repeat(width - filled)
-    )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UiMode {
+    Plain,
+    SingleLine,
+    Tui,
 }
 
-fn truncate_text(text: &str, max: usize, use_unicode: bool) -> String {
-    if text.chars().count() <= max {
-        return text.to_string();
-    }
-    if max == 0 {
-        return String::new();
-    }
-    let suffix = if use_unicode { "…" } else { "..." };
-    let suffix_len = suffix.chars().count();
-    if max <= suffix_len {
-        return text.chars().take(max).collect();
-    }
-    let mut s = text
-        .chars()
-        .take(max.saturating_sub(suffix_len))
-        .collect::<String>();
-    s.push_str(suffix);
-    s
-}
-
-fn pad_to_width(text: &str, width: usize) -> String {
-    let len = text.chars().count();
-    if len >= width {
-        return text.chars().take(width).collect();
-    }
-    let padding = width - len;
-    format!("{text}{}", " ".repeat(padding))
-}
-
-fn ascii_icon(icon: &str) -> &str {
-    match icon {
-        "󰐊" => ">",
-        "󰏤" => "||",
-        "󰔟" => "...",
-        "󰅚" => "!",
-        "󰩈" => "x",
-        _ => ">",
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopState {
+    Running,
+    Holding,
+    Waiting,
+    Delay,
+    Sending,
+    Error,
+    Stopped,
 }
 
-fn state_color(state: LoopState) -> u8 {
-    match state {
-        LoopState::Running => 71,
-        LoopState::Holding => 179,
-        LoopState::Waiting | LoopState::Delay => 109,
-        LoopState::Error => 166,
-        LoopState::Stopped => 246,
-        LoopState::Sending => 109,
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LayoutMode {
+    Compact,
+    Standard,
+    Wide,
 }
 
-fn style_prefix(fg: Option<u8>, bg: Option<u8>, bold: bool) -> String {
-    let mut prefix = String::new();
-    if bold {
-        prefix.push_str("\x1B[1m");
-    }
-    if let Some(fg) = fg {
-        prefix.push_str(&format!("\x1B[38;5;{fg}m"));
-    }
-    if let Some(bg) = bg {
-        prefix.push_str(&format!("\x1B[48;5;{bg}m"));
-    }
-    prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IconMode {
+    Nerd,
+    Ascii,
 }
 
-fn fit_line(text: &str, width: usize, use_unicode: bool) -> String {
-    if text.chars().count() <= width {
-        return pad_to_width(text, width);
-    }
-    truncate_text(text, width, use_unicode)
+#[derive(Debug, Clone, Copy)]
+struct StyleConfig {
+    use_color: bool,
+    use_bg: bool,
+    use_unicode_ellipsis: bool,
+    dim_logs: bool,
+    palette: UiColors,
 }
 
-fn timestamp_now() -> String {
-    OffsetDateTime::now_utc()
-        .format(&time::format_description::well_known::Rfc3339)
-        .unwrap_or_else(|_| "unknown".into())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TuiAction {
+    Pause,
+    Resume,
+    HoldToggle,
+    Fleet,
+    Stop,
+    Next,
+    Renew,
+    Redraw,
+    Quit,
+    /// Applies to the pane focused in the preview/table (`Tab` cycles).
+    PaneControl(PaneCommand),
+    EditPrompt,
+    ManualSend,
 }
 
-fn timestamp_local_now() -> String {
-    OffsetDateTime::now_local()
-        .unwrap_or_else(|_| OffsetDateTime::now_utc())
-        .format(&time::format_description::well_known::Rfc3339)
-        .unwrap_or_else(|_| "unknown".into())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaneCommand {
+    Hold,
+    Next,
+    Skip,
 }
 
-fn log_line_color(line: &str) -> u8 {
-    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
-    log_line_color_at(line, now)
+struct TuiState {
+    width: u16,
+    height: u16,
+    icon_mode: IconMode,
+    style: StyleConfig,
+    logs: Vec<String>,
+    max_logs: usize,
+    preview: PreviewPane,
+    pane_rows: Vec<PaneRow>,
+    show_pane_table: bool,
+    ui: UiSettings,
+    show_help: bool,
+    rotation: Option<String>,
+    fires: Option<String>,
+    /// Preview highlighters keyed by the active rule they were built for.
+    highlighter: Option<(Option<String>, PreviewHighlighter)>,
+}
+
+/// Live capture of one polled source shown next to (or below) the log.
+#[derive(Debug, Default)]
+struct PreviewPane {
+    visible: bool,
+    targets: Vec<String>,
+    selected: usize,
+    content: String,
+    /// Lines scrolled up from the bottom; 0 follows new output.
+    scroll: usize,
+    refreshed_at: Option<std::time::Instant>,
 }
 
-fn log_line_color_at(line: &str, now: OffsetDateTime) -> u8 {
-    if let Some(timestamp) = parse_log_timestamp(line) {
-        let local_timestamp = timestamp.to_offset(now.offset());
-        if local_timestamp.date() == now.date() {
-            return 251;
+impl PreviewPane {
+    fn target(&self) -> Option<&str> {
+        if !self.visible {
+            return None;
         }
-        return 244;
-    }
-    if looks_like_compact_time_prefix(line) {
-        return 249;
+        self.targets.get(self.selected).map(String::as_str)
     }
-    245
-}
 
-#[cfg(test)]
-fn log_line_date(line: &str) -> Option<&str> {
-    if !line.starts_with('[') {
-        return None;
+    fn set_targets(&mut self, targets: &[String]) {
+        let current = self.targets.get(self.selected).cloned();
+        self.targets = targets.to_vec();
+        self.selected = current
+            .and_then(|current| self.targets.iter().position(|target| *target == current))
+            .unwrap_or(0);
     }
-    let close = line.find(']')?;
-    let ts = line.get(1..close)?;
-    let date = ts.split('T').next()?;
-    if date.len() == 10 { Some(date) } else { None }
-}
 
-fn parse_log_timestamp(line: &str) -> Option<OffsetDateTime> {
-    if !line.starts_with('[') {
-        return None;
+    fn cycle(&mut self, forward: bool) {
+        if self.targets.len() < 2 {
+            return;
+        }
+        let len = self.targets.len();
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+        self.content.clear();
+        self.scroll = 0;
+        self.refreshed_at = None;
     }
-    let close = line.find(']')?;
-    let ts = line.get(1..close)?;
-    OffsetDateTime::parse(ts, &time::format_description::well_known::Rfc3339).ok()
-}
-
-fn looks_like_compact_time_prefix(line: &str) -> bool {
-    let mut parts = line.split(':');
-    let (Some(h), Some(m), Some(s)) = (parts.next(), parts.next(), parts.next()) else {
-        return false;
-    };
-    h.len() == 2
-        && m.len() == 2
-        && s.len() >= 2
-        && h.chars().all(|ch| ch.is_ascii_digit())
-        && m.chars().all(|ch| ch.is_ascii_digit())
-        && s.chars().take(2).all(|ch| ch.is_ascii_digit())
-}
 
-fn parse_duration(value: &str) -> Result<Duration> {
-    let trimmed = value.trim();
-    if trimmed.is_empty() {
-        bail!("duration is empty");
-    }
-    let mut number_part = String::new();
-    let mut unit_part = String::new();
-    for ch in trimmed.chars() {
-        if ch.is_ascii_digit() || ch == '.' {
-            if !unit_part.is_empty() {
-                bail!("invalid duration: {value}");
-            }
-            number_part.push(ch);
-        } else if !ch.is_whitespace() {
-            unit_part.push(ch);
+    fn refresh(&mut self) {
+        let Some(target) = self.target().map(str::to_string) else {
+            return;
+        };
+        if self
+            .refreshed_at
+            .is_some_and(|at| at.elapsed() < Duration::from_secs(1))
+        {
+            return;
+        }
+        self.refreshed_at = Some(std::time::Instant::now());
+        match capture_source(&target, CaptureWindow::Tail(PREVIEW_CAPTURE_LINES)) {
+            Ok(content) => self.content = content,
+            Err(err) => self.content = format!("<capture failed: {err}>"),
         }
     }
-    if number_part.is_empty() || unit_part.is_empty() {
-        bail!("invalid duration: {value}");
-    }
-    let amount: f64 = number_part
-        .parse()
-        .with_context(|| format!("invalid duration number: {value}"))?;
-    if amount <= 0.0 {
-        bail!("duration must be > 0: {value}");
-    }
-    let unit = unit_part.to_lowercase();
-    let seconds = match unit.as_str() {
-        "s" | "sec" | "secs" | "second" | "seconds" => amount,
-        "m" | "min" | "mins" | "minute" | "minutes" => amount * 60.0,
-        "h" | "hr" | "hrs" | "hour" | "hours" => amount * 3600.0,
-        "d" | "day" | "days" => amount * 86_400.0,
-        "w" | "wk" | "wks" | "week" | "weeks" => amount * 604_800.0,
-        "mon" | "month" | "months" => amount * 2_592_000.0,
-        "y" | "yr" | "yrs" | "year" | "years" => amount * 31_536_000.0,
-        _ => bail!("invalid duration unit: {unit_part}"),
-    };
-    Ok(Duration::from_secs_f64(seconds))
 }
 
-#[derive(Debug, Clone)]
-struct LoggingConfigResolved {
-    path: Option<PathBuf>,
-    format: LogFormatResolved,
-}
+impl TuiState {
+    fn new(ui: UiSettings) -> Result<Self> {
+        enable_raw_mode().context("failed to enable raw mode")?;
+        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        let style = StyleConfig {
+            palette: ui.colors,
+            ..detect_style()
+        };
+        Ok(Self {
+            width,
+            height,
+            icon_mode: ui.icon_mode,
+            style,
+            logs: Vec::new(),
+            max_logs: height.saturating_sub(3) as usize,
+            preview: PreviewPane {
+                visible: true,
+                ..PreviewPane::default()
+            },
+            pane_rows: Vec::new(),
+            show_pane_table: true,
+            ui,
+            show_help: false,
+            rotation: None,
+            fires: None,
+            highlighter: None,
+        })
+    }
 
-#[derive(Debug, Clone, Copy)]
-enum LogFormatResolved {
-    Text,
-    Jsonl,
-}
+    fn set_preview_targets(&mut self, targets: &[String]) {
+        self.preview.set_targets(targets);
+    }
 
-#[derive(Debug)]
-struct BackoffState {
-    attempts: u32,
-    last_sent: Option<OffsetDateTime>,
-}
+    fn set_pane_rows(&mut self, rows: Vec<PaneRow>) {
+        self.pane_rows = rows;
+    }
 
-struct ExecInFlight {
-    command: String,
-    child: std::process::Child,
-    started_at: std::time::Instant,
-}
+    /// Step of the last sent rotating prompt, shown next to the trigger.
+    fn set_rotation(&mut self, label: Option<String>) {
+        self.rotation = label;
+    }
 
-fn resolve_config(
-    mut config: Config,
-    target_override: Option<Vec<String>>,
-    iterations_override: Option<u32>,
-    skip_tmux: bool,
-    tail_override: Option<usize>,
-    head_override: Option<usize>,
-    once: bool,
-    single_line: bool,
-    tui: bool,
-    trigger_edge_override: Option<bool>,
-    recheck_before_send_override: Option<bool>,
-    profile_id: Option<String>,
-) -> Result<ResolvedConfig> {
-    if let Some(targets) = target_override {
-        if let Some(first) = targets.first() {
-            config.target = Some(first.clone());
-            config.targets = Some(targets);
-        }
+    /// Fire counter of the last fired rule when it has limits, shown next to the trigger.
+    fn set_fires(&mut self, label: Option<String>) {
+        self.fires = label;
     }
-    if let Some(iterations) = iterations_override {
-        config.iterations = Some(iterations);
-        config.infinite = Some(false);
+
+    /// Pane addressed by per-pane controls: the one selected with `Tab`.
+    fn focused_pane(&self) -> Option<String> {
+        self.preview.targets.get(self.preview.selected).cloned()
     }
 
-    let exec_command = config
-        .exec
-        .as_ref()
-        .map(|value| value.command.trim().to_string())
-        .filter(|value| !value.is_empty());
-    if config.exec.is_some() && exec_command.is_none() {
-        bail!("exec.command is required and cannot be empty");
-    }
+    fn update(
+        &mut self,
+        state: LoopState,
+        config: &ResolvedConfig,
+        current: u32,
+        total: u32,
+        rule_id: Option<&str>,
+        active_elapsed: std::time::Duration,
+        _last_status: &str,
+    ) -> Result<()> {
+        let elapsed = format_std_duration(active_elapsed);
+        let remaining_duration = config
+            .duration
+            .map(|limit| format_std_duration(limit.saturating_sub(active_elapsed)));
+        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        self.width = width;
+        self.height = height;
+        self.max_logs = height.saturating_sub(3) as usize;
 
-    if exec_command.is_some()
-        && (config.default_action.is_some()
-            || config.rules.as_ref().is_some_and(|rules| !rules.is_empty())
-            || config.target.is_some()
-            || config
-                .targets
-                .as_ref()
-                .is_some_and(|targets| !targets.is_empty())
-            || config.files.as_ref().is_some_and(|files| !files.is_empty()))
-    {
-        bail!(
-            "exec mode cannot be combined with target/targets/files/default_action/rules in the same config"
+        let layout = layout_mode(width, self.ui.layout);
+        let extras = [&self.rotation, &self.fires]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let trigger_label = (!extras.is_empty())
+            .then(|| format!("{} {}", rule_id.unwrap_or("-"), extras.join(" ")));
+        let bar = render_status_bar(
+            state,
+            layout,
+            self.icon_mode,
+            self.style,
+            width,
+            config,
+            current,
+            total,
+            trigger_label.as_deref().or(rule_id),
+            &elapsed,
+            remaining_duration.as_deref(),
         );
-    }
 
-    let requested_targets = if exec_command.is_some() {
-        Vec::new()
-    } else {
-        config
-            .targets
-            .clone()
-            .unwrap_or_else(|| config.target.clone().into_iter().collect())
-    };
-    if exec_command.is_none() {
-        if let Some(files) = &config.files {
-            validate_file_sources(files)?;
-        }
-    }
+        self.preview.refresh();
+        let body_height = if width < 60 { 0 } else { self.max_logs };
+        let preview_layout = preview_layout(
+            width,
+            body_height,
+            self.preview.target().is_some() && body_height > 0,
+        );
 
-    let explicit_targets = if exec_command.is_some() {
-        None
-    } else if requested_targets.len() > 1 {
-        Some(resolve_explicit_targets(&requested_targets, skip_tmux)?)
-    } else {
-        None
-    };
-    let (target_scope, target_label) = if let Some(command) = exec_command.as_ref() {
-        (TargetScope::All, format!("exec://{command}"))
-    } else if let Some(targets) = explicit_targets.as_ref() {
-        (TargetScope::All, targets.join(","))
-    } else {
-        let target_input = requested_targets.first().map(String::as_str);
-        if skip_tmux {
-            resolve_target_scope_offline(target_input)?
+        let mut out = std::io::stdout();
+        let _ = out.queue(MoveTo(0, 0));
+        let _ = out.queue(Clear(ClearType::All));
+        let _ = write!(out, "{bar}");
+
+        if self.show_help {
+            let mut help = vec![format!(
+                "keys (remap under ui.keys.run){}",
+                if self.style.use_unicode_ellipsis {
+                    " · esc/? close"
+                } else {
+                    " . esc/? close"
+                }
+            )];
+            help.extend(self.ui.run_keys.help_lines());
+            help.push(format!("{:>7}  {:<12} {}", "tab", "", "cycle focused pane"));
+            help.push(format!(
+                "{:>7}  {:<12} {}",
+                "arrows", "", "scroll preview (pgup/pgdn/end)"
+            ));
+            help.push(format!("{:>7}  {:<12} {}", "^C", "", "stop run"));
+            for (idx, line) in help.iter().take(body_height).enumerate() {
+                let _ = out.queue(MoveTo(0, (idx + 1) as u16));
+                let _ = write!(
+                    out,
+                    "{}",
+                    fit_line(line, width as usize, self.style.use_unicode_ellipsis)
+                );
+            }
         } else {
-            resolve_target_scope(target_input)?
+            if self
+                .highlighter
+                .as_ref()
+                .is_none_or(|(cached, _)| cached.as_deref() != rule_id)
+            {
+                let criteria = preview_highlight_criteria(config, rule_id);
+                self.highlighter = Some((
+                    rule_id.map(str::to_string),
+                    PreviewHighlighter::new(&criteria),
+                ));
+            }
+            self.render_body(&mut out, preview_layout, body_height);
         }
-    };
 
-    let infinite = config.infinite.unwrap_or(false);
-    let iterations = config.iterations;
-    if infinite && iterations.is_some() {
-        bail!("iterations must be omitted when infinite is true");
-    }
-    if !infinite && iterations.unwrap_or(0) == 0 {
-        bail!("iterations must be > 0 unless infinite is true");
+        let footer_row = self.height.saturating_sub(1);
+        let footer_summary = if state == LoopState::Stopped {
+            Some(render_footer_summary(config, current, total, &elapsed))
+        } else {
+            None
+        };
+        let footer = render_footer(
+            self.style,
+            width,
+            footer_summary.as_deref(),
+            &self.ui.run_keys,
+        );
+        let _ = out.queue(MoveTo(0, footer_row));
+        let _ = out.queue(Clear(ClearType::CurrentLine));
+        let _ = write!(out, "{footer}");
+        let _ = out.flush();
+        Ok(())
     }
 
-    let duration = if let Some(ref value) = config.duration {
-        Some(parse_duration(value).with_context(|| "invalid duration")?)
-    } else {
-        None
-    };
-
-    let (default_action, has_prompt, prompt_placeholders, template_var_keys, rule_eval, rules) =
-        if exec_command.is_some() {
-            (
-                Action {
-                    pre: None,
-                    prompt: None,
-                    post: None,
-                },
-                false,
-                Vec::new(),
-                Vec::new(),
-                RuleEval::FirstMatch,
-                Vec::new(),
+    fn render_body(
+        &self,
+        out: &mut std::io::Stdout,
+        preview_layout: PreviewLayout,
+        body_height: usize,
+    ) {
+        let width = self.width;
+        let (log_height, log_width) = match preview_layout {
+            PreviewLayout::Hidden => (body_height, width),
+            PreviewLayout::Inline => (body_height.saturating_sub(1), width),
+            PreviewLayout::Stacked { log_rows } => (log_rows, width),
+            PreviewLayout::Split { log_cols } => (body_height, log_cols),
+        };
+        let table_lines = if self.show_pane_table && self.pane_rows.len() > 1 {
+            let focused = self.focused_pane();
+            render_pane_table(
+                &self.pane_rows,
+                focused.as_deref(),
+                log_width as usize,
+                log_height / 2,
+                self.style.use_unicode_ellipsis,
             )
         } else {
-            let Some(default_action) = config.default_action else {
-                bail!("default_action.prompt is required");
+            Vec::new()
+        };
+        for (idx, line) in table_lines.iter().enumerate() {
+            let line = if idx == 0 && self.style.use_color {
+                let prefix = style_prefix(Some(252), None, true);
+                format!("{prefix}{line}\x1B[0m")
+            } else {
+                line.clone()
             };
-            let has_prompt = default_action.prompt.as_ref().is_some();
-            if !has_prompt {
-                bail!("default_action.prompt is required");
+            let _ = out.queue(MoveTo(0, (idx + 1) as u16));
+            let _ = out.queue(Clear(ClearType::CurrentLine));
+            let _ = write!(out, "{line}");
+        }
+        let table_height = table_lines.len();
+        let log_height = log_height.saturating_sub(table_height);
+        for idx in 0..log_height {
+            let raw_line = self
+                .logs
+                .iter()
+                .rev()
+                .take(log_height)
+                .rev()
+                .nth(idx)
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| "".to_string());
+            let mut line = fit_line(
+                &raw_line,
+                log_width as usize,
+                self.style.use_unicode_ellipsis,
+            );
+            if self.style.use_color && self.style.dim_logs && !line.is_empty() {
+                let log_prefix = style_prefix(Some(log_line_color(&raw_line)), None, false);
+                line = format!("{log_prefix}{line}\x1B[0m");
             }
-            let prompt_placeholders = collect_template_placeholders(&default_action, &config.rules);
-            let template_vars = config.template_vars.unwrap_or_default();
-            let template_var_keys = template_vars.keys().cloned().collect::<Vec<_>>();
-            let missing_template_vars = find_missing_vars(&prompt_placeholders, &template_vars);
-            if !missing_template_vars.is_empty() {
-                bail!(
-                    "missing template_vars: {}",
-                    missing_template_vars.join(", ")
+            let _ = out.queue(MoveTo(0, (table_height + idx + 1) as u16));
+            let _ = out.queue(Clear(ClearType::CurrentLine));
+            let _ = write!(out, "{line}");
+        }
+        self.render_preview(out, preview_layout, body_height);
+    }
+
+    fn render_preview(&self, out: &mut std::io::Stdout, layout: PreviewLayout, body_height: usize) {
+        let Some(target) = self.preview.target() else {
+            return;
+        };
+        let width = self.width as usize;
+        let unicode = self.style.use_unicode_ellipsis;
+        let (top, left, rows, cols) = match layout {
+            PreviewLayout::Hidden => return,
+            PreviewLayout::Inline => {
+                let (count, folded) = extract_trigger_preview(&self.preview.content, 3, unicode);
+                let line = fit_line(
+                    &format!("preview {target} {count}L {folded}"),
+                    width,
+                    unicode,
                 );
+                let _ = out.queue(MoveTo(0, body_height as u16));
+                let _ = write!(out, "{}", self.paint_preview_header(&line));
+                return;
+            }
+            PreviewLayout::Stacked { log_rows } => {
+                (log_rows + 1, 0, body_height.saturating_sub(log_rows), width)
+            }
+            PreviewLayout::Split { log_cols } => {
+                let left = log_cols as usize + 1;
+                (1, left, body_height, width.saturating_sub(left))
             }
-            let rule_eval = config.rule_eval.unwrap_or(RuleEval::FirstMatch);
-            let rules = config.rules.unwrap_or_default();
-            validate_rules(&rules)?;
-            (
-                default_action,
-                true,
-                prompt_placeholders,
-                template_var_keys,
-                rule_eval,
-                rules,
-            )
         };
-    let logging = resolve_logging(config.logging);
+        if rows < 2 || cols < 10 {
+            return;
+        }
 
-    let delay = config.delay;
-    if let Some(ref delay) = delay {
-        validate_delay(delay)?;
+        let position = format!(
+            "{}/{}",
+            self.preview.selected + 1,
+            self.preview.targets.len()
+        );
+        let scroll = if self.preview.scroll > 0 {
+            format!(" +{}", self.preview.scroll)
+        } else {
+            String::new()
+        };
+        let header = fit_line(
+            &format!("preview {target} [{position}]{scroll} tab pane, up/dn scroll, v hide"),
+            cols,
+            unicode,
+        );
+        let body = preview_window_lines(&self.preview.content, rows - 1, self.preview.scroll);
+        for row in 0..rows {
+            let text = if row == 0 {
+                self.paint_preview_header(&header)
+            } else {
+                let raw = body.get(row - 1).copied().unwrap_or("");
+                let fitted = fit_line(raw, cols, unicode);
+                if let Some((_, highlighter)) =
+                    self.highlighter.as_ref().filter(|_| self.style.use_color)
+                {
+                    let ranges = highlighter.ranges(&fitted);
+                    paint_ranges(&fitted, &ranges, &style_prefix(Some(16), Some(179), true))
+                } else {
+                    fitted
+                }
+            };
+            let _ = out.queue(MoveTo(left as u16, (top + row) as u16));
+            if matches!(layout, PreviewLayout::Split { .. }) {
+                let sep = if unicode { "│" } else { "|" };
+                let _ = out.queue(MoveTo(left.saturating_sub(1) as u16, (top + row) as u16));
+                let _ = write!(out, "{sep}");
+            }
+            let _ = write!(out, "{text}");
+        }
     }
 
-    let poll = config.poll.unwrap_or(5).max(1);
-    let trigger_confirm_seconds = config
-        .trigger_confirm_seconds
-        .unwrap_or(DEFAULT_TRIGGER_CONFIRM_SECONDS);
-    let trigger_edge = trigger_edge_override.unwrap_or(config.trigger_edge.unwrap_or(true));
-    let recheck_before_send =
-        recheck_before_send_override.unwrap_or(config.recheck_before_send.unwrap_or(true));
-    let log_preview_lines = config.log_preview_lines.unwrap_or(3).max(1);
+    fn paint_preview_header(&self, line: &str) -> String {
+        if self.style.use_color {
+            let prefix = style_prefix(Some(252), self.style.use_bg.then_some(238), false);
+            format!("{prefix}{line}\x1B[0m")
+        } else {
+            line.to_string()
+        }
+    }
 
-    let fanout = config.fanout.unwrap_or(FanoutMode::Matched);
+    fn push_log(&mut self, line: String) {
+        self.logs.push(line);
+        if self.logs.len() > 500 {
+            self.logs.drain(0..self.logs.len().saturating_sub(500));
+        }
+    }
 
-    if exec_command.is_none() && !skip_tmux {
-        if let Some(targets) = explicit_targets.as_ref() {
-            validate_tmux_targets(targets)?;
+    fn poll_input(&mut self) -> Result<Option<TuiAction>> {
+        if event::poll(Duration::from_millis(10)).context("poll input failed")? {
+            let ev = event::read()?;
+            return Ok(match ev {
+                Event::Resize(_, _) => Some(TuiAction::Redraw),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers,
+                    ..
+                }) if modifiers.contains(KeyModifiers::CONTROL) => Some(TuiAction::Stop),
+                Event::Key(KeyEvent { code, .. }) => self
+                    .ui
+                    .run_keys
+                    .translate(code)
+                    .and_then(|code| self.handle_key(code)),
+                _ => None,
+            });
         }
-        validate_tmux_scope(&target_scope)?;
+        Ok(None)
     }
 
-    if tail_override.is_some() && head_override.is_some() {
-        bail!("--tail and --head are mutually exclusive");
+    /// Handles a key already translated to its default binding.
+    fn handle_key(&mut self, code: KeyCode) -> Option<TuiAction> {
+        let page = (self.max_logs / 2).max(1);
+        match code {
+            KeyCode::Char('?') => {
+                self.show_help = !self.show_help;
+                Some(TuiAction::Redraw)
+            }
+            KeyCode::Esc if self.show_help => {
+                self.show_help = false;
+                Some(TuiAction::Redraw)
+            }
+            KeyCode::Char('e') => Some(TuiAction::EditPrompt),
+            KeyCode::Char('m') => Some(TuiAction::ManualSend),
+            KeyCode::Char('t') => {
+                self.show_pane_table = !self.show_pane_table;
+                Some(TuiAction::Redraw)
+            }
+            KeyCode::Char('H') => Some(TuiAction::PaneControl(PaneCommand::Hold)),
+            KeyCode::Char('N') => Some(TuiAction::PaneControl(PaneCommand::Next)),
+            KeyCode::Char('S') => Some(TuiAction::PaneControl(PaneCommand::Skip)),
+            KeyCode::Char('v') => {
+                self.preview.visible = !self.preview.visible;
+                self.preview.refreshed_at = None;
+                Some(TuiAction::Redraw)
+            }
+            KeyCode::Tab => {
+                self.preview.cycle(true);
+                Some(TuiAction::Redraw)
+            }
+            KeyCode::BackTab => {
+                self.preview.cycle(false);
+                Some(TuiAction::Redraw)
+            }
+            KeyCode::Up | KeyCode::PageUp | KeyCode::Down | KeyCode::PageDown | KeyCode::End => {
+                let step = if matches!(code, KeyCode::PageUp | KeyCode::PageDown) {
+                    page
+                } else {
+                    1
+                };
+                self.preview.scroll = match code {
+                    KeyCode::Up | KeyCode::PageUp => self.preview.scroll.saturating_add(step),
+                    KeyCode::End => 0,
+                    _ => self.preview.scroll.saturating_sub(step),
+                };
+                Some(TuiAction::Redraw)
+            }
+            KeyCode::Char('p') => Some(TuiAction::Pause),
+            KeyCode::Char('r') => Some(TuiAction::Resume),
+            KeyCode::Char('h') => Some(TuiAction::HoldToggle),
+            KeyCode::Char('f') => Some(TuiAction::Fleet),
+            KeyCode::Char('R') => Some(TuiAction::Renew),
+            KeyCode::Char('s') => Some(TuiAction::Stop),
+            KeyCode::Char('n') => Some(TuiAction::Next),
+            KeyCode::Char('q') => Some(TuiAction::Quit),
+            _ => None,
+        }
     }
-    let tail = tail_override.or(config.tail).unwrap_or(1);
-    let once = once || config.once.unwrap_or(false);
-    let single_line = single_line || config.single_line.unwrap_or(false);
-    let tui = tui || config.tui.unwrap_or(false);
-    let window = CaptureWindow::from_overrides(tail_override.or(Some(tail)), head_override);
 
-    Ok(ResolvedConfig {
-        profile_id,
-        exec_command,
-        target_scope,
-        target_label,
-        explicit_targets,
-        file_sources: config.files.unwrap_or_default(),
-        iterations,
-        infinite,
-        has_prompt,
-        poll,
-        trigger_confirm_seconds,
-        log_preview_lines,
-        trigger_edge,
-        recheck_before_send,
-        fanout,
-        duration,
-        rule_eval,
-        rules,
-        delay,
-        prompt_placeholders,
-        template_vars: template_var_keys,
-        default_action,
-        logging,
-        capture_window: window,
-        once,
-        single_line,
-        tui,
-    })
-}
-
-fn print_validation(config: &ResolvedConfig) {
-    println!("Validation OK");
-    println!("- target: {}", config.target_label);
-    if let Some(command) = config.exec_command.as_deref() {
-        println!("- exec.command: {command}");
-    }
-    if !config.file_sources.is_empty() {
-        println!("- file_sources: {}", config.file_sources.join(", "));
+    fn shutdown(&mut self) -> Result<()> {
+        disable_raw_mode().context("failed to disable raw mode")?;
+        Ok(())
     }
-    if config.infinite {
-        println!("- iterations: infinite");
-    } else if let Some(iterations) = config.iterations {
-        println!("- iterations: {iterations}");
+
+    /// Leaves raw mode around `f` so it can run an editor or read stdin.
+    fn suspended<T>(&mut self, f: impl FnOnce() -> T) -> Result<T> {
+        disable_raw_mode().context("failed to disable raw mode")?;
+        let mut out = std::io::stdout();
+        let _ = out.queue(MoveTo(0, 0));
+        let _ = out.queue(Clear(ClearType::All));
+        let _ = out.flush();
+        let result = f();
+        enable_raw_mode().context("failed to enable raw mode")?;
+        Ok(result)
     }
-    println!("- prompt: {}", if config.has_prompt { "yes" } else { "no" });
-    if config.exec_command.is_none() {
-        println!("- rule_eval: {}", rule_eval_label(&config.rule_eval));
-        println!("- rules: {}", config.rules.len());
-        if let Some(delay) = &config.delay {
-            println!("- delay: {}", delay_summary(delay));
-        }
-        if !config.prompt_placeholders.is_empty() {
-            println!("- template vars: {}", config.prompt_placeholders.join(", "));
-        }
-        if !config.template_vars.is_empty() {
-            println!("- template_vars: {}", config.template_vars.join(", "));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ManualPrompt {
+    label: String,
+    prompt: String,
+}
+
+/// Picker entries: `default_action`, each rule action, then recent history prompts.
+fn manual_prompt_choices(config: &ResolvedConfig, history: &[HistoryEntry]) -> Vec<ManualPrompt> {
+    let mut choices = vec![ManualPrompt {
+        label: "default_action".to_string(),
+        prompt: build_prompt(&config.default_action),
+    }];
+    for rule in &config.rules {
+        if let Some(action) = rule.action.as_ref() {
+            choices.push(ManualPrompt {
+                label: format!("rule {}", rule.id.as_deref().unwrap_or("<unnamed>")),
+                prompt: build_prompt(action),
+            });
         }
     }
-    if let Some(path) = &config.logging.path {
-        println!(
-            "- logging: {} ({})",
-            path.display(),
-            log_format_label(config.logging.format)
-        );
-    } else {
-        println!(
-            "- logging: stdout ({})",
-            log_format_label(config.logging.format)
-        );
-    }
-    if config.exec_command.is_none() {
-        match config.capture_window {
-            CaptureWindow::Tail(lines) => println!("- tail: {lines}"),
-            CaptureWindow::Head(lines) => println!("- head: {lines}"),
+    for entry in history {
+        let prompt = build_prompt(&Action {
+            pre: entry.pre.clone().map(PromptBlock::Single),
+            prompt: Some(PromptBlock::Single(entry.prompt.clone())),
+            post: entry.post.clone().map(PromptBlock::Single),
+        });
+        if prompt.trim().is_empty() || choices.iter().any(|choice| choice.prompt == prompt) {
+            continue;
         }
-    }
-    println!("- poll: {}s", config.poll);
-    println!(
-        "- trigger_confirm_seconds: {}s",
-        config.trigger_confirm_seconds
-    );
-    println!("- log_preview_lines: {}", config.log_preview_lines);
-    println!(
-        "- trigger_edge: {}",
-        if config.trigger_edge { "yes" } else { "no" }
-    );
-    println!(
-        "- recheck_before_send: {}",
-        if config.recheck_before_send {
-            "yes"
-        } else {
-            "no"
+        choices.push(ManualPrompt {
+            label: format!("history {}", entry.last_run),
+            prompt,
+        });
+        if choices.len() >= config.rules.len() + 1 + MANUAL_HISTORY_CHOICES {
+            break;
         }
-    );
-    println!("- fanout: {}", fanout_label(config.fanout));
-    if let Some(duration) = config.duration {
-        println!("- duration: {}s", duration.as_secs_f64());
     }
-    println!("- once: {}", if config.once { "yes" } else { "no" });
-    println!(
-        "- single_line: {}",
-        if config.single_line { "yes" } else { "no" }
-    );
-    println!("- tui: {}", if config.tui { "yes" } else { "no" });
-    println!("- note: dry-run only, no tmux commands sent");
+    choices.retain(|choice| !choice.prompt.trim().is_empty());
+    choices
 }
 
-fn rule_eval_label(rule_eval: &RuleEval) -> &'static str {
-    match rule_eval {
-        RuleEval::FirstMatch => "first_match",
-        RuleEval::MultiMatch => "multi_match",
-        RuleEval::Priority => "priority",
+fn pick_manual_prompt(config: &ResolvedConfig) -> Result<Option<ManualPrompt>> {
+    let history = load_run_history().unwrap_or_default();
+    let choices = manual_prompt_choices(config, &history.entries);
+    println!("Send now (bypasses triggers, confirm window and delays):");
+    for (index, choice) in choices.iter().enumerate() {
+        let first_line = choice.prompt.lines().next().unwrap_or("");
+        println!(
+            "  {}. {}: {}",
+            index + 1,
+            choice.label,
+            truncate_text(first_line, 70, supports_unicode())
+        );
     }
+    println!("  {}. type a prompt", choices.len() + 1);
+    let Ok(index) = read_numbered_choice("Prompt", choices.len() + 1) else {
+        return Ok(None);
+    };
+    if let Some(choice) = choices.get(index) {
+        return Ok(Some(choice.clone()));
+    }
+    print!("Prompt text (empty to cancel): ");
+    let _ = std::io::stdout().flush();
+    let mut input = String::new();
+    std::io::stdin()
+        .read_line(&mut input)
+        .context("failed to read prompt")?;
+    let prompt = input.trim().to_string();
+    if prompt.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ManualPrompt {
+        label: "typed".to_string(),
+        prompt,
+    }))
+}
+
+/// The TUI `m` action: picks a prompt and sends it to the current recipients.
+fn tui_manual_send(
+    tui_state: &mut TuiState,
+    config: &ResolvedConfig,
+    panes: &mut PaneTable,
+    logger: &mut Logger,
+    recorder: Option<&mut SessionRecorder>,
+    send_count: &mut u32,
+) -> Result<()> {
+    let picked = tui_state.suspended(|| pick_manual_prompt(config))?;
+    let detail = match picked.and_then(|choice| {
+        choice
+            .map(|choice| {
+                send_manual_prompt(config, &choice, panes, logger, recorder, send_count)
+                    .map(|sent| (choice.label, sent))
+            })
+            .transpose()
+    }) {
+        Ok(Some((label, sent))) => format!(
+            "sent source=manual prompt=\"{}\" targets={}",
+            truncate_text(&label, 60, true),
+            sent.join(",")
+        ),
+        Ok(None) => "manual send cancelled".to_string(),
+        Err(err) => format!(
+            "manual send error=\"{}\"",
+            truncate_text(&err.to_string(), 100, true)
+        ),
+    };
+    tui_state.push_log(format!("[{}] {detail}", timestamp_now()));
+    Ok(())
 }
 
-fn fanout_label(mode: FanoutMode) -> &'static str {
-    match mode {
-        FanoutMode::Matched => "matched",
-        FanoutMode::Broadcast => "broadcast",
-    }
+/// The TUI `e` action: edits the active rule's action in `$EDITOR`.
+fn tui_edit_prompt(
+    tui_state: &mut TuiState,
+    config: &mut ResolvedConfig,
+    logger: &mut Logger,
+    active_rule: Option<&str>,
+) -> Result<()> {
+    let edited = tui_state.suspended(|| edit_action_interactive(config, active_rule))?;
+    let detail = match edited {
+        Ok(detail) => detail,
+        Err(err) => format!(
+            "edit error=\"{}\"",
+            truncate_text(&err.to_string(), 100, true)
+        ),
+    };
+    tui_state.push_log(format!("[{}] {detail}", timestamp_now()));
+    logger.log(LogEvent::status(config, detail))
 }
 
-fn log_format_label(format: LogFormatResolved) -> &'static str {
-    match format {
-        LogFormatResolved::Text => "text",
-        LogFormatResolved::Jsonl => "jsonl",
+/// Per-pane TUI actions (`H`/`N`/`S`) on the focused pane.
+fn tui_pane_control(
+    tui_state: &mut TuiState,
+    command: PaneCommand,
+    config: &ResolvedConfig,
+    panes: &mut PaneTable,
+    tracker: &mut TriggerTracker,
+    logger: &mut Logger,
+) -> Result<()> {
+    if let Some(target) = tui_state.focused_pane() {
+        let detail = panes.apply(command, &target, tracker);
+        tui_state.push_log(format!("[{}] {detail}", timestamp_now()));
+        logger.log(LogEvent::status(config, detail))?;
     }
+    Ok(())
 }
 
-fn delay_summary(delay: &DelayConfig) -> String {
-    match delay.mode {
-        DelayMode::Fixed => format!("fixed {}s", delay.value.unwrap_or(0)),
-        DelayMode::Range => {
-            let min = delay.min.unwrap_or(0);
-            let max = delay.max.unwrap_or(0);
-            format!("range {min}-{max}s")
-        }
-        DelayMode::Jitter => {
-            let min = delay.min.unwrap_or(0);
-            let max = delay.max.unwrap_or(0);
-            let jitter = delay.jitter.unwrap_or(0.0);
-            format!("jitter {min}-{max}s {jitter}")
+/// Sends the picked prompt to every current recipient. Each delivered send counts toward
+/// `send_count` and is recorded before a later recipient can fail.
+fn send_manual_prompt(
+    config: &ResolvedConfig,
+    choice: &ManualPrompt,
+    panes: &mut PaneTable,
+    logger: &mut Logger,
+    recorder: Option<&mut SessionRecorder>,
+    send_count: &mut u32,
+) -> Result<Vec<String>> {
+    let recipients = if let Some(explicit) = &config.explicit_targets {
+        explicit.clone()
+    } else {
+        select_targets_for_scope(&config.target_scope, &list_tmux_panes()?)
+    };
+    let recipients = recipients
+        .into_iter()
+        .filter(|target| !panes.is_held(target))
+        .collect::<Vec<_>>();
+    if recipients.is_empty() {
+        bail!("no recipients to send to");
+    }
+    let mut recorder = recorder;
+    for target in &recipients {
+        send_prompt(target, &choice.prompt, &config.submit_keys)
+            .with_context(|| format!("manual send to {target} failed"))?;
+        *send_count = send_count.saturating_add(1);
+        let timestamp = timestamp_now();
+        panes.record_send(target, &timestamp);
+        if let Some(recorder) = recorder.as_deref_mut() {
+            recorder.record_manual_send(target, &choice.prompt)?;
         }
-        DelayMode::Backoff => {
-            if let Some(backoff) = &delay.backoff {
-                let max = backoff.max.map_or(String::new(), |v| format!(", max {v}s"));
-                format!("backoff base {}s x{}{}", backoff.base, backoff.factor, max)
-            } else {
-                "backoff".to_string()
-            }
+        if config.capture == CaptureMode::SinceLastSend {
+            update_capture_mark(config, panes, logger, target)?;
         }
+        logger.log(LogEvent::sent_manual(
+            config,
+            target,
+            timestamp,
+            &choice.prompt,
+        ))?;
     }
+    Ok(recipients)
 }
 
-fn resolve_logging(config: Option<LoggingConfig>) -> LoggingConfigResolved {
-    let config = config.unwrap_or(LoggingConfig {
-        path: None,
-        format: None,
-    });
-    let format = match config.format.unwrap_or(LogFormat::Text) {
-        LogFormat::Text => LogFormatResolved::Text,
-        LogFormat::Jsonl => LogFormatResolved::Jsonl,
+/// Moves `source`'s `since_last_send` mark to its current output. The prompt already went out,
+/// so a failed tmux query only logs and keeps the previous mark.
+fn update_capture_mark(
+    config: &ResolvedConfig,
+    panes: &mut PaneTable,
+    logger: &mut Logger,
+    source: &str,
+) -> Result<()> {
+    let flags = PaneCaptureFlags {
+        ansi: config.capture_ansi,
+        join: config.capture_join,
     };
-    LoggingConfigResolved {
-        path: config.path,
-        format,
+    match output_mark(source, flags) {
+        Ok(mark) => panes.set_capture_mark(source, mark),
+        Err(err) => logger.log(LogEvent::error(
+            config,
+            format!("capture mark not updated for {source}: {err}"),
+        ))?,
     }
+    Ok(())
 }
 
-fn validate_rules(rules: &[Rule]) -> Result<()> {
-    let mut ids = HashSet::new();
-    let mut has_ids = false;
-    for (idx, rule) in rules.iter().enumerate() {
-        let id = rule.id.as_deref().unwrap_or("<unnamed>");
-        if let Some(id_value) = rule.id.as_ref() {
-            has_ids = true;
-            if !ids.insert(id_value.clone()) {
-                bail!("duplicate rule id: {id_value}");
-            }
-        }
-        let match_defined = rule.match_.as_ref().map(has_match).unwrap_or(false);
-        let exclude_defined = rule.exclude.as_ref().map(has_match).unwrap_or(false);
-        if !match_defined && !exclude_defined {
-            bail!("rule {idx} ({id}) requires match or exclude");
-        }
+/// Opens the active rule's action (or `default_action`) in `$VISUAL`/`$EDITOR`, applies
+/// the result to the running config and offers to write it back to the source YAML.
+fn edit_action_interactive(
+    config: &mut ResolvedConfig,
+    active_rule: Option<&str>,
+) -> Result<String> {
+    let rule_index = active_rule.and_then(|rule_id| {
+        config
+            .rules
+            .iter()
+            .position(|rule| rule.id.as_deref() == Some(rule_id) && rule.action.is_some())
+    });
+    let (label, yaml_path, current) = match rule_index {
+        Some(index) => (
+            format!("rule {}", active_rule.unwrap_or("<unnamed>")),
+            format!("rules[{index}].action"),
+            config.rules[index].action.clone().unwrap_or(Action {
+                pre: None,
+                prompt: None,
+                post: None,
+            }),
+        ),
+        None => (
+            "default_action".to_string(),
+            "default_action".to_string(),
+            config.default_action.clone(),
+        ),
+    };
+
+    let prompt_ref = config
+        .source_path
+        .as_deref()
+        .map(|source_path| source_action_ref(source_path, rule_index))
+        .transpose()?
+        .flatten();
+    if let Some(name) = prompt_ref {
+        bail!(
+            "{label} uses prompt ref `{name}`; edit the library entry with `loopmux prompts edit {name}`"
+        );
     }
-    if has_ids {
-        for (idx, rule) in rules.iter().enumerate() {
-            if let Some(next) = &rule.next {
-                if next == "stop" {
-                    continue;
-                }
-                if !ids.contains(next) {
-                    let id = rule.id.as_deref().unwrap_or("<unnamed>");
-                    bail!("rule {idx} ({id}) references unknown next: {next}");
-                }
-            }
-        }
+
+    let original = action_yaml(&current)?;
+    let path = std::env::temp_dir().join(format!("loopmux-edit-{}.yaml", std::process::id()));
+    std::fs::write(
+        &path,
+        format!(
+            "# loopmux: editing {label}\n# Save and quit to apply to the next sends; delete everything to cancel.\n{original}"
+        ),
+    )
+    .with_context(|| format!("failed to write {}", path.display()))?;
+    let edited = run_editor(&path).and_then(|()| {
+        std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))
+    });
+    let _ = std::fs::remove_file(&path);
+    let edited = edited?;
+    if edited
+        .lines()
+        .all(|line| line.trim().is_empty() || line.trim_start().starts_with('#'))
+    {
+        return Ok(format!("edit cancelled target={label}"));
     }
-    Ok(())
-}
+    let action: Action = parse_yaml_source(&edited, &path)?;
+    if action.prompt.is_none() {
+        bail!("edited {label} needs a prompt");
+    }
+    let updated = action_yaml(&action)?;
+    if updated == original {
+        return Ok(format!("edit unchanged target={label}"));
+    }
+    apply_edited_action(config, rule_index, &action, &yaml_path)?;
 
-fn has_match(criteria: &MatchCriteria) -> bool {
-    has_text(&criteria.regex)
-        || has_text(&criteria.trigger_expr)
-        || has_text(&criteria.exact_line)
-        || has_text(&criteria.contains)
-        || has_text(&criteria.starts_with)
+    let Some(source_path) = config.source_path.clone() else {
+        return Ok(format!("edit applied target={label} scope=run"));
+    };
+    // The edit is already live, so a failed write-back is reported rather than raised.
+    match write_back_action(&source_path, &yaml_path, &updated) {
+        Ok(true) => Ok(format!(
+            "edit applied target={label} scope=run+file path={}",
+            source_path.display()
+        )),
+        Ok(false) => Ok(format!("edit applied target={label} scope=run")),
+        Err(err) => Ok(format!(
+            "edit applied, not saved target={label} scope=run error=\"{err:#}\""
+        )),
+    }
 }
 
-fn has_text(value: &Option<String>) -> bool {
-    value
-        .as_ref()
-        .map(|text| !text.trim().is_empty())
-        .unwrap_or(false)
-}
-
-fn validate_tmux_scope(scope: &TargetScope) -> Result<()> {
-    let output = std::process::Command::new("tmux")
-        .arg("-V")
-        .output()
-        .context("failed to run tmux -V")?;
-    if !output.status.success() {
-        bail!("tmux not available on PATH");
+/// First prompt ref of the action as written in the config file. Refs are expanded in the
+/// running config, so saving an inline edit would replace the ref with a copy of its text.
+fn source_action_ref(source_path: &Path, rule_index: Option<usize>) -> Result<Option<String>> {
+    let contents = std::fs::read_to_string(source_path)
+        .with_context(|| format!("failed to read {}", source_path.display()))?;
+    let config: Config = parse_yaml_source(&contents, source_path)?;
+    let action = match rule_index {
+        Some(index) => config
+            .rules
+            .and_then(|rules| rules.into_iter().nth(index))
+            .and_then(|rule| rule.action),
+        None => config.default_action,
+    };
+    Ok(action.and_then(|action| {
+        [action.pre, action.prompt, action.post]
+            .into_iter()
+            .find_map(|block| match block {
+                Some(PromptBlock::Ref(prompt_ref)) => Some(prompt_ref.name),
+                _ => None,
+            })
+    }))
+}
+
+/// Shows the YAML diff for an edited action and writes it after confirmation.
+/// Returns whether the file was written.
+fn write_back_action(source_path: &Path, yaml_path: &str, updated: &str) -> Result<bool> {
+    let source = std::fs::read_to_string(source_path)
+        .with_context(|| format!("failed to read {}", source_path.display()))?;
+    let rewritten = splice_yaml_block(&source, yaml_path, updated)?;
+    println!("{}", source_path.display());
+    for line in line_diff(&source, &rewritten) {
+        println!("{line}");
+    }
+    print!("Write changes to {}? [y/N] ", source_path.display());
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("failed to read confirmation")?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return Ok(false);
     }
+    std::fs::write(source_path, rewritten)
+        .with_context(|| format!("failed to write {}", source_path.display()))?;
+    Ok(true)
+}
 
-    if matches!(scope, TargetScope::All) {
-        return Ok(());
+/// Expands and validates an edited action like config load does, then puts it in place.
+/// A rejected edit leaves the running config untouched.
+fn apply_edited_action(
+    config: &mut ResolvedConfig,
+    rule_index: Option<usize>,
+    action: &Action,
+    label: &str,
+) -> Result<()> {
+    let mut expanded = action.clone();
+    if action_has_prompt_refs(&expanded) {
+        expand_action_refs(
+            &mut expanded,
+            &load_prompt_library(config.source_path.as_deref())?,
+        )?;
+    }
+    validate_prompt_blocks(&expanded, label)?;
+    match rule_index {
+        Some(index) => config.rules[index].action = Some(expanded),
+        None => config.default_action = expanded,
     }
+    Ok(())
+}
 
-    let panes = list_tmux_panes()?;
-    let candidates = select_targets_for_scope(scope, &panes);
-    if candidates.is_empty() {
-        bail!("tmux target scope not found: {}", target_scope_label(scope));
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("failed to launch editor `{editor}`"))?;
+    if !status.success() {
+        bail!("editor `{editor}` exited with {status}");
     }
     Ok(())
 }
 
-fn validate_tmux_targets(targets: &[String]) -> Result<()> {
-    let panes = list_tmux_panes()?;
-    let available = panes
-        .iter()
-        .map(|pane| pane.target.as_str())
-        .collect::<HashSet<_>>();
-    for target in targets {
-        if !available.contains(target.as_str()) {
-            bail!("tmux target not found: {target}");
+/// Block-style YAML for an action, omitting unset blocks.
+fn action_yaml(action: &Action) -> Result<String> {
+    let mut mapping = serde_yaml::Mapping::new();
+    for (key, block) in [
+        ("pre", &action.pre),
+        ("prompt", &action.prompt),
+        ("post", &action.post),
+    ] {
+        if let Some(block) = block {
+            mapping.insert(
+                serde_yaml::Value::String(key.to_string()),
+                serde_yaml::to_value(block).context("failed to serialize prompt block")?,
+            );
         }
     }
-    Ok(())
+    serde_yaml::to_string(&mapping).context("failed to serialize action")
 }
 
-fn validate_file_sources(files: &[String]) -> Result<()> {
-    for file in files {
-        let path = PathBuf::from(file);
-        if !path.exists() {
-            bail!("file source not found: {}", path.display());
+/// Replaces the value under `yaml_path` (as indexed by `yaml_key_locations`) with `body`,
+/// leaving the rest of the file, comments included, untouched.
+fn splice_yaml_block(source: &str, yaml_path: &str, body: &str) -> Result<String> {
+    let Some(&(line, column)) = yaml_key_locations(source).get(yaml_path) else {
+        bail!("could not find `{yaml_path}` in block style; edit the file by hand");
+    };
+    let lines = source.lines().collect::<Vec<_>>();
+    let start = line - 1;
+    let indent = column - 1;
+    let mut end = start + 1;
+    let mut last_content = start + 1;
+    while end < lines.len() {
+        let raw = lines[end];
+        let trimmed = raw.trim_start();
+        if !trimmed.is_empty() {
+            if raw.len() - trimmed.len() <= indent {
+                break;
+            }
+            last_content = end + 1;
         }
-        if !path.is_file() {
-            bail!("file source is not a regular file: {}", path.display());
+        end += 1;
+    }
+    let key_line = lines[start];
+    let key = key_line[..key_line.find(':').unwrap_or(key_line.len())].to_string();
+    let mut replaced = lines[..start]
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    replaced.push(format!("{key}:"));
+    let pad = " ".repeat(indent + 2);
+    replaced.extend(body.lines().map(|line| format!("{pad}{line}")));
+    replaced.extend(lines[last_content..].iter().map(|line| line.to_string()));
+    let mut rewritten = replaced.join("\n");
+    if source.ends_with('\n') {
+        rewritten.push('\n');
+    }
+    Ok(rewritten)
+}
+
+/// Minimal line diff (`-`/`+` with `@@ line N` hunk headers) for confirmation prompts.
+fn line_diff(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
         }
-        std::fs::read_to_string(&path)
-            .with_context(|| format!("failed to read file source: {}", path.display()))?;
     }
-    Ok(())
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            in_hunk = false;
+            i += 1;
+            j += 1;
+            continue;
+        }
+        if !in_hunk {
+            diff.push(format!("@@ line {}", i + 1));
+            in_hunk = true;
+        }
+        if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            diff.push(format!("+{}", new[j]));
+            j += 1;
+        } else {
+            diff.push(format!("-{}", old[i]));
+            i += 1;
+        }
+    }
+    diff
 }
 
-fn file_source_key(path: &str) -> String {
-    format!("file://{path}")
+/// Per-pane bookkeeping for multi-target runs, shown as the TUI pane table.
+#[derive(Debug, Default)]
+struct PaneTable {
+    panes: std::collections::BTreeMap<String, PaneState>,
 }
 
-fn file_source_path(key: &str) -> Option<&str> {
-    key.strip_prefix("file://")
+#[derive(Debug, Default)]
+struct PaneState {
+    held: bool,
+    skip_next: bool,
+    last_rule: Option<String>,
+    sends: u32,
+    last_send: Option<String>,
+    delay_until: Option<std::time::Instant>,
+    /// Output position at the last send, for `capture: since_last_send`.
+    capture_mark: Option<CaptureMark>,
 }
 
-fn list_tmux_panes() -> Result<Vec<TmuxPane>> {
-    let output = std::process::Command::new("tmux")
-        .args([
-            "list-panes",
-            "-a",
-            "-F",
-            "#{session_name}\t#{window_index}\t#{pane_index}\t#{session_name}:#{window_index}.#{pane_index}",
-        ])
-        .output()
-        .context("failed to run tmux list-panes")?;
-    if !output.status.success() {
-        bail!("tmux list-panes failed");
-    }
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PaneRow {
+    target: String,
+    last_rule: Option<String>,
+    edge: &'static str,
+    confirm_seconds: Option<u64>,
+    delay_seconds: Option<u64>,
+    sends: u32,
+    last_send: Option<String>,
+    held: bool,
+    skip_next: bool,
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut panes = Vec::new();
-    for line in stdout.lines() {
-        let mut parts = line.split('\t');
-        let session = parts.next().unwrap_or("").trim();
-        let window = parts.next().unwrap_or("").trim();
-        let _pane = parts.next().unwrap_or("").trim();
-        let target = parts.next().unwrap_or("").trim();
-        if session.is_empty() || window.is_empty() || target.is_empty() {
-            continue;
+impl PaneTable {
+    fn sync(&mut self, targets: &[String]) {
+        self.panes.retain(|target, _| targets.contains(target));
+        for target in targets {
+            self.panes.entry(target.clone()).or_default();
         }
-        panes.push(TmuxPane {
-            target: target.to_string(),
-            session: session.to_string(),
-            window: window.to_string(),
-        });
     }
-    Ok(panes)
-}
-
-fn resolve_target(target: &str) -> Result<String> {
-    resolve_target_with_current(target, tmux_current_target)
-}
 
-fn resolve_target_offline(target: &str) -> Result<String> {
-    if target.contains(':') {
-        return Ok(target.to_string());
+    fn is_held(&self, target: &str) -> bool {
+        self.panes.get(target).is_some_and(|pane| pane.held)
     }
-    bail!("target shorthand requires tmux; use session:window.pane")
-}
-
-fn resolve_target_scope(target: Option<&str>) -> Result<(TargetScope, String)> {
-    resolve_target_scope_with(target, resolve_target)
-}
 
-fn resolve_target_scope_offline(target: Option<&str>) -> Result<(TargetScope, String)> {
-    resolve_target_scope_with(target, resolve_target_offline)
-}
+    fn take_skip(&mut self, target: &str) -> bool {
+        self.panes
+            .get_mut(target)
+            .is_some_and(|pane| std::mem::take(&mut pane.skip_next))
+    }
 
-fn resolve_explicit_targets(targets: &[String], skip_tmux: bool) -> Result<Vec<String>> {
-    let mut resolved = Vec::with_capacity(targets.len());
-    for target in targets {
-        let candidate = if skip_tmux {
-            resolve_target_offline(target)?
-        } else {
-            resolve_target(target)?
-        };
-        parse_target(&candidate)?;
-        resolved.push(candidate);
+    fn record_match(&mut self, target: &str, rule_id: Option<&str>) {
+        if let Some(pane) = self.panes.get_mut(target) {
+            pane.last_rule = Some(rule_id.unwrap_or("<unnamed>").to_string());
+        }
     }
-    Ok(dedupe_preserve_order(resolved))
-}
 
-fn resolve_target_scope_with(
-    target: Option<&str>,
-    pane_resolver: fn(&str) -> Result<String>,
-) -> Result<(TargetScope, String)> {
-    let Some(raw) = target
-        .map(|value| value.trim())
-        .filter(|value| !value.is_empty())
-    else {
-        return Ok((TargetScope::All, "all sessions/windows/panes".to_string()));
-    };
+    fn record_send(&mut self, target: &str, timestamp: &str) {
+        if let Some(pane) = self.panes.get_mut(target) {
+            pane.sends = pane.sends.saturating_add(1);
+            pane.last_send = Some(short_clock(timestamp));
+        }
+    }
 
-    if raw.eq_ignore_ascii_case("all") {
-        return Ok((TargetScope::All, "all sessions/windows/panes".to_string()));
+    fn capture_mark(&self, target: &str) -> Option<CaptureMark> {
+        self.panes
+            .get(target)
+            .and_then(|pane| pane.capture_mark.clone())
     }
 
-    if raw.contains(':') {
-        if raw.contains('.') {
-            let resolved = pane_resolver(raw)?;
-            parse_target(&resolved)?;
-            return Ok((TargetScope::Pane(resolved.clone()), resolved));
+    fn set_capture_mark(&mut self, target: &str, mark: CaptureMark) {
+        if let Some(pane) = self.panes.get_mut(target) {
+            pane.capture_mark = Some(mark);
         }
+    }
 
-        let (session, window) = parse_session_window(raw)?;
-        let label = format!("{session}:{window}.*");
-        return Ok((
-            TargetScope::Window {
-                session: session.to_string(),
-                window: window.to_string(),
-            },
-            label,
-        ));
+    fn set_delay(&mut self, target: &str, until: Option<std::time::Instant>) {
+        if let Some(pane) = self.panes.get_mut(target) {
+            pane.delay_until = until;
+        }
     }
 
-    if raw.contains('.') || raw.chars().all(|c| c.is_ascii_digit()) {
-        let resolved = pane_resolver(raw)?;
-        parse_target(&resolved)?;
-        return Ok((TargetScope::Pane(resolved.clone()), resolved));
+    /// Applies a per-pane control and returns the log detail.
+    fn apply(
+        &mut self,
+        command: PaneCommand,
+        target: &str,
+        tracker: &mut TriggerTracker,
+    ) -> String {
+        let pane = self.panes.entry(target.to_string()).or_default();
+        match command {
+            PaneCommand::Hold => {
+                pane.held = !pane.held;
+                let state = if pane.held { "hold" } else { "resume" };
+                format!("pane {state} target={target}")
+            }
+            PaneCommand::Next => {
+                pane.skip_next = false;
+                tracker.reset_target(target);
+                format!("pane next target={target}")
+            }
+            PaneCommand::Skip => {
+                pane.skip_next = !pane.skip_next;
+                let state = if pane.skip_next { "armed" } else { "cleared" };
+                format!("pane skip {state} target={target}")
+            }
+        }
     }
 
-    Ok((TargetScope::Session(raw.to_string()), format!("{raw}:*.*")))
+    fn rows(
+        &self,
+        tracker: &TriggerTracker,
+        config: &ResolvedConfig,
+        now: std::time::Instant,
+    ) -> Vec<PaneRow> {
+        self.panes
+            .iter()
+            .map(|(target, pane)| PaneRow {
+                target: target.clone(),
+                last_rule: pane.last_rule.clone(),
+                edge: tracker.edge_state(target, config.trigger_edge),
+                confirm_seconds: tracker.confirm_remaining(config, target, now),
+                delay_seconds: pane
+                    .delay_until
+                    .map(|until| until.saturating_duration_since(now).as_secs()),
+                sends: pane.sends,
+                last_send: pane.last_send.clone(),
+                held: pane.held,
+                skip_next: pane.skip_next,
+            })
+            .collect()
+    }
 }
 
-fn parse_session_window(value: &str) -> Result<(&str, &str)> {
-    let mut parts = value.splitn(2, ':');
-    let session = parts.next().unwrap_or("").trim();
-    let window = parts.next().unwrap_or("").trim();
-    if session.is_empty() || window.is_empty() {
-        bail!("target must be in the format session, session:window, or session:window.pane");
-    }
-    Ok((session, window))
+/// `HH:MM:SS` from an RFC3339 timestamp, or the input unchanged.
+fn short_clock(timestamp: &str) -> String {
+    timestamp
+        .split_once('T')
+        .map(|(_, time)| time.chars().take(8).collect())
+        .unwrap_or_else(|| timestamp.to_string())
 }
 
-fn select_targets_for_scope(scope: &TargetScope, panes: &[TmuxPane]) -> Vec<String> {
-    panes
+fn render_pane_table(
+    rows: &[PaneRow],
+    focused: Option<&str>,
+    width: usize,
+    max_rows: usize,
+    use_unicode: bool,
+) -> Vec<String> {
+    if max_rows < 2 || width < 40 {
+        return Vec::new();
+    }
+    let target_width = rows
         .iter()
-        .filter(|pane| match scope {
-            TargetScope::All => true,
-            TargetScope::Session(session) => &pane.session == session,
-            TargetScope::Window { session, window } => {
-                &pane.session == session && &pane.window == window
-            }
-            TargetScope::Pane(target) => &pane.target == target,
-        })
-        .map(|pane| pane.target.clone())
-        .collect()
+        .map(|row| row.target.chars().count())
+        .max()
+        .unwrap_or(6)
+        .clamp(6, 24);
+    let mut lines = vec![fit_line(
+        &format!(
+            "  {:<target_width$} {:<12} {:<5} {:>7} {:>5} {:>5} {:<8}",
+            "target", "rule", "edge", "confirm", "delay", "sends", "last"
+        ),
+        width,
+        use_unicode,
+    )];
+    for row in rows.iter().take(max_rows - 1) {
+        let marker = if focused == Some(row.target.as_str()) {
+            '>'
+        } else {
+            ' '
+        };
+        let flag = if row.held {
+            "H"
+        } else if row.skip_next {
+            "S"
+        } else {
+            " "
+        };
+        let rule = truncate_text(row.last_rule.as_deref().unwrap_or("-"), 12, use_unicode);
+        let target = truncate_text(&row.target, target_width, use_unicode);
+        let confirm = row
+            .confirm_seconds
+            .map(|value| format!("{value}s"))
+            .unwrap_or_else(|| "-".to_string());
+        let delay = row
+            .delay_seconds
+            .map(|value| format!("{value}s"))
+            .unwrap_or_else(|| "-".to_string());
+        lines.push(fit_line(
+            &format!(
+                "{marker}{flag}{target:<target_width$} {rule:<12} {:<5} {confirm:>7} {delay:>5} {:>5} {:<8}",
+                row.edge,
+                row.sends,
+                row.last_send.as_deref().unwrap_or("-")
+            ),
+            width,
+            use_unicode,
+        ));
+    }
+    lines
 }
 
-fn target_scope_label(scope: &TargetScope) -> String {
-    match scope {
-        TargetScope::All => "all sessions/windows/panes".to_string(),
-        TargetScope::Session(session) => format!("{session}:*.*"),
-        TargetScope::Window { session, window } => format!("{session}:{window}.*"),
-        TargetScope::Pane(target) => target.clone(),
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PreviewLayout {
+    Hidden,
+    /// Too short for a panel: one folded line above the footer.
+    Inline,
+    Stacked {
+        log_rows: usize,
+    },
+    Split {
+        log_cols: u16,
+    },
 }
 
-fn resolve_target_with_current(target: &str, current_fn: fn() -> Result<String>) -> Result<String> {
-    if target.contains(':') {
-        return Ok(target.to_string());
+fn preview_layout(width: u16, body_height: usize, enabled: bool) -> PreviewLayout {
+    if !enabled {
+        return PreviewLayout::Hidden;
     }
-
-    let current = current_fn()
-        .map_err(|_| anyhow::anyhow!("target shorthand requires tmux; use session:window.pane"))?;
-    let (session, window, _pane) = parse_target(&current)?;
-
-    if target.contains('.') {
-        return Ok(format!("{session}:{target}"));
+    if width >= 120 && body_height >= 4 {
+        return PreviewLayout::Split {
+            log_cols: width * 2 / 5,
+        };
     }
-
-    if target.chars().all(|c| c.is_ascii_digit()) {
-        return Ok(format!("{session}:{window}.{target}"));
+    if body_height >= 10 {
+        return PreviewLayout::Stacked {
+            log_rows: (body_height / 3).max(3),
+        };
     }
-
-    bail!("invalid target format: {target}");
+    PreviewLayout::Inline
 }
 
-fn tmux_current_target() -> Result<String> {
-    let output = std::process::Command::new("tmux")
-        .args([
-            "display-message",
-            "-p",
-            "#{session_name}:#{window_index}.#{pane_index}",
-        ])
-        .output()
-        .context("failed to query current tmux target")?;
-    if !output.status.success() {
-        bail!("tmux not available for target shorthand");
+/// Last `rows` lines of the capture, shifted up by `scroll`; trailing blank rows are dropped.
+fn preview_window_lines(content: &str, rows: usize, scroll: usize) -> Vec<&str> {
+    let mut lines = content.lines().collect::<Vec<_>>();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
     }
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    let end = lines
+        .len()
+        .saturating_sub(scroll)
+        .max(rows.min(lines.len()));
+    let start = end.saturating_sub(rows);
+    lines[start..end].to_vec()
 }
 
-fn parse_target(target: &str) -> Result<(&str, &str, &str)> {
-    let mut parts = target.splitn(2, ':');
-    let session = parts.next().unwrap_or("");
-    let rest = parts.next().unwrap_or("");
-    if session.trim().is_empty() || rest.trim().is_empty() {
-        bail!("target must be in the format session:window.pane");
-    }
+fn preview_highlight_criteria<'a>(
+    config: &'a ResolvedConfig,
+    rule_id: Option<&str>,
+) -> Vec<&'a MatchCriteria> {
+    let active = config
+        .rules
+        .iter()
+        .filter(|rule| rule_id.is_some() && rule.id.as_deref() == rule_id)
+        .collect::<Vec<_>>();
+    let rules = if active.is_empty() {
+        config.rules.iter().collect()
+    } else {
+        active
+    };
+    rules
+        .into_iter()
+        .filter_map(|rule| rule.match_.as_ref())
+        .collect()
+}
 
-    let mut rest_parts = rest.splitn(2, '.');
-    let window = rest_parts.next().unwrap_or("");
-    let pane = rest_parts.next().unwrap_or("");
-    if window.trim().is_empty() || pane.trim().is_empty() {
-        bail!("target must be in the format session:window.pane");
+/// Preview highlight matchers compiled once from the active rule's criteria.
+#[derive(Debug, Default)]
+struct PreviewHighlighter {
+    regexes: Vec<Regex>,
+    atoms: Vec<TriggerAtom>,
+    exact_lines: Vec<String>,
+    contains: Vec<String>,
+    starts_with: Vec<String>,
+}
+
+impl PreviewHighlighter {
+    fn new(criteria: &[&MatchCriteria]) -> Self {
+        let mut highlighter = Self::default();
+        for criteria in criteria {
+            highlighter.regexes.extend(
+                criteria
+                    .regex
+                    .as_deref()
+                    .and_then(|value| Regex::new(value).ok()),
+            );
+            highlighter.atoms.extend(
+                criteria
+                    .trigger_expr
+                    .as_deref()
+                    .and_then(|value| parse_trigger_expr(value).ok())
+                    .map(|expr| expr.terms)
+                    .unwrap_or_default(),
+            );
+            highlighter.exact_lines.extend(
+                criteria
+                    .exact_line
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string),
+            );
+            highlighter
+                .contains
+                .extend(criteria.contains.clone().filter(|value| !value.is_empty()));
+            highlighter.starts_with.extend(
+                criteria
+                    .starts_with
+                    .clone()
+                    .filter(|value| !value.is_empty()),
+            );
+        }
+        highlighter
     }
 
-    Ok((session, window, pane))
-}
-
-fn validate_delay(delay: &DelayConfig) -> Result<()> {
-    match delay.mode {
-        DelayMode::Fixed => {
-            if delay.value.unwrap_or(0) == 0 {
-                bail!("delay.mode=fixed requires value > 0");
-            }
+    /// Byte ranges of `line` matched by any criteria, sorted and merged.
+    fn ranges(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        for regex in &self.regexes {
+            ranges.extend(
+                regex
+                    .find_iter(line)
+                    .map(|found| (found.start(), found.end())),
+            );
         }
-        DelayMode::Range | DelayMode::Jitter => {
-            let min = delay.min.unwrap_or(0);
-            let max = delay.max.unwrap_or(0);
-            if min == 0 || max == 0 || min > max {
-                bail!("delay.mode range/jitter requires min/max with min <= max and > 0");
-            }
-            if let DelayMode::Jitter = delay.mode {
-                let jitter = delay.jitter.unwrap_or(0.0);
-                if !(0.0..=1.0).contains(&jitter) {
-                    bail!("delay.mode=jitter requires jitter between 0.0 and 1.0");
-                }
-            }
+        for atom in &self.atoms {
+            ranges.extend(atom.highlight_ranges(line));
         }
-        DelayMode::Backoff => {
-            let backoff = delay
-                .backoff
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("delay.mode=backoff requires backoff"))?;
-            if backoff.base == 0 {
-                bail!("delay.backoff.base must be > 0");
-            }
-            if backoff.factor < 1.0 {
-                bail!("delay.backoff.factor must be >= 1.0");
+        let trimmed = line.trim();
+        if self.exact_lines.iter().any(|expected| trimmed == expected) {
+            let start = line.len() - line.trim_start().len();
+            ranges.push((start, start + trimmed.len()));
+        }
+        for needle in &self.contains {
+            ranges.extend(
+                line.match_indices(needle.as_str())
+                    .map(|(start, found)| (start, start + found.len())),
+            );
+        }
+        for prefix in &self.starts_with {
+            if line.starts_with(prefix.as_str()) {
+                ranges.push((0, prefix.len()));
             }
-            if let Some(max) = backoff.max {
-                if max < backoff.base {
-                    bail!("delay.backoff.max must be >= base");
-                }
+        }
+        ranges.retain(|(start, end)| start < end);
+        ranges.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
             }
         }
+        merged
     }
-    Ok(())
 }
 
-#[derive(Debug, Serialize)]
-struct LogEvent {
-    event: String,
-    timestamp: String,
-    target: String,
-    rule_id: Option<String>,
-    detail: Option<String>,
-    sends: Option<u32>,
+fn paint_ranges(line: &str, ranges: &[(usize, usize)], prefix: &str) -> String {
+    let mut painted = String::with_capacity(line.len());
+    let mut cursor = 0;
+    for (start, end) in ranges {
+        painted.push_str(&line[cursor..*start]);
+        painted.push_str(prefix);
+        painted.push_str(&line[*start..*end]);
+        painted.push_str("\x1B[0m");
+        cursor = *end;
+    }
+    painted.push_str(&line[cursor..]);
+    painted
 }
 
-impl LogEvent {
-    fn started(config: &ResolvedConfig, timestamp: String) -> Self {
-        Self {
-            event: "started".to_string(),
-            timestamp,
-            target: config.target_label.clone(),
-            rule_id: None,
-            detail: None,
-            sends: None,
-        }
+fn layout_mode(width: u16, layout: UiLayout) -> LayoutMode {
+    if width <= layout.compact_max.unwrap_or(80) {
+        LayoutMode::Compact
+    } else if width <= layout.standard_max.unwrap_or(120) {
+        LayoutMode::Standard
+    } else {
+        LayoutMode::Wide
     }
+}
 
-    fn sent(
-        config: &ResolvedConfig,
-        rule_id: Option<&str>,
-        timestamp: String,
-        prompt: &str,
-    ) -> Self {
-        Self {
-            event: "sent".to_string(),
-            timestamp,
-            target: config.target_label.clone(),
-            rule_id: rule_id.map(|value| value.to_string()),
-            detail: Some(prompt.to_string()),
-            sends: None,
-        }
+fn detect_icon_mode() -> IconMode {
+    if std::env::var("LOOPMUX_NO_NERD_FONT").is_ok() {
+        return IconMode::Ascii;
     }
+    IconMode::Nerd
+}
 
-    fn delay_scheduled(config: &ResolvedConfig, rule_id: Option<&str>, detail: String) -> Self {
-        Self {
-            event: "delay".to_string(),
-            timestamp: String::new(),
-            target: config.target_label.clone(),
-            rule_id: rule_id.map(|value| value.to_string()),
-            detail: Some(detail),
-            sends: None,
-        }
+fn detect_style() -> StyleConfig {
+    let no_color = std::env::var("NO_COLOR").is_ok();
+    let term = std::env::var("TERM").unwrap_or_default();
+    let color_term = std::env::var("COLORTERM").unwrap_or_default();
+    let use_color = !no_color && term != "dumb";
+    let use_bg = use_color && (term.contains("256color") || !color_term.is_empty());
+    let use_unicode_ellipsis = supports_unicode();
+    let dim_logs = std::env::var("LOOPMUX_TUI_BRIGHT_LOGS").is_err();
+    StyleConfig {
+        use_color,
+        use_bg,
+        use_unicode_ellipsis,
+        dim_logs,
+        palette: UiColors::default(),
     }
+}
 
-    fn stopped(config: &ResolvedConfig, detail: &str, sends: u32) -> Self {
-        Self {
-            event: "stopped".to_string(),
-            timestamp: String::new(),
-            target: config.target_label.clone(),
-            rule_id: None,
-            detail: Some(detail.to_string()),
-            sends: Some(sends),
-        }
-    }
+fn supports_unicode() -> bool {
+    let locale = std::env::var("LC_ALL")
+        .or_else(|_| std::env::var("LC_CTYPE"))
+        .or_else(|_| std::env::var("LANG"))
+        .unwrap_or_default();
+    let locale = locale.to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
 
-    fn matched(config: &ResolvedConfig, rule_id: Option<&str>) -> Self {
-        Self {
-            event: "match".to_string(),
-            timestamp: String::new(),
-            target: config.target_label.clone(),
-            rule_id: rule_id.map(|value| value.to_string()),
-            detail: None,
-            sends: None,
+/// Remappable run TUI actions: name, default key, help text.
+const RUN_KEY_ACTIONS: &[(&str, &str, &str)] = &[
+    ("hold", "h", "hold/resume run"),
+    ("pause", "p", "hold run"),
+    ("resume", "r", "resume run"),
+    ("fleet", "f", "open fleet manager"),
+    ("renew", "R", "renew send counter"),
+    ("next", "n", "reset trigger state"),
+    ("edit", "e", "edit active prompt in $EDITOR"),
+    ("send", "m", "manual send"),
+    ("preview", "v", "show/hide pane preview"),
+    ("panes", "t", "show/hide pane table"),
+    ("pane_hold", "H", "hold/resume focused pane"),
+    ("pane_next", "N", "reset focused pane"),
+    ("pane_skip", "S", "skip focused pane's next send"),
+    ("stop", "s", "stop run"),
+    ("quit", "q", "quit run view"),
+    ("help", "?", "show/hide this help"),
+];
+
+/// Remappable fleet manager actions: name, default key, help text.
+const FLEET_KEY_ACTIONS: &[(&str, &str, &str)] = &[
+    ("select_prev", "<", "select previous run"),
+    ("select_next", ">", "select next run"),
+    ("mark", "space", "mark/unmark run"),
+    ("clear_marks", "a", "clear marks"),
+    ("stale", "x", "show/hide stale runs"),
+    ("mismatch", "v", "mismatch-only filter"),
+    ("state_filter", "f", "cycle state filter"),
+    ("sort", "o", "cycle sort"),
+    ("preset", "p", "cycle presets"),
+    ("preset_1", "1", "default preset"),
+    ("preset_2", "2", "needs-attention preset"),
+    ("preset_3", "3", "mismatch-only preset"),
+    ("preset_4", "4", "holding-focus preset"),
+    ("search", "/", "search"),
+    ("stop", "s", "arm stop"),
+    ("bulk_stop", "S", "arm bulk stop"),
+    ("bulk_hold", "H", "arm bulk hold"),
+    ("bulk_resume", "P", "arm bulk resume"),
+    ("bulk_next", "N", "arm bulk next"),
+    ("bulk_renew", "U", "arm bulk renew"),
+    ("confirm", "enter", "confirm action / jump"),
+    ("cancel", "c", "cancel pending action"),
+    ("copy_id", "i", "copy run id"),
+    ("copy_stop", "y", "copy stop command"),
+    ("hold", "h", "hold run"),
+    ("resume", "r", "resume run"),
+    ("next", "n", "next"),
+    ("renew", "R", "renew"),
+    ("quit", "q", "quit manager"),
+    ("help", "?", "show/hide this help"),
+];
+
+/// Resolved `ui:` settings for the run and fleet TUIs.
+#[derive(Debug, Clone)]
+struct UiSettings {
+    colors: UiColors,
+    icon_mode: IconMode,
+    layout: UiLayout,
+    run_keys: KeyBindings,
+    fleet_keys: KeyBindings,
+}
+
+#[derive(Debug, Clone)]
+struct KeyBindings {
+    bindings: Vec<KeyBinding>,
+}
+
+#[derive(Debug, Clone)]
+struct KeyBinding {
+    action: &'static str,
+    help: &'static str,
+    default: KeyCode,
+    key: KeyCode,
+}
+
+impl KeyBindings {
+    fn new(
+        scope: &str,
+        table: &[(&'static str, &'static str, &'static str)],
+        overrides: Option<&BTreeMap<String, String>>,
+    ) -> Result<Self> {
+        let known = table
+            .iter()
+            .map(|(action, _, _)| action.to_string())
+            .collect::<Vec<_>>();
+        if let Some(overrides) = overrides {
+            for action in overrides.keys() {
+                if !known.contains(action) {
+                    match suggest_key(action, &known) {
+                        Some(suggestion) => bail!(
+                            "ui.keys.{scope}: unknown action `{action}` (did you mean `{suggestion}`?)"
+                        ),
+                        None => bail!("ui.keys.{scope}: unknown action `{action}`"),
+                    }
+                }
+            }
         }
+        let mut bindings: Vec<KeyBinding> = Vec::new();
+        for (action, default, help) in table {
+            let default = parse_key_spec(default)?;
+            let key = match overrides.and_then(|overrides| overrides.get(*action)) {
+                Some(spec) => {
+                    parse_key_spec(spec).with_context(|| format!("ui.keys.{scope}.{action}"))?
+                }
+                None => default,
+            };
+            if let Some(existing) = bindings.iter().find(|binding| binding.key == key) {
+                bail!(
+                    "ui.keys.{scope}: `{}` is bound to both `{}` and `{action}`",
+                    key_label(key),
+                    existing.action
+                );
+            }
+            bindings.push(KeyBinding {
+                action,
+                help,
+                default,
+                key,
+            });
+        }
+        Ok(Self { bindings })
     }
 
-    fn error(config: &ResolvedConfig, detail: String) -> Self {
-        Self {
-            event: "error".to_string(),
-            timestamp: String::new(),
-            target: config.target_label.clone(),
-            rule_id: None,
-            detail: Some(detail),
-            sends: None,
+    /// Maps a pressed key to the default key of the action bound to it. Default keys
+    /// that were remapped away are swallowed; keys outside the table pass through.
+    fn translate(&self, code: KeyCode) -> Option<KeyCode> {
+        if let Some(binding) = self.bindings.iter().find(|binding| binding.key == code) {
+            return Some(binding.default);
+        }
+        if self.bindings.iter().any(|binding| binding.default == code) {
+            return None;
         }
+        Some(code)
     }
 
-    fn status(config: &ResolvedConfig, detail: String) -> Self {
-        Self {
-            event: "status".to_string(),
-            timestamp: String::new(),
-            target: config.target_label.clone(),
-            rule_id: None,
-            detail: Some(detail),
-            sends: None,
-        }
+    fn key(&self, action: &str) -> String {
+        self.bindings
+            .iter()
+            .find(|binding| binding.action == action)
+            .map(|binding| key_label(binding.key))
+            .unwrap_or_default()
     }
 
-    fn exec(config: &ResolvedConfig, event: &str, detail: String) -> Self {
-        Self {
-            event: event.to_string(),
-            timestamp: String::new(),
-            target: config.target_label.clone(),
-            rule_id: None,
-            detail: Some(detail),
-            sends: None,
-        }
+    fn help_lines(&self) -> Vec<String> {
+        self.bindings
+            .iter()
+            .map(|binding| {
+                format!(
+                    "{:>7}  {:<12} {}",
+                    key_label(binding.key),
+                    binding.action,
+                    binding.help
+                )
+            })
+            .collect()
     }
 }
 
-struct Logger {
-    config: LoggingConfigResolved,
-    file: Option<std::fs::File>,
+fn parse_key_spec(spec: &str) -> Result<KeyCode> {
+    let mut chars = spec.chars();
+    if let (Some(single), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(single));
+    }
+    let lower = spec.to_ascii_lowercase();
+    Ok(match lower.as_str() {
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => match lower
+            .strip_prefix('f')
+            .and_then(|number| number.parse::<u8>().ok())
+        {
+            Some(number @ 1..=12) => KeyCode::F(number),
+            _ => bail!(
+                "unknown key `{spec}` (use one character, space, enter, esc, tab, arrows, or f1-f12)"
+            ),
+        },
+    })
 }
 
-impl Logger {
-    fn new(config: LoggingConfigResolved) -> Result<Self> {
-        let file = if let Some(path) = &config.path {
-            Some(
-                std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(path)
-                    .with_context(|| format!("failed to open log file {}", path.display()))?,
-            )
-        } else {
-            None
-        };
-        Ok(Self { config, file })
-    }
+fn key_label(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(value) => value.to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::PageUp => "pageup".to_string(),
+        KeyCode::PageDown => "pagedown".to_string(),
+        KeyCode::F(number) => format!("f{number}"),
+        other => format!("{other:?}").to_lowercase(),
+    }
+}
+
+fn ui_config_path() -> Option<PathBuf> {
+    let home = std::env::var("HOME").ok()?;
+    Some(
+        PathBuf::from(home)
+            .join(".config")
+            .join("loopmux")
+            .join("ui.yaml"),
+    )
+}
 
-    fn log(&mut self, mut event: LogEvent) -> Result<()> {
-        let timestamp = OffsetDateTime::now_utc()
-            .format(&time::format_description::well_known::Rfc3339)
-            .unwrap_or_else(|_| "unknown".into());
-        if event.timestamp.is_empty() {
-            event.timestamp = timestamp;
+/// `~/.config/loopmux/ui.yaml` overlaid with the run config's `ui:` section.
+fn load_ui_settings(config_ui: Option<&UiConfig>) -> Result<UiSettings> {
+    let mut ui = match ui_config_path().filter(|path| path.exists()) {
+        Some(path) => {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            if contents.trim().is_empty() {
+                UiConfig::default()
+            } else {
+                parse_yaml_source::<UiConfig>(&contents, &path)?
+            }
         }
-        match self.config.format {
-            LogFormatResolved::Text => self.log_text(&event),
-            LogFormatResolved::Jsonl => self.log_json(&event),
+        None => UiConfig::default(),
+    };
+    if let Some(overlay) = config_ui {
+        ui = merge_ui_config(ui, overlay);
+    }
+    build_ui_settings(&ui)
+}
+
+fn merge_ui_config(base: UiConfig, overlay: &UiConfig) -> UiConfig {
+    let colors = match (base.colors, overlay.colors) {
+        (Some(base), Some(overlay)) => Some(UiColors {
+            running: overlay.running.or(base.running),
+            holding: overlay.holding.or(base.holding),
+            waiting: overlay.waiting.or(base.waiting),
+            delay: overlay.delay.or(base.delay),
+            sending: overlay.sending.or(base.sending),
+            error: overlay.error.or(base.error),
+            stopped: overlay.stopped.or(base.stopped),
+        }),
+        (base, overlay) => overlay.or(base),
+    };
+    let layout = match (base.layout, overlay.layout) {
+        (Some(base), Some(overlay)) => Some(UiLayout {
+            compact_max: overlay.compact_max.or(base.compact_max),
+            standard_max: overlay.standard_max.or(base.standard_max),
+        }),
+        (base, overlay) => overlay.or(base),
+    };
+    let merge_keys = |base: Option<BTreeMap<String, String>>,
+                      overlay: Option<&BTreeMap<String, String>>| {
+        match (base, overlay) {
+            (Some(mut base), Some(overlay)) => {
+                base.extend(overlay.clone());
+                Some(base)
+            }
+            (base, overlay) => overlay.cloned().or(base),
         }
+    };
+    let base_keys = base.keys.unwrap_or_default();
+    let overlay_keys = overlay.keys.clone().unwrap_or_default();
+    let keys = UiKeys {
+        run: merge_keys(base_keys.run, overlay_keys.run.as_ref()),
+        fleet: merge_keys(base_keys.fleet, overlay_keys.fleet.as_ref()),
+    };
+    UiConfig {
+        colors,
+        icons: overlay.icons.or(base.icons),
+        layout,
+        keys: Some(keys),
     }
+}
 
-    fn log_text(&mut self, event: &LogEvent) -> Result<()> {
-        let mut line = format!(
-            "[{}] {} target={}",
-            event.timestamp, event.event, event.target
-        );
-        if let Some(rule_id) = event.rule_id.as_ref() {
-            line.push_str(&format!(" rule={rule_id}"));
-        }
-        if let Some(detail) = event.detail.as_ref() {
-            let sanitized = detail.replace('"', "'");
-            line.push_str(&format!(" detail=\"{}\"", sanitized));
-        }
-        if let Some(sends) = event.sends {
-            line.push_str(&format!(" sends={sends}"));
-        }
-        line.push('\n');
-        self.write_line(&line)
-    }
-
-    fn log_json(&mut self, event: &LogEvent) -> Result<()> {
-        let value = json!({
-            "event": event.event,
-            "timestamp": event.timestamp,
-            "target": event.target,
-            "rule_id": event.rule_id,
-            "detail": event.detail,
-            "sends": event.sends,
-        });
-        let mut line = serde_json::to_string(&value).context("failed to serialize log JSON")?;
-        line.push('\n');
-        self.write_line(&line)
+fn build_ui_settings(ui: &UiConfig) -> Result<UiSettings> {
+    let layout = ui.layout.unwrap_or_default();
+    if layout.compact_max.unwrap_or(80) >= layout.standard_max.unwrap_or(120) {
+        bail!("ui.layout.compact_max must be < standard_max");
     }
+    let icon_mode = match ui.icons.unwrap_or(UiIconSet::Auto) {
+        UiIconSet::Auto => detect_icon_mode(),
+        UiIconSet::Nerd => IconMode::Nerd,
+        UiIconSet::Ascii => IconMode::Ascii,
+    };
+    let keys = ui.keys.clone().unwrap_or_default();
+    Ok(UiSettings {
+        colors: ui.colors.unwrap_or_default(),
+        icon_mode,
+        layout,
+        run_keys: KeyBindings::new("run", RUN_KEY_ACTIONS, keys.run.as_ref())?,
+        fleet_keys: KeyBindings::new("fleet", FLEET_KEY_ACTIONS, keys.fleet.as_ref())?,
+    })
+}
 
-    fn write_line(&mut self, line: &str) -> Result<()> {
-        if let Some(file) = &mut self.file {
-            file.write_all(line.as_bytes())?;
-        } else {
-            print!("{line}");
-        }
-        Ok(())
+fn render_footer(
+    style: StyleConfig,
+    width: u16,
+    summary: Option<&str>,
+    keys: &KeyBindings,
+) -> String {
+    let sep_text = if style.use_unicode_ellipsis {
+        " · "
+    } else {
+        " . "
+    };
+    let text = if let Some(summary) = summary {
+        format!(
+            "stopped{sep_text}{summary}{sep_text}{} quit",
+            keys.key("quit")
+        )
+    } else {
+        let key = |action: &str| keys.key(action);
+        format!(
+            "{} hold/resume ({}/{}){sep_text}{} fleet{sep_text}{} renew{sep_text}{} next{sep_text}{} edit{sep_text}{} send{sep_text}{} preview{sep_text}{} panes{sep_text}{}/^C stop{sep_text}{} quit{sep_text}{} help",
+            key("hold"),
+            key("pause"),
+            key("resume"),
+            key("fleet"),
+            key("renew"),
+            key("next"),
+            key("edit"),
+            key("send"),
+            key("preview"),
+            key("panes"),
+            key("stop"),
+            key("quit"),
+            key("help"),
+        )
+    };
+    let line = pad_to_width(&text, width as usize);
+    if style.use_color {
+        let prefix = style_prefix(Some(240), style.use_bg.then_some(235), false);
+        format!("{prefix}{line}\x1B[0m")
+    } else {
+        line
     }
 }
 
-fn effective_elapsed(
-    run_started: std::time::Instant,
-    held_total: std::time::Duration,
-    hold_started: Option<std::time::Instant>,
-) -> std::time::Duration {
-    let mut total_held = held_total;
-    if let Some(started_at) = hold_started {
-        total_held += started_at.elapsed();
-    }
-    run_started.elapsed().saturating_sub(total_held)
+/// Fleet manager key hints, built from the active bindings like the run view's footer.
+fn fleet_footer_hints(keys: &KeyBindings) -> String {
+    let key = |action: &str| keys.key(action);
+    format!(
+        "nav <-/-> {}/{} · mark {} · clear {} · presets {}/{}-{} · sort {} · filters {}/{}/{} · search {} · single {}/{}/{}/{}/{} · bulk {}/{}/{}/{}/{} · {} confirm · {} cancel · {} id · {} stop-cmd · {} help · {}",
+        key("select_prev"),
+        key("select_next"),
+        key("mark"),
+        key("clear_marks"),
+        key("preset"),
+        key("preset_1"),
+        key("preset_4"),
+        key("sort"),
+        key("stale"),
+        key("mismatch"),
+        key("state_filter"),
+        key("search"),
+        key("hold"),
+        key("resume"),
+        key("next"),
+        key("renew"),
+        key("stop"),
+        key("bulk_stop"),
+        key("bulk_hold"),
+        key("bulk_resume"),
+        key("bulk_next"),
+        key("bulk_renew"),
+        key("confirm"),
+        key("cancel"),
+        key("copy_id"),
+        key("copy_stop"),
+        key("help"),
+        key("quit"),
+    )
 }
 
-fn format_std_duration(duration: std::time::Duration) -> String {
-    let total_seconds = duration.as_secs();
-    let hours = total_seconds / 3600;
-    let minutes = (total_seconds % 3600) / 60;
-    let seconds = total_seconds % 60;
-    if hours > 0 {
-        format!("{hours}h{minutes}m{seconds}s")
-    } else if minutes > 0 {
-        format!("{minutes}m{seconds}s")
+fn render_footer_summary(
+    config: &ResolvedConfig,
+    current: u32,
+    total: u32,
+    elapsed: &str,
+) -> String {
+    if config.infinite || total == 0 || total == u32::MAX {
+        format!("sends {current} elapsed {elapsed}")
     } else {
-        format!("{seconds}s")
+        format!("iter {current}/{total} elapsed {elapsed}")
     }
 }
 
-fn status_line(
+fn render_status_bar(
+    state: LoopState,
+    layout: LayoutMode,
+    icon_mode: IconMode,
+    style: StyleConfig,
+    width: u16,
     config: &ResolvedConfig,
-    send_count: u32,
-    max_sends: u32,
+    current: u32,
+    total: u32,
     rule_id: Option<&str>,
     elapsed: &str,
+    remaining_duration: Option<&str>,
 ) -> String {
+    let (icon, label) = state_label(state, icon_mode);
     let progress = if config.infinite {
-        String::from("infinite")
+        "inf".to_string()
     } else {
-        format!("{}/{}", send_count, max_sends)
+        format!("{}/{}", current, total)
     };
-    let rule = rule_id.unwrap_or("<unnamed>");
-    let (selector_label, selector_value) = if config.exec_command.is_some() {
-        ("event", format_exec_event_label(rule))
+    let percent = if config.infinite || total == 0 {
+        "--".to_string()
     } else {
-        ("rule", rule.to_string())
+        format!("{}%", (current * 100 / total))
     };
+    let bar = render_progress_bar(current, total, layout, style.use_unicode_ellipsis);
+    let trigger = rule_id.unwrap_or("-");
     let profile = config.profile_id.as_deref().unwrap_or("-");
-    let icon = ">";
-    let color = "\u{001B}[32m";
-    let reset = "\u{001B}[0m";
-    format!(
-        "{}{} status:{} profile={} target={} progress={} {}={} elapsed={}{}",
-        color,
-        icon,
-        reset,
-        profile,
-        config.target_label,
-        progress,
-        selector_label,
-        selector_value,
-        elapsed,
-        reset
-    )
-}
 
-fn format_exec_event_label(value: &str) -> String {
-    match value {
-        "exec:started" => "started".to_string(),
-        "exec:running" => "running".to_string(),
-        "exec:ok" => "ok".to_string(),
-        "exec:fail" => "fail".to_string(),
-        _ => value.to_string(),
-    }
-}
+    let icon_glyph = if style.use_unicode_ellipsis {
+        icon
+    } else {
+        ascii_icon(icon)
+    };
+    let state_text = format!("{icon_glyph} {label}");
+    let iter_text = if config.infinite {
+        "iter ∞".to_string()
+    } else {
+        format!("iter {progress}")
+    };
+    let trigger_text = truncate_text(
+        trigger,
+        match layout {
+            LayoutMode::Compact => 16,
+            LayoutMode::Standard => 28,
+            LayoutMode::Wide => 44,
+        },
+        style.use_unicode_ellipsis,
+    );
+    let trigger_prefix = if config.exec_command.is_some() {
+        "evt"
+    } else {
+        "trg"
+    };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    let sep_text = if style.use_unicode_ellipsis {
+        " · "
+    } else {
+        " . "
+    };
 
-    fn rule_with(match_: Option<MatchCriteria>, exclude: Option<MatchCriteria>) -> Rule {
-        Rule {
-            id: None,
-            match_,
-            exclude,
-            action: None,
-            delay: None,
-            confirm_seconds: None,
-            next: None,
-            priority: None,
-        }
-    }
+    let mut left_parts = Vec::new();
+    left_parts.push(state_text.clone());
+    left_parts.push(iter_text);
+    left_parts.push(format!("{bar} {percent}"));
 
-    fn match_regex(pattern: &str) -> MatchCriteria {
-        MatchCriteria {
-            regex: Some(pattern.to_string()),
-            trigger_expr: None,
-            exact_line: None,
-            contains: None,
-            starts_with: None,
+    let mut right_parts = Vec::new();
+    match layout {
+        LayoutMode::Compact => {
+            if let Some(remaining) = remaining_duration {
+                right_parts.push(format!("rem {remaining}"));
+            }
+            right_parts.push(format!("run {profile}"));
+            right_parts.push(format!("{trigger_prefix} {trigger_text}"));
+            right_parts.push(format!("v{}", LOOPMUX_VERSION));
+            right_parts.push(config.target_label.clone());
         }
-    }
-
-    fn match_contains(value: &str) -> MatchCriteria {
-        MatchCriteria {
-            regex: None,
-            trigger_expr: None,
-            exact_line: None,
-            contains: Some(value.to_string()),
-            starts_with: None,
+        LayoutMode::Standard => {
+            if let Some(remaining) = remaining_duration {
+                right_parts.push(format!("rem {remaining}"));
+            }
+            right_parts.push(format!("run {profile}"));
+            right_parts.push(format!("{trigger_prefix} {trigger_text}"));
+            right_parts.push(format!("last {elapsed}"));
+            right_parts.push(format!("v{}", LOOPMUX_VERSION));
+            right_parts.push(config.target_label.clone());
+        }
+        LayoutMode::Wide => {
+            if let Some(remaining) = remaining_duration {
+                right_parts.push(format!("rem {remaining}"));
+            }
+            right_parts.push(format!("run {profile}"));
+            right_parts.push(format!("{trigger_prefix} {trigger_text}"));
+            right_parts.push(format!("last {elapsed}"));
+            right_parts.push(format!("v{}", LOOPMUX_VERSION));
+            right_parts.push(format!("target {}", config.target_label));
         }
     }
 
-    #[test]
-    fn trigger_expr_respects_precedence() {
-        let expr = "A || B && C";
-        assert!(matches_trigger_expr(expr, "A only").unwrap());
-        assert!(!matches_trigger_expr(expr, "B only").unwrap());
-        assert!(matches_trigger_expr(expr, "B C").unwrap());
-    }
-
-    #[test]
-    fn trigger_expr_respects_parentheses() {
-        let expr = "(A || B) && C";
-        assert!(!matches_trigger_expr(expr, "B only").unwrap());
-        assert!(matches_trigger_expr(expr, "B C").unwrap());
-    }
-
-    #[test]
-    fn trigger_expr_trailing_operator_error() {
-        let err = parse_trigger_expr("READY &&").unwrap_err();
-        assert!(err.to_string().contains("trailing operator"));
-    }
-
-    #[test]
-    fn trigger_expr_empty_term_error() {
-        let err = parse_trigger_expr("READY && || DONE").unwrap_err();
-        assert!(err.to_string().contains("expected term after '&&'"));
-    }
+    let left_sep_text = if matches!(layout, LayoutMode::Compact) {
+        " "
+    } else {
+        sep_text
+    };
+    let left_text = left_parts.join(left_sep_text);
+    let right_sep_text = if matches!(layout, LayoutMode::Compact) {
+        " "
+    } else {
+        sep_text
+    };
+    let mut right_text = right_parts.join(right_sep_text);
+    let mut line = if right_text.is_empty() {
+        left_text.clone()
+    } else {
+        let width_usize = width as usize;
+        let left_len = left_text.chars().count();
+        let right_len = right_text.chars().count();
+        let gap = 1;
+        if left_len + gap + right_len > width_usize {
+            let available = width_usize.saturating_sub(left_len + gap);
+            if available > 0 {
+                right_text = truncate_text(&right_text, available, style.use_unicode_ellipsis);
+                format!("{left_text}{}{}", " ".repeat(gap), right_text)
+            } else {
+                left_text.clone()
+            }
+        } else {
+            let padding = width_usize.saturating_sub(left_len + gap + right_len);
+            format!(
+                "{left_text}{}{}{}",
+                " ".repeat(gap),
+                " ".repeat(padding),
+                right_text
+            )
+        }
+    };
+    line = pad_to_width(&line, width as usize);
 
-    #[test]
-    fn trigger_expr_missing_paren_error() {
-        let err = parse_trigger_expr("(READY || DONE").unwrap_err();
-        assert!(err.to_string().contains("missing right parenthesis"));
+    if style.use_color {
+        let label_color = state_color(state, style.palette);
+        let base_prefix = style_prefix(Some(248), style.use_bg.then_some(236), false);
+        let state_prefix = format!("\x1B[38;5;{label_color}m");
+        let sep_prefix = style_prefix(Some(240), style.use_bg.then_some(236), false);
+        let colored_state = format!("{state_prefix}{state_text}{base_prefix}");
+        let mut colored_line = line.replacen(&state_text, &colored_state, 1);
+        colored_line =
+            colored_line.replace(sep_text, &format!("{sep_prefix}{sep_text}{base_prefix}"));
+        format!("{base_prefix}{colored_line}\x1B[0m")
+    } else {
+        line
     }
+}
 
-    #[test]
-    fn trigger_expr_unexpected_token_error() {
-        let err = parse_trigger_expr(") READY").unwrap_err();
-        assert!(err.to_string().contains("unexpected token"));
+fn state_label(state: LoopState, icon_mode: IconMode) -> (&'static str, &'static str) {
+    match (state, icon_mode) {
+        (LoopState::Running, IconMode::Nerd) => ("󰐊", "RUN"),
+        (LoopState::Holding, IconMode::Nerd) => ("󰏤", "HOLD"),
+        (LoopState::Delay, IconMode::Nerd) => ("󰔟", "DELAY"),
+        (LoopState::Error, IconMode::Nerd) => ("󰅚", "ERROR"),
+        (LoopState::Stopped, IconMode::Nerd) => ("󰩈", "STOP"),
+        (LoopState::Waiting, IconMode::Nerd) => ("󰔟", "WAIT"),
+        (LoopState::Sending, IconMode::Nerd) => ("󰐊", "SEND"),
+        (LoopState::Running, IconMode::Ascii) => (">", "RUN"),
+        (LoopState::Holding, IconMode::Ascii) => ("||", "HOLD"),
+        (LoopState::Delay, IconMode::Ascii) => ("...", "DELAY"),
+        (LoopState::Error, IconMode::Ascii) => ("!", "ERROR"),
+        (LoopState::Stopped, IconMode::Ascii) => ("x", "STOP"),
+        (LoopState::Waiting, IconMode::Ascii) => ("...", "WAIT"),
+        (LoopState::Sending, IconMode::Ascii) => (">", "SEND"),
     }
+}
 
-    #[test]
-    fn trigger_expr_invalid_regex_error() {
-        let err = parse_trigger_expr("[").unwrap_err();
-        assert!(err.to_string().contains("invalid regex term"));
+fn render_progress_bar(current: u32, total: u32, layout: LayoutMode, unicode: bool) -> String {
+    let width = match layout {
+        LayoutMode::Compact => 6,
+        LayoutMode::Standard => 10,
+        LayoutMode::Wide => 14,
+    };
+    if total == 0 {
+        return if unicode {
+            "░".repeat(width)
+        } else {
+            ".".repeat(width)
+        };
     }
+    let filled = ((current as f64 / total as f64) * width as f64).round() as usize;
+    let filled = filled.min(width);
+    let filled_char = if unicode { "▰" } else { "=" };
+    let empty_char = if unicode { "▱" } else { "." };
+    format!(
+        "{}{}",
+        filled_char.repeat(filled),
+        empty_char.repeat(width - filled)
+    )
+}
 
-    #[test]
-    fn wildcard_match_handles_star_patterns() {
-        assert!(wildcard_match("/tmp/*/repo", "/tmp/demo/repo"));
-        assert!(wildcard_match(
-            "/Users/*/Codes/*",
-            "/Users/diego/Codes/Projects"
-        ));
-        assert!(!wildcard_match("/tmp/*/repo", "/tmp/demo/repo/sub"));
+fn truncate_text(text: &str, max: usize, use_unicode: bool) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let suffix = if use_unicode { "…" } else { "..." };
+    let suffix_len = suffix.chars().count();
+    if max <= suffix_len {
+        return text.chars().take(max).collect();
     }
+    let mut s = text
+        .chars()
+        .take(max.saturating_sub(suffix_len))
+        .collect::<String>();
+    s.push_str(suffix);
+    s
+}
 
-    #[test]
-    fn workspace_loader_merges_main_runs_events_and_imports() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-workspace-test-{}",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-        let imported = root.join("imported.yaml");
-        let main = root.join("config.yaml");
+fn pad_to_width(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len >= width {
+        return text.chars().take(width).collect();
+    }
+    let padding = width - len;
+    format!("{text}{}", " ".repeat(padding))
+}
 
-        std::fs::write(
-            &imported,
-            r#"
-runs:
-  - id: imported-run
-    target: "ai:9.0"
-    iterations: 1
-    default_action:
-      prompt: "imported"
-"#,
-        )
-        .unwrap();
+fn ascii_icon(icon: &str) -> &str {
+    match icon {
+        "󰐊" => ">",
+        "󰏤" => "||",
+        "󰔟" => "...",
+        "󰅚" => "!",
+        "󰩈" => "x",
+        _ => ">",
+    }
+}
 
-        std::fs::write(
-            &main,
-            format!(
-                r#"
-id: main-run
-target: "ai:1.0"
-iterations: 1
-default_action:
-  prompt: "main"
-imports:
-  - {}
-runs:
-  - id: child-run
-    target: "ai:2.0"
-    iterations: 1
-    default_action:
-      prompt: "child"
-events:
-  - id: event-run
-    target: "ai:3.0"
-    iterations: 1
-    default_action:
-      prompt: "event"
-"#,
-                imported.display()
-            ),
-        )
-        .unwrap();
+fn state_color(state: LoopState, palette: UiColors) -> u8 {
+    let configured = match state {
+        LoopState::Running => palette.running,
+        LoopState::Holding => palette.holding,
+        LoopState::Waiting => palette.waiting,
+        LoopState::Delay => palette.delay,
+        LoopState::Error => palette.error,
+        LoopState::Stopped => palette.stopped,
+        LoopState::Sending => palette.sending,
+    };
+    configured.unwrap_or(match state {
+        LoopState::Running => 71,
+        LoopState::Holding => 179,
+        LoopState::Waiting | LoopState::Delay => 109,
+        LoopState::Error => 166,
+        LoopState::Stopped => 246,
+        LoopState::Sending => 109,
+    })
+}
 
-        let profiles = load_workspace_profiles(&main).unwrap();
-        let ids = profiles
-            .iter()
-            .map(|profile| profile.id.clone())
-            .collect::<Vec<_>>();
-        assert!(ids.contains(&"main-run".to_string()));
-        assert!(ids.contains(&"child-run".to_string()));
-        assert!(ids.contains(&"event-run".to_string()));
-        assert!(ids.contains(&"imported-run".to_string()));
+fn style_prefix(fg: Option<u8>, bg: Option<u8>, bold: bool) -> String {
+    let mut prefix = String::new();
+    if bold {
+        prefix.push_str("\x1B[1m");
+    }
+    if let Some(fg) = fg {
+        prefix.push_str(&format!("\x1B[38;5;{fg}m"));
+    }
+    if let Some(bg) = bg {
+        prefix.push_str(&format!("\x1B[48;5;{bg}m"));
+    }
+    prefix
+}
 
-        std::fs::remove_dir_all(root).unwrap();
+fn fit_line(text: &str, width: usize, use_unicode: bool) -> String {
+    if text.chars().count() <= width {
+        return pad_to_width(text, width);
     }
+    truncate_text(text, width, use_unicode)
+}
 
-    #[test]
-    fn selected_workspace_profiles_respects_enabled_and_cwd() {
-        let cwd = PathBuf::from("/tmp/demo");
-        let profiles = vec![
-            ResolvedRunProfile {
-                id: "match-enabled".to_string(),
-                source_path: PathBuf::from("/tmp/config.yaml"),
-                config: Config::default(),
-                enabled: true,
-                when: RunProfileWhen {
-                    cwd_matches: Some(vec!["/tmp/*".to_string()]),
-                },
-            },
-            ResolvedRunProfile {
-                id: "match-disabled".to_string(),
-                source_path: PathBuf::from("/tmp/config.yaml"),
-                config: Config::default(),
-                enabled: false,
-                when: RunProfileWhen {
-                    cwd_matches: Some(vec!["/tmp/*".to_string()]),
-                },
-            },
-            ResolvedRunProfile {
-                id: "non-match-enabled".to_string(),
-                source_path: PathBuf::from("/tmp/config.yaml"),
-                config: Config::default(),
-                enabled: true,
-                when: RunProfileWhen {
-                    cwd_matches: Some(vec!["/repo/*".to_string()]),
-                },
-            },
-        ];
+fn timestamp_now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_else(|_| "unknown".into())
+}
 
-        let startup = selected_workspace_profiles(&profiles, &cwd, false)
-            .into_iter()
-            .map(|profile| profile.id)
-            .collect::<Vec<_>>();
-        assert_eq!(startup, vec!["match-enabled".to_string()]);
+fn timestamp_local_now() -> String {
+    OffsetDateTime::now_local()
+        .unwrap_or_else(|_| OffsetDateTime::now_utc())
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_else(|_| "unknown".into())
+}
 
-        let all = selected_workspace_profiles(&profiles, &cwd, true)
-            .into_iter()
-            .map(|profile| profile.id)
-            .collect::<Vec<_>>();
-        assert_eq!(all.len(), 3);
-    }
+fn log_line_color(line: &str) -> u8 {
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    log_line_color_at(line, now)
+}
 
-    #[test]
-    fn resolve_workspace_config_path_uses_override() {
-        let path = PathBuf::from("/tmp/loopmux-custom.yaml");
-        let resolved = resolve_workspace_config_path(Some(&path)).unwrap();
-        assert_eq!(resolved, path);
+fn log_line_color_at(line: &str, now: OffsetDateTime) -> u8 {
+    if let Some(timestamp) = parse_log_timestamp(line) {
+        let local_timestamp = timestamp.to_offset(now.offset());
+        if local_timestamp.date() == now.date() {
+            return 251;
+        }
+        return 244;
+    }
+    if looks_like_compact_time_prefix(line) {
+        return 249;
     }
+    245
+}
 
-    #[test]
-    fn ensure_default_workspace_config_creates_template_with_continue_loop_event() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-ensure-config-create-{}",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        ));
-        let config_path = root.join("loopmux").join("config.yaml");
+#[cfg(test)]
+fn log_line_date(line: &str) -> Option<&str> {
+    if !line.starts_with('[') {
+        return None;
+    }
+    let close = line.find(']')?;
+    let ts = line.get(1..close)?;
+    let date = ts.split('T').next()?;
+    if date.len() == 10 { Some(date) } else { None }
+}
 
-        ensure_default_workspace_config_exists(&config_path).unwrap();
+fn parse_log_timestamp(line: &str) -> Option<OffsetDateTime> {
+    if !line.starts_with('[') {
+        return None;
+    }
+    let close = line.find(']')?;
+    let ts = line.get(1..close)?;
+    OffsetDateTime::parse(ts, &time::format_description::well_known::Rfc3339).ok()
+}
 
-        let contents = std::fs::read_to_string(&config_path).unwrap();
-        assert!(contents.contains("exact_line: \"<CONTINUE-LOOP>\""));
-        assert!(contents.contains("continue-loop"));
+fn looks_like_compact_time_prefix(line: &str) -> bool {
+    let mut parts = line.split(':');
+    let (Some(h), Some(m), Some(s)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    h.len() == 2
+        && m.len() == 2
+        && s.len() >= 2
+        && h.chars().all(|ch| ch.is_ascii_digit())
+        && m.chars().all(|ch| ch.is_ascii_digit())
+        && s.chars().take(2).all(|ch| ch.is_ascii_digit())
+}
 
-        std::fs::remove_dir_all(root).unwrap();
+fn parse_duration(value: &str) -> Result<Duration> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        bail!("duration is empty");
+    }
+    let mut number_part = String::new();
+    let mut unit_part = String::new();
+    for ch in trimmed.chars() {
+        if ch.is_ascii_digit() || ch == '.' {
+            if !unit_part.is_empty() {
+                bail!("invalid duration: {value}");
+            }
+            number_part.push(ch);
+        } else if !ch.is_whitespace() {
+            unit_part.push(ch);
+        }
+    }
+    if number_part.is_empty() || unit_part.is_empty() {
+        bail!("invalid duration: {value}");
     }
+    let amount: f64 = number_part
+        .parse()
+        .with_context(|| format!("invalid duration number: {value}"))?;
+    if amount <= 0.0 {
+        bail!("duration must be > 0: {value}");
+    }
+    let unit = unit_part.to_lowercase();
+    let seconds = match unit.as_str() {
+        "s" | "sec" | "secs" | "second" | "seconds" => amount,
+        "m" | "min" | "mins" | "minute" | "minutes" => amount * 60.0,
+        "h" | "hr" | "hrs" | "hour" | "hours" => amount * 3600.0,
+        "d" | "day" | "days" => amount * 86_400.0,
+        "w" | "wk" | "wks" | "week" | "weeks" => amount * 604_800.0,
+        "mon" | "month" | "months" => amount * 2_592_000.0,
+        "y" | "yr" | "yrs" | "year" | "years" => amount * 31_536_000.0,
+        _ => bail!("invalid duration unit: {unit_part}"),
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
 
-    #[test]
-    fn ensure_default_workspace_config_keeps_existing_file() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-ensure-config-existing-{}",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-        let config_path = root.join("config.yaml");
-        std::fs::write(&config_path, "id: existing\n").unwrap();
+#[derive(Debug, Clone)]
+struct LoggingConfigResolved {
+    path: Option<PathBuf>,
+    format: LogFormatResolved,
+}
 
-        ensure_default_workspace_config_exists(&config_path).unwrap();
+#[derive(Debug, Clone, Copy)]
+enum LogFormatResolved {
+    Text,
+    Jsonl,
+}
 
-        let contents = std::fs::read_to_string(&config_path).unwrap();
-        assert_eq!(contents, "id: existing\n");
+#[derive(Debug)]
+struct BackoffState {
+    attempts: u32,
+    last_sent: Option<OffsetDateTime>,
+}
 
-        std::fs::remove_dir_all(root).unwrap();
+struct ExecInFlight {
+    command: String,
+    child: std::process::Child,
+    started_at: std::time::Instant,
+}
+
+fn resolve_config(
+    mut config: Config,
+    target_override: Option<Vec<String>>,
+    iterations_override: Option<u32>,
+    skip_tmux: bool,
+    tail_override: Option<usize>,
+    head_override: Option<usize>,
+    once: bool,
+    single_line: bool,
+    tui: bool,
+    trigger_edge_override: Option<bool>,
+    recheck_before_send_override: Option<bool>,
+    profile_id: Option<String>,
+) -> Result<ResolvedConfig> {
+    if let Some(targets) = target_override {
+        if let Some(first) = targets.first() {
+            config.target = Some(first.clone());
+            config.targets = Some(targets);
+        }
+    }
+    if let Some(iterations) = iterations_override {
+        config.iterations = Some(iterations);
+        config.infinite = Some(false);
     }
 
-    #[test]
-    fn config_doctor_reports_duplicate_profile_ids() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-doctor-dup-{}",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-        let config_path = root.join("config.yaml");
-        std::fs::write(
-            &config_path,
-            r#"
-runs:
-  - id: same
-    target: "ai:1.0"
-    iterations: 1
-    default_action:
-      prompt: "a"
-  - id: same
-    target: "ai:2.0"
-    iterations: 1
-    default_action:
-      prompt: "b"
-"#,
-        )
-        .unwrap();
+    let exec_command = config
+        .exec
+        .as_ref()
+        .map(|value| value.command.trim().to_string())
+        .filter(|value| !value.is_empty());
+    if config.exec.is_some() && exec_command.is_none() {
+        bail!("exec.command is required and cannot be empty");
+    }
 
-        let err = config_doctor(Some(&config_path), true).unwrap_err();
-        assert!(err.to_string().contains("duplicate profile id"));
-        std::fs::remove_dir_all(root).unwrap();
+    if exec_command.is_some()
+        && (config.default_action.is_some()
+            || config.rules.as_ref().is_some_and(|rules| !rules.is_empty())
+            || config.target.is_some()
+            || config
+                .targets
+                .as_ref()
+                .is_some_and(|targets| !targets.is_empty())
+            || config.files.as_ref().is_some_and(|files| !files.is_empty()))
+    {
+        bail!(
+            "exec mode cannot be combined with target/targets/files/default_action/rules in the same config"
+        );
     }
 
-    #[test]
-    fn config_doctor_reports_multiple_tui_profiles() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-doctor-tui-{}",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-        let config_path = root.join("config.yaml");
-        std::fs::write(
-            &config_path,
-            r#"
-runs:
-  - id: one
-    target: "ai:1.0"
-    iterations: 1
-    tui: true
-    default_action:
-      prompt: "a"
-  - id: two
+    let requested_targets = if exec_command.is_some() {
+        Vec::new()
+    } else {
+        config
+            .targets
+            .clone()
+            .unwrap_or_else(|| config.target.clone().into_iter().collect())
+    };
+    if exec_command.is_none() {
+        if let Some(files) = &config.files {
+            validate_file_sources(files)?;
+        }
+    }
+
+    if requested_targets.len() > 1
+        && requested_targets
+            .iter()
+            .any(|target| is_pane_selector(target.trim()))
+    {
+        bail!("a pane selector must be the only target; combine conditions with commas instead");
+    }
+
+    let explicit_targets = if exec_command.is_some() {
+        None
+    } else if requested_targets.len() > 1 {
+        Some(resolve_explicit_targets(&requested_targets, skip_tmux)?)
+    } else {
+        None
+    };
+    let (target_scope, target_label) = if let Some(command) = exec_command.as_ref() {
+        (TargetScope::All, format!("exec://{command}"))
+    } else if let Some(targets) = explicit_targets.as_ref() {
+        (TargetScope::All, targets.join(","))
+    } else {
+        let target_input = requested_targets.first().map(String::as_str);
+        if skip_tmux {
+            resolve_target_scope_offline(target_input)?
+        } else {
+            resolve_target_scope(target_input)?
+        }
+    };
+
+    let infinite = config.infinite.unwrap_or(false);
+    let iterations = config.iterations;
+    if infinite && iterations.is_some() {
+        bail!("iterations must be omitted when infinite is true");
+    }
+    if !infinite && iterations.unwrap_or(0) == 0 {
+        bail!("iterations must be > 0 unless infinite is true");
+    }
+
+    let duration = if let Some(ref value) = config.duration {
+        Some(parse_duration(value).with_context(|| "invalid duration")?)
+    } else {
+        None
+    };
+
+    if config_has_prompt_refs(&config) {
+        let library = load_prompt_library(config.workspace_path.as_deref())?;
+        expand_config_prompt_refs(&mut config, &library)?;
+    }
+
+    let (default_action, has_prompt, prompt_placeholders, template_var_keys, rule_eval, rules) =
+        if exec_command.is_some() {
+            (
+                Action {
+                    pre: None,
+                    prompt: None,
+                    post: None,
+                },
+                false,
+                Vec::new(),
+                Vec::new(),
+                RuleEval::FirstMatch,
+                Vec::new(),
+            )
+        } else {
+            let Some(default_action) = config.default_action else {
+                bail!("default_action.prompt is required");
+            };
+            let has_prompt = default_action.prompt.as_ref().is_some();
+            if !has_prompt {
+                bail!("default_action.prompt is required");
+            }
+            validate_prompt_blocks(&default_action, "default_action")?;
+            for (index, rule) in config.rules.iter().flatten().enumerate() {
+                if let Some(action) = &rule.action {
+                    let label = rule.id.clone().unwrap_or_else(|| format!("rules[{index}]"));
+                    validate_prompt_blocks(action, &label)?;
+                }
+            }
+            let prompt_placeholders = collect_template_placeholders(&default_action, &config.rules);
+            let template_vars = config.template_vars.unwrap_or_default();
+            let template_var_keys = template_vars.keys().cloned().collect::<Vec<_>>();
+            let missing_template_vars = find_missing_vars(&prompt_placeholders, &template_vars);
+            if !missing_template_vars.is_empty() {
+                bail!(
+                    "missing template_vars: {}",
+                    missing_template_vars.join(", ")
+                );
+            }
+            let rule_eval = config.rule_eval.unwrap_or(RuleEval::FirstMatch);
+            let rules = config.rules.unwrap_or_default();
+            validate_rules(&rules, config.states.as_deref().unwrap_or_default())?;
+            (
+                default_action,
+                true,
+                prompt_placeholders,
+                template_var_keys,
+                rule_eval,
+                rules,
+            )
+        };
+    let logging = resolve_logging(config.logging);
+    let done_when = config.done_when;
+    if let Some(done) = done_when.as_ref() {
+        validate_done_when(done)?;
+    }
+    let ui = config.ui.unwrap_or_default();
+    build_ui_settings(&ui)?;
+
+    let delay = config.delay;
+    if let Some(ref delay) = delay {
+        validate_delay(delay)?;
+    }
+
+    let poll = config.poll.unwrap_or(5).max(1);
+    let trigger_confirm_seconds = config
+        .trigger_confirm_seconds
+        .unwrap_or(DEFAULT_TRIGGER_CONFIRM_SECONDS);
+    let trigger_edge = trigger_edge_override.unwrap_or(config.trigger_edge.unwrap_or(true));
+    let recheck_before_send =
+        recheck_before_send_override.unwrap_or(config.recheck_before_send.unwrap_or(true));
+    let log_preview_lines = config.log_preview_lines.unwrap_or(3).max(1);
+
+    let fanout = config.fanout.unwrap_or(FanoutMode::Matched);
+    let submit_keys = config
+        .submit_keys
+        .unwrap_or_else(|| vec![DEFAULT_SUBMIT_KEY.to_string()]);
+    if submit_keys.iter().any(|key| key.trim().is_empty()) {
+        bail!("submit_keys entries cannot be empty");
+    }
+
+    if exec_command.is_none() && !skip_tmux {
+        if let Some(targets) = explicit_targets.as_ref() {
+            validate_tmux_targets(targets)?;
+        }
+        validate_tmux_scope(&target_scope)?;
+    }
+
+    if tail_override.is_some() && head_override.is_some() {
+        bail!("--tail and --head are mutually exclusive");
+    }
+    let tail = tail_override.or(config.tail).unwrap_or(1);
+    let once = once || config.once.unwrap_or(false);
+    let single_line = single_line || config.single_line.unwrap_or(false);
+    let tui = tui || config.tui.unwrap_or(false);
+    let window = CaptureWindow::from_overrides(tail_override.or(Some(tail)), head_override);
+    let capture = config.capture.unwrap_or_default();
+    let capture_ansi =
+        config.capture_ansi.unwrap_or(false) || rules_use_style(&rules, done_when.as_ref());
+    let capture_join = config.capture_join.unwrap_or(false);
+    let states = config.states.unwrap_or_default();
+    if !states.is_empty() {
+        validate_states(&states, &rules)?;
+    }
+
+    Ok(ResolvedConfig {
+        profile_id,
+        exec_command,
+        target_scope,
+        target_label,
+        explicit_targets,
+        file_sources: config.files.unwrap_or_default(),
+        iterations,
+        infinite,
+        has_prompt,
+        poll,
+        trigger_confirm_seconds,
+        log_preview_lines,
+        trigger_edge,
+        recheck_before_send,
+        fanout,
+        submit_keys,
+        duration,
+        seed: config.seed,
+        rule_eval,
+        rules,
+        delay,
+        prompt_placeholders,
+        template_vars: template_var_keys,
+        default_action,
+        logging,
+        capture_window: window,
+        once,
+        single_line,
+        tui,
+        source_path: None,
+        ui,
+        done_when,
+        capture,
+        capture_ansi,
+        capture_join,
+        states,
+    })
+}
+
+fn print_validation(config: &ResolvedConfig) {
+    println!("Validation OK");
+    println!("- target: {}", config.target_label);
+    if let Some(command) = config.exec_command.as_deref() {
+        println!("- exec.command: {command}");
+    }
+    if !config.file_sources.is_empty() {
+        println!("- file_sources: {}", config.file_sources.join(", "));
+    }
+    if config.infinite {
+        println!("- iterations: infinite");
+    } else if let Some(iterations) = config.iterations {
+        println!("- iterations: {iterations}");
+    }
+    println!("- prompt: {}", if config.has_prompt { "yes" } else { "no" });
+    if config.exec_command.is_none() {
+        println!("- rule_eval: {}", rule_eval_label(&config.rule_eval));
+        println!("- rules: {}", config.rules.len());
+        if let Some(delay) = &config.delay {
+            println!("- delay: {}", delay_summary(delay));
+        }
+        if !config.prompt_placeholders.is_empty() {
+            println!("- template vars: {}", config.prompt_placeholders.join(", "));
+        }
+        if !config.template_vars.is_empty() {
+            println!("- template_vars: {}", config.template_vars.join(", "));
+        }
+    }
+    if let Some(path) = &config.logging.path {
+        println!(
+            "- logging: {} ({})",
+            path.display(),
+            log_format_label(config.logging.format)
+        );
+    } else {
+        println!(
+            "- logging: stdout ({})",
+            log_format_label(config.logging.format)
+        );
+    }
+    if config.exec_command.is_none() {
+        match config.capture_window {
+            CaptureWindow::Tail(lines) => println!("- tail: {lines}"),
+            CaptureWindow::Head(lines) => println!("- head: {lines}"),
+        }
+        if config.capture != CaptureMode::Window {
+            println!("- capture: {}", config.capture.label());
+        }
+        if config.capture_ansi {
+            println!("- capture_ansi: on");
+        }
+        if config.capture_join {
+            println!("- capture_join: on");
+        }
+    }
+    println!("- poll: {}s", config.poll);
+    println!(
+        "- trigger_confirm_seconds: {}s",
+        config.trigger_confirm_seconds
+    );
+    println!("- log_preview_lines: {}", config.log_preview_lines);
+    println!(
+        "- trigger_edge: {}",
+        if config.trigger_edge { "yes" } else { "no" }
+    );
+    println!(
+        "- recheck_before_send: {}",
+        if config.recheck_before_send {
+            "yes"
+        } else {
+            "no"
+        }
+    );
+    println!("- fanout: {}", fanout_label(config.fanout));
+    if config.exec_command.is_none() {
+        println!("- submit_keys: {}", config.submit_keys.join(" "));
+    }
+    if let Some(duration) = config.duration {
+        println!("- duration: {}s", duration.as_secs_f64());
+    }
+    println!("- once: {}", if config.once { "yes" } else { "no" });
+    println!(
+        "- single_line: {}",
+        if config.single_line { "yes" } else { "no" }
+    );
+    println!("- tui: {}", if config.tui { "yes" } else { "no" });
+    println!("- note: dry-run only, no tmux commands sent");
+}
+
+fn rule_eval_label(rule_eval: &RuleEval) -> &'static str {
+    match rule_eval {
+        RuleEval::FirstMatch => "first_match",
+        RuleEval::MultiMatch => "multi_match",
+        RuleEval::Priority => "priority",
+    }
+}
+
+fn fanout_label(mode: FanoutMode) -> &'static str {
+    match mode {
+        FanoutMode::Matched => "matched",
+        FanoutMode::Broadcast => "broadcast",
+    }
+}
+
+fn log_format_label(format: LogFormatResolved) -> &'static str {
+    match format {
+        LogFormatResolved::Text => "text",
+        LogFormatResolved::Jsonl => "jsonl",
+    }
+}
+
+fn delay_summary(delay: &DelayConfig) -> String {
+    match delay.mode {
+        DelayMode::Fixed => format!("fixed {}s", delay.value.unwrap_or(0)),
+        DelayMode::Range => {
+            let min = delay.min.unwrap_or(0);
+            let max = delay.max.unwrap_or(0);
+            format!("range {min}-{max}s")
+        }
+        DelayMode::Jitter => {
+            let min = delay.min.unwrap_or(0);
+            let max = delay.max.unwrap_or(0);
+            let jitter = delay.jitter.unwrap_or(0.0);
+            format!("jitter {min}-{max}s {jitter}")
+        }
+        DelayMode::Backoff => {
+            if let Some(backoff) = &delay.backoff {
+                let max = backoff.max.map_or(String::new(), |v| format!(", max {v}s"));
+                format!("backoff base {}s x{}{}", backoff.base, backoff.factor, max)
+            } else {
+                "backoff".to_string()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlowShape {
+    Terminal,
+    State,
+    Rule,
+    Action,
+}
+
+/// Node of the rule flow diagram; `lines` become the label, one per row.
+#[derive(Debug)]
+struct FlowNode {
+    id: String,
+    lines: Vec<String>,
+    shape: FlowShape,
+}
+
+#[derive(Debug)]
+struct FlowEdge {
+    from: String,
+    to: String,
+    label: Option<String>,
+    dashed: bool,
+}
+
+impl FlowEdge {
+    fn new(from: &str, to: &str) -> Self {
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            label: None,
+            dashed: false,
+        }
+    }
+
+    fn dashed(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self.dashed = true;
+        self
+    }
+
+    fn labeled(mut self, label: String) -> Self {
+        self.label = Some(label);
+        self
+    }
+}
+
+/// Nodes and edges of the rule flow: states, rules with their `next` steps, the default
+/// action and the `stop` terminal.
+fn rule_flow(config: &ResolvedConfig) -> (Vec<FlowNode>, Vec<FlowEdge>) {
+    let node_of = |name: &str| -> Option<String> {
+        if name == "stop" {
+            return Some("stop".to_string());
+        }
+        config
+            .states
+            .iter()
+            .position(|state| state.id == name)
+            .map(|index| format!("state_{index}"))
+            .or_else(|| {
+                config
+                    .rules
+                    .iter()
+                    .position(|rule| rule.id.as_deref() == Some(name))
+                    .map(|index| format!("rule_{index}"))
+            })
+    };
+    let step_edges = |from: &str, step: &NextStep, label: Option<String>| -> Vec<FlowEdge> {
+        step.ids()
+            .iter()
+            .filter_map(|id| node_of(id))
+            .map(|to| {
+                let edge = FlowEdge::new(from, &to);
+                match &label {
+                    Some(label) => edge.labeled(label.clone()),
+                    None => edge,
+                }
+            })
+            .collect()
+    };
+
+    let mut nodes = vec![FlowNode {
+        id: "start".to_string(),
+        lines: vec!["start".to_string()],
+        shape: FlowShape::Terminal,
+    }];
+    let mut edges = Vec::new();
+    if let Some(first) = config.states.first() {
+        edges.push(FlowEdge::new("start", "state_0").labeled(first.id.clone()));
+    }
+    for (index, state) in config.states.iter().enumerate() {
+        let id = format!("state_{index}");
+        nodes.push(FlowNode {
+            id: id.clone(),
+            lines: vec![format!("state {}", state.id)],
+            shape: FlowShape::State,
+        });
+        for rule in &state.rules {
+            edges.extend(node_of(rule).map(|to| FlowEdge::new(&id, &to)));
+        }
+        for (rule, step) in &state.transitions {
+            edges.extend(step_edges(&id, step, Some(format!("on {rule}"))));
+        }
+        if let Some(step) = &state.on_timeout {
+            let label = match &state.timeout {
+                Some(timeout) => format!("timeout {timeout}"),
+                None => "timeout".to_string(),
+            };
+            for mut edge in step_edges(&id, step, Some(label)) {
+                edge.dashed = true;
+                edges.push(edge);
+            }
+        }
+    }
+    for (index, rule) in config.rules.iter().enumerate() {
+        let id = format!("rule_{index}");
+        let escape = rule.escape.unwrap_or(false);
+        let name = rule.id.clone().unwrap_or_else(|| format!("rules[{index}]"));
+        let mut lines = vec![if escape {
+            format!("{name} (escape)")
+        } else {
+            name
+        }];
+        lines.push(format!(
+            "match: {}",
+            rule.match_
+                .as_ref()
+                .map_or_else(|| "any output".to_string(), match_summary)
+        ));
+        if let Some(exclude) = &rule.exclude {
+            lines.push(format!("exclude: {}", match_summary(exclude)));
+        }
+        if let Some(delay) = rule.delay.as_ref().or(config.delay.as_ref()) {
+            lines.push(format!("delay: {}", delay_summary(delay)));
+        }
+        lines.push(format!(
+            "confirm: {}s",
+            rule.confirm_seconds
+                .unwrap_or(config.trigger_confirm_seconds)
+        ));
+        if let Some(prompt) = rule
+            .action
+            .as_ref()
+            .and_then(|action| action.prompt.as_ref())
+        {
+            lines.push(format!("prompt: {}", prompt_block_summary(prompt)));
+        }
+        if let Some(targets) = &rule.targets {
+            lines.push(format!("targets: {}", targets.join(", ")));
+        }
+        if let Some(send_to) = &rule.send_to {
+            lines.push(format!("send_to: {send_to}"));
+        }
+        let mut limits = Vec::new();
+        limits.extend(rule.max_fires.map(|max| format!("max {max}")));
+        limits.extend(
+            rule.budget
+                .as_ref()
+                .map(|budget| format!("{}/{}", budget.fires, budget.per)),
+        );
+        limits.extend(
+            rule.cooldown
+                .as_ref()
+                .map(|cooldown| format!("cooldown {cooldown}")),
+        );
+        if !limits.is_empty() {
+            lines.push(format!("limits: {}", limits.join(", ")));
+        }
+        nodes.push(FlowNode {
+            id: id.clone(),
+            lines,
+            shape: FlowShape::Rule,
+        });
+
+        if config.states.is_empty() {
+            edges.push(FlowEdge::new("start", &id));
+        } else if escape {
+            edges.push(FlowEdge::new("start", &id).dashed("any state"));
+        }
+        match &rule.next {
+            Some(step) => edges.extend(step_edges(&id, step, None)),
+            None if config.states.is_empty() => {
+                edges.push(FlowEdge::new(&id, "start").dashed("any rule"));
+            }
+            None => {}
+        }
+        // A used-up limit without `on_exhausted` ends the run.
+        let exhausted = rule.on_exhausted.clone().or_else(|| {
+            (rule.max_fires.is_some() || rule.budget.is_some())
+                .then(|| NextStep::One("stop".to_string()))
+        });
+        if let Some(step) = &exhausted {
+            for mut edge in step_edges(&id, step, Some("exhausted".to_string())) {
+                edge.dashed = true;
+                edges.push(edge);
+            }
+        }
+        if rule.action.is_none() {
+            edges.push(FlowEdge::new(&id, "default_action").dashed("sends"));
+        }
+    }
+
+    if config.rules.is_empty() {
+        edges.push(FlowEdge::new("start", "default_action"));
+    }
+    let mut lines = vec!["default action".to_string()];
+    if let Some(prompt) = &config.default_action.prompt {
+        lines.push(format!("prompt: {}", prompt_block_summary(prompt)));
+    }
+    nodes.push(FlowNode {
+        id: "default_action".to_string(),
+        lines,
+        shape: FlowShape::Action,
+    });
+    if edges.iter().any(|edge| edge.to == "stop") {
+        nodes.push(FlowNode {
+            id: "stop".to_string(),
+            lines: vec!["stop".to_string()],
+            shape: FlowShape::Terminal,
+        });
+    }
+    (nodes, edges)
+}
+
+/// One-line description of a rule's criteria: text matchers joined by `|` (any of them),
+/// then the `idle_for`/`prompt_ready` gates that must also hold.
+fn match_summary(criteria: &MatchCriteria) -> String {
+    let mut any = Vec::new();
+    if let Some(expr) = &criteria.trigger_expr {
+        any.push(format!("expr {expr}"));
+    }
+    if let Some(line) = &criteria.exact_line {
+        any.push(format!("line \"{line}\""));
+    }
+    if let Some(regex) = &criteria.regex {
+        any.push(format!("/{regex}/"));
+    }
+    if let Some(contains) = &criteria.contains {
+        any.push(format!("contains \"{contains}\""));
+    }
+    if let Some(prefix) = &criteria.starts_with {
+        any.push(format!("starts_with \"{prefix}\""));
+    }
+    if let Some(style) = &criteria.style {
+        let mut parts = vec!["style".to_string()];
+        parts.extend(style.fg.as_ref().map(|fg| format!("fg={fg}")));
+        parts.extend(style.bg.as_ref().map(|bg| format!("bg={bg}")));
+        for (name, value) in [
+            ("bold", style.bold),
+            ("dim", style.dim),
+            ("italic", style.italic),
+            ("underline", style.underline),
+        ] {
+            parts.extend(value.map(|on| {
+                if on {
+                    name.to_string()
+                } else {
+                    format!("!{name}")
+                }
+            }));
+        }
+        parts.extend(style.regex.as_ref().map(|regex| format!("/{regex}/")));
+        any.push(parts.join(" "));
+    }
+    let mut summary = any.join(" | ");
+    let mut gates = Vec::new();
+    gates.extend(
+        criteria
+            .idle_for
+            .as_ref()
+            .map(|idle| format!("idle {idle}")),
+    );
+    gates.extend(
+        criteria
+            .prompt_ready
+            .as_ref()
+            .map(|prompt| format!("prompt_ready /{prompt}/")),
+    );
+    for gate in gates {
+        if !summary.is_empty() {
+            summary.push_str(" & ");
+        }
+        summary.push_str(&gate);
+    }
+    if summary.is_empty() {
+        summary.push_str("any output");
+    }
+    truncate_text(&summary, 60, false)
+}
+
+fn prompt_block_summary(block: &PromptBlock) -> String {
+    let summary = match block {
+        PromptBlock::Single(text) => text.lines().next().unwrap_or("").to_string(),
+        PromptBlock::Multi(lines) => lines.first().cloned().unwrap_or_default(),
+        PromptBlock::Ref(prompt_ref) => format!("ref {}", prompt_ref.name),
+        PromptBlock::Rotate(rotation) => format!("rotation of {} items", rotation.items.len()),
+        PromptBlock::Forward(forward) => match (&forward.forward.between, &forward.forward.after) {
+            (Some([start, end]), _) => format!("forward {start}..{end}"),
+            (None, Some(after)) => format!("forward after /{after}/"),
+            (None, None) => "forward output".to_string(),
+        },
+        PromptBlock::Source(source) => match (&source.file, &source.command) {
+            (Some(file), _) => format!("file {file}"),
+            (None, Some(command)) => format!("command {command}"),
+            (None, None) => "source".to_string(),
+        },
+    };
+    truncate_text(&summary, 40, false)
+}
+
+/// Renders [`rule_flow`] as a Mermaid flowchart or a Graphviz digraph.
+fn rule_flow_graph(config: &ResolvedConfig, format: GraphFormat) -> String {
+    let (nodes, edges) = rule_flow(config);
+    let mut out = String::new();
+    match format {
+        GraphFormat::Mermaid => {
+            let escape = |text: &str| {
+                text.replace('"', "#quot;")
+                    .replace('<', "#lt;")
+                    .replace('>', "#gt;")
+            };
+            out.push_str("flowchart TD\n");
+            for node in &nodes {
+                let label = node
+                    .lines
+                    .iter()
+                    .map(|line| escape(line))
+                    .collect::<Vec<_>>()
+                    .join("<br/>");
+                let (open, close) = match node.shape {
+                    FlowShape::Terminal => ("([", "])"),
+                    FlowShape::State => ("(", ")"),
+                    FlowShape::Rule => ("[", "]"),
+                    FlowShape::Action => ("[/", "/]"),
+                };
+                out.push_str(&format!("    {}{open}\"{label}\"{close}\n", node.id));
+            }
+            for edge in &edges {
+                let arrow = if edge.dashed { "-.->" } else { "-->" };
+                let label = edge
+                    .label
+                    .as_ref()
+                    .map_or(String::new(), |label| format!("|\"{}\"|", escape(label)));
+                out.push_str(&format!("    {} {arrow}{label} {}\n", edge.from, edge.to));
+            }
+        }
+        GraphFormat::Dot => {
+            let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+            out.push_str("digraph loopmux {\n");
+            for node in &nodes {
+                let label = node
+                    .lines
+                    .iter()
+                    .map(|line| escape(line))
+                    .collect::<Vec<_>>()
+                    .join("\\n");
+                let shape = match node.shape {
+                    FlowShape::Terminal => "shape=oval",
+                    FlowShape::State => "shape=box, style=rounded",
+                    FlowShape::Rule => "shape=box",
+                    FlowShape::Action => "shape=parallelogram",
+                };
+                out.push_str(&format!("    {} [label=\"{label}\", {shape}];\n", node.id));
+            }
+            for edge in &edges {
+                let mut attrs = Vec::new();
+                if let Some(label) = &edge.label {
+                    attrs.push(format!("label=\"{}\"", escape(label)));
+                }
+                if edge.dashed {
+                    attrs.push("style=dashed".to_string());
+                }
+                let attrs = if attrs.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", attrs.join(", "))
+                };
+                out.push_str(&format!("    {} -> {}{attrs};\n", edge.from, edge.to));
+            }
+            out.push_str("}\n");
+        }
+    }
+    out
+}
+
+fn resolve_logging(config: Option<LoggingConfig>) -> LoggingConfigResolved {
+    let config = config.unwrap_or(LoggingConfig {
+        path: None,
+        format: None,
+    });
+    let format = match config.format.unwrap_or(LogFormat::Text) {
+        LogFormat::Text => LogFormatResolved::Text,
+        LogFormat::Jsonl => LogFormatResolved::Jsonl,
+    };
+    LoggingConfigResolved {
+        path: config.path,
+        format,
+    }
+}
+
+fn validate_rules(rules: &[Rule], states: &[RuleState]) -> Result<()> {
+    let mut ids = HashSet::new();
+    let mut has_ids = false;
+    for (idx, rule) in rules.iter().enumerate() {
+        let id = rule.id.as_deref().unwrap_or("<unnamed>");
+        if let Some(id_value) = rule.id.as_ref() {
+            has_ids = true;
+            if !ids.insert(id_value.clone()) {
+                bail!("duplicate rule id: {id_value}");
+            }
+        }
+        let match_defined = rule.match_.as_ref().map(has_match).unwrap_or(false);
+        let exclude_defined = rule.exclude.as_ref().map(has_match).unwrap_or(false);
+        if !match_defined && !exclude_defined {
+            bail!("rule {idx} ({id}) requires match or exclude");
+        }
+        for criteria in [rule.match_.as_ref(), rule.exclude.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            validate_match_gates(criteria).with_context(|| format!("rule {idx} ({id})"))?;
+        }
+        validate_rule_limits(rule).with_context(|| format!("rule {idx} ({id})"))?;
+        validate_rule_targets(rule).with_context(|| format!("rule {idx} ({id})"))?;
+    }
+    if has_ids {
+        for (idx, rule) in rules.iter().enumerate() {
+            for next in [&rule.next, &rule.on_exhausted].into_iter().flatten() {
+                for next in next.ids() {
+                    if next == "stop" || states.iter().any(|state| state.id == *next) {
+                        continue;
+                    }
+                    if !ids.contains(next) {
+                        let id = rule.id.as_deref().unwrap_or("<unnamed>");
+                        bail!("rule {idx} ({id}) references unknown next: {next}");
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_rule_limits(rule: &Rule) -> Result<()> {
+    if let Some(cooldown) = &rule.cooldown {
+        parse_duration(cooldown).context("invalid cooldown")?;
+    }
+    if let Some(budget) = &rule.budget {
+        if budget.fires == 0 {
+            bail!("budget.fires must be at least 1");
+        }
+        parse_duration(&budget.per).context("invalid budget.per")?;
+    }
+    if rule.on_exhausted.is_some() && rule.max_fires.is_none() && rule.budget.is_none() {
+        bail!("on_exhausted needs max_fires or budget");
+    }
+    Ok(())
+}
+
+fn validate_rule_targets(rule: &Rule) -> Result<()> {
+    if rule.targets.as_ref().is_some_and(Vec::is_empty) {
+        bail!("targets must list at least one pattern");
+    }
+    for pattern in rule.targets.iter().flatten().chain(&rule.send_to) {
+        let pattern = pattern.trim();
+        if pattern.is_empty()
+            || pattern
+                .strip_prefix("title:")
+                .is_some_and(|title| title.trim().is_empty())
+        {
+            bail!("empty target pattern");
+        }
+    }
+    if rule
+        .send_to
+        .as_deref()
+        .is_some_and(|pattern| pattern.trim().starts_with("file://"))
+    {
+        bail!("send_to must name a tmux pane");
+    }
+    Ok(())
+}
+
+fn validate_match_gates(criteria: &MatchCriteria) -> Result<()> {
+    if let Some(idle_for) = criteria
+        .idle_for
+        .as_deref()
+        .filter(|value| !value.trim().is_empty())
+    {
+        parse_duration(idle_for).context("invalid idle_for")?;
+    }
+    if let Some(pattern) = criteria
+        .prompt_ready
+        .as_deref()
+        .filter(|value| !value.trim().is_empty())
+    {
+        Regex::new(pattern).context("invalid prompt_ready regex")?;
+    }
+    if let Some(style) = &criteria.style {
+        if !style.has_filter() {
+            bail!("style needs at least one of fg, bg, bold, dim, italic or underline");
+        }
+        for spec in [&style.fg, &style.bg].into_iter().flatten() {
+            color_matches(spec, None).context("invalid style color")?;
+        }
+        if let Some(regex) = &style.regex {
+            Regex::new(regex).context("invalid style regex")?;
+        }
+    }
+    Ok(())
+}
+
+impl NextStep {
+    fn ids(&self) -> &[String] {
+        match self {
+            Self::One(id) => std::slice::from_ref(id),
+            Self::Any(ids) => ids,
+        }
+    }
+
+    fn is_stop(&self) -> bool {
+        self.ids() == ["stop"]
+    }
+
+    fn label(&self) -> String {
+        self.ids().join("|")
+    }
+}
+
+/// State a target is in: the first state named by its pending step, or the initial state.
+fn current_state<'a>(states: &'a [RuleState], step: Option<&NextStep>) -> Option<&'a RuleState> {
+    match step {
+        None => states.first(),
+        Some(step) => step
+            .ids()
+            .iter()
+            .find_map(|id| states.iter().find(|state| state.id == *id)),
+    }
+}
+
+/// The active state that listens for `rule`; with `next: [a, b]` that is the alternative whose
+/// rules include it, not simply the first one.
+fn owning_state<'a>(
+    states: &'a [RuleState],
+    step: Option<&NextStep>,
+    rule: &Rule,
+) -> Option<&'a RuleState> {
+    let owner = rule.id.as_deref().and_then(|id| {
+        let mut active = match step {
+            Some(step) => step
+                .ids()
+                .iter()
+                .filter_map(|id| states.iter().find(|state| state.id == *id))
+                .collect::<Vec<_>>(),
+            None => states.first().into_iter().collect(),
+        };
+        active.retain(|state| state.rules.iter().any(|rule_id| rule_id == id));
+        active.first().copied()
+    });
+    owner.or_else(|| current_state(states, step))
+}
+
+/// Rule indices a target may match next; `None` means every rule (no flow restriction).
+/// Escape rules are candidates everywhere.
+fn candidate_rules(
+    rules: &[Rule],
+    states: &[RuleState],
+    step: Option<&NextStep>,
+) -> Option<Vec<usize>> {
+    let initial;
+    let ids = match step {
+        Some(step) => step.ids(),
+        None => {
+            initial = [states.first()?.id.clone()];
+            &initial[..]
+        }
+    };
+    let mut wanted = HashSet::new();
+    for id in ids {
+        match states.iter().find(|state| state.id == *id) {
+            Some(state) => wanted.extend(state.rules.iter().map(String::as_str)),
+            None => {
+                wanted.insert(id.as_str());
+            }
+        }
+    }
+    Some(
+        rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| {
+                rule.escape.unwrap_or(false)
+                    || rule.id.as_deref().is_some_and(|id| wanted.contains(id))
+            })
+            .map(|(index, _)| index)
+            .collect(),
+    )
+}
+
+/// Step taken after `rule` fires in `state`: the state's transition for it, else the rule's
+/// own `next`, else staying in the state.
+fn next_step_after(state: Option<&RuleState>, rule: &Rule) -> Option<NextStep> {
+    state
+        .zip(rule.id.as_deref())
+        .and_then(|(state, id)| state.transitions.get(id))
+        .cloned()
+        .or_else(|| rule.next.clone())
+        .or_else(|| state.map(|state| NextStep::One(state.id.clone())))
+}
+
+fn validate_next_step(step: &NextStep, known: &HashSet<&str>, owner: &str) -> Result<()> {
+    if step.ids().is_empty() {
+        bail!("{owner} has an empty next list");
+    }
+    if step.ids().len() > 1 && step.ids().iter().any(|id| id == "stop") {
+        bail!("{owner}: `stop` cannot be combined with other next alternatives");
+    }
+    for id in step.ids() {
+        if id != "stop" && !known.contains(id.as_str()) {
+            bail!("{owner} references unknown next: {id}");
+        }
+    }
+    Ok(())
+}
+
+/// Checks state references, then walks the flow from the initial state to find
+/// unreachable states and states that can never be left.
+fn validate_states(states: &[RuleState], rules: &[Rule]) -> Result<()> {
+    let rule_ids = rules
+        .iter()
+        .filter_map(|rule| rule.id.as_deref())
+        .collect::<HashSet<_>>();
+    let mut known = rule_ids.clone();
+    for state in states {
+        if state.id == "stop" {
+            bail!("state id `stop` is reserved");
+        }
+        if rule_ids.contains(state.id.as_str()) {
+            bail!("state id {} collides with a rule id", state.id);
+        }
+        if !known.insert(state.id.as_str()) {
+            bail!("duplicate state id: {}", state.id);
+        }
+    }
+    for state in states {
+        let owner = format!("state {}", state.id);
+        for rule_id in &state.rules {
+            if !rule_ids.contains(rule_id.as_str()) {
+                bail!("{owner} lists unknown rule: {rule_id}");
+            }
+        }
+        for (rule_id, step) in &state.transitions {
+            if !state.rules.contains(rule_id)
+                && !rules
+                    .iter()
+                    .any(|rule| rule.escape.unwrap_or(false) && rule.id.as_ref() == Some(rule_id))
+            {
+                bail!("{owner} has a transition for rule {rule_id}, which it does not listen for");
+            }
+            validate_next_step(step, &known, &owner)?;
+        }
+        match (&state.timeout, &state.on_timeout) {
+            (Some(timeout), Some(step)) => {
+                parse_duration(timeout).with_context(|| format!("{owner}: invalid timeout"))?;
+                validate_next_step(step, &known, &owner)?;
+            }
+            (Some(_), None) => bail!("{owner} has a timeout but no on_timeout"),
+            (None, Some(_)) => bail!("{owner} has on_timeout but no timeout"),
+            (None, None) => {}
+        }
+    }
+    for (idx, rule) in rules.iter().enumerate() {
+        for step in [&rule.next, &rule.on_exhausted].into_iter().flatten() {
+            let owner = format!("rule {idx} ({})", rule.id.as_deref().unwrap_or("<unnamed>"));
+            validate_next_step(step, &known, &owner)?;
+        }
+    }
+
+    let mut reached = HashSet::new();
+    let mut queue = vec![states[0].id.clone()];
+    while let Some(node) = queue.pop() {
+        if !reached.insert(node.clone()) {
+            continue;
+        }
+        let step = NextStep::One(node);
+        let state = current_state(states, Some(&step));
+        let candidates = candidate_rules(rules, states, Some(&step)).unwrap_or_default();
+        let mut exits = candidates
+            .iter()
+            .map(|index| next_step_after(state, &rules[*index]))
+            .collect::<Vec<_>>();
+        exits.extend(
+            candidates
+                .iter()
+                .filter_map(|index| rules[*index].on_exhausted.clone())
+                .map(Some),
+        );
+        exits.extend(state.map(|state| state.on_timeout.clone()));
+        for exit in exits {
+            match exit {
+                // No step left means the flow restarts at the initial state.
+                None => queue.push(states[0].id.clone()),
+                Some(step) => queue.extend(step.ids().iter().filter(|id| *id != "stop").cloned()),
+            }
+        }
+    }
+    for state in states {
+        if !reached.contains(&state.id) {
+            bail!(
+                "state {} is unreachable from initial state {}",
+                state.id,
+                states[0].id
+            );
+        }
+        let listens = candidate_rules(rules, states, Some(&NextStep::One(state.id.clone())))
+            .is_some_and(|candidates| !candidates.is_empty());
+        if !listens && state.on_timeout.is_none() {
+            bail!(
+                "state {} is a dead end: no rules and no on_timeout",
+                state.id
+            );
+        }
+    }
+    Ok(())
+}
+
+fn validate_done_when(done: &DoneWhen) -> Result<()> {
+    if done.match_.is_none() && done.exec.is_none() {
+        bail!("done_when requires match or exec");
+    }
+    if let Some(criteria) = done.match_.as_ref() {
+        if !has_match(criteria) {
+            bail!("done_when.match needs at least one criterion");
+        }
+        validate_match_gates(criteria).context("invalid done_when.match")?;
+        if let Some(regex) = &criteria.regex {
+            Regex::new(regex).context("invalid done_when.match.regex")?;
+        }
+        if let Some(expr) = &criteria.trigger_expr {
+            parse_trigger_expr(expr).context("invalid done_when.match.trigger_expr")?;
+        }
+    }
+    if done
+        .exec
+        .as_deref()
+        .is_some_and(|command| command.trim().is_empty())
+    {
+        bail!("done_when.exec cannot be empty");
+    }
+    Ok(())
+}
+
+/// `target=... line="..."` when `done_when.match` holds for `output`.
+fn done_when_match_evidence(
+    done: &DoneWhen,
+    target: &str,
+    output: &str,
+    ctx: &MatchContext,
+) -> Result<Option<String>> {
+    let Some(criteria) = done.match_.as_ref() else {
+        return Ok(None);
+    };
+    if !matches_criteria_at(criteria, output, ctx)? {
+        return Ok(None);
+    }
+    let mut line = None;
+    for candidate in output.lines().filter(|line| !line.trim().is_empty()) {
+        if matches_criteria_at(criteria, candidate, ctx)? {
+            line = Some(candidate);
+            break;
+        }
+    }
+    let line = line
+        .or_else(|| output.lines().rev().find(|line| !line.trim().is_empty()))
+        .unwrap_or("")
+        .trim();
+    Ok(Some(format!(
+        "target={target} line=\"{}\"",
+        truncate_text(line, 120, false)
+    )))
+}
+
+/// Runs `done_when.exec` (if any); the goal holds only when it exits 0.
+fn confirm_done_exec(done: &DoneWhen, evidence: String) -> Result<Option<String>> {
+    let Some(command) = done.exec.as_deref() else {
+        return Ok(Some(evidence));
+    };
+    let status = std::process::Command::new("sh")
+        .args(["-lc", command])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .with_context(|| format!("failed to run done_when.exec `{command}`"))?;
+    if !status.success() {
+        return Ok(None);
+    }
+    let exec = format!("exec=\"{command}\" exit=0");
+    Ok(Some(if evidence.is_empty() {
+        exec
+    } else {
+        format!("{evidence} {exec}")
+    }))
+}
+
+fn has_match(criteria: &MatchCriteria) -> bool {
+    has_text_match(criteria) || has_text(&criteria.idle_for) || has_text(&criteria.prompt_ready)
+}
+
+fn has_text_match(criteria: &MatchCriteria) -> bool {
+    criteria.style.is_some()
+        || has_text(&criteria.regex)
+        || has_text(&criteria.trigger_expr)
+        || has_text(&criteria.exact_line)
+        || has_text(&criteria.contains)
+        || has_text(&criteria.starts_with)
+}
+
+fn has_text(value: &Option<String>) -> bool {
+    value
+        .as_ref()
+        .map(|text| !text.trim().is_empty())
+        .unwrap_or(false)
+}
+
+fn validate_tmux_scope(scope: &TargetScope) -> Result<()> {
+    let output = std::process::Command::new("tmux")
+        .arg("-V")
+        .output()
+        .context("failed to run tmux -V")?;
+    if !output.status.success() {
+        bail!("tmux not available on PATH");
+    }
+
+    if matches!(scope, TargetScope::All) {
+        return Ok(());
+    }
+
+    let panes = list_tmux_panes()?;
+    let candidates = select_targets_for_scope(scope, &panes);
+    if candidates.is_empty() {
+        bail!("tmux target scope not found: {}", target_scope_label(scope));
+    }
+    Ok(())
+}
+
+fn validate_tmux_targets(targets: &[String]) -> Result<()> {
+    let panes = list_tmux_panes()?;
+    let available = panes
+        .iter()
+        .map(|pane| pane.target.as_str())
+        .collect::<HashSet<_>>();
+    for target in targets {
+        if !available.contains(target.as_str()) {
+            bail!("tmux target not found: {target}");
+        }
+    }
+    Ok(())
+}
+
+fn validate_file_sources(files: &[String]) -> Result<()> {
+    for file in files {
+        let path = PathBuf::from(file);
+        if !path.exists() {
+            bail!("file source not found: {}", path.display());
+        }
+        if !path.is_file() {
+            bail!("file source is not a regular file: {}", path.display());
+        }
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read file source: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn file_source_key(path: &str) -> String {
+    format!("file://{path}")
+}
+
+fn file_source_path(key: &str) -> Option<&str> {
+    key.strip_prefix("file://")
+}
+
+fn list_tmux_panes() -> Result<Vec<TmuxPane>> {
+    let output = std::process::Command::new("tmux")
+        .args([
+            "list-panes",
+            "-a",
+            "-F",
+            "#{session_name}\t#{window_index}\t#{pane_index}\t#{session_name}:#{window_index}.#{pane_index}\t#{pane_current_command}\t#{pane_current_path}\t#{@loopmux}\t#{pane_title}",
+        ])
+        .output()
+        .context("failed to run tmux list-panes")?;
+    if !output.status.success() {
+        bail!("tmux list-panes failed");
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut panes = Vec::new();
+    for line in stdout.lines() {
+        let mut parts = line.split('\t');
+        let session = parts.next().unwrap_or("").trim();
+        let window = parts.next().unwrap_or("").trim();
+        let _pane = parts.next().unwrap_or("").trim();
+        let target = parts.next().unwrap_or("").trim();
+        let current_command = parts.next().unwrap_or("").trim();
+        let cwd = parts.next().unwrap_or("").trim();
+        let tag = parts.next().unwrap_or("").trim();
+        let title = parts.next().unwrap_or("").trim();
+        if session.is_empty() || window.is_empty() || target.is_empty() {
+            continue;
+        }
+        panes.push(TmuxPane {
+            target: target.to_string(),
+            session: session.to_string(),
+            window: window.to_string(),
+            current_command: current_command.to_string(),
+            cwd: cwd.to_string(),
+            tag: tag.to_string(),
+            title: title.to_string(),
+        });
+    }
+    Ok(panes)
+}
+
+fn resolve_target(target: &str) -> Result<String> {
+    resolve_target_with_current(target, tmux_current_target)
+}
+
+fn resolve_target_offline(target: &str) -> Result<String> {
+    if target.contains(':') {
+        return Ok(target.to_string());
+    }
+    bail!("target shorthand requires tmux; use session:window.pane")
+}
+
+fn resolve_target_scope(target: Option<&str>) -> Result<(TargetScope, String)> {
+    resolve_target_scope_with(target, resolve_target)
+}
+
+fn resolve_target_scope_offline(target: Option<&str>) -> Result<(TargetScope, String)> {
+    resolve_target_scope_with(target, resolve_target_offline)
+}
+
+fn resolve_explicit_targets(targets: &[String], skip_tmux: bool) -> Result<Vec<String>> {
+    let mut resolved = Vec::with_capacity(targets.len());
+    for target in targets {
+        let candidate = if skip_tmux {
+            resolve_target_offline(target)?
+        } else {
+            resolve_target(target)?
+        };
+        parse_target(&candidate)?;
+        resolved.push(candidate);
+    }
+    Ok(dedupe_preserve_order(resolved))
+}
+
+fn resolve_target_scope_with(
+    target: Option<&str>,
+    pane_resolver: fn(&str) -> Result<String>,
+) -> Result<(TargetScope, String)> {
+    let Some(raw) = target
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok((TargetScope::All, "all sessions/windows/panes".to_string()));
+    };
+
+    if raw.eq_ignore_ascii_case("all") {
+        return Ok((TargetScope::All, "all sessions/windows/panes".to_string()));
+    }
+
+    if is_pane_selector(raw) {
+        let selectors = parse_pane_selectors(raw)?;
+        let label = raw.to_string();
+        return Ok((
+            TargetScope::Select {
+                selectors,
+                label: label.clone(),
+            },
+            label,
+        ));
+    }
+
+    if raw.contains(':') {
+        if raw.contains('.') {
+            let resolved = pane_resolver(raw)?;
+            parse_target(&resolved)?;
+            return Ok((TargetScope::Pane(resolved.clone()), resolved));
+        }
+
+        let (session, window) = parse_session_window(raw)?;
+        let label = format!("{session}:{window}.*");
+        return Ok((
+            TargetScope::Window {
+                session: session.to_string(),
+                window: window.to_string(),
+            },
+            label,
+        ));
+    }
+
+    if raw.contains('.') || raw.chars().all(|c| c.is_ascii_digit()) {
+        let resolved = pane_resolver(raw)?;
+        parse_target(&resolved)?;
+        return Ok((TargetScope::Pane(resolved.clone()), resolved));
+    }
+
+    Ok((TargetScope::Session(raw.to_string()), format!("{raw}:*.*")))
+}
+
+fn is_pane_selector(raw: &str) -> bool {
+    raw.starts_with("contains:") || raw.starts_with('@') || raw.contains('=')
+}
+
+/// Parses comma-separated selectors (all must match); `contains:` terms are checked last
+/// because they capture the pane.
+fn parse_pane_selectors(raw: &str) -> Result<Vec<PaneSelector>> {
+    let mut selectors = Vec::new();
+    for term in split_selector_terms(raw) {
+        let term = term.trim();
+        if term.is_empty() {
+            continue;
+        }
+        if let Some(text) = term.strip_prefix("contains:") {
+            let text = unquote_selector(text.trim());
+            if text.is_empty() {
+                bail!("pane selector contains: needs text");
+            }
+            selectors.push(PaneSelector::Contains(text.to_string()));
+            continue;
+        }
+        if let Some(tag) = term.strip_prefix('@').filter(|tag| !tag.contains('=')) {
+            selectors.push(PaneSelector::Tag(SelectorValue::Glob(tag.to_string())));
+            continue;
+        }
+        let Some((key, value)) = term.split_once('=') else {
+            bail!("invalid pane selector: {term} (expected key=value or contains:\"text\")");
+        };
+        let key = key.trim();
+        let value = unquote_selector(value.trim());
+        let is_cwd = matches!(key, "cwd" | "pane_current_path");
+        let matcher = if is_cwd && value.starts_with("~/") {
+            SelectorValue::Glob(expand_workspace_pattern(value)?)
+        } else if let Some(pattern) = value.strip_prefix('~') {
+            SelectorValue::Regex(
+                Regex::new(pattern)
+                    .with_context(|| format!("invalid regex in pane selector: {term}"))?,
+            )
+        } else {
+            SelectorValue::Glob(value.to_string())
+        };
+        let selector = match key {
+            "session" | "session_name" => PaneSelector::Session(matcher),
+            "pane_title" | "title" => PaneSelector::Title(matcher),
+            "current_command" | "command" => PaneSelector::Command(matcher),
+            "cwd" | "pane_current_path" => PaneSelector::Cwd(matcher),
+            "@loopmux" | "tag" => PaneSelector::Tag(matcher),
+            _ => bail!(
+                "unknown pane selector key: {key} (expected session, pane_title, current_command, cwd, @loopmux)"
+            ),
+        };
+        selectors.push(selector);
+    }
+    if selectors.is_empty() {
+        bail!("pane selector is empty");
+    }
+    selectors.sort_by_key(|selector| matches!(selector, PaneSelector::Contains(_)));
+    Ok(selectors)
+}
+
+/// Splits on commas outside quotes and brackets, so `pane_title=~a{1,3}` stays one term.
+fn split_selector_terms(raw: &str) -> Vec<&str> {
+    let mut terms = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut depth = 0usize;
+    for (index, ch) in raw.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '{' | '[' | '(' if !quoted => depth += 1,
+            '}' | ']' | ')' if !quoted => depth = depth.saturating_sub(1),
+            ',' if !quoted && depth == 0 => {
+                terms.push(&raw[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push(&raw[start..]);
+    terms
+}
+
+fn unquote_selector(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|inner| inner.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+impl SelectorValue {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            SelectorValue::Glob(pattern) => wildcard_match(pattern, value),
+            SelectorValue::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl PaneSelector {
+    fn matches(&self, pane: &TmuxPane) -> bool {
+        match self {
+            PaneSelector::Session(value) => value.matches(&pane.session),
+            PaneSelector::Title(value) => value.matches(&pane.title),
+            PaneSelector::Command(value) => value.matches(&pane.current_command),
+            PaneSelector::Cwd(value) => value.matches(&pane.cwd),
+            PaneSelector::Tag(value) => !pane.tag.is_empty() && value.matches(&pane.tag),
+            PaneSelector::Contains(text) => {
+                capture_pane(&pane.target, CaptureWindow::Tail(PREVIEW_CAPTURE_LINES))
+                    .is_ok_and(|output| output.contains(text.as_str()))
+            }
+        }
+    }
+}
+
+fn parse_session_window(value: &str) -> Result<(&str, &str)> {
+    let mut parts = value.splitn(2, ':');
+    let session = parts.next().unwrap_or("").trim();
+    let window = parts.next().unwrap_or("").trim();
+    if session.is_empty() || window.is_empty() {
+        bail!("target must be in the format session, session:window, or session:window.pane");
+    }
+    Ok((session, window))
+}
+
+fn select_targets_for_scope(scope: &TargetScope, panes: &[TmuxPane]) -> Vec<String> {
+    panes
+        .iter()
+        .filter(|pane| match scope {
+            TargetScope::All => true,
+            TargetScope::Session(session) => &pane.session == session,
+            TargetScope::Window { session, window } => {
+                &pane.session == session && &pane.window == window
+            }
+            TargetScope::Pane(target) => &pane.target == target,
+            TargetScope::Select { selectors, .. } => {
+                selectors.iter().all(|selector| selector.matches(pane))
+            }
+        })
+        .map(|pane| pane.target.clone())
+        .collect()
+}
+
+fn target_scope_label(scope: &TargetScope) -> String {
+    match scope {
+        TargetScope::All => "all sessions/windows/panes".to_string(),
+        TargetScope::Session(session) => format!("{session}:*.*"),
+        TargetScope::Window { session, window } => format!("{session}:{window}.*"),
+        TargetScope::Pane(target) => target.clone(),
+        TargetScope::Select { label, .. } => label.clone(),
+    }
+}
+
+fn resolve_target_with_current(target: &str, current_fn: fn() -> Result<String>) -> Result<String> {
+    if target.contains(':') {
+        return Ok(target.to_string());
+    }
+
+    let current = current_fn()
+        .map_err(|_| anyhow::anyhow!("target shorthand requires tmux; use session:window.pane"))?;
+    let (session, window, _pane) = parse_target(&current)?;
+
+    if target.contains('.') {
+        return Ok(format!("{session}:{target}"));
+    }
+
+    if target.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(format!("{session}:{window}.{target}"));
+    }
+
+    bail!("invalid target format: {target}");
+}
+
+fn tmux_current_target() -> Result<String> {
+    let output = std::process::Command::new("tmux")
+        .args([
+            "display-message",
+            "-p",
+            "#{session_name}:#{window_index}.#{pane_index}",
+        ])
+        .output()
+        .context("failed to query current tmux target")?;
+    if !output.status.success() {
+        bail!("tmux not available for target shorthand");
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn parse_target(target: &str) -> Result<(&str, &str, &str)> {
+    let mut parts = target.splitn(2, ':');
+    let session = parts.next().unwrap_or("");
+    let rest = parts.next().unwrap_or("");
+    if session.trim().is_empty() || rest.trim().is_empty() {
+        bail!("target must be in the format session:window.pane");
+    }
+
+    let mut rest_parts = rest.splitn(2, '.');
+    let window = rest_parts.next().unwrap_or("");
+    let pane = rest_parts.next().unwrap_or("");
+    if window.trim().is_empty() || pane.trim().is_empty() {
+        bail!("target must be in the format session:window.pane");
+    }
+
+    Ok((session, window, pane))
+}
+
+fn validate_delay(delay: &DelayConfig) -> Result<()> {
+    match delay.mode {
+        DelayMode::Fixed => {
+            if delay.value.unwrap_or(0) == 0 {
+                bail!("delay.mode=fixed requires value > 0");
+            }
+        }
+        DelayMode::Range | DelayMode::Jitter => {
+            let min = delay.min.unwrap_or(0);
+            let max = delay.max.unwrap_or(0);
+            if min == 0 || max == 0 || min > max {
+                bail!("delay.mode range/jitter requires min/max with min <= max and > 0");
+            }
+            if let DelayMode::Jitter = delay.mode {
+                let jitter = delay.jitter.unwrap_or(0.0);
+                if !(0.0..=1.0).contains(&jitter) {
+                    bail!("delay.mode=jitter requires jitter between 0.0 and 1.0");
+                }
+            }
+        }
+        DelayMode::Backoff => {
+            let backoff = delay
+                .backoff
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("delay.mode=backoff requires backoff"))?;
+            if backoff.base == 0 {
+                bail!("delay.backoff.base must be > 0");
+            }
+            if backoff.factor < 1.0 {
+                bail!("delay.backoff.factor must be >= 1.0");
+            }
+            if let Some(max) = backoff.max {
+                if max < backoff.base {
+                    bail!("delay.backoff.max must be >= base");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct LogEvent {
+    event: String,
+    timestamp: String,
+    target: String,
+    rule_id: Option<String>,
+    detail: Option<String>,
+    sends: Option<u32>,
+    source: Option<String>,
+    /// What satisfied `done_when` (matched line, passing exec).
+    evidence: Option<String>,
+}
+
+impl LogEvent {
+    fn started(config: &ResolvedConfig, timestamp: String) -> Self {
+        Self {
+            event: "started".to_string(),
+            timestamp,
+            target: config.target_label.clone(),
+            rule_id: None,
+            detail: None,
+            sends: None,
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn sent(
+        config: &ResolvedConfig,
+        rule_id: Option<&str>,
+        timestamp: String,
+        prompt: &str,
+    ) -> Self {
+        Self {
+            event: "sent".to_string(),
+            timestamp,
+            target: config.target_label.clone(),
+            rule_id: rule_id.map(|value| value.to_string()),
+            detail: Some(prompt.to_string()),
+            sends: None,
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn delay_scheduled(config: &ResolvedConfig, rule_id: Option<&str>, detail: String) -> Self {
+        Self {
+            event: "delay".to_string(),
+            timestamp: String::new(),
+            target: config.target_label.clone(),
+            rule_id: rule_id.map(|value| value.to_string()),
+            detail: Some(detail),
+            sends: None,
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn stopped(config: &ResolvedConfig, detail: &str, sends: u32) -> Self {
+        Self {
+            event: "stopped".to_string(),
+            timestamp: String::new(),
+            target: config.target_label.clone(),
+            rule_id: None,
+            detail: Some(detail.to_string()),
+            sends: Some(sends),
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn matched(config: &ResolvedConfig, rule_id: Option<&str>) -> Self {
+        Self {
+            event: "match".to_string(),
+            timestamp: String::new(),
+            target: config.target_label.clone(),
+            rule_id: rule_id.map(|value| value.to_string()),
+            detail: None,
+            sends: None,
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn error(config: &ResolvedConfig, detail: String) -> Self {
+        Self {
+            event: "error".to_string(),
+            timestamp: String::new(),
+            target: config.target_label.clone(),
+            rule_id: None,
+            detail: Some(detail),
+            sends: None,
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn status(config: &ResolvedConfig, detail: String) -> Self {
+        Self {
+            event: "status".to_string(),
+            timestamp: String::new(),
+            target: config.target_label.clone(),
+            rule_id: None,
+            detail: Some(detail),
+            sends: None,
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn exec(config: &ResolvedConfig, event: &str, detail: String) -> Self {
+        Self {
+            event: event.to_string(),
+            timestamp: String::new(),
+            target: config.target_label.clone(),
+            rule_id: None,
+            detail: Some(detail),
+            sends: None,
+            source: None,
+            evidence: None,
+        }
+    }
+
+    fn goal_reached(config: &ResolvedConfig, sends: u32, evidence: String) -> Self {
+        Self {
+            evidence: Some(evidence),
+            ..Self::stopped(config, "goal_reached", sends)
+        }
+    }
+
+    /// A send triggered from the TUI picker rather than a rule, logged per recipient.
+    fn sent_manual(config: &ResolvedConfig, target: &str, timestamp: String, prompt: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            source: Some("manual".to_string()),
+            ..Self::sent(config, None, timestamp, prompt)
+        }
+    }
+}
+
+/// One line of a `run --record` session file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SessionRecord {
+    Session {
+        version: u32,
+        run_id: String,
+        started: String,
+        target: String,
+        poll: u64,
+        capture_window: String,
+    },
+    Capture {
+        t_ms: u64,
+        timestamp: String,
+        target: String,
+        hash: String,
+        output: String,
+    },
+    Decision {
+        t_ms: u64,
+        target: String,
+        matched: Vec<Option<String>>,
+        held: Vec<(Option<String>, String)>,
+        planned: Vec<Option<String>>,
+    },
+    Send {
+        t_ms: u64,
+        timestamp: String,
+        target: String,
+        rule_id: Option<String>,
+        prompt: String,
+        /// `manual` for TUI picker sends, which replay does not reproduce.
+        #[serde(default)]
+        source: Option<String>,
+    },
+    /// Pane titles by target, written when they change, so replay can resolve `title:`.
+    Panes {
+        t_ms: u64,
+        titles: BTreeMap<String, String>,
+    },
+}
+
+struct SessionRecorder {
+    file: std::fs::File,
+    started: std::time::Instant,
+    titles: BTreeMap<String, String>,
+}
+
+impl SessionRecorder {
+    fn create(path: &Path, config: &ResolvedConfig, identity: &RunIdentity) -> Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("failed to create session file {}", path.display()))?;
+        let mut recorder = Self {
+            file,
+            started: std::time::Instant::now(),
+            titles: BTreeMap::new(),
+        };
+        recorder.write(&SessionRecord::Session {
+            version: 1,
+            run_id: identity.id.clone(),
+            started: timestamp_now(),
+            target: config.target_label.clone(),
+            poll: config.poll,
+            capture_window: capture_window_label(config.capture_window),
+        })?;
+        Ok(recorder)
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn record_capture(&mut self, target: &str, output: &str) -> Result<()> {
+        self.write(&SessionRecord::Capture {
+            t_ms: self.elapsed_ms(),
+            timestamp: timestamp_now(),
+            target: target.to_string(),
+            hash: hash_output(output),
+            output: output.to_string(),
+        })
+    }
+
+    fn record_panes(&mut self, panes: &[TmuxPane]) -> Result<()> {
+        let titles = panes
+            .iter()
+            .map(|pane| (pane.target.clone(), pane.title.clone()))
+            .collect::<BTreeMap<_, _>>();
+        if titles.is_empty() || titles == self.titles {
+            return Ok(());
+        }
+        self.titles = titles.clone();
+        self.write(&SessionRecord::Panes {
+            t_ms: self.elapsed_ms(),
+            titles,
+        })
+    }
+
+    fn record_decision(&mut self, target: &str, scan: &TargetScan) -> Result<()> {
+        if scan.matched_rule_ids.is_empty() {
+            return Ok(());
+        }
+        self.write(&SessionRecord::Decision {
+            t_ms: self.elapsed_ms(),
+            target: target.to_string(),
+            matched: scan.matched_rule_ids.clone(),
+            held: scan
+                .suppressed
+                .iter()
+                .map(|(rule_id, reason)| (rule_id.clone(), reason.to_string()))
+                .collect(),
+            planned: scan.plans.iter().map(|plan| plan.rule_id.clone()).collect(),
+        })
+    }
+
+    fn record_send(&mut self, target: &str, plan: &SendPlan) -> Result<()> {
+        self.write(&SessionRecord::Send {
+            t_ms: self.elapsed_ms(),
+            timestamp: timestamp_now(),
+            target: target.to_string(),
+            rule_id: plan.rule_id.clone(),
+            prompt: plan.prompt.clone(),
+            source: None,
+        })
+    }
+
+    fn record_manual_send(&mut self, target: &str, prompt: &str) -> Result<()> {
+        self.write(&SessionRecord::Send {
+            t_ms: self.elapsed_ms(),
+            timestamp: timestamp_now(),
+            target: target.to_string(),
+            rule_id: None,
+            prompt: prompt.to_string(),
+            source: Some("manual".to_string()),
+        })
+    }
+
+    fn write(&mut self, record: &SessionRecord) -> Result<()> {
+        let line = serde_json::to_string(record).context("failed to encode session record")?;
+        writeln!(self.file, "{line}").context("failed to write session record")?;
+        Ok(())
+    }
+}
+
+fn read_session_records(path: &Path) -> Result<Vec<SessionRecord>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!("invalid session record at {}:{}", path.display(), idx + 1)
+            })
+        })
+        .collect()
+}
+
+fn capture_window_label(window: CaptureWindow) -> String {
+    match window {
+        CaptureWindow::Tail(lines) => format!("tail:{lines}"),
+        CaptureWindow::Head(lines) => format!("head:{lines}"),
+    }
+}
+
+struct Logger {
+    config: LoggingConfigResolved,
+    file: Option<std::fs::File>,
+}
+
+impl Logger {
+    fn new(config: LoggingConfigResolved) -> Result<Self> {
+        let file = if let Some(path) = &config.path {
+            Some(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open log file {}", path.display()))?,
+            )
+        } else {
+            None
+        };
+        Ok(Self { config, file })
+    }
+
+    fn log(&mut self, mut event: LogEvent) -> Result<()> {
+        let timestamp = OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .unwrap_or_else(|_| "unknown".into());
+        if event.timestamp.is_empty() {
+            event.timestamp = timestamp;
+        }
+        match self.config.format {
+            LogFormatResolved::Text => self.log_text(&event),
+            LogFormatResolved::Jsonl => self.log_json(&event),
+        }
+    }
+
+    fn log_text(&mut self, event: &LogEvent) -> Result<()> {
+        let mut line = format!(
+            "[{}] {} target={}",
+            event.timestamp, event.event, event.target
+        );
+        if let Some(source) = event.source.as_ref() {
+            line.push_str(&format!(" source={source}"));
+        }
+        if let Some(rule_id) = event.rule_id.as_ref() {
+            line.push_str(&format!(" rule={rule_id}"));
+        }
+        if let Some(detail) = event.detail.as_ref() {
+            let sanitized = detail.replace('"', "'");
+            line.push_str(&format!(" detail=\"{}\"", sanitized));
+        }
+        if let Some(sends) = event.sends {
+            line.push_str(&format!(" sends={sends}"));
+        }
+        if let Some(evidence) = event.evidence.as_ref() {
+            line.push_str(&format!(" evidence=\"{}\"", evidence.replace('"', "'")));
+        }
+        line.push('\n');
+        self.write_line(&line)
+    }
+
+    fn log_json(&mut self, event: &LogEvent) -> Result<()> {
+        let mut value = json!({
+            "event": event.event,
+            "timestamp": event.timestamp,
+            "target": event.target,
+            "rule_id": event.rule_id,
+            "detail": event.detail,
+            "sends": event.sends,
+        });
+        if let Some(source) = event.source.as_ref() {
+            value["source"] = json!(source);
+        }
+        if let Some(evidence) = event.evidence.as_ref() {
+            value["evidence"] = json!(evidence);
+        }
+        let mut line = serde_json::to_string(&value).context("failed to serialize log JSON")?;
+        line.push('\n');
+        self.write_line(&line)
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        if let Some(file) = &mut self.file {
+            file.write_all(line.as_bytes())?;
+        } else {
+            print!("{line}");
+        }
+        Ok(())
+    }
+}
+
+fn effective_elapsed_at(
+    now: std::time::Instant,
+    run_started: std::time::Instant,
+    held_total: std::time::Duration,
+    hold_started: Option<std::time::Instant>,
+) -> std::time::Duration {
+    let mut total_held = held_total;
+    if let Some(started_at) = hold_started {
+        total_held += now.saturating_duration_since(started_at);
+    }
+    now.saturating_duration_since(run_started)
+        .saturating_sub(total_held)
+}
+
+fn format_std_duration(duration: std::time::Duration) -> String {
+    let total_seconds = duration.as_secs();
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let seconds = total_seconds % 60;
+    if hours > 0 {
+        format!("{hours}h{minutes}m{seconds}s")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+fn status_line(
+    config: &ResolvedConfig,
+    send_count: u32,
+    max_sends: u32,
+    rule_id: Option<&str>,
+    elapsed: &str,
+) -> String {
+    let progress = if config.infinite {
+        String::from("infinite")
+    } else {
+        format!("{}/{}", send_count, max_sends)
+    };
+    let rule = rule_id.unwrap_or("<unnamed>");
+    let (selector_label, selector_value) = if config.exec_command.is_some() {
+        ("event", format_exec_event_label(rule))
+    } else {
+        ("rule", rule.to_string())
+    };
+    let profile = config.profile_id.as_deref().unwrap_or("-");
+    let icon = ">";
+    let color = "\u{001B}[32m";
+    let reset = "\u{001B}[0m";
+    format!(
+        "{}{} status:{} profile={} target={} progress={} {}={} elapsed={}{}",
+        color,
+        icon,
+        reset,
+        profile,
+        config.target_label,
+        progress,
+        selector_label,
+        selector_value,
+        elapsed,
+        reset
+    )
+}
+
+fn format_exec_event_label(value: &str) -> String {
+    match value {
+        "exec:started" => "started".to_string(),
+        "exec:running" => "running".to_string(),
+        "exec:ok" => "ok".to_string(),
+        "exec:fail" => "fail".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_with(match_: Option<MatchCriteria>, exclude: Option<MatchCriteria>) -> Rule {
+        Rule {
+            id: None,
+            match_,
+            exclude,
+            action: None,
+            delay: None,
+            confirm_seconds: None,
+            next: None,
+            priority: None,
+            escape: None,
+            max_fires: None,
+            cooldown: None,
+            budget: None,
+            targets: None,
+            send_to: None,
+            on_exhausted: None,
+        }
+    }
+
+    fn match_regex(pattern: &str) -> MatchCriteria {
+        MatchCriteria {
+            regex: Some(pattern.to_string()),
+            trigger_expr: None,
+            exact_line: None,
+            contains: None,
+            starts_with: None,
+            idle_for: None,
+            prompt_ready: None,
+            style: None,
+        }
+    }
+
+    fn match_contains(value: &str) -> MatchCriteria {
+        MatchCriteria {
+            regex: None,
+            trigger_expr: None,
+            exact_line: None,
+            contains: Some(value.to_string()),
+            starts_with: None,
+            idle_for: None,
+            prompt_ready: None,
+            style: None,
+        }
+    }
+
+    #[test]
+    fn trigger_expr_respects_precedence() {
+        let expr = "A || B && C";
+        assert!(matches_trigger_expr(expr, "A only").unwrap());
+        assert!(!matches_trigger_expr(expr, "B only").unwrap());
+        assert!(matches_trigger_expr(expr, "B C").unwrap());
+    }
+
+    #[test]
+    fn trigger_expr_respects_parentheses() {
+        let expr = "(A || B) && C";
+        assert!(!matches_trigger_expr(expr, "B only").unwrap());
+        assert!(matches_trigger_expr(expr, "B C").unwrap());
+    }
+
+    #[test]
+    fn trigger_expr_trailing_operator_error() {
+        let err = parse_trigger_expr("READY &&").unwrap_err();
+        assert!(err.to_string().contains("trailing operator"));
+    }
+
+    #[test]
+    fn trigger_expr_empty_term_error() {
+        let err = parse_trigger_expr("READY && || DONE").unwrap_err();
+        assert!(err.to_string().contains("expected term after '&&'"));
+    }
+
+    #[test]
+    fn trigger_expr_missing_paren_error() {
+        let err = parse_trigger_expr("(READY || DONE").unwrap_err();
+        assert!(err.to_string().contains("missing right parenthesis"));
+    }
+
+    #[test]
+    fn trigger_expr_unexpected_token_error() {
+        let err = parse_trigger_expr(") READY").unwrap_err();
+        assert!(err.to_string().contains("unexpected token"));
+    }
+
+    #[test]
+    fn trigger_expr_invalid_regex_error() {
+        let err = parse_trigger_expr("[").unwrap_err();
+        assert!(err.to_string().contains("invalid regex term"));
+    }
+
+    #[test]
+    fn trigger_expr_supports_negation() {
+        let expr = "DONE && !ERROR";
+        assert!(matches_trigger_expr(expr, "DONE").unwrap());
+        assert!(!matches_trigger_expr(expr, "DONE\nERROR").unwrap());
+        assert!(matches_trigger_expr("!(A || B)", "C").unwrap());
+        let err = parse_trigger_expr("DONE && !").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("at pos 8: expected term after '!'")
+        );
+    }
+
+    #[test]
+    fn trigger_expr_typed_atoms_scope_matches() {
+        let output = "  READY  \nnote: not ready (yet)\n> \n\n";
+        assert!(matches_trigger_expr(r#"line:"READY""#, output).unwrap());
+        assert!(!matches_trigger_expr(r#"line:"ready""#, output).unwrap());
+        assert!(matches_trigger_expr(r#"contains:"ready (yet)""#, output).unwrap());
+        assert!(matches_trigger_expr("last_line:/^>\\s*$/", output).unwrap());
+        assert!(!matches_trigger_expr(r#"last_line:"READY""#, output).unwrap());
+        assert!(matches_trigger_expr(r#"contains:"say \"hi\"""#, "say \"hi\"").unwrap());
+        let err = parse_trigger_expr(r#"A && line:"READY"#).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("at pos 5: unterminated string in 'line:' atom")
+        );
+    }
+
+    #[test]
+    fn trigger_expr_then_requires_order() {
+        let expr = "PLAN then DONE";
+        assert!(matches_trigger_expr(expr, "PLAN\nwork\nDONE").unwrap());
+        assert!(!matches_trigger_expr(expr, "DONE\nPLAN").unwrap());
+        assert!(matches_trigger_expr("A then B && C", "C A B").unwrap());
+        assert!(matches_trigger_expr("thenable", "thenable").unwrap());
+        let err = parse_trigger_expr("then DONE").unwrap_err();
+        assert!(err.to_string().contains("expected term before 'then'"));
+    }
+
+    #[test]
+    fn trigger_expr_or_then_resumes_after_earliest_operand() {
+        let expr = "(X || Y) then Z";
+        assert!(matches_trigger_expr(expr, "Y Z X").unwrap());
+        assert!(matches_trigger_expr(expr, "X Z").unwrap());
+        assert!(!matches_trigger_expr(expr, "Z Y X").unwrap());
+    }
+
+    #[test]
+    fn wildcard_match_handles_star_patterns() {
+        assert!(wildcard_match("/tmp/*/repo", "/tmp/demo/repo"));
+        assert!(wildcard_match(
+            "/Users/*/Codes/*",
+            "/Users/diego/Codes/Projects"
+        ));
+        assert!(!wildcard_match("/tmp/*/repo", "/tmp/demo/repo/sub"));
+    }
+
+    #[test]
+    fn workspace_loader_merges_main_runs_events_and_imports() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-workspace-test-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let imported = root.join("imported.yaml");
+        let main = root.join("config.yaml");
+
+        std::fs::write(
+            &imported,
+            r#"
+runs:
+  - id: imported-run
+    target: "ai:9.0"
+    iterations: 1
+    default_action:
+      prompt: "imported"
+"#,
+        )
+        .unwrap();
+
+        std::fs::write(
+            &main,
+            format!(
+                r#"
+id: main-run
+target: "ai:1.0"
+iterations: 1
+default_action:
+  prompt: "main"
+imports:
+  - {}
+runs:
+  - id: child-run
+    target: "ai:2.0"
+    iterations: 1
+    default_action:
+      prompt: "child"
+events:
+  - id: event-run
+    target: "ai:3.0"
+    iterations: 1
+    default_action:
+      prompt: "event"
+"#,
+                imported.display()
+            ),
+        )
+        .unwrap();
+
+        let profiles = load_workspace_profiles(&main).unwrap();
+        let ids = profiles
+            .iter()
+            .map(|profile| profile.id.clone())
+            .collect::<Vec<_>>();
+        assert!(ids.contains(&"main-run".to_string()));
+        assert!(ids.contains(&"child-run".to_string()));
+        assert!(ids.contains(&"event-run".to_string()));
+        assert!(ids.contains(&"imported-run".to_string()));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn selected_workspace_profiles_respects_enabled_and_cwd() {
+        let cwd = PathBuf::from("/tmp/demo");
+        let profiles = vec![
+            ResolvedRunProfile {
+                id: "match-enabled".to_string(),
+                source_path: PathBuf::from("/tmp/config.yaml"),
+                config: Config::default(),
+                enabled: true,
+                when: RunProfileWhen {
+                    cwd_matches: Some(vec!["/tmp/*".to_string()]),
+                },
+            },
+            ResolvedRunProfile {
+                id: "match-disabled".to_string(),
+                source_path: PathBuf::from("/tmp/config.yaml"),
+                config: Config::default(),
+                enabled: false,
+                when: RunProfileWhen {
+                    cwd_matches: Some(vec!["/tmp/*".to_string()]),
+                },
+            },
+            ResolvedRunProfile {
+                id: "non-match-enabled".to_string(),
+                source_path: PathBuf::from("/tmp/config.yaml"),
+                config: Config::default(),
+                enabled: true,
+                when: RunProfileWhen {
+                    cwd_matches: Some(vec!["/repo/*".to_string()]),
+                },
+            },
+        ];
+
+        let startup = selected_workspace_profiles(&profiles, &cwd, false)
+            .into_iter()
+            .map(|profile| profile.id)
+            .collect::<Vec<_>>();
+        assert_eq!(startup, vec!["match-enabled".to_string()]);
+
+        let all = selected_workspace_profiles(&profiles, &cwd, true)
+            .into_iter()
+            .map(|profile| profile.id)
+            .collect::<Vec<_>>();
+        assert_eq!(all.len(), 3);
+    }
+
+    #[test]
+    fn resolve_workspace_config_path_uses_override() {
+        let path = PathBuf::from("/tmp/loopmux-custom.yaml");
+        let resolved = resolve_workspace_config_path(Some(&path)).unwrap();
+        assert_eq!(resolved, path);
+    }
+
+    #[test]
+    fn ensure_default_workspace_config_creates_template_with_continue_loop_event() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-ensure-config-create-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        let config_path = root.join("loopmux").join("config.yaml");
+
+        ensure_default_workspace_config_exists(&config_path).unwrap();
+
+        let contents = std::fs::read_to_string(&config_path).unwrap();
+        assert!(contents.contains("exact_line: \"<CONTINUE-LOOP>\""));
+        assert!(contents.contains("continue-loop"));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn ensure_default_workspace_config_keeps_existing_file() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-ensure-config-existing-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let config_path = root.join("config.yaml");
+        std::fs::write(&config_path, "id: existing\n").unwrap();
+
+        ensure_default_workspace_config_exists(&config_path).unwrap();
+
+        let contents = std::fs::read_to_string(&config_path).unwrap();
+        assert_eq!(contents, "id: existing\n");
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn config_doctor_reports_duplicate_profile_ids() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-doctor-dup-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let config_path = root.join("config.yaml");
+        std::fs::write(
+            &config_path,
+            r#"
+runs:
+  - id: same
+    target: "ai:1.0"
+    iterations: 1
+    default_action:
+      prompt: "a"
+  - id: same
+    target: "ai:2.0"
+    iterations: 1
+    default_action:
+      prompt: "b"
+"#,
+        )
+        .unwrap();
+
+        let err = config_doctor(Some(&config_path), true).unwrap_err();
+        assert!(err.to_string().contains("duplicate profile id"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn config_doctor_reports_multiple_tui_profiles() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-doctor-tui-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let config_path = root.join("config.yaml");
+        std::fs::write(
+            &config_path,
+            r#"
+runs:
+  - id: one
+    target: "ai:1.0"
+    iterations: 1
+    tui: true
+    default_action:
+      prompt: "a"
+  - id: two
+    target: "ai:2.0"
+    iterations: 1
+    tui: true
+    default_action:
+      prompt: "b"
+"#,
+        )
+        .unwrap();
+
+        let err = config_doctor(Some(&config_path), true).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("multiple selected profiles enable `tui`")
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn config_test_reports_missing_profile() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-config-test-missing-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let config_path = root.join("config.yaml");
+        std::fs::write(
+            &config_path,
+            r#"
+runs:
+  - id: one
+    target: "ai:1.0"
+    iterations: 1
+    default_action:
+      prompt: "a"
+"#,
+        )
+        .unwrap();
+
+        let err = config_test(Some(&config_path), "missing").unwrap_err();
+        assert!(err.to_string().contains("not found"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn config_test_reports_duplicate_profile_ids() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-config-test-dup-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let config_path = root.join("config.yaml");
+        std::fs::write(
+            &config_path,
+            r#"
+runs:
+  - id: same
+    target: "ai:1.0"
+    iterations: 1
+    default_action:
+      prompt: "a"
+  - id: same
     target: "ai:2.0"
     iterations: 1
-    tui: true
     default_action:
       prompt: "b"
 "#,
         )
-        .unwrap();
+        .unwrap();
+
+        let err = config_test(Some(&config_path), "same").unwrap_err();
+        assert!(err.to_string().contains("duplicated"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn matches_criteria_regex_and_contains() {
+        let output = "hello world";
+        assert!(matches_criteria(&match_regex("hello"), output).unwrap());
+        assert!(matches_criteria(&match_contains("world"), output).unwrap());
+        assert!(!matches_criteria(&match_contains("missing"), output).unwrap());
+    }
+
+    #[test]
+    fn matches_criteria_exact_line() {
+        let criteria = MatchCriteria {
+            regex: None,
+            trigger_expr: None,
+            exact_line: Some("<CONTINUE-LOOP>".to_string()),
+            contains: None,
+            starts_with: None,
+            idle_for: None,
+            prompt_ready: None,
+            style: None,
+        };
+        assert!(matches_criteria(&criteria, "foo\n  <CONTINUE-LOOP>  \nbar").unwrap());
+        assert!(!matches_criteria(&criteria, "foo <CONTINUE-LOOP> bar").unwrap());
+    }
+
+    #[test]
+    fn matches_criteria_trigger_expr() {
+        let criteria = MatchCriteria {
+            regex: None,
+            trigger_expr: Some("(READY || DONE) && GO".to_string()),
+            exact_line: None,
+            contains: None,
+            starts_with: None,
+            idle_for: None,
+            prompt_ready: None,
+            style: None,
+        };
+        assert!(matches_criteria(&criteria, "READY GO").unwrap());
+        assert!(!matches_criteria(&criteria, "READY").unwrap());
+    }
+
+    #[test]
+    fn matches_criteria_invalid_regex() {
+        let output = "hello";
+        assert!(matches_criteria(&match_regex("["), output).is_err());
+    }
+
+    #[test]
+    fn matches_rule_respects_exclude() {
+        let rule = rule_with(Some(match_regex("hello")), Some(match_regex("world")));
+        let output = "hello world";
+        assert!(!matches_rule(&rule, output).unwrap());
+    }
+
+    #[test]
+    fn matches_rule_exclude_only() {
+        let rule = rule_with(None, Some(match_regex("skip")));
+        assert!(matches_rule(&rule, "ok").unwrap());
+        assert!(!matches_rule(&rule, "skip this").unwrap());
+    }
+
+    #[test]
+    fn select_rules_priority() {
+        let mut rule_a = rule_with(Some(match_contains("hit")), None);
+        rule_a.priority = Some(1);
+        let mut rule_b = rule_with(Some(match_contains("hit")), None);
+        rule_b.priority = Some(2);
+        let rules = vec![rule_a, rule_b];
+        let matches = select_rules(
+            "hit",
+            &rules,
+            &RuleEval::Priority,
+            None,
+            &MatchContext::default(),
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].index, 1);
+    }
+
+    #[test]
+    fn select_rules_multi_match() {
+        let rule_a = rule_with(Some(match_contains("hit")), None);
+        let rule_b = rule_with(Some(match_contains("hit")), None);
+        let rules = vec![rule_a, rule_b];
+        let matches = select_rules(
+            "hit",
+            &rules,
+            &RuleEval::MultiMatch,
+            None,
+            &MatchContext::default(),
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].index, 0);
+        assert_eq!(matches[1].index, 1);
+    }
+
+    #[test]
+    fn resolve_run_config_requires_trigger() {
+        let args = RunArgs {
+            config: None,
+            prompt: Some("Do it".to_string()),
+            trigger: None,
+            trigger_expr: None,
+            trigger_exact_line: false,
+            exclude: None,
+            pre: None,
+            post: None,
+            exec: None,
+            target: vec!["ai:5.0".to_string()],
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(1),
+            tail: None,
+            head: None,
+            once: false,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: None,
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: None,
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: None,
+        };
+        assert!(resolve_run_config(&args).is_err());
+    }
+
+    #[test]
+    fn resolve_run_config_inline_exec_builds_exec_config() {
+        let args = RunArgs {
+            config: None,
+            prompt: None,
+            trigger: None,
+            trigger_expr: None,
+            trigger_exact_line: false,
+            exclude: None,
+            pre: None,
+            post: None,
+            exec: Some("gw-watch-comp --mode check".to_string()),
+            target: Vec::new(),
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(3),
+            tail: None,
+            head: None,
+            once: false,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: Some(5),
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: Some("30s".to_string()),
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: Some("gw-watch".to_string()),
+        };
+
+        let config = resolve_run_config(&args).unwrap();
+        assert_eq!(
+            config.exec.as_ref().map(|value| value.command.as_str()),
+            Some("gw-watch-comp --mode check")
+        );
+        assert!(config.default_action.is_none());
+        assert!(config.rules.is_none());
+    }
+
+    #[test]
+    fn resolve_run_config_rejects_exec_with_prompt_mode_flags() {
+        let args = RunArgs {
+            config: None,
+            prompt: Some("Do it".to_string()),
+            trigger: Some("Done".to_string()),
+            trigger_expr: None,
+            trigger_exact_line: false,
+            exclude: None,
+            pre: None,
+            post: None,
+            exec: Some("gw-watch-comp".to_string()),
+            target: Vec::new(),
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(1),
+            tail: None,
+            head: None,
+            once: false,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: None,
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: None,
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: None,
+        };
+
+        let err = resolve_run_config(&args).unwrap_err();
+        assert!(err.to_string().contains("--exec cannot be combined"));
+    }
+
+    #[test]
+    fn resolve_run_config_inline_builds_rule() {
+        let args = RunArgs {
+            config: None,
+            prompt: Some("Do it".to_string()),
+            trigger: Some("Done".to_string()),
+            trigger_expr: None,
+            trigger_exact_line: false,
+            exclude: Some("PROD".to_string()),
+            pre: Some("pre".to_string()),
+            post: Some("post".to_string()),
+            exec: None,
+            target: vec!["ai:5.0".to_string()],
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(2),
+            tail: Some(123),
+            head: None,
+            once: true,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: None,
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: None,
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: None,
+        };
+        let config = resolve_run_config(&args).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, args.tail, args.head, args.once, false, false, None, None,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(resolved.capture_window, CaptureWindow::Tail(123)));
+        assert!(resolved.once);
+        assert_eq!(resolved.rules.len(), 1);
+        assert_eq!(
+            resolved.trigger_confirm_seconds,
+            DEFAULT_TRIGGER_CONFIRM_SECONDS
+        );
+        assert_eq!(
+            resolved.rules[0].match_.as_ref().unwrap().regex.as_deref(),
+            Some("Done")
+        );
+        assert_eq!(
+            resolved.rules[0].exclude.as_ref().unwrap().regex.as_deref(),
+            Some("PROD")
+        );
+    }
+
+    #[test]
+    fn resolve_run_config_inline_trigger_expr_mode() {
+        let args = RunArgs {
+            config: None,
+            prompt: Some("Do it".to_string()),
+            trigger: None,
+            trigger_expr: Some("READY && GO".to_string()),
+            trigger_exact_line: false,
+            exclude: None,
+            pre: None,
+            post: None,
+            exec: None,
+            target: vec!["ai:5.0".to_string()],
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(1),
+            tail: Some(1),
+            head: None,
+            once: false,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: None,
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: None,
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: None,
+        };
+        let config = resolve_run_config(&args).unwrap();
+        let mut rules = config.rules.unwrap();
+        let matcher = rules.remove(0).match_.unwrap();
+        assert!(matcher.regex.is_none());
+        assert_eq!(matcher.trigger_expr.as_deref(), Some("READY && GO"));
+        assert!(matcher.exact_line.is_none());
+    }
+
+    #[test]
+    fn resolve_run_config_inline_exact_line_mode() {
+        let args = RunArgs {
+            config: None,
+            prompt: Some("Do it".to_string()),
+            trigger: Some("<CONTINUE-LOOP>".to_string()),
+            trigger_expr: None,
+            trigger_exact_line: true,
+            exclude: None,
+            pre: None,
+            post: None,
+            exec: None,
+            target: vec!["ai:5.0".to_string()],
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(2),
+            tail: Some(1),
+            head: None,
+            once: true,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: None,
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: None,
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: None,
+        };
+        let config = resolve_run_config(&args).unwrap();
+        let mut rules = config.rules.unwrap();
+        let rule = rules.remove(0);
+        let matcher = rule.match_.unwrap();
+        assert!(matcher.regex.is_none());
+        assert_eq!(matcher.exact_line.as_deref(), Some("<CONTINUE-LOOP>"));
+    }
+
+    #[test]
+    fn resolve_config_prefers_head_window_when_set() {
+        let args = RunArgs {
+            config: None,
+            prompt: Some("Do it".to_string()),
+            trigger: Some("Done".to_string()),
+            trigger_expr: None,
+            trigger_exact_line: false,
+            exclude: None,
+            pre: None,
+            post: None,
+            exec: None,
+            target: vec!["ai:5.0".to_string()],
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(1),
+            tail: None,
+            head: Some(7),
+            once: false,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: None,
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: None,
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: None,
+        };
+        let config = resolve_run_config(&args).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, args.tail, args.head, false, false, false, None, None, None,
+        )
+        .unwrap();
+        assert!(matches!(resolved.capture_window, CaptureWindow::Head(7)));
+    }
+
+    #[test]
+    fn resolve_config_supports_multiple_explicit_tmux_targets() {
+        let args = RunArgs {
+            config: None,
+            prompt: Some("Do it".to_string()),
+            trigger: Some("Done".to_string()),
+            trigger_expr: None,
+            trigger_exact_line: false,
+            exclude: None,
+            pre: None,
+            post: None,
+            exec: None,
+            target: vec!["ai:5.0".to_string(), "codex:1.0".to_string()],
+            targets_file: Vec::new(),
+            file: Vec::new(),
+            files_file: Vec::new(),
+            iterations: Some(1),
+            tail: Some(5),
+            head: None,
+            once: false,
+            dry_run: false,
+            single_line: false,
+            tui: false,
+            poll: None,
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            no_trigger_edge: false,
+            no_recheck_before_send: false,
+            fanout: FanoutMode::Matched,
+            duration: None,
+            history_limit: None,
+            record: None,
+            seed: None,
+            name: None,
+        };
+        let config = resolve_run_config(&args).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, args.tail, args.head, false, false, false, None, None, None,
+        )
+        .unwrap();
+        assert_eq!(
+            resolved.explicit_targets,
+            Some(vec!["ai:5.0".to_string(), "codex:1.0".to_string()])
+        );
+    }
+
+    #[test]
+    fn resolve_config_rejects_missing_file_source() {
+        let config = Config {
+            target: Some("ai:5.0".to_string()),
+            targets: None,
+            files: Some(vec!["/tmp/loopmux-missing-source.log".to_string()]),
+            exec: None,
+            iterations: Some(1),
+            infinite: None,
+            poll: Some(1),
+            trigger_confirm_seconds: Some(0),
+            log_preview_lines: Some(1),
+            trigger_edge: Some(true),
+            recheck_before_send: Some(true),
+            fanout: Some(FanoutMode::Matched),
+            submit_keys: None,
+            duration: None,
+            seed: None,
+            rule_eval: Some(RuleEval::FirstMatch),
+            default_action: Some(Action {
+                pre: None,
+                prompt: Some(PromptBlock::Single("go".to_string())),
+                post: None,
+            }),
+            delay: None,
+            rules: Some(vec![rule_with(Some(match_contains("ok")), None)]),
+            logging: None,
+            template_vars: None,
+            tail: Some(1),
+            once: Some(false),
+            single_line: Some(false),
+            tui: Some(false),
+            name: Some("test".to_string()),
+            ui: None,
+            done_when: None,
+            capture: None,
+            capture_ansi: None,
+            capture_join: None,
+            states: None,
+            workspace_path: None,
+        };
+        let err = resolve_config(
+            config,
+            None,
+            None,
+            true,
+            Some(1),
+            None,
+            false,
+            false,
+            false,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("file source not found"));
+    }
+
+    #[test]
+    fn resolve_config_exec_mode_without_prompt_target_or_rules() {
+        let config = Config {
+            target: None,
+            targets: None,
+            files: None,
+            exec: Some(ExecConfig {
+                command: "gw-watch-comp".to_string(),
+            }),
+            iterations: Some(3),
+            infinite: None,
+            poll: Some(7),
+            trigger_confirm_seconds: None,
+            log_preview_lines: None,
+            trigger_edge: None,
+            recheck_before_send: None,
+            fanout: None,
+            submit_keys: None,
+            duration: Some("30s".to_string()),
+            seed: None,
+            rule_eval: None,
+            default_action: None,
+            delay: None,
+            rules: None,
+            logging: None,
+            template_vars: None,
+            tail: None,
+            once: None,
+            single_line: None,
+            tui: None,
+            name: Some("watcher".to_string()),
+            ui: None,
+            done_when: None,
+            capture: None,
+            capture_ansi: None,
+            capture_join: None,
+            states: None,
+            workspace_path: None,
+        };
+
+        let resolved = resolve_config(
+            config,
+            None,
+            None,
+            true,
+            None,
+            None,
+            false,
+            false,
+            false,
+            None,
+            None,
+            Some("watcher".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(resolved.exec_command.as_deref(), Some("gw-watch-comp"));
+        assert_eq!(resolved.target_label, "exec://gw-watch-comp");
+        assert!(!resolved.has_prompt);
+        assert!(resolved.rules.is_empty());
+    }
+
+    #[test]
+    fn parse_target_valid() {
+        let (session, window, pane) = parse_target("ai:5.0").unwrap();
+        assert_eq!(session, "ai");
+        assert_eq!(window, "5");
+        assert_eq!(pane, "0");
+    }
+
+    #[test]
+    fn parse_target_invalid() {
+        assert!(parse_target("ai").is_err());
+        assert!(parse_target("ai:5").is_err());
+        assert!(parse_target("ai:.0").is_err());
+    }
+
+    #[test]
+    fn resolve_target_shorthand_pane_only() {
+        let resolved = resolve_target_with_current("0", || Ok("ai:5.2".to_string())).unwrap();
+        assert_eq!(resolved, "ai:5.0");
+    }
+
+    #[test]
+    fn resolve_target_shorthand_window_pane() {
+        let resolved = resolve_target_with_current("2.1", || Ok("ai:5.2".to_string())).unwrap();
+        assert_eq!(resolved, "ai:2.1");
+    }
+
+    #[test]
+    fn resolve_target_scope_defaults_to_all() {
+        let (scope, label) =
+            resolve_target_scope_with(None, |value| Ok(value.to_string())).unwrap();
+        assert!(matches!(scope, TargetScope::All));
+        assert_eq!(label, "all sessions/windows/panes");
+    }
+
+    #[test]
+    fn resolve_target_scope_session() {
+        let (scope, label) =
+            resolve_target_scope_with(Some("ai"), |value| Ok(value.to_string())).unwrap();
+        assert!(matches!(scope, TargetScope::Session(ref value) if value == "ai"));
+        assert_eq!(label, "ai:*.*");
+    }
+
+    #[test]
+    fn resolve_target_scope_window() {
+        let (scope, label) =
+            resolve_target_scope_with(Some("ai:5"), |value| Ok(value.to_string())).unwrap();
+        assert!(
+            matches!(scope, TargetScope::Window { ref session, ref window } if session == "ai" && window == "5")
+        );
+        assert_eq!(label, "ai:5.*");
+    }
+
+    #[test]
+    fn resolve_explicit_targets_dedupes_preserving_order() {
+        let targets = vec![
+            "ai:5.0".to_string(),
+            "codex:1.0".to_string(),
+            "ai:5.0".to_string(),
+        ];
+        let resolved = resolve_explicit_targets(&targets, true).unwrap();
+        assert_eq!(resolved, vec!["ai:5.0", "codex:1.0"]);
+    }
+
+    #[test]
+    fn collect_source_inputs_merges_and_dedupes_in_order() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-test-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let targets_file = root.join("targets.txt");
+        std::fs::write(&targets_file, "# comment\nai:5.0\nclaude:2.0\nai:5.0\n").unwrap();
+        let files_file = root.join("files.txt");
+        std::fs::write(
+            &files_file,
+            "# comment\n/tmp/a.log\n/tmp/b.log\n/tmp/a.log\n",
+        )
+        .unwrap();
+
+        let sources = collect_source_inputs(
+            &["codex:1.0".to_string(), "ai:5.0".to_string()],
+            std::slice::from_ref(&targets_file),
+            &[PathBuf::from("/tmp/a.log")],
+            std::slice::from_ref(&files_file),
+        )
+        .unwrap();
+
+        assert_eq!(
+            sources.tmux_targets,
+            vec!["codex:1.0", "ai:5.0", "claude:2.0"]
+        );
+        assert_eq!(sources.file_paths, vec!["/tmp/a.log", "/tmp/b.log"]);
+
+        let _ = std::fs::remove_file(targets_file);
+        let _ = std::fs::remove_file(files_file);
+        let _ = std::fs::remove_dir(root);
+    }
+
+    #[test]
+    fn collect_source_inputs_errors_for_missing_list_file() {
+        let missing = PathBuf::from("/tmp/loopmux-missing-targets-file.txt");
+        let err = collect_source_inputs(&[], &[missing], &[], &[]).unwrap_err();
+        assert!(err.to_string().contains("failed to read list file"));
+    }
+
+    #[test]
+    fn capture_file_respects_head_and_tail_windows() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-capture-test-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let file = root.join("sample.log");
+        std::fs::write(&file, "A\nB\nC\nD\n").unwrap();
+
+        let tail = capture_file(&file.display().to_string(), CaptureWindow::Tail(2)).unwrap();
+        let head = capture_file(&file.display().to_string(), CaptureWindow::Head(2)).unwrap();
+
+        assert_eq!(tail, "C\nD");
+        assert_eq!(head, "A\nB");
+
+        let _ = std::fs::remove_file(file);
+        let _ = std::fs::remove_dir(root);
+    }
+
+    #[test]
+    fn capture_since_last_send_skips_output_before_mark() {
+        let file = std::env::temp_dir().join(format!(
+            "loopmux-capture-since-{}.log",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::write(&file, "build\nAll tests passed\n").unwrap();
+        let source = file_source_key(&file.display().to_string());
+        let yaml = "target: \"ai:1.0\"\niterations: 1\ntail: 5\ncapture: since_last_send\ndefault_action:\n  prompt: go\nrules:\n  - match:\n      contains: \"All tests passed\"\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .unwrap();
+        assert_eq!(resolved.capture, CaptureMode::SinceLastSend);
+
+        assert!(effective_capture_window(&resolved, None).is_some());
+        assert_eq!(
+            capture_for_scan(&source, &resolved, None).unwrap(),
+            "build\nAll tests passed"
+        );
+
+        let flags = PaneCaptureFlags {
+            ansi: false,
+            join: false,
+        };
+        let mark = output_mark(&source, flags).unwrap();
+        assert_eq!(mark.position, 2);
+        std::fs::write(&file, "build\nAll tests passed\nrunning again\n").unwrap();
+        assert!(effective_capture_window(&resolved, Some(&mark)).is_none());
+        let output = capture_for_scan(&source, &resolved, Some(&mark)).unwrap();
+        assert_eq!(output, "running again");
+        assert!(!matches_rule(&resolved.rules[0], &output).unwrap());
+
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn find_mark_anchor_relocates_mark_after_history_trim() {
+        let anchor = [
+            "$ cargo test".to_string(),
+            "test result: FAILED".to_string(),
+        ];
+        // The anchor sat at lines 4-5; tmux then dropped the 3 oldest lines.
+        let trimmed = [
+            "old 4",
+            "$ cargo test",
+            "test result: FAILED",
+            "fixing",
+            "$ cargo test",
+            "test result: ok",
+        ];
+        assert_eq!(find_mark_anchor(&trimmed, 4, &anchor), Some(3));
+        assert_eq!(
+            trimmed[3..].join("\n"),
+            "fixing\n$ cargo test\ntest result: ok"
+        );
+        assert_eq!(find_mark_anchor(&trimmed[3..], 4, &anchor), None);
+        assert_eq!(find_mark_anchor(&trimmed, 4, &[]), None);
+    }
+
+    #[test]
+    fn file_source_key_round_trip() {
+        let key = file_source_key("/tmp/a.log");
+        assert_eq!(file_source_path(&key), Some("/tmp/a.log"));
+        assert!(file_source_path("ai:5.0").is_none());
+    }
+
+    #[test]
+    fn sanitize_run_name_normalizes_chars() {
+        assert_eq!(sanitize_run_name(" My Run #1 "), "my-run--1");
+        assert_eq!(sanitize_run_name("alpha_beta"), "alpha_beta");
+    }
+
+    #[test]
+    fn external_control_renew_resets_runtime_state() {
+        let mut loop_state = LoopState::Running;
+        let mut hold_started = None;
+        let mut held_total = std::time::Duration::from_secs(0);
+        let mut send_count = 9;
+        let mut tracker = TriggerTracker::default();
+        tracker
+            .last_hash_by_target
+            .insert("ai:1.0".to_string(), "abc".to_string());
+        let mut active_rule = Some("next".to_string());
+        tracker.active_rule_by_target.insert(
+            "ai:1.0".to_string(),
+            Some(NextStep::One("next".to_string())),
+        );
+
+        let should_stop = apply_external_control(
+            FleetControlCommand::Renew,
+            &mut loop_state,
+            &mut hold_started,
+            &mut held_total,
+            &mut send_count,
+            &mut tracker,
+            &mut active_rule,
+            std::time::Instant::now(),
+        );
+
+        assert!(!should_stop);
+        assert_eq!(send_count, 0);
+        assert!(tracker.last_hash_by_target.is_empty());
+        assert!(active_rule.is_none());
+        assert!(tracker.active_rule_by_target.is_empty());
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("5s").unwrap().as_secs(), 5);
+        assert_eq!(parse_duration("2m").unwrap().as_secs(), 120);
+        assert_eq!(parse_duration("1h").unwrap().as_secs(), 3600);
+        assert_eq!(parse_duration("1d").unwrap().as_secs(), 86_400);
+        assert_eq!(parse_duration("1w").unwrap().as_secs(), 604_800);
+        assert_eq!(parse_duration("1mon").unwrap().as_secs(), 2_592_000);
+        assert_eq!(parse_duration("1y").unwrap().as_secs(), 31_536_000);
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid() {
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("5x").is_err());
+    }
+
+    #[test]
+    fn key_bindings_remap_and_swallow_old_default() {
+        let overrides = BTreeMap::from([
+            ("hold".to_string(), "space".to_string()),
+            ("quit".to_string(), "Q".to_string()),
+        ]);
+        let keys = KeyBindings::new("run", RUN_KEY_ACTIONS, Some(&overrides)).expect("bindings");
+        assert_eq!(keys.translate(KeyCode::Char(' ')), Some(KeyCode::Char('h')));
+        assert_eq!(keys.translate(KeyCode::Char('h')), None);
+        assert_eq!(keys.translate(KeyCode::Char('Q')), Some(KeyCode::Char('q')));
+        assert_eq!(keys.translate(KeyCode::Char('n')), Some(KeyCode::Char('n')));
+        assert_eq!(keys.translate(KeyCode::Tab), Some(KeyCode::Tab));
+        assert_eq!(keys.key("hold"), "space");
+        assert!(keys.help_lines()[0].contains("space  hold"));
+
+        let duplicate = BTreeMap::from([("next".to_string(), "h".to_string())]);
+        let err = KeyBindings::new("run", RUN_KEY_ACTIONS, Some(&duplicate)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ui.keys.run: `h` is bound to both `hold` and `next`"
+        );
+        let unknown = BTreeMap::from([("hodl".to_string(), "x".to_string())]);
+        let err = KeyBindings::new("run", RUN_KEY_ACTIONS, Some(&unknown)).unwrap_err();
+        assert!(err.to_string().contains("did you mean `hold`?"));
+        let bad = BTreeMap::from([("confirm".to_string(), "ctrl-x".to_string())]);
+        assert!(KeyBindings::new("fleet", FLEET_KEY_ACTIONS, Some(&bad)).is_err());
+
+        let fleet = KeyBindings::new("fleet", FLEET_KEY_ACTIONS, None).expect("bindings");
+        assert_eq!(
+            fleet_footer_hints(&fleet),
+            "nav <-/-> </> · mark space · clear a · presets p/1-4 · sort o · filters x/v/f · search / · single h/r/n/R/s · bulk S/H/P/N/U · enter confirm · c cancel · i id · y stop-cmd · ? help · q"
+        );
+        let remapped = BTreeMap::from([
+            ("hold".to_string(), "z".to_string()),
+            ("quit".to_string(), "Q".to_string()),
+        ]);
+        let fleet =
+            KeyBindings::new("fleet", FLEET_KEY_ACTIONS, Some(&remapped)).expect("bindings");
+        let hints = fleet_footer_hints(&fleet);
+        assert!(hints.contains("single z/r/n/R/s"), "{hints}");
+        assert!(hints.ends_with("? help · Q"), "{hints}");
+    }
+
+    #[test]
+    fn parse_key_spec_accepts_names_and_function_keys() {
+        assert_eq!(parse_key_spec("x").unwrap(), KeyCode::Char('x'));
+        assert_eq!(parse_key_spec("Enter").unwrap(), KeyCode::Enter);
+        assert_eq!(parse_key_spec("f5").unwrap(), KeyCode::F(5));
+        assert!(parse_key_spec("f13").is_err());
+        assert_eq!(key_label(parse_key_spec("space").unwrap()), "space");
+    }
+
+    #[test]
+    fn ui_config_overlay_merges_fields_and_layout_breakpoints() {
+        let base: UiConfig = serde_yaml::from_str(
+            "colors:\n  running: 33\n  error: 160\nicons: ascii\nlayout:\n  compact_max: 90\nkeys:\n  run:\n    hold: x\n",
+        )
+        .expect("base");
+        let overlay: UiConfig =
+            serde_yaml::from_str("colors:\n  error: 200\nkeys:\n  run:\n    quit: Q\n")
+                .expect("overlay");
+        let merged = merge_ui_config(base, &overlay);
+        let settings = build_ui_settings(&merged).expect("settings");
+        assert_eq!(settings.icon_mode, IconMode::Ascii);
+        assert_eq!(state_color(LoopState::Running, settings.colors), 33);
+        assert_eq!(state_color(LoopState::Error, settings.colors), 200);
+        assert_eq!(state_color(LoopState::Holding, settings.colors), 179);
+        assert_eq!(settings.run_keys.key("hold"), "x");
+        assert_eq!(settings.run_keys.key("quit"), "Q");
+        assert_eq!(layout_mode(85, settings.layout), LayoutMode::Compact);
+        assert_eq!(layout_mode(100, settings.layout), LayoutMode::Standard);
+        assert_eq!(layout_mode(85, UiLayout::default()), LayoutMode::Standard);
+
+        let inverted: UiConfig =
+            serde_yaml::from_str("layout:\n  compact_max: 130\n").expect("yaml");
+        assert!(build_ui_settings(&inverted).is_err());
+    }
 
-        let err = config_doctor(Some(&config_path), true).unwrap_err();
-        assert!(
-            err.to_string()
-                .contains("multiple selected profiles enable `tui`")
+    #[test]
+    fn render_status_bar_compact() {
+        let config = ResolvedConfig {
+            profile_id: None,
+            exec_command: None,
+            target_scope: TargetScope::Pane("ai:5.0".to_string()),
+            target_label: "ai:5.0".to_string(),
+            explicit_targets: None,
+            file_sources: Vec::new(),
+            iterations: Some(10),
+            infinite: false,
+            has_prompt: true,
+            rule_eval: RuleEval::FirstMatch,
+            rules: Vec::new(),
+            delay: None,
+            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
+            prompt_placeholders: Vec::new(),
+            template_vars: Vec::new(),
+            default_action: Action {
+                pre: None,
+                prompt: Some(PromptBlock::Single("hi".to_string())),
+                post: None,
+            },
+            logging: LoggingConfigResolved {
+                path: None,
+                format: LogFormatResolved::Text,
+            },
+            capture_window: CaptureWindow::Tail(200),
+            once: false,
+            single_line: false,
+            tui: false,
+            poll: 5,
+            log_preview_lines: 3,
+            trigger_edge: true,
+            recheck_before_send: true,
+            fanout: FanoutMode::Matched,
+            submit_keys: vec!["Enter".to_string()],
+            seed: None,
+            duration: None,
+            source_path: None,
+            ui: UiConfig::default(),
+            done_when: None,
+            capture: CaptureMode::Window,
+            capture_ansi: false,
+            capture_join: false,
+            states: Vec::new(),
+        };
+        let bar = render_status_bar(
+            LoopState::Running,
+            LayoutMode::Compact,
+            IconMode::Ascii,
+            StyleConfig {
+                use_color: false,
+                use_bg: false,
+                use_unicode_ellipsis: false,
+                dim_logs: true,
+                palette: UiColors::default(),
+            },
+            80,
+            &config,
+            5,
+            10,
+            Some("Concluded"),
+            "00:10",
+            None,
         );
-        std::fs::remove_dir_all(root).unwrap();
+        assert!(bar.contains("RUN"));
+        assert!(bar.contains("5/10"));
+        assert!(bar.contains("ai:5.0"));
     }
 
     #[test]
-    fn config_test_reports_missing_profile() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-config-test-missing-{}",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-        let config_path = root.join("config.yaml");
-        std::fs::write(
-            &config_path,
-            r#"
-runs:
-  - id: one
-    target: "ai:1.0"
-    iterations: 1
-    default_action:
-      prompt: "a"
-"#,
-        )
-        .unwrap();
+    fn render_status_bar_standard_truncates_trigger() {
+        let config = ResolvedConfig {
+            profile_id: None,
+            exec_command: None,
+            target_scope: TargetScope::Pane("ai:5.0".to_string()),
+            target_label: "ai:5.0".to_string(),
+            explicit_targets: None,
+            file_sources: Vec::new(),
+            iterations: Some(10),
+            infinite: false,
+            has_prompt: true,
+            rule_eval: RuleEval::FirstMatch,
+            rules: Vec::new(),
+            delay: None,
+            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
+            prompt_placeholders: Vec::new(),
+            template_vars: Vec::new(),
+            default_action: Action {
+                pre: None,
+                prompt: Some(PromptBlock::Single("hi".to_string())),
+                post: None,
+            },
+            logging: LoggingConfigResolved {
+                path: None,
+                format: LogFormatResolved::Text,
+            },
+            capture_window: CaptureWindow::Tail(200),
+            once: false,
+            single_line: false,
+            tui: false,
+            poll: 5,
+            log_preview_lines: 3,
+            trigger_edge: true,
+            recheck_before_send: true,
+            fanout: FanoutMode::Matched,
+            submit_keys: vec!["Enter".to_string()],
+            seed: None,
+            duration: None,
+            source_path: None,
+            ui: UiConfig::default(),
+            done_when: None,
+            capture: CaptureMode::Window,
+            capture_ansi: false,
+            capture_join: false,
+            states: Vec::new(),
+        };
+        let bar = render_status_bar(
+            LoopState::Running,
+            LayoutMode::Standard,
+            IconMode::Ascii,
+            StyleConfig {
+                use_color: false,
+                use_bg: false,
+                use_unicode_ellipsis: true,
+                dim_logs: true,
+                palette: UiColors::default(),
+            },
+            120,
+            &config,
+            1,
+            10,
+            Some("This is a very long trigger string that should truncate"),
+            "00:10",
+            Some("1m20s"),
+        );
+        assert!(bar.contains("trg"));
+        assert!(bar.contains("rem 1m20s"));
+        assert!(bar.contains("…"));
+    }
 
-        let err = config_test(Some(&config_path), "missing").unwrap_err();
-        assert!(err.to_string().contains("not found"));
-        std::fs::remove_dir_all(root).unwrap();
+    #[test]
+    fn render_status_bar_exec_uses_event_label() {
+        let config = ResolvedConfig {
+            profile_id: Some("watcher".to_string()),
+            exec_command: Some("gw-watch-comp".to_string()),
+            target_scope: TargetScope::All,
+            target_label: "exec://gw-watch-comp".to_string(),
+            explicit_targets: None,
+            file_sources: Vec::new(),
+            iterations: Some(3),
+            infinite: false,
+            has_prompt: false,
+            rule_eval: RuleEval::FirstMatch,
+            rules: Vec::new(),
+            delay: None,
+            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
+            prompt_placeholders: Vec::new(),
+            template_vars: Vec::new(),
+            default_action: Action {
+                pre: None,
+                prompt: None,
+                post: None,
+            },
+            logging: LoggingConfigResolved {
+                path: None,
+                format: LogFormatResolved::Text,
+            },
+            capture_window: CaptureWindow::Tail(1),
+            once: false,
+            single_line: false,
+            tui: true,
+            poll: 10,
+            log_preview_lines: 3,
+            trigger_edge: true,
+            recheck_before_send: true,
+            fanout: FanoutMode::Matched,
+            submit_keys: vec!["Enter".to_string()],
+            seed: None,
+            duration: None,
+            source_path: None,
+            ui: UiConfig::default(),
+            done_when: None,
+            capture: CaptureMode::Window,
+            capture_ansi: false,
+            capture_join: false,
+            states: Vec::new(),
+        };
+        let bar = render_status_bar(
+            LoopState::Running,
+            LayoutMode::Standard,
+            IconMode::Ascii,
+            StyleConfig {
+                use_color: false,
+                use_bg: false,
+                use_unicode_ellipsis: true,
+                dim_logs: true,
+                palette: UiColors::default(),
+            },
+            120,
+            &config,
+            1,
+            3,
+            Some("exec:running"),
+            "00:05",
+            None,
+        );
+        assert!(bar.contains("evt exec:running"));
+        assert!(bar.contains("exec://gw-watch-comp"));
     }
 
     #[test]
-    fn config_test_reports_duplicate_profile_ids() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-config-test-dup-{}",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-        let config_path = root.join("config.yaml");
-        std::fs::write(
-            &config_path,
-            r#"
-runs:
-  - id: same
-    target: "ai:1.0"
-    iterations: 1
-    default_action:
-      prompt: "a"
-  - id: same
-    target: "ai:2.0"
-    iterations: 1
-    default_action:
-      prompt: "b"
-"#,
-        )
-        .unwrap();
+    fn status_line_exec_uses_friendly_event_label() {
+        let config = ResolvedConfig {
+            profile_id: Some("watcher".to_string()),
+            exec_command: Some("gw-watch-comp".to_string()),
+            target_scope: TargetScope::All,
+            target_label: "exec://gw-watch-comp".to_string(),
+            explicit_targets: None,
+            file_sources: Vec::new(),
+            iterations: Some(3),
+            infinite: false,
+            has_prompt: false,
+            rule_eval: RuleEval::FirstMatch,
+            rules: Vec::new(),
+            delay: None,
+            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
+            prompt_placeholders: Vec::new(),
+            template_vars: Vec::new(),
+            default_action: Action {
+                pre: None,
+                prompt: None,
+                post: None,
+            },
+            logging: LoggingConfigResolved {
+                path: None,
+                format: LogFormatResolved::Text,
+            },
+            capture_window: CaptureWindow::Tail(1),
+            once: false,
+            single_line: true,
+            tui: false,
+            poll: 10,
+            log_preview_lines: 3,
+            trigger_edge: true,
+            recheck_before_send: true,
+            fanout: FanoutMode::Matched,
+            submit_keys: vec!["Enter".to_string()],
+            seed: None,
+            duration: None,
+            source_path: None,
+            ui: UiConfig::default(),
+            done_when: None,
+            capture: CaptureMode::Window,
+            capture_ansi: false,
+            capture_join: false,
+            states: Vec::new(),
+        };
 
-        let err = config_test(Some(&config_path), "same").unwrap_err();
-        assert!(err.to_string().contains("duplicated"));
-        std::fs::remove_dir_all(root).unwrap();
+        let line = status_line(&config, 1, 3, Some("exec:running"), "5s");
+        assert!(line.contains("event=running"));
+        assert!(!line.contains("rule=exec:running"));
     }
 
     #[test]
-    fn matches_criteria_regex_and_contains() {
-        let output = "hello world";
-        assert!(matches_criteria(&match_regex("hello"), output).unwrap());
-        assert!(matches_criteria(&match_contains("world"), output).unwrap());
-        assert!(!matches_criteria(&match_contains("missing"), output).unwrap());
+    fn format_exec_event_label_maps_known_values() {
+        assert_eq!(format_exec_event_label("exec:started"), "started");
+        assert_eq!(format_exec_event_label("exec:running"), "running");
+        assert_eq!(format_exec_event_label("exec:ok"), "ok");
+        assert_eq!(format_exec_event_label("exec:fail"), "fail");
+        assert_eq!(format_exec_event_label("custom"), "custom");
     }
 
     #[test]
-    fn matches_criteria_exact_line() {
-        let criteria = MatchCriteria {
-            regex: None,
-            trigger_expr: None,
-            exact_line: Some("<CONTINUE-LOOP>".to_string()),
-            contains: None,
-            starts_with: None,
-        };
-        assert!(matches_criteria(&criteria, "foo\n  <CONTINUE-LOOP>  \nbar").unwrap());
-        assert!(!matches_criteria(&criteria, "foo <CONTINUE-LOOP> bar").unwrap());
+    fn trigger_edge_rearms_after_clear() {
+        let mut active = HashSet::new();
+        active.insert("ai:7.0|inline|0".to_string());
+
+        let matched_now = HashSet::new();
+        refresh_trigger_edges_for_target(&mut active, "ai:7.0", &matched_now, true);
+        assert!(!active.contains("ai:7.0|inline|0"));
+
+        active.insert("other:1.0|inline|0".to_string());
+        refresh_trigger_edges_for_target(&mut active, "ai:7.0", &matched_now, true);
+        assert!(active.contains("other:1.0|inline|0"));
     }
 
     #[test]
-    fn matches_criteria_trigger_expr() {
-        let criteria = MatchCriteria {
-            regex: None,
-            trigger_expr: Some("(READY || DONE) && GO".to_string()),
-            exact_line: None,
-            contains: None,
-            starts_with: None,
-        };
-        assert!(matches_criteria(&criteria, "READY GO").unwrap());
-        assert!(!matches_criteria(&criteria, "READY").unwrap());
+    fn edge_guard_allowance_respects_toggle() {
+        let mut active = HashSet::new();
+        active.insert("ai:7.0|inline|0".to_string());
+        assert!(!edge_guard_allows(&active, "ai:7.0|inline|0", true));
+        assert!(edge_guard_allows(&active, "ai:7.0|inline|0", false));
+        assert!(edge_guard_allows(&active, "ai:7.0|inline|1", true));
     }
 
     #[test]
-    fn matches_criteria_invalid_regex() {
-        let output = "hello";
-        assert!(matches_criteria(&match_regex("["), output).is_err());
+    fn hash_skip_depends_on_trigger_edge_mode() {
+        assert!(should_skip_scan_by_hash(true, "same", "same", false));
+        assert!(!should_skip_scan_by_hash(true, "same", "same", true));
+        assert!(!should_skip_scan_by_hash(false, "same", "same", false));
+        assert!(!should_skip_scan_by_hash(true, "new", "old", false));
     }
 
     #[test]
-    fn matches_rule_respects_exclude() {
-        let rule = rule_with(Some(match_regex("hello")), Some(match_regex("world")));
-        let output = "hello world";
-        assert!(!matches_rule(&rule, output).unwrap());
+    fn pending_confirm_detected_per_target() {
+        let mut pending = std::collections::HashMap::new();
+        let now = std::time::Instant::now();
+        pending.insert("ai:7.0|inline|0".to_string(), now);
+        pending.insert("other:1.0|inline|0".to_string(), now);
+        assert!(has_pending_confirm_for_target(&pending, "ai:7.0"));
+        assert!(has_pending_confirm_for_target(&pending, "other:1.0"));
+        assert!(!has_pending_confirm_for_target(&pending, "ai:8.0"));
     }
 
     #[test]
-    fn matches_rule_exclude_only() {
-        let rule = rule_with(None, Some(match_regex("skip")));
-        assert!(matches_rule(&rule, "ok").unwrap());
-        assert!(!matches_rule(&rule, "skip this").unwrap());
+    fn confirm_window_elapsed_requires_persisted_match() {
+        let mut pending = std::collections::HashMap::new();
+        let now = std::time::Instant::now();
+        assert!(!confirm_window_elapsed(
+            5,
+            None,
+            "ai:7.0|inline|0",
+            &mut pending,
+            now
+        ));
+        assert!(!confirm_window_elapsed(
+            5,
+            Some(3),
+            "ai:7.0|inline|0",
+            &mut pending,
+            now + std::time::Duration::from_secs(2),
+        ));
+        assert!(confirm_window_elapsed(
+            5,
+            Some(3),
+            "ai:7.0|inline|0",
+            &mut pending,
+            now + std::time::Duration::from_secs(3),
+        ));
     }
 
     #[test]
-    fn select_rules_priority() {
-        let mut rule_a = rule_with(Some(match_contains("hit")), None);
-        rule_a.priority = Some(1);
-        let mut rule_b = rule_with(Some(match_contains("hit")), None);
-        rule_b.priority = Some(2);
-        let rules = vec![rule_a, rule_b];
-        let matches = select_rules("hit", &rules, &RuleEval::Priority, None).unwrap();
-        assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].index, 1);
+    fn confirm_window_elapsed_zero_is_immediate() {
+        let mut pending = std::collections::HashMap::new();
+        assert!(confirm_window_elapsed(
+            5,
+            Some(0),
+            "ai:7.0|inline|0",
+            &mut pending,
+            std::time::Instant::now(),
+        ));
+        assert!(pending.is_empty());
     }
 
     #[test]
-    fn select_rules_multi_match() {
-        let rule_a = rule_with(Some(match_contains("hit")), None);
-        let rule_b = rule_with(Some(match_contains("hit")), None);
-        let rules = vec![rule_a, rule_b];
-        let matches = select_rules("hit", &rules, &RuleEval::MultiMatch, None).unwrap();
-        assert_eq!(matches.len(), 2);
-        assert_eq!(matches[0].index, 0);
-        assert_eq!(matches[1].index, 1);
+    fn truncate_text_respects_ascii_max_width() {
+        let truncated = truncate_text("abcdefghijk", 8, false);
+        assert_eq!(truncated.chars().count(), 8);
+        assert_eq!(truncated, "abcde...");
     }
 
     #[test]
-    fn resolve_run_config_requires_trigger() {
-        let args = RunArgs {
-            config: None,
-            prompt: Some("Do it".to_string()),
-            trigger: None,
-            trigger_expr: None,
-            trigger_exact_line: false,
-            exclude: None,
-            pre: None,
-            post: None,
-            exec: None,
-            target: vec!["ai:5.0".to_string()],
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(1),
-            tail: None,
-            head: None,
-            once: false,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: None,
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: None,
-            history_limit: None,
-            name: None,
-        };
-        assert!(resolve_run_config(&args).is_err());
+    fn extract_trigger_preview_ascii_separator() {
+        let output = "line1\nline2\nline3\n";
+        let (_, preview) = extract_trigger_preview(output, 2, false);
+        assert!(preview.contains(" | "));
+        assert!(!preview.contains(" │ "));
     }
 
     #[test]
-    fn resolve_run_config_inline_exec_builds_exec_config() {
-        let args = RunArgs {
-            config: None,
-            prompt: None,
-            trigger: None,
-            trigger_expr: None,
-            trigger_exact_line: false,
-            exclude: None,
-            pre: None,
-            post: None,
-            exec: Some("gw-watch-comp --mode check".to_string()),
-            target: Vec::new(),
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(3),
-            tail: None,
-            head: None,
-            once: false,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: Some(5),
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: Some("30s".to_string()),
-            history_limit: None,
-            name: Some("gw-watch".to_string()),
-        };
-
-        let config = resolve_run_config(&args).unwrap();
-        assert_eq!(
-            config.exec.as_ref().map(|value| value.command.as_str()),
-            Some("gw-watch-comp --mode check")
-        );
-        assert!(config.default_action.is_none());
-        assert!(config.rules.is_none());
+    fn preview_window_lines_follows_tail_and_scrolls() {
+        let content = "a\nb\nc\nd\ne\n\n";
+        assert_eq!(preview_window_lines(content, 2, 0), vec!["d", "e"]);
+        assert_eq!(preview_window_lines(content, 2, 2), vec!["b", "c"]);
+        assert_eq!(preview_window_lines(content, 2, 99), vec!["a", "b"]);
+        assert_eq!(preview_window_lines("x", 3, 0), vec!["x"]);
     }
 
     #[test]
-    fn resolve_run_config_rejects_exec_with_prompt_mode_flags() {
-        let args = RunArgs {
-            config: None,
-            prompt: Some("Do it".to_string()),
-            trigger: Some("Done".to_string()),
-            trigger_expr: None,
-            trigger_exact_line: false,
-            exclude: None,
-            pre: None,
-            post: None,
-            exec: Some("gw-watch-comp".to_string()),
-            target: Vec::new(),
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(1),
-            tail: None,
-            head: None,
-            once: false,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: None,
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: None,
-            history_limit: None,
-            name: None,
+    fn preview_highlighter_merges_overlapping_matches() {
+        let criteria = MatchCriteria {
+            regex: Some("Do you want".to_string()),
+            trigger_expr: Some("(proceed || yes)".to_string()),
+            exact_line: None,
+            contains: Some("want to".to_string()),
+            starts_with: Some("Do".to_string()),
+            idle_for: None,
+            prompt_ready: None,
+            style: None,
         };
-
-        let err = resolve_run_config(&args).unwrap_err();
-        assert!(err.to_string().contains("--exec cannot be combined"));
+        let line = "Do you want to proceed?";
+        assert_eq!(
+            PreviewHighlighter::new(&[&criteria]).ranges(line),
+            vec![(0, 14), (15, 22)]
+        );
+        assert_eq!(
+            paint_ranges("ab cd", &[(3, 5)], "<"),
+            "ab <cd\x1B[0m".to_string()
+        );
     }
 
     #[test]
-    fn resolve_run_config_inline_builds_rule() {
-        let args = RunArgs {
-            config: None,
-            prompt: Some("Do it".to_string()),
-            trigger: Some("Done".to_string()),
-            trigger_expr: None,
-            trigger_exact_line: false,
-            exclude: Some("PROD".to_string()),
-            pre: Some("pre".to_string()),
-            post: Some("post".to_string()),
-            exec: None,
-            target: vec!["ai:5.0".to_string()],
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(2),
-            tail: Some(123),
-            head: None,
-            once: true,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: None,
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: None,
-            history_limit: None,
-            name: None,
+    fn preview_pane_cycles_and_keeps_selection() {
+        let mut pane = PreviewPane {
+            visible: true,
+            ..PreviewPane::default()
         };
-        let config = resolve_run_config(&args).unwrap();
+        pane.set_targets(&["a:1.0".to_string(), "b:1.0".to_string()]);
+        pane.cycle(true);
+        assert_eq!(pane.target(), Some("b:1.0"));
+        pane.cycle(true);
+        assert_eq!(pane.target(), Some("a:1.0"));
+        pane.cycle(false);
+        pane.set_targets(&["c:1.0".to_string(), "b:1.0".to_string()]);
+        assert_eq!(pane.target(), Some("b:1.0"));
+        pane.visible = false;
+        assert_eq!(pane.target(), None);
+    }
+
+    #[test]
+    fn manual_prompt_choices_list_actions_then_unique_history() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 5
+default_action:
+  prompt: "continue"
+rules:
+  - id: done
+    match:
+      contains: "DONE"
+  - id: review
+    match:
+      contains: "LGTM"
+    action:
+      pre: "note"
+      prompt: "ship it"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
         let resolved = resolve_config(
-            config, None, None, true, args.tail, args.head, args.once, false, false, None, None,
-            None,
+            config, None, None, true, None, None, false, false, false, None, None, None,
         )
-        .unwrap();
-        assert!(matches!(resolved.capture_window, CaptureWindow::Tail(123)));
-        assert!(resolved.once);
-        assert_eq!(resolved.rules.len(), 1);
-        assert_eq!(
-            resolved.trigger_confirm_seconds,
-            DEFAULT_TRIGGER_CONFIRM_SECONDS
-        );
-        assert_eq!(
-            resolved.rules[0].match_.as_ref().unwrap().regex.as_deref(),
-            Some("Done")
+        .expect("resolve");
+        let history = |prompt: &str| -> HistoryEntry {
+            serde_json::from_value(json!({
+                "last_run": "2026-02-17T00:00:00Z",
+                "target": "ai:1.0",
+                "prompt": prompt,
+                "trigger": "Done",
+                "once": false,
+            }))
+            .expect("history entry")
+        };
+        let choices = manual_prompt_choices(
+            &resolved,
+            &[history("continue"), history("fix lint"), history("  ")],
         );
+        let labels = choices
+            .iter()
+            .map(|choice| choice.label.as_str())
+            .collect::<Vec<_>>();
         assert_eq!(
-            resolved.rules[0].exclude.as_ref().unwrap().regex.as_deref(),
-            Some("PROD")
+            labels,
+            vec![
+                "default_action",
+                "rule review",
+                "history 2026-02-17T00:00:00Z"
+            ]
         );
+        assert_eq!(choices[1].prompt, "note\nship it");
+        assert_eq!(choices[2].prompt, "fix lint");
     }
 
     #[test]
-    fn resolve_run_config_inline_trigger_expr_mode() {
-        let args = RunArgs {
-            config: None,
-            prompt: Some("Do it".to_string()),
-            trigger: None,
-            trigger_expr: Some("READY && GO".to_string()),
-            trigger_exact_line: false,
-            exclude: None,
+    fn splice_yaml_block_replaces_only_target_action() {
+        let source = "# keep me\ndefault_action:\n  prompt: old\n\nrules:\n  - id: done\n    match:\n      contains: DONE\n    action:\n      prompt: |\n        first\n        second\n    next: stop\n";
+        let action = Action {
             pre: None,
+            prompt: Some(PromptBlock::Single("new".to_string())),
             post: None,
-            exec: None,
-            target: vec!["ai:5.0".to_string()],
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(1),
-            tail: Some(1),
-            head: None,
-            once: false,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: None,
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: None,
-            history_limit: None,
-            name: None,
         };
-        let config = resolve_run_config(&args).unwrap();
-        let mut rules = config.rules.unwrap();
-        let matcher = rules.remove(0).match_.unwrap();
-        assert!(matcher.regex.is_none());
-        assert_eq!(matcher.trigger_expr.as_deref(), Some("READY && GO"));
-        assert!(matcher.exact_line.is_none());
+        let body = action_yaml(&action).expect("serialize");
+        assert_eq!(body, "prompt: new\n");
+
+        let rewritten = splice_yaml_block(source, "rules[0].action", &body).expect("splice");
+        assert_eq!(
+            rewritten,
+            "# keep me\ndefault_action:\n  prompt: old\n\nrules:\n  - id: done\n    match:\n      contains: DONE\n    action:\n      prompt: new\n    next: stop\n"
+        );
+        let config: Config = serde_yaml::from_str(&rewritten).expect("still valid yaml");
+        assert_eq!(config.rules.expect("rules").len(), 1);
+
+        let rewritten = splice_yaml_block(source, "default_action", &body).expect("splice");
+        assert!(rewritten.starts_with("# keep me\ndefault_action:\n  prompt: new\n\nrules:"));
+        assert!(splice_yaml_block(source, "rules[3].action", &body).is_err());
     }
 
     #[test]
-    fn resolve_run_config_inline_exact_line_mode() {
-        let args = RunArgs {
-            config: None,
-            prompt: Some("Do it".to_string()),
-            trigger: Some("<CONTINUE-LOOP>".to_string()),
-            trigger_expr: None,
-            trigger_exact_line: true,
-            exclude: None,
-            pre: None,
-            post: None,
-            exec: None,
-            target: vec!["ai:5.0".to_string()],
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(2),
-            tail: Some(1),
-            head: None,
-            once: true,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: None,
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: None,
-            history_limit: None,
-            name: None,
-        };
-        let config = resolve_run_config(&args).unwrap();
-        let mut rules = config.rules.unwrap();
-        let rule = rules.remove(0);
-        let matcher = rule.match_.unwrap();
-        assert!(matcher.regex.is_none());
-        assert_eq!(matcher.exact_line.as_deref(), Some("<CONTINUE-LOOP>"));
+    fn line_diff_reports_changed_lines_only() {
+        let diff = line_diff("a\nb\nc\nd\n", "a\nB\nc\nd\ne\n");
+        assert_eq!(diff, vec!["@@ line 2", "+B", "-b", "@@ line 5", "+e"]);
+        assert!(line_diff("same\n", "same\n").is_empty());
     }
 
     #[test]
-    fn resolve_config_prefers_head_window_when_set() {
-        let args = RunArgs {
-            config: None,
-            prompt: Some("Do it".to_string()),
-            trigger: Some("Done".to_string()),
-            trigger_expr: None,
-            trigger_exact_line: false,
-            exclude: None,
-            pre: None,
-            post: None,
-            exec: None,
-            target: vec!["ai:5.0".to_string()],
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(1),
-            tail: None,
-            head: Some(7),
-            once: false,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: None,
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: None,
-            history_limit: None,
-            name: None,
+    fn pane_table_controls_apply_to_one_pane() {
+        let mut panes = PaneTable::default();
+        let mut tracker = TriggerTracker::default();
+        tracker.edge_active.insert("ai:1.0|done|0".to_string());
+        tracker.edge_active.insert("ai:2.0|done|0".to_string());
+        tracker
+            .last_hash_by_target
+            .insert("ai:1.0".to_string(), "abc".to_string());
+        panes.sync(&["ai:1.0".to_string(), "ai:2.0".to_string()]);
+
+        assert_eq!(
+            panes.apply(PaneCommand::Hold, "ai:1.0", &mut tracker),
+            "pane hold target=ai:1.0"
+        );
+        assert!(panes.is_held("ai:1.0"));
+        assert!(!panes.is_held("ai:2.0"));
+
+        panes.apply(PaneCommand::Skip, "ai:2.0", &mut tracker);
+        assert!(panes.take_skip("ai:2.0"));
+        assert!(!panes.take_skip("ai:2.0"));
+
+        panes.apply(PaneCommand::Next, "ai:1.0", &mut tracker);
+        assert_eq!(tracker.edge_state("ai:1.0", true), "ready");
+        assert_eq!(tracker.edge_state("ai:2.0", true), "armed");
+        assert!(tracker.last_hash_by_target.is_empty());
+
+        panes.record_send("ai:2.0", "2026-02-17T00:12:34Z");
+        panes.sync(&["ai:2.0".to_string()]);
+        assert_eq!(panes.panes.len(), 1);
+        assert_eq!(panes.panes["ai:2.0"].sends, 1);
+        assert_eq!(panes.panes["ai:2.0"].last_send.as_deref(), Some("00:12:34"));
+    }
+
+    #[test]
+    fn render_pane_table_marks_focus_and_flags() {
+        let row = |target: &str, held: bool| PaneRow {
+            target: target.to_string(),
+            last_rule: Some("done".to_string()),
+            edge: "armed",
+            confirm_seconds: Some(3),
+            delay_seconds: None,
+            sends: 2,
+            last_send: Some("00:12:34".to_string()),
+            held,
+            skip_next: false,
         };
-        let config = resolve_run_config(&args).unwrap();
-        let resolved = resolve_config(
-            config, None, None, true, args.tail, args.head, false, false, false, None, None, None,
+        let lines = render_pane_table(
+            &[row("ai:1.0", false), row("ai:2.0", true)],
+            Some("ai:1.0"),
+            100,
+            10,
+            false,
+        );
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("confirm"));
+        assert!(lines[1].starts_with("> ai:1.0"));
+        assert!(lines[2].starts_with(" Hai:2.0"));
+        assert!(lines[2].contains("armed") && lines[2].contains("3s"));
+        assert!(render_pane_table(&[row("a", false)], None, 100, 1, false).is_empty());
+    }
+
+    #[test]
+    fn preview_layout_picks_split_stack_or_inline() {
+        assert_eq!(preview_layout(160, 30, false), PreviewLayout::Hidden);
+        assert_eq!(
+            preview_layout(160, 30, true),
+            PreviewLayout::Split { log_cols: 64 }
+        );
+        assert_eq!(
+            preview_layout(80, 30, true),
+            PreviewLayout::Stacked { log_rows: 10 }
+        );
+        assert_eq!(preview_layout(80, 5, true), PreviewLayout::Inline);
+    }
+
+    #[test]
+    fn log_line_date_extracts_rfc3339_prefix() {
+        let line = "[2026-02-17T00:12:34Z] started target=ai:7.0";
+        assert_eq!(log_line_date(line), Some("2026-02-17"));
+        assert_eq!(log_line_date("23:11:04 > ai:7.0"), None);
+    }
+
+    #[test]
+    fn compact_time_prefix_detection() {
+        assert!(looks_like_compact_time_prefix("23:11:04 > ai:7.0"));
+        assert!(!looks_like_compact_time_prefix(
+            "[2026-02-17T00:12:34Z] sent"
+        ));
+    }
+
+    #[test]
+    fn log_line_color_same_and_prior_day() {
+        let now = OffsetDateTime::parse(
+            "2026-02-17T10:00:00Z",
+            &time::format_description::well_known::Rfc3339,
         )
         .unwrap();
-        assert!(matches!(resolved.capture_window, CaptureWindow::Head(7)));
+        assert_eq!(log_line_color_at("[2026-02-17T01:02:03Z] sent", now), 251);
+        assert_eq!(log_line_color_at("[2026-02-16T23:59:59Z] sent", now), 244);
     }
 
     #[test]
-    fn resolve_config_supports_multiple_explicit_tmux_targets() {
-        let args = RunArgs {
-            config: None,
-            prompt: Some("Do it".to_string()),
-            trigger: Some("Done".to_string()),
-            trigger_expr: None,
-            trigger_exact_line: false,
-            exclude: None,
-            pre: None,
-            post: None,
-            exec: None,
-            target: vec!["ai:5.0".to_string(), "codex:1.0".to_string()],
-            targets_file: Vec::new(),
-            file: Vec::new(),
-            files_file: Vec::new(),
-            iterations: Some(1),
-            tail: Some(5),
-            head: None,
-            once: false,
-            dry_run: false,
-            single_line: false,
-            tui: false,
-            poll: None,
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            no_trigger_edge: false,
-            no_recheck_before_send: false,
-            fanout: FanoutMode::Matched,
-            duration: None,
-            history_limit: None,
-            name: None,
-        };
-        let config = resolve_run_config(&args).unwrap();
-        let resolved = resolve_config(
-            config, None, None, true, args.tail, args.head, false, false, false, None, None, None,
+    fn log_line_color_handles_timezone_offsets() {
+        let now = OffsetDateTime::parse(
+            "2026-02-17T00:30:00+00:00",
+            &time::format_description::well_known::Rfc3339,
         )
         .unwrap();
         assert_eq!(
-            resolved.explicit_targets,
-            Some(vec!["ai:5.0".to_string(), "codex:1.0".to_string()])
+            log_line_color_at("[2026-02-16T23:30:00-02:00] sent", now),
+            251
         );
     }
 
     #[test]
-    fn resolve_config_rejects_missing_file_source() {
-        let config = Config {
-            target: Some("ai:5.0".to_string()),
-            targets: None,
-            files: Some(vec!["/tmp/loopmux-missing-source.log".to_string()]),
-            exec: None,
-            iterations: Some(1),
-            infinite: None,
-            poll: Some(1),
-            trigger_confirm_seconds: Some(0),
-            log_preview_lines: Some(1),
-            trigger_edge: Some(true),
-            recheck_before_send: Some(true),
-            fanout: Some(FanoutMode::Matched),
-            duration: None,
-            rule_eval: Some(RuleEval::FirstMatch),
-            default_action: Some(Action {
-                pre: None,
-                prompt: Some(PromptBlock::Single("go".to_string())),
-                post: None,
-            }),
-            delay: None,
-            rules: Some(vec![rule_with(Some(match_contains("ok")), None)]),
-            logging: None,
-            template_vars: None,
-            tail: Some(1),
-            once: Some(false),
-            single_line: Some(false),
-            tui: Some(false),
-            name: Some("test".to_string()),
-        };
-        let err = resolve_config(
-            config,
-            None,
-            None,
-            true,
-            Some(1),
-            None,
-            false,
-            false,
-            false,
-            None,
-            None,
-            None,
+    fn log_line_color_compact_prefix_still_dimmed() {
+        let now = OffsetDateTime::parse(
+            "2026-02-17T00:30:00+00:00",
+            &time::format_description::well_known::Rfc3339,
         )
-        .unwrap_err();
-        assert!(err.to_string().contains("file source not found"));
+        .unwrap();
+        assert_eq!(log_line_color_at("23:11:04 > ai:7.0", now), 249);
     }
 
-    #[test]
-    fn resolve_config_exec_mode_without_prompt_target_or_rules() {
-        let config = Config {
-            target: None,
-            targets: None,
-            files: None,
-            exec: Some(ExecConfig {
-                command: "gw-watch-comp".to_string(),
-            }),
-            iterations: Some(3),
-            infinite: None,
-            poll: Some(7),
-            trigger_confirm_seconds: None,
-            log_preview_lines: None,
-            trigger_edge: None,
-            recheck_before_send: None,
-            fanout: None,
-            duration: Some("30s".to_string()),
-            rule_eval: None,
-            default_action: None,
-            delay: None,
-            rules: None,
-            logging: None,
-            template_vars: None,
-            tail: None,
-            once: None,
-            single_line: None,
-            tui: None,
-            name: Some("watcher".to_string()),
-        };
+    fn fleet_test_record(
+        id: &str,
+        name: &str,
+        state: &str,
+        sends: u32,
+        version: &str,
+    ) -> FleetRunRecord {
+        FleetRunRecord {
+            id: id.to_string(),
+            name: name.to_string(),
+            profile_id: name.to_string(),
+            pid: 1,
+            host: "local".to_string(),
+            target: "ai:1.0".to_string(),
+            state: state.to_string(),
+            sends,
+            poll_seconds: 5,
+            started_at: "2026-02-17T00:00:00Z".to_string(),
+            last_seen: "2026-02-17T00:00:00Z".to_string(),
+            version: version.to_string(),
+            events: Vec::new(),
+            prompt_positions: BTreeMap::new(),
+            rule_fires: BTreeMap::new(),
+        }
+    }
 
-        let resolved = resolve_config(
-            config,
-            None,
-            None,
-            true,
-            None,
-            None,
+    fn fleet_listed(record: FleetRunRecord, stale: bool, version_mismatch: bool) -> FleetListedRun {
+        let (health_score, health_label) = fleet_health(&record, stale, version_mismatch);
+        FleetListedRun {
+            record,
+            stale,
+            version_mismatch,
+            health_score,
+            health_label,
+            needs_attention: stale || version_mismatch || health_score < 70,
+        }
+    }
+
+    #[test]
+    fn fleet_manager_hides_stale_by_default() {
+        let active = fleet_listed(
+            fleet_test_record("run-1", "alpha", "waiting", 1, LOOPMUX_VERSION),
             false,
             false,
+        );
+        let stale = fleet_listed(
+            fleet_test_record("run-2", "beta", "waiting", 1, LOOPMUX_VERSION),
+            true,
             false,
+        );
+
+        let hidden = fleet_manager_visible_runs(
+            &vec![active.clone(), stale.clone()],
             None,
-            None,
-            Some("watcher".to_string()),
-        )
-        .unwrap();
+            false,
+            false,
+            FleetStateFilter::All,
+            "",
+            FleetSortMode::LastSeen,
+            FleetViewPreset::Default,
+        );
+        assert_eq!(hidden.len(), 1);
+        assert_eq!(hidden[0].record.id, "run-1");
 
-        assert_eq!(resolved.exec_command.as_deref(), Some("gw-watch-comp"));
-        assert_eq!(resolved.target_label, "exec://gw-watch-comp");
-        assert!(!resolved.has_prompt);
-        assert!(resolved.rules.is_empty());
+        let all = fleet_manager_visible_runs(
+            &vec![active, stale],
+            None,
+            true,
+            false,
+            FleetStateFilter::All,
+            "",
+            FleetSortMode::LastSeen,
+            FleetViewPreset::Default,
+        );
+        assert_eq!(all.len(), 2);
     }
 
     #[test]
-    fn parse_target_valid() {
-        let (session, window, pane) = parse_target("ai:5.0").unwrap();
-        assert_eq!(session, "ai");
-        assert_eq!(window, "5");
-        assert_eq!(pane, "0");
+    fn version_mismatch_detection_uses_local_version() {
+        assert!(!is_version_mismatch(LOOPMUX_VERSION));
+        assert!(is_version_mismatch("0.0.1"));
+        assert!(is_version_mismatch(""));
     }
 
     #[test]
-    fn parse_target_invalid() {
-        assert!(parse_target("ai").is_err());
-        assert!(parse_target("ai:5").is_err());
-        assert!(parse_target("ai:.0").is_err());
+    fn fleet_manager_mismatch_filter_works() {
+        let run_match = fleet_listed(
+            fleet_test_record("run-1", "alpha", "waiting", 1, LOOPMUX_VERSION),
+            false,
+            false,
+        );
+        let run_mismatch = fleet_listed(
+            fleet_test_record("run-2", "beta", "holding", 2, "0.0.1"),
+            false,
+            true,
+        );
+        let filtered = fleet_manager_visible_runs(
+            &vec![run_match, run_mismatch.clone()],
+            None,
+            true,
+            true,
+            FleetStateFilter::All,
+            "",
+            FleetSortMode::LastSeen,
+            FleetViewPreset::Default,
+        );
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].record.id, run_mismatch.record.id);
     }
 
     #[test]
-    fn resolve_target_shorthand_pane_only() {
-        let resolved = resolve_target_with_current("0", || Ok("ai:5.2".to_string())).unwrap();
-        assert_eq!(resolved, "ai:5.0");
+    fn fleet_manager_state_filter_holding_only() {
+        let waiting = fleet_listed(
+            fleet_test_record("run-1", "alpha", "waiting", 1, LOOPMUX_VERSION),
+            false,
+            false,
+        );
+        let holding = fleet_listed(
+            fleet_test_record("run-2", "beta", "holding", 2, LOOPMUX_VERSION),
+            false,
+            false,
+        );
+        let filtered = fleet_manager_visible_runs(
+            &vec![waiting, holding.clone()],
+            None,
+            true,
+            false,
+            FleetStateFilter::Holding,
+            "",
+            FleetSortMode::LastSeen,
+            FleetViewPreset::Default,
+        );
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].record.id, holding.record.id);
     }
 
     #[test]
-    fn resolve_target_shorthand_window_pane() {
-        let resolved = resolve_target_with_current("2.1", || Ok("ai:5.2".to_string())).unwrap();
-        assert_eq!(resolved, "ai:2.1");
+    fn fleet_manager_search_matches_name_or_target() {
+        let run = fleet_listed(
+            fleet_test_record("run-1", "planner-a", "waiting", 1, LOOPMUX_VERSION),
+            false,
+            false,
+        );
+        let by_name = fleet_manager_visible_runs(
+            &vec![run.clone()],
+            None,
+            true,
+            false,
+            FleetStateFilter::All,
+            "planner",
+            FleetSortMode::LastSeen,
+            FleetViewPreset::Default,
+        );
+        assert_eq!(by_name.len(), 1);
+
+        let by_target = fleet_manager_visible_runs(
+            &vec![run],
+            None,
+            true,
+            false,
+            FleetStateFilter::All,
+            "ai:1",
+            FleetSortMode::LastSeen,
+            FleetViewPreset::Default,
+        );
+        assert_eq!(by_target.len(), 1);
     }
 
     #[test]
-    fn resolve_target_scope_defaults_to_all() {
-        let (scope, label) =
-            resolve_target_scope_with(None, |value| Ok(value.to_string())).unwrap();
-        assert!(matches!(scope, TargetScope::All));
-        assert_eq!(label, "all sessions/windows/panes");
+    fn fleet_profile_filter_matches_profile_or_name() {
+        let run = fleet_listed(
+            fleet_test_record("run-1", "planner-a", "waiting", 1, LOOPMUX_VERSION),
+            false,
+            false,
+        );
+        assert!(run_matches_profile_filter(&run, "planner-a"));
+        assert!(!run_matches_profile_filter(&run, "docs"));
     }
 
     #[test]
-    fn resolve_target_scope_session() {
-        let (scope, label) =
-            resolve_target_scope_with(Some("ai"), |value| Ok(value.to_string())).unwrap();
-        assert!(matches!(scope, TargetScope::Session(ref value) if value == "ai"));
-        assert_eq!(label, "ai:*.*");
+    fn fleet_status_line_includes_profile_and_search() {
+        let line = fleet_status_line(
+            FleetViewPreset::Default,
+            FleetSortMode::LastSeen,
+            FleetStateFilter::All,
+            false,
+            true,
+            "needle",
+            Some("docs"),
+        );
+        assert!(line.contains("search=needle"));
+        assert!(line.contains("profile=docs"));
+        assert!(line.contains("mismatch=on"));
     }
 
     #[test]
-    fn resolve_target_scope_window() {
-        let (scope, label) =
-            resolve_target_scope_with(Some("ai:5"), |value| Ok(value.to_string())).unwrap();
-        assert!(
-            matches!(scope, TargetScope::Window { ref session, ref window } if session == "ai" && window == "5")
+    fn fleet_run_list_line_includes_mismatch_tag_and_version() {
+        let run = fleet_listed(
+            fleet_test_record("run-2", "beta", "holding", 2, "0.0.1"),
+            false,
+            true,
         );
-        assert_eq!(label, "ai:5.*");
+        let line = fleet_run_list_line(&run, true, true, true);
+        assert!(line.contains(">[x] beta"));
+        assert!(line.contains("[active"));
+        assert!(line.contains("mismatch"));
+        assert!(line.contains("ver=0.0.1"));
     }
 
     #[test]
-    fn resolve_explicit_targets_dedupes_preserving_order() {
-        let targets = vec![
-            "ai:5.0".to_string(),
-            "codex:1.0".to_string(),
-            "ai:5.0".to_string(),
-        ];
-        let resolved = resolve_explicit_targets(&targets, true).unwrap();
-        assert_eq!(resolved, vec!["ai:5.0", "codex:1.0"]);
+    fn fleet_stop_snippet_uses_run_id() {
+        let snippet = fleet_stop_snippet("run-123");
+        assert_eq!(snippet, "loopmux runs stop run-123");
     }
 
     #[test]
-    fn collect_source_inputs_merges_and_dedupes_in_order() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-test-{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-
-        let targets_file = root.join("targets.txt");
-        std::fs::write(&targets_file, "# comment\nai:5.0\nclaude:2.0\nai:5.0\n").unwrap();
-        let files_file = root.join("files.txt");
-        std::fs::write(
-            &files_file,
-            "# comment\n/tmp/a.log\n/tmp/b.log\n/tmp/a.log\n",
-        )
-        .unwrap();
-
-        let sources = collect_source_inputs(
-            &["codex:1.0".to_string(), "ai:5.0".to_string()],
-            std::slice::from_ref(&targets_file),
-            &[PathBuf::from("/tmp/a.log")],
-            std::slice::from_ref(&files_file),
-        )
-        .unwrap();
-
+    fn config_key_lint_reports_location_and_suggestion() {
+        let source = "target: \"a:1.0\"\nrules:\n  - id: one\n    match:\n      regx: \"Done\"\n    action:\n      prompt: |\n        note: not a key\nlogging:\n  pth: /tmp/x\n";
+        let value: serde_yaml::Value = serde_yaml::from_str(source).expect("yaml");
+        let issues =
+            lint_config_value_keys(source, &value, SchemaKind::Run, Path::new("loop.yaml"));
+        let rendered = issues.iter().map(ToString::to_string).collect::<Vec<_>>();
         assert_eq!(
-            sources.tmux_targets,
-            vec!["codex:1.0", "ai:5.0", "claude:2.0"]
+            rendered,
+            vec![
+                "loop.yaml:5:7: unknown key `regx` at `rules[0].match.regx` (did you mean `regex`?)",
+                "loop.yaml:10:3: unknown key `pth` at `logging.pth` (did you mean `path`?)",
+            ]
         );
-        assert_eq!(sources.file_paths, vec!["/tmp/a.log", "/tmp/b.log"]);
-
-        let _ = std::fs::remove_file(targets_file);
-        let _ = std::fs::remove_file(files_file);
-        let _ = std::fs::remove_dir(root);
     }
 
     #[test]
-    fn collect_source_inputs_errors_for_missing_list_file() {
-        let missing = PathBuf::from("/tmp/loopmux-missing-targets-file.txt");
-        let err = collect_source_inputs(&[], &[missing], &[], &[]).unwrap_err();
-        assert!(err.to_string().contains("failed to read list file"));
+    fn config_key_lint_accepts_workspace_runs_and_prompt_lists() {
+        let source = "imports:\n  - extra.yaml\nruns:\n  - id: a\n    target: \"a:1.0\"\n    when:\n      cwd_matches: [\"/repo\"]\n    default_action:\n      prompt:\n        - one\n        - two\n";
+        let value: serde_yaml::Value = serde_yaml::from_str(source).expect("yaml");
+        let issues = lint_config_value_keys(
+            source,
+            &value,
+            SchemaKind::Workspace,
+            Path::new("config.yaml"),
+        );
+        assert!(issues.is_empty(), "{issues:?}");
     }
 
     #[test]
-    fn capture_file_respects_head_and_tail_windows() {
-        let root = std::env::temp_dir().join(format!(
-            "loopmux-capture-test-{}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-        ));
-        std::fs::create_dir_all(&root).unwrap();
-        let file = root.join("sample.log");
-        std::fs::write(&file, "A\nB\nC\nD\n").unwrap();
-
-        let tail = capture_file(&file.display().to_string(), CaptureWindow::Tail(2)).unwrap();
-        let head = capture_file(&file.display().to_string(), CaptureWindow::Head(2)).unwrap();
-
-        assert_eq!(tail, "C\nD");
-        assert_eq!(head, "A\nB");
-
-        let _ = std::fs::remove_file(file);
-        let _ = std::fs::remove_dir(root);
+    fn config_schema_describes_rule_properties() {
+        let schema = config_schema(SchemaKind::Run);
+        let rule = &schema["$defs"]["rule"]["properties"];
+        for key in [
+            "id", "match", "exclude", "action", "delay", "next", "priority",
+        ] {
+            assert!(rule.get(key).is_some(), "missing rule property {key}");
+        }
+        assert_eq!(schema["additionalProperties"], json!(false));
     }
 
     #[test]
-    fn file_source_key_round_trip() {
-        let key = file_source_key("/tmp/a.log");
-        assert_eq!(file_source_path(&key), Some("/tmp/a.log"));
-        assert!(file_source_path("ai:5.0").is_none());
+    fn init_presets_resolve_offline() {
+        for preset in [
+            InitPreset::ClaudeCode,
+            InitPreset::Codex,
+            InitPreset::OpenCode,
+            InitPreset::ExecWatcher,
+        ] {
+            let template = preset_template(preset, "ai:1.0");
+            let config: Config = serde_yaml::from_str(&template).expect("preset yaml");
+            let resolved = resolve_config(
+                config, None, None, true, None, None, false, false, false, None, None, None,
+            )
+            .expect("preset resolves");
+            if preset == InitPreset::ExecWatcher {
+                assert!(resolved.exec_command.is_some());
+            } else {
+                assert_eq!(resolved.target_label, "ai:1.0");
+                assert_eq!(resolved.submit_keys, vec!["Enter".to_string()]);
+                assert_eq!(resolved.rules.len(), 1);
+            }
+        }
     }
 
     #[test]
-    fn sanitize_run_name_normalizes_chars() {
-        assert_eq!(sanitize_run_name(" My Run #1 "), "my-run--1");
-        assert_eq!(sanitize_run_name("alpha_beta"), "alpha_beta");
+    fn detect_assistant_prefers_command_then_content() {
+        assert_eq!(
+            detect_assistant("codex", "Claude Code banner"),
+            Some(InitPreset::Codex)
+        );
+        assert_eq!(
+            detect_assistant("node", "* Welcome to Claude Code!"),
+            Some(InitPreset::ClaudeCode)
+        );
+        assert_eq!(
+            detect_assistant("bun", "opencode v0.3"),
+            Some(InitPreset::OpenCode)
+        );
+        assert_eq!(detect_assistant("zsh", "$ ls"), None);
     }
 
     #[test]
-    fn external_control_renew_resets_runtime_state() {
-        let mut loop_state = LoopState::Running;
-        let mut hold_started = None;
-        let mut held_total = std::time::Duration::from_secs(0);
-        let mut send_count = 9;
-        let mut last_hash_by_target = std::collections::HashMap::new();
-        last_hash_by_target.insert("ai:1.0".to_string(), "abc".to_string());
-        let mut active_rule = Some("next".to_string());
-        let mut active_rule_by_target = std::collections::HashMap::new();
-        active_rule_by_target.insert("ai:1.0".to_string(), Some("next".to_string()));
-
-        let should_stop = apply_external_control(
-            FleetControlCommand::Renew,
-            &mut loop_state,
-            &mut hold_started,
-            &mut held_total,
-            &mut send_count,
-            &mut last_hash_by_target,
-            &mut active_rule,
-            &mut active_rule_by_target,
+    fn split_snapshots_uses_delimiter_lines() {
+        let input = "--- snapshot 1 ---\nworking\n--- snapshot 2 ---\ndone\n<CONTINUE-LOOP>\n";
+        assert_eq!(
+            split_snapshots(input),
+            vec!["working".to_string(), "done\n<CONTINUE-LOOP>".to_string()]
         );
-
-        assert!(!should_stop);
-        assert_eq!(send_count, 0);
-        assert!(last_hash_by_target.is_empty());
-        assert!(active_rule.is_none());
-        assert!(active_rule_by_target.is_empty());
+        assert_eq!(split_snapshots("single\n"), vec!["single".to_string()]);
     }
 
     #[test]
-    fn parse_duration_units() {
-        assert_eq!(parse_duration("5s").unwrap().as_secs(), 5);
-        assert_eq!(parse_duration("2m").unwrap().as_secs(), 120);
-        assert_eq!(parse_duration("1h").unwrap().as_secs(), 3600);
-        assert_eq!(parse_duration("1d").unwrap().as_secs(), 86_400);
-        assert_eq!(parse_duration("1w").unwrap().as_secs(), 604_800);
-        assert_eq!(parse_duration("1mon").unwrap().as_secs(), 2_592_000);
-        assert_eq!(parse_duration("1y").unwrap().as_secs(), 31_536_000);
+    fn parse_capture_window_accepts_tail_and_head() {
+        assert!(matches!(
+            parse_capture_window("tail:5").unwrap(),
+            CaptureWindow::Tail(5)
+        ));
+        assert!(matches!(
+            parse_capture_window("head:2").unwrap(),
+            CaptureWindow::Head(2)
+        ));
+        assert!(parse_capture_window("tail:0").is_err());
+        assert!(parse_capture_window("middle:3").is_err());
     }
 
     #[test]
-    fn parse_duration_rejects_invalid() {
-        assert!(parse_duration("0s").is_err());
-        assert!(parse_duration("5").is_err());
-        assert!(parse_duration("s").is_err());
-        assert!(parse_duration("5x").is_err());
+    fn test_rules_report_applies_confirm_edge_and_next_chain() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 5
+poll: 5
+trigger_confirm_seconds: 5
+default_action:
+  prompt: "continue"
+rules:
+  - id: done
+    match:
+      exact_line: "<DONE>"
+    next: review
+  - id: review
+    match:
+      contains: "LGTM"
+    action:
+      prompt: "ship it"
+    next: stop
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let snapshots = ["busy", "<DONE>", "<DONE>", "<DONE>", "LGTM", "LGTM", "LGTM"]
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let report =
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).expect("report");
+        assert_eq!(
+            report,
+            "#1 t=0s no match\n\
+#2 t=5s held rule=done reason=confirm-pending\n\
+#3 t=10s fire rule=done next=review delay=none prompt=\"continue\"\n\
+#4 t=15s unchanged\n\
+#5 t=20s held rule=review reason=confirm-pending\n\
+#6 t=25s fire rule=review next=stop delay=none prompt=\"ship it\"\n\
+#6 t=25s stopped reason=rule_stop\n\
+summary snapshots=7 sends=2\n"
+        );
     }
 
     #[test]
-    fn render_status_bar_compact() {
-        let config = ResolvedConfig {
-            profile_id: None,
-            exec_command: None,
-            target_scope: TargetScope::Pane("ai:5.0".to_string()),
-            target_label: "ai:5.0".to_string(),
-            explicit_targets: None,
-            file_sources: Vec::new(),
-            iterations: Some(10),
-            infinite: false,
-            has_prompt: true,
-            rule_eval: RuleEval::FirstMatch,
-            rules: Vec::new(),
-            delay: None,
-            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
-            prompt_placeholders: Vec::new(),
-            template_vars: Vec::new(),
-            default_action: Action {
-                pre: None,
-                prompt: Some(PromptBlock::Single("hi".to_string())),
-                post: None,
-            },
-            logging: LoggingConfigResolved {
-                path: None,
-                format: LogFormatResolved::Text,
-            },
-            capture_window: CaptureWindow::Tail(200),
-            once: false,
-            single_line: false,
-            tui: false,
-            poll: 5,
-            log_preview_lines: 3,
-            trigger_edge: true,
-            recheck_before_send: true,
-            fanout: FanoutMode::Matched,
-            duration: None,
-        };
-        let bar = render_status_bar(
-            LoopState::Running,
-            LayoutMode::Compact,
-            IconMode::Ascii,
-            StyleConfig {
-                use_color: false,
-                use_bg: false,
-                use_unicode_ellipsis: false,
-                dim_logs: true,
-            },
-            80,
-            &config,
-            5,
-            10,
-            Some("Concluded"),
-            "00:10",
-            None,
+    fn test_rules_report_fires_idle_rule_once_output_settles() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 5
+poll: 5
+trigger_confirm_seconds: 0
+default_action:
+  prompt: "continue"
+rules:
+  - id: settled
+    match:
+      idle_for: 10s
+      prompt_ready: '^>\s*$'
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let snapshots = ["busy", "busy", "busy", "> ", "> ", "> ", "> ", "busy", "> "]
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let report =
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).expect("report");
+        assert_eq!(
+            report,
+            "#1 t=0s no match\n\
+#2 t=5s no match\n\
+#3 t=10s no match\n\
+#4 t=15s no match\n\
+#5 t=20s no match\n\
+#6 t=25s fire rule=settled next=- delay=none prompt=\"continue\"\n\
+#7 t=30s held rule=settled reason=edge-guard\n\
+#8 t=35s no match\n\
+#9 t=40s no match\n\
+summary snapshots=9 sends=1\n"
         );
-        assert!(bar.contains("RUN"));
-        assert!(bar.contains("5/10"));
-        assert!(bar.contains("ai:5.0"));
+
+        let mut rule = resolved.rules[0].clone();
+        rule.match_.as_mut().unwrap().idle_for = Some("soon".to_string());
+        let err = validate_rules(&[rule], &[]).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid idle_for"));
     }
 
     #[test]
-    fn render_status_bar_standard_truncates_trigger() {
-        let config = ResolvedConfig {
-            profile_id: None,
-            exec_command: None,
-            target_scope: TargetScope::Pane("ai:5.0".to_string()),
-            target_label: "ai:5.0".to_string(),
-            explicit_targets: None,
-            file_sources: Vec::new(),
-            iterations: Some(10),
-            infinite: false,
-            has_prompt: true,
-            rule_eval: RuleEval::FirstMatch,
-            rules: Vec::new(),
-            delay: None,
-            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
-            prompt_placeholders: Vec::new(),
-            template_vars: Vec::new(),
-            default_action: Action {
-                pre: None,
-                prompt: Some(PromptBlock::Single("hi".to_string())),
-                post: None,
-            },
-            logging: LoggingConfigResolved {
-                path: None,
-                format: LogFormatResolved::Text,
-            },
-            capture_window: CaptureWindow::Tail(200),
-            once: false,
-            single_line: false,
-            tui: false,
-            poll: 5,
-            log_preview_lines: 3,
-            trigger_edge: true,
-            recheck_before_send: true,
-            fanout: FanoutMode::Matched,
-            duration: None,
+    fn style_criteria_match_sgr_runs() {
+        let (text, runs) = parse_sgr(
+            "ok \x1b[1;31mError:\x1b[22m build failed\x1b[0m\n\x1b[38;5;245mthinking\x1b[39m",
+        );
+        assert_eq!(text, "ok Error: build failed\nthinking");
+        assert_eq!(runs[1].text, "Error:");
+        assert_eq!(runs[1].style.fg, Some(AnsiColor::Indexed(1)));
+        assert!(runs[1].style.bold);
+        assert_eq!(runs[3].style.fg, Some(AnsiColor::Indexed(245)));
+
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 1
+default_action:
+  prompt: "fix it"
+rules:
+  - id: red-error
+    match:
+      style: { fg: red, bold: true, regex: "^Error" }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .unwrap();
+        assert!(resolved.capture_ansi);
+        let rule = &resolved.rules[0];
+        let ctx = MatchContext {
+            styles: &runs,
+            ..MatchContext::default()
         };
-        let bar = render_status_bar(
-            LoopState::Running,
-            LayoutMode::Standard,
-            IconMode::Ascii,
-            StyleConfig {
-                use_color: false,
-                use_bg: false,
-                use_unicode_ellipsis: true,
-                dim_logs: true,
-            },
-            120,
-            &config,
-            1,
-            10,
-            Some("This is a very long trigger string that should truncate"),
-            "00:10",
-            Some("1m20s"),
+        assert!(matches_rule_at(rule, &text, &ctx).unwrap());
+        let (plain, plain_runs) = parse_sgr("Error: build failed");
+        let ctx = MatchContext {
+            styles: &plain_runs,
+            ..MatchContext::default()
+        };
+        assert!(!matches_rule_at(rule, &plain, &ctx).unwrap());
+        let (_, grey) = parse_sgr("\x1b[90mError: retrying\x1b[0m");
+        let style = rule.match_.as_ref().unwrap().style.as_ref().unwrap();
+        assert!(!style_matches(style, &grey).unwrap());
+        assert!(color_matches("grey", None).is_err());
+
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 1
+default_action:
+  prompt: "continue"
+done_when:
+  match:
+    style: { fg: green, regex: "^PASS" }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .unwrap();
+        assert!(resolved.capture_ansi);
+    }
+
+    #[test]
+    fn test_rules_report_follows_state_machine() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 10
+poll: 5
+trigger_confirm_seconds: 0
+trigger_edge: false
+default_action:
+  prompt: "continue"
+states:
+  - id: build
+    rules: [tests-failed, tests-passed]
+    transitions:
+      tests-passed: review
+    timeout: 10s
+    on_timeout: stop
+  - id: review
+    rules: [lgtm, changes]
+    transitions:
+      changes: build
+rules:
+  - id: tests-failed
+    match: { contains: "FAILED" }
+    action: { prompt: "fix the tests" }
+  - id: tests-passed
+    match: { contains: "passed" }
+    action: { prompt: "request review" }
+  - id: lgtm
+    match: { contains: "LGTM" }
+    next: stop
+  - id: changes
+    match: { contains: "changes requested" }
+  - id: crash
+    escape: true
+    match: { contains: "panicked" }
+    action: { prompt: "restart" }
+    next: [build, review]
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let snapshots = [
+            "FAILED",
+            "LGTM",
+            "passed",
+            "FAILED",
+            "panicked",
+            "changes requested",
+            "idle",
+            "idle",
+            "idle",
+        ]
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>();
+        let report =
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).expect("report");
+        assert_eq!(
+            report,
+            "#1 t=0s fire rule=tests-failed next=build delay=none prompt=\"fix the tests\"\n\
+#2 t=5s no match\n\
+#3 t=10s fire rule=tests-passed next=review delay=none prompt=\"request review\"\n\
+#4 t=15s no match\n\
+#5 t=20s fire rule=crash next=build|review delay=none prompt=\"restart\"\n\
+#6 t=25s fire rule=changes next=build delay=none prompt=\"continue\"\n\
+#7 t=30s no match\n\
+#8 t=35s no match\n\
+#9 t=40s state timeout state=build after=10s next=stop\n\
+#9 t=40s stopped reason=state_timeout\n\
+summary snapshots=9 sends=4\n"
         );
-        assert!(bar.contains("trg"));
-        assert!(bar.contains("rem 1m20s"));
-        assert!(bar.contains("…"));
     }
 
     #[test]
-    fn render_status_bar_exec_uses_event_label() {
-        let config = ResolvedConfig {
-            profile_id: Some("watcher".to_string()),
-            exec_command: Some("gw-watch-comp".to_string()),
-            target_scope: TargetScope::All,
-            target_label: "exec://gw-watch-comp".to_string(),
-            explicit_targets: None,
-            file_sources: Vec::new(),
-            iterations: Some(3),
-            infinite: false,
-            has_prompt: false,
-            rule_eval: RuleEval::FirstMatch,
-            rules: Vec::new(),
-            delay: None,
-            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
-            prompt_placeholders: Vec::new(),
-            template_vars: Vec::new(),
-            default_action: Action {
-                pre: None,
-                prompt: None,
-                post: None,
-            },
-            logging: LoggingConfigResolved {
-                path: None,
-                format: LogFormatResolved::Text,
-            },
-            capture_window: CaptureWindow::Tail(1),
-            once: false,
-            single_line: false,
-            tui: true,
-            poll: 10,
-            log_preview_lines: 3,
-            trigger_edge: true,
-            recheck_before_send: true,
-            fanout: FanoutMode::Matched,
-            duration: None,
-        };
-        let bar = render_status_bar(
-            LoopState::Running,
-            LayoutMode::Standard,
-            IconMode::Ascii,
-            StyleConfig {
-                use_color: false,
-                use_bg: false,
-                use_unicode_ellipsis: true,
-                dim_logs: true,
-            },
-            120,
-            &config,
-            1,
-            3,
-            Some("exec:running"),
-            "00:05",
-            None,
+    fn test_rules_report_uses_transitions_of_the_alternative_that_matched() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 10
+poll: 5
+trigger_confirm_seconds: 0
+trigger_edge: false
+default_action:
+  prompt: "continue"
+states:
+  - id: idle
+    rules: [kick]
+    transitions:
+      kick: [build, review]
+  - id: build
+    rules: [failed]
+    transitions:
+      failed: idle
+  - id: review
+    rules: [lgtm]
+    transitions:
+      lgtm: stop
+rules:
+  - id: kick
+    match: { contains: "go" }
+  - id: failed
+    match: { contains: "FAILED" }
+  - id: lgtm
+    match: { contains: "LGTM" }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let snapshots = ["go", "LGTM"]
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let report =
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).expect("report");
+        assert!(
+            report.contains("#2 t=5s fire rule=lgtm next=stop"),
+            "{report}"
         );
-        assert!(bar.contains("evt exec:running"));
-        assert!(bar.contains("exec://gw-watch-comp"));
     }
 
     #[test]
-    fn status_line_exec_uses_friendly_event_label() {
-        let config = ResolvedConfig {
-            profile_id: Some("watcher".to_string()),
-            exec_command: Some("gw-watch-comp".to_string()),
-            target_scope: TargetScope::All,
-            target_label: "exec://gw-watch-comp".to_string(),
-            explicit_targets: None,
-            file_sources: Vec::new(),
-            iterations: Some(3),
-            infinite: false,
-            has_prompt: false,
-            rule_eval: RuleEval::FirstMatch,
-            rules: Vec::new(),
-            delay: None,
-            trigger_confirm_seconds: DEFAULT_TRIGGER_CONFIRM_SECONDS,
-            prompt_placeholders: Vec::new(),
-            template_vars: Vec::new(),
-            default_action: Action {
-                pre: None,
-                prompt: None,
-                post: None,
-            },
-            logging: LoggingConfigResolved {
-                path: None,
-                format: LogFormatResolved::Text,
-            },
-            capture_window: CaptureWindow::Tail(1),
-            once: false,
-            single_line: true,
-            tui: false,
-            poll: 10,
-            log_preview_lines: 3,
-            trigger_edge: true,
-            recheck_before_send: true,
-            fanout: FanoutMode::Matched,
-            duration: None,
-        };
+    fn validate_states_finds_unreachable_and_dead_end_states() {
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            "- id: a\n  match: { contains: A }\n  next: two\n- id: b\n  match: { contains: B }\n",
+        )
+        .unwrap();
+        let states = |yaml: &str| serde_yaml::from_str::<Vec<RuleState>>(yaml).unwrap();
 
-        let line = status_line(&config, 1, 3, Some("exec:running"), "5s");
-        assert!(line.contains("event=running"));
-        assert!(!line.contains("rule=exec:running"));
+        let ok = states("- id: one\n  rules: [a]\n- id: two\n  rules: [b]\n");
+        validate_states(&ok, &rules).unwrap();
+
+        let unreachable = states("- id: one\n  rules: [b]\n- id: two\n  rules: [a]\n");
+        let err = validate_states(&unreachable, &rules).unwrap_err();
+        assert!(err.to_string().contains("state two is unreachable"));
+
+        let dead_end = states("- id: one\n  rules: [a]\n- id: two\n");
+        let err = validate_states(&dead_end, &rules).unwrap_err();
+        assert!(err.to_string().contains("state two is a dead end"));
+
+        let unknown = states(
+            "- id: one\n  rules: [a]\n  on_timeout: nowhere\n  timeout: 5m\n- id: two\n  rules: [b]\n",
+        );
+        let err = validate_states(&unknown, &rules).unwrap_err();
+        assert!(err.to_string().contains("references unknown next: nowhere"));
     }
 
     #[test]
-    fn format_exec_event_label_maps_known_values() {
-        assert_eq!(format_exec_event_label("exec:started"), "started");
-        assert_eq!(format_exec_event_label("exec:running"), "running");
-        assert_eq!(format_exec_event_label("exec:ok"), "ok");
-        assert_eq!(format_exec_event_label("exec:fail"), "fail");
-        assert_eq!(format_exec_event_label("custom"), "custom");
+    fn rule_flow_graph_renders_next_edges_and_stop() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 3
+trigger_confirm_seconds: 5
+default_action:
+  prompt: "continue"
+rules:
+  - id: failed
+    match: { regex: "FAIL", idle_for: 30s }
+    delay: { mode: fixed, value: 10 }
+    next: [failed, done]
+  - id: done
+    match: { contains: "LGTM" }
+    confirm_seconds: 2
+    action: { prompt: "ship \"it\"" }
+    next: stop
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+
+        let mermaid = rule_flow_graph(&resolved, GraphFormat::Mermaid);
+        assert_eq!(
+            mermaid,
+            r#"flowchart TD
+    start(["start"])
+    rule_0["failed<br/>match: /FAIL/ & idle 30s<br/>delay: fixed 10s<br/>confirm: 5s"]
+    rule_1["done<br/>match: contains #quot;LGTM#quot;<br/>confirm: 2s<br/>prompt: ship #quot;it#quot;"]
+    default_action[/"default action<br/>prompt: continue"/]
+    stop(["stop"])
+    start --> rule_0
+    rule_0 --> rule_0
+    rule_0 --> rule_1
+    rule_0 -.->|"sends"| default_action
+    start --> rule_1
+    rule_1 --> stop
+"#
+        );
+
+        let dot = rule_flow_graph(&resolved, GraphFormat::Dot);
+        assert!(dot.starts_with("digraph loopmux {\n"));
+        assert!(dot.contains(
+            "rule_1 [label=\"done\\nmatch: contains \\\"LGTM\\\"\\nconfirm: 2s\\nprompt: ship \\\"it\\\"\", shape=box];"
+        ));
+        assert!(dot.contains("rule_0 -> default_action [label=\"sends\", style=dashed];"));
+        assert!(dot.ends_with("rule_1 -> stop;\n}\n"));
     }
 
     #[test]
-    fn trigger_edge_rearms_after_clear() {
-        let mut active = HashSet::new();
-        active.insert("ai:7.0|inline|0".to_string());
+    fn test_rules_report_applies_cooldown_and_max_fires() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 10
+poll: 60
+trigger_confirm_seconds: 0
+trigger_edge: false
+default_action:
+  prompt: "continue"
+rules:
+  - id: repair
+    match: { contains: "FAIL" }
+    action: { prompt: "fix it" }
+    max_fires: 2
+    on_exhausted: escalate
+  - id: escalate
+    match: { contains: "FAIL" }
+    action: { prompt: "ask a human" }
+    next: stop
+  - id: retry
+    match: { contains: "flaky" }
+    cooldown: 2m
+    action: { prompt: "retry" }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let snapshots = ["flaky", "flaky", "flaky", "FAIL", "FAIL", "FAIL", "FAIL"]
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let report =
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).expect("report");
+        assert_eq!(
+            report,
+            "#1 t=0s fire rule=retry next=- delay=none prompt=\"retry\"\n\
+#2 t=60s held rule=retry reason=cooldown\n\
+#3 t=120s fire rule=retry next=- delay=none prompt=\"retry\"\n\
+#4 t=180s fire rule=repair next=- delay=none prompt=\"fix it\"\n\
+#5 t=240s fire rule=repair next=- delay=none prompt=\"fix it\"\n\
+#6 t=300s rule exhausted rule=repair limit=max_fires=2 next=escalate\n\
+#7 t=360s fire rule=escalate next=stop delay=none prompt=\"ask a human\"\n\
+#7 t=360s stopped reason=rule_stop\n\
+summary snapshots=7 sends=5\n"
+        );
+    }
 
-        let matched_now = HashSet::new();
-        refresh_trigger_edges_for_target(&mut active, "ai:7.0", &matched_now, true);
-        assert!(!active.contains("ai:7.0|inline|0"));
+    #[test]
+    fn test_rules_report_stops_once_when_rule_exhausts_without_on_exhausted() {
+        let yaml = r#"
+target: "ai:1.0"
+iterations: 10
+poll: 60
+trigger_confirm_seconds: 0
+trigger_edge: false
+default_action:
+  prompt: "continue"
+rules:
+  - id: repair
+    match: { contains: "FAIL" }
+    max_fires: 1
+  - id: fallback
+    match: { contains: "FAIL" }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let snapshots = ["FAIL", "FAIL", "FAIL"]
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let report =
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).expect("report");
+        assert_eq!(
+            report,
+            "#1 t=0s fire rule=repair next=- delay=none prompt=\"continue\"\n\
+#2 t=60s rule exhausted rule=repair limit=max_fires=1 next=stop\n\
+#2 t=60s stopped reason=rule_exhausted\n\
+summary snapshots=3 sends=1\n"
+        );
 
-        active.insert("other:1.0|inline|0".to_string());
-        refresh_trigger_edges_for_target(&mut active, "ai:7.0", &matched_now, true);
-        assert!(active.contains("other:1.0|inline|0"));
+        // With `on_exhausted`, the used-up rule stops shadowing later rules under first_match.
+        let config: Config = serde_yaml::from_str(
+            &yaml.replace("max_fires: 1", "max_fires: 1\n    on_exhausted: fallback"),
+        )
+        .expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let snapshots = ["FAIL", "FAIL", "FAIL", "FAIL"]
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let report =
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).expect("report");
+        assert_eq!(report.matches("rule exhausted").count(), 1, "{report}");
+        assert!(report.contains("#4 t=180s fire rule=fallback"), "{report}");
     }
 
     #[test]
-    fn edge_guard_allowance_respects_toggle() {
-        let mut active = HashSet::new();
-        active.insert("ai:7.0|inline|0".to_string());
-        assert!(!edge_guard_allows(&active, "ai:7.0|inline|0", true));
-        assert!(edge_guard_allows(&active, "ai:7.0|inline|0", false));
-        assert!(edge_guard_allows(&active, "ai:7.0|inline|1", true));
+    fn target_patterns_match_sessions_windows_and_titles() {
+        assert!(target_pattern_matches("ai", "ai:1.0", ""));
+        assert!(target_pattern_matches("ai:1", "ai:1.2", ""));
+        assert!(!target_pattern_matches("ai:1", "ai:10.2", ""));
+        assert!(target_pattern_matches("ai:*.1", "ai:3.1", ""));
+        assert!(!target_pattern_matches("reviewer", "work:2.0", "reviewer"));
+        assert!(target_pattern_matches("title:rev*", "work:2.0", "reviewer"));
+        assert!(!target_pattern_matches(
+            "title:coder",
+            "work:2.0",
+            "reviewer"
+        ));
+        assert!(target_pattern_matches(
+            "file://notes.md",
+            "file://notes.md",
+            ""
+        ));
+        assert!(!target_pattern_matches("file", "file://notes.md", ""));
     }
 
     #[test]
-    fn hash_skip_depends_on_trigger_edge_mode() {
-        assert!(should_skip_scan_by_hash(true, "same", "same", false));
-        assert!(!should_skip_scan_by_hash(true, "same", "same", true));
-        assert!(!should_skip_scan_by_hash(false, "same", "same", false));
-        assert!(!should_skip_scan_by_hash(true, "new", "old", false));
+    fn replay_scopes_rules_to_targets_and_sends_elsewhere() {
+        let yaml = r#"
+target: "ai"
+iterations: 10
+trigger_confirm_seconds: 0
+trigger_edge: false
+default_action:
+  prompt: "continue"
+rules:
+  - id: coder-done
+    targets: ["ai:1.0"]
+    match: { contains: "DONE" }
+    action: { prompt: "please review" }
+    send_to: "ai:1.1"
+  - id: review-done
+    targets: ["ai:1.1"]
+    match: { contains: "DONE" }
+    action: { prompt: "address the review" }
+    send_to: "ai:1.0"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let capture = |seconds: u64, target: &str, output: &str| ReplayCapture {
+            target: target.to_string(),
+            at: Duration::from_secs(seconds),
+            output: output.to_string(),
+        };
+        let captures = [
+            capture(0, "ai:1.0", "DONE"),
+            capture(5, "ai:1.1", "DONE"),
+            capture(10, "ai:1.1", "working"),
+        ];
+        let (lines, sends) = replay_captures(
+            &resolved,
+            &captures,
+            &BTreeMap::new(),
+            CaptureWindow::Tail(1),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            lines,
+            [
+                "#1 t=0s target=ai:1.0 fire rule=coder-done to=ai:1.1 next=- delay=none prompt=\"please review\"",
+                "#2 t=5s target=ai:1.1 fire rule=review-done to=ai:1.0 next=- delay=none prompt=\"address the review\"",
+                "#3 t=10s target=ai:1.1 no match",
+            ]
+        );
+        assert_eq!(sends, 2);
     }
 
     #[test]
-    fn pending_confirm_detected_per_target() {
-        let mut pending = std::collections::HashMap::new();
-        let now = std::time::Instant::now();
-        pending.insert("ai:7.0|inline|0".to_string(), now);
-        pending.insert("other:1.0|inline|0".to_string(), now);
-        assert!(has_pending_confirm_for_target(&pending, "ai:7.0"));
-        assert!(has_pending_confirm_for_target(&pending, "other:1.0"));
-        assert!(!has_pending_confirm_for_target(&pending, "ai:8.0"));
-    }
+    fn forward_region_cuts_sanitizes_and_wraps_output() {
+        let forward = |yaml: &str| serde_yaml::from_str::<PromptForward>(yaml).unwrap();
+        let output = "old <REVIEW>\nstale\n</REVIEW>\n$ review\n<REVIEW>\n  \x1b[31m1. fix naming\x1b[0m  \n2. add a test\x07\n\n</REVIEW>\n> ";
 
-    #[test]
-    fn confirm_window_elapsed_requires_persisted_match() {
-        let mut pending = std::collections::HashMap::new();
-        let now = std::time::Instant::now();
-        assert!(!confirm_window_elapsed(
-            5,
-            None,
-            "ai:7.0|inline|0",
-            &mut pending,
-            now
-        ));
-        assert!(!confirm_window_elapsed(
-            5,
-            Some(3),
-            "ai:7.0|inline|0",
-            &mut pending,
-            now + std::time::Duration::from_secs(2),
-        ));
-        assert!(confirm_window_elapsed(
-            5,
-            Some(3),
-            "ai:7.0|inline|0",
-            &mut pending,
-            now + std::time::Duration::from_secs(3),
-        ));
+        let between = forward(
+            "forward: { between: [\"<REVIEW>\", \"</REVIEW>\"] }\ntemplate: \"Reviewer:\\n{{output}}\"",
+        );
+        assert_eq!(
+            forward_region(&between, output).unwrap(),
+            "Reviewer:\n  1. fix naming\n2. add a test"
+        );
+
+        let after = forward("forward: { after: \"^\\\\$ review\", lines: 2 }\nmax_bytes: 10");
+        assert_eq!(
+            forward_region(&after, output).unwrap(),
+            "</REVIEW>\n[forwarded output truncated] (9 of 11 bytes)"
+        );
+        let long_line = forward("forward: { lines: 1 }\nmax_bytes: 4");
+        assert_eq!(
+            forward_region(&long_line, "ünïcode findings").unwrap(),
+            "ün\n[forwarded output truncated] (3 of 18 bytes)"
+        );
+
+        let missing = forward("forward: { between: [\"<PLAN>\", \"</PLAN>\"] }");
+        assert_eq!(
+            forward_region(&missing, output).unwrap_err(),
+            "forward end marker `</PLAN>` not found"
+        );
     }
 
     #[test]
-    fn confirm_window_elapsed_zero_is_immediate() {
-        let mut pending = std::collections::HashMap::new();
-        assert!(confirm_window_elapsed(
-            5,
-            Some(0),
-            "ai:7.0|inline|0",
-            &mut pending,
-            std::time::Instant::now(),
-        ));
-        assert!(pending.is_empty());
+    fn replay_forwards_source_region_to_send_to_pane() {
+        let yaml = r#"
+target: "ai"
+iterations: 10
+trigger_confirm_seconds: 0
+trigger_edge: false
+default_action:
+  prompt: "continue"
+rules:
+  - id: review-ready
+    targets: ["ai:1.1"]
+    match: { contains: "</REVIEW>" }
+    send_to: "ai:1.0"
+    action:
+      prompt:
+        forward: { between: ["<REVIEW>", "</REVIEW>"] }
+        template: "Reviewer says: {{output}}"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let capture = |seconds: u64, target: &str, output: &str| ReplayCapture {
+            target: target.to_string(),
+            at: Duration::from_secs(seconds),
+            output: output.to_string(),
+        };
+        let captures = [
+            capture(0, "ai:1.0", "coding"),
+            capture(5, "ai:1.1", "$ review\n<REVIEW>\nrename foo\n</REVIEW>\n> "),
+        ];
+        let (lines, sends) = replay_captures(
+            &resolved,
+            &captures,
+            &BTreeMap::new(),
+            CaptureWindow::Tail(10),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            lines,
+            [
+                "#1 t=0s target=ai:1.0 no match",
+                "#2 t=5s target=ai:1.1 fire rule=review-ready to=ai:1.0 next=- delay=none prompt=\"Reviewer says: rename foo\"",
+            ]
+        );
+        assert_eq!(sends, 1);
     }
 
     #[test]
-    fn truncate_text_respects_ascii_max_width() {
-        let truncated = truncate_text("abcdefghijk", 8, false);
-        assert_eq!(truncated.chars().count(), 8);
-        assert_eq!(truncated, "abcde...");
+    fn replay_resolves_send_to_titles_from_recorded_panes() {
+        let yaml = r#"
+target: "ai"
+iterations: 10
+trigger_confirm_seconds: 0
+trigger_edge: false
+default_action:
+  prompt: "continue"
+rules:
+  - id: review-done
+    targets: ["title:reviewer"]
+    match: { contains: "DONE" }
+    action: { prompt: "address the review" }
+    send_to: "title:coder"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let captures = [ReplayCapture {
+            target: "ai:1.1".to_string(),
+            at: Duration::ZERO,
+            output: "DONE".to_string(),
+        }];
+        let titles = BTreeMap::from([
+            ("ai:1.0".to_string(), "coder".to_string()),
+            ("ai:1.1".to_string(), "reviewer".to_string()),
+        ]);
+        let (lines, sends) =
+            replay_captures(&resolved, &captures, &titles, CaptureWindow::Tail(1), true).unwrap();
+        assert_eq!(
+            lines,
+            [
+                "#1 t=0s target=ai:1.1 fire rule=review-done to=ai:1.0 next=- delay=none prompt=\"address the review\""
+            ]
+        );
+        assert_eq!(sends, 1);
     }
 
     #[test]
-    fn extract_trigger_preview_ascii_separator() {
-        let output = "line1\nline2\nline3\n";
-        let (_, preview) = extract_trigger_preview(output, 2, false);
-        assert!(preview.contains(" | "));
-        assert!(!preview.contains(" │ "));
+    fn pane_selectors_match_title_command_cwd_and_tag() {
+        let pane = |target: &str, command: &str, cwd: &str, tag: &str, title: &str| TmuxPane {
+            target: target.to_string(),
+            session: "work".to_string(),
+            window: "1".to_string(),
+            current_command: command.to_string(),
+            cwd: cwd.to_string(),
+            tag: tag.to_string(),
+            title: title.to_string(),
+        };
+        let panes = vec![
+            pane("work:1.0", "node", "/src/app", "", "Claude Code"),
+            pane("work:1.1", "zsh", "/src/app", "reviewer", "shell"),
+            pane("work:1.2", "node", "/tmp", "", "claude"),
+        ];
+
+        let (scope, label) =
+            resolve_target_scope_offline(Some("pane_title=~(?i)claude, cwd=/src/*")).unwrap();
+        assert_eq!(label, "pane_title=~(?i)claude, cwd=/src/*");
+        assert_eq!(select_targets_for_scope(&scope, &panes), vec!["work:1.0"]);
+
+        let (scope, _) = resolve_target_scope_offline(Some("current_command=node")).unwrap();
+        assert_eq!(
+            select_targets_for_scope(&scope, &panes),
+            vec!["work:1.0", "work:1.2"]
+        );
+
+        let (scope, _) = resolve_target_scope_offline(Some("@reviewer")).unwrap();
+        assert_eq!(select_targets_for_scope(&scope, &panes), vec!["work:1.1"]);
+        let (scope, _) = resolve_target_scope_offline(Some("@loopmux=rev*")).unwrap();
+        assert_eq!(select_targets_for_scope(&scope, &panes), vec!["work:1.1"]);
+
+        // `contains:` captures the pane again every cycle; a pane whose text is gone (here:
+        // no such tmux pane) drops out even though its other conditions still hold.
+        let gone = TmuxPane {
+            target: "loopmux-test-absent:1.0".to_string(),
+            ..panes[0].clone()
+        };
+        let (scope, _) =
+            resolve_target_scope_offline(Some(r#"current_command=node,contains:"Claude Code""#))
+                .unwrap();
+        assert!(select_targets_for_scope(&scope, &[gone]).is_empty());
     }
 
     #[test]
-    fn log_line_date_extracts_rfc3339_prefix() {
-        let line = "[2026-02-17T00:12:34Z] started target=ai:7.0";
-        assert_eq!(log_line_date(line), Some("2026-02-17"));
-        assert_eq!(log_line_date("23:11:04 > ai:7.0"), None);
+    fn pane_selectors_parse_quoted_contains_last_and_reject_unknown_keys() {
+        let selectors =
+            parse_pane_selectors(r#"contains:"Claude Code, ready",current_command=node"#).unwrap();
+        assert_eq!(selectors.len(), 2);
+        assert!(matches!(selectors[0], PaneSelector::Command(_)));
+        assert!(
+            matches!(&selectors[1], PaneSelector::Contains(text) if text == "Claude Code, ready")
+        );
+
+        let selectors = parse_pane_selectors("pane_title=~a{1,3},command=node").unwrap();
+        assert_eq!(selectors.len(), 2);
+        assert!(
+            matches!(&selectors[0], PaneSelector::Title(SelectorValue::Regex(regex)) if regex.as_str() == "a{1,3}")
+        );
+
+        let selectors = parse_pane_selectors(r#"contains:"Claude Code""#).unwrap();
+        assert!(matches!(&selectors[..], [PaneSelector::Contains(text)] if text == "Claude Code"));
+
+        let err = parse_pane_selectors("pane_id=%3").unwrap_err();
+        assert!(err.to_string().contains("unknown pane selector key"));
+        assert!(parse_pane_selectors("pane_title=~(").is_err());
     }
 
     #[test]
-    fn compact_time_prefix_detection() {
-        assert!(looks_like_compact_time_prefix("23:11:04 > ai:7.0"));
-        assert!(!looks_like_compact_time_prefix(
-            "[2026-02-17T00:12:34Z] sent"
-        ));
+    fn delay_rng_is_reproducible_per_seed() {
+        let mut first = DelayRng::new(42);
+        let mut second = DelayRng::new(42);
+        let a = (0..16)
+            .map(|_| first.between(5, 120).unwrap())
+            .collect::<Vec<_>>();
+        let b = (0..16)
+            .map(|_| second.between(5, 120).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|value| (5..=120).contains(value)));
+        let mut other = DelayRng::new(43);
+        let c = (0..16)
+            .map(|_| other.between(5, 120).unwrap())
+            .collect::<Vec<_>>();
+        assert_ne!(a, c);
+        assert!(DelayRng::new(1).between(9, 3).is_err());
     }
 
     #[test]
-    fn log_line_color_same_and_prior_day() {
-        let now = OffsetDateTime::parse(
-            "2026-02-17T10:00:00Z",
-            &time::format_description::well_known::Rfc3339,
-        )
-        .unwrap();
-        assert_eq!(log_line_color_at("[2026-02-17T01:02:03Z] sent", now), 251);
-        assert_eq!(log_line_color_at("[2026-02-16T23:59:59Z] sent", now), 244);
+    fn virtual_clock_drives_duration_accounting() {
+        let mut clock = Clock::virtual_now();
+        let run_started = clock.now();
+        clock.advance_to(Duration::from_secs(3 * 3600));
+        let hold_started = Some(run_started + Duration::from_secs(2 * 3600));
+        let elapsed = effective_elapsed_at(
+            clock.now(),
+            run_started,
+            Duration::from_secs(600),
+            hold_started,
+        );
+        assert_eq!(elapsed, Duration::from_secs(3 * 3600 - 3600 - 600));
+        clock.advance_to(Duration::from_secs(60));
+        assert_eq!(
+            clock.now().duration_since(run_started),
+            Duration::from_secs(3 * 3600)
+        );
     }
 
     #[test]
-    fn log_line_color_handles_timezone_offsets() {
-        let now = OffsetDateTime::parse(
-            "2026-02-17T00:30:00+00:00",
-            &time::format_description::well_known::Rfc3339,
+    fn run_loop_waits_rule_delay_on_virtual_clock() {
+        thread_local! {
+            static SENT: std::cell::RefCell<Vec<(String, String)>> =
+                const { std::cell::RefCell::new(Vec::new()) };
+        }
+        let io = PaneIo {
+            list: || {
+                Ok(vec![TmuxPane {
+                    target: "loopmux-test-delay:0.0".to_string(),
+                    session: "loopmux-test-delay".to_string(),
+                    window: "0".to_string(),
+                    current_command: "sh".to_string(),
+                    cwd: "/tmp".to_string(),
+                    tag: String::new(),
+                    title: String::new(),
+                }])
+            },
+            capture: |_, _, _| Ok("build\n<DONE>".to_string()),
+            send: |target, prompt, _| {
+                SENT.with(|sent| {
+                    sent.borrow_mut()
+                        .push((target.to_string(), prompt.to_string()))
+                });
+                Ok(())
+            },
+        };
+        let yaml = "target: \"loopmux-test-delay:0.0\"\niterations: 1\npoll: 1\ntrigger_confirm_seconds: 0\nrecheck_before_send: false\ndefault_action:\n  prompt: go\nrules:\n  - id: done\n    match:\n      contains: \"<DONE>\"\n    delay: { mode: fixed, value: 3600 }\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
         )
         .unwrap();
+        let identity = RunIdentity {
+            id: format!(
+                "loopmux-test-{}",
+                OffsetDateTime::now_utc().unix_timestamp_nanos()
+            ),
+            name: "loopmux-test-delay".to_string(),
+        };
+        let mut clock = Clock::virtual_now();
+        let started = clock.now();
+        let real_started = std::time::Instant::now();
+
+        let outcome = run_loop(resolved, identity, None, &mut clock, io).unwrap();
+
+        assert_eq!(outcome, RunOutcome::Finished);
         assert_eq!(
-            log_line_color_at("[2026-02-16T23:30:00-02:00] sent", now),
-            251
+            SENT.with(|sent| sent.borrow().clone()),
+            vec![("loopmux-test-delay:0.0".to_string(), "go".to_string())]
         );
+        assert!(clock.elapsed(started) >= Duration::from_secs(3600));
+        assert!(real_started.elapsed() < Duration::from_secs(30));
     }
 
     #[test]
-    fn log_line_color_compact_prefix_still_dimmed() {
-        let now = OffsetDateTime::parse(
-            "2026-02-17T00:30:00+00:00",
-            &time::format_description::well_known::Rfc3339,
+    fn test_rules_report_delays_follow_seed() {
+        let yaml = "target: \"ai:1.0\"\niterations: 3\ntrigger_confirm_seconds: 0\ntrigger_edge: false\ndefault_action:\n  prompt: go\ndelay:\n  mode: range\n  min: 5\n  max: 120\nrules:\n  - id: done\n    match:\n      contains: \"<DONE>\"\n";
+        let report_for = |seed: u64| {
+            let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+            config.seed = Some(seed);
+            let resolved = resolve_config(
+                config, None, None, true, None, None, false, false, false, None, None, None,
+            )
+            .unwrap();
+            let snapshots = vec!["<DONE>".to_string(); 3];
+            test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).unwrap()
+        };
+        let delays = |report: String| {
+            report
+                .lines()
+                .filter_map(|line| line.split_once("5-120s (").map(|(_, rest)| rest))
+                .filter_map(|rest| rest.split_once(')').map(|(delay, _)| delay.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(report_for(7), report_for(7));
+        assert_eq!(delays(report_for(7)), ["68s", "89s", "99s"]);
+        assert_eq!(delays(report_for(8)), ["67s", "26s", "106s"]);
+
+        // A weighted rotation draws from its own stream and leaves the delays alone.
+        let weighted = yaml.replace(
+            "  prompt: go\n",
+            "  prompt:\n    rotate: weighted\n    items:\n      - { text: a, weight: 1 }\n      - { text: b, weight: 1 }\n",
+        );
+        let mut config: Config = serde_yaml::from_str(&weighted).unwrap();
+        config.seed = Some(7);
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
         )
         .unwrap();
-        assert_eq!(log_line_color_at("23:11:04 > ai:7.0", now), 249);
-    }
-
-    fn fleet_test_record(
-        id: &str,
-        name: &str,
-        state: &str,
-        sends: u32,
-        version: &str,
-    ) -> FleetRunRecord {
-        FleetRunRecord {
-            id: id.to_string(),
-            name: name.to_string(),
-            profile_id: name.to_string(),
-            pid: 1,
-            host: "local".to_string(),
-            target: "ai:1.0".to_string(),
-            state: state.to_string(),
-            sends,
-            poll_seconds: 5,
-            started_at: "2026-02-17T00:00:00Z".to_string(),
-            last_seen: "2026-02-17T00:00:00Z".to_string(),
-            version: version.to_string(),
-            events: Vec::new(),
-        }
-    }
-
-    fn fleet_listed(record: FleetRunRecord, stale: bool, version_mismatch: bool) -> FleetListedRun {
-        let (health_score, health_label) = fleet_health(&record, stale, version_mismatch);
-        FleetListedRun {
-            record,
-            stale,
-            version_mismatch,
-            health_score,
-            health_label,
-            needs_attention: stale || version_mismatch || health_score < 70,
-        }
+        let snapshots = vec!["<DONE>".to_string(); 3];
+        let report = test_rules_report(&resolved, &snapshots, CaptureWindow::Tail(1)).unwrap();
+        assert_eq!(delays(report), ["68s", "89s", "99s"]);
     }
 
     #[test]
-    fn fleet_manager_hides_stale_by_default() {
-        let active = fleet_listed(
-            fleet_test_record("run-1", "alpha", "waiting", 1, LOOPMUX_VERSION),
-            false,
-            false,
-        );
-        let stale = fleet_listed(
-            fleet_test_record("run-2", "beta", "waiting", 1, LOOPMUX_VERSION),
+    fn session_records_round_trip_and_replay() {
+        let path = std::env::temp_dir().join(format!(
+            "loopmux-session-test-{}.jsonl",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        let records = [
+            SessionRecord::Capture {
+                t_ms: 0,
+                timestamp: "t0".to_string(),
+                target: "ai:1.0".to_string(),
+                hash: hash_output("busy"),
+                output: "busy".to_string(),
+            },
+            SessionRecord::Capture {
+                t_ms: 2500,
+                timestamp: "t1".to_string(),
+                target: "ai:1.0".to_string(),
+                hash: hash_output("<DONE>"),
+                output: "<DONE>".to_string(),
+            },
+            SessionRecord::Send {
+                t_ms: 3000,
+                timestamp: "t2".to_string(),
+                target: "ai:1.0".to_string(),
+                rule_id: None,
+                prompt: "status?".to_string(),
+                source: Some("manual".to_string()),
+            },
+            SessionRecord::Panes {
+                t_ms: 3000,
+                titles: BTreeMap::from([("ai:1.0".to_string(), "coder".to_string())]),
+            },
+        ];
+        let body = records
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        std::fs::write(&path, body).unwrap();
+        let loaded = read_session_records(&path).expect("records");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded, records);
+        // Sessions recorded before manual sends were tagged still load as rule sends.
+        let older: SessionRecord = serde_json::from_str(
+            r#"{"type":"send","t_ms":1,"timestamp":"t","target":"ai:1.0","rule_id":"done","prompt":"go"}"#,
+        )
+        .unwrap();
+        assert!(matches!(older, SessionRecord::Send { source: None, .. }));
+
+        let config: Config = serde_yaml::from_str(
+            "target: \"ai:1.0\"\niterations: 1\ntrigger_confirm_seconds: 0\ndefault_action:\n  prompt: go\nrules:\n  - id: done\n    match:\n      exact_line: \"<DONE>\"\n",
+        )
+        .unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .unwrap();
+        let captures = loaded
+            .into_iter()
+            .filter_map(|record| match record {
+                SessionRecord::Capture {
+                    t_ms,
+                    target,
+                    output,
+                    ..
+                } => Some(ReplayCapture {
+                    target,
+                    at: Duration::from_millis(t_ms),
+                    output,
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let (lines, sends) = replay_captures(
+            &resolved,
+            &captures,
+            &BTreeMap::new(),
+            CaptureWindow::Tail(1),
             true,
-            false,
+        )
+        .unwrap();
+        assert_eq!(sends, 1);
+        assert_eq!(lines[0], "#1 t=0s target=ai:1.0 no match");
+        assert_eq!(
+            lines[1],
+            "#2 t=2.500s target=ai:1.0 fire rule=done next=- delay=none prompt=\"go\""
         );
+    }
 
-        let hidden = fleet_manager_visible_runs(
-            &vec![active.clone(), stale.clone()],
-            None,
-            false,
-            false,
-            FleetStateFilter::All,
-            "",
-            FleetSortMode::LastSeen,
-            FleetViewPreset::Default,
+    #[test]
+    fn prompt_refs_expand_from_library_with_vars() {
+        let yaml = r#"
+default_action:
+  prompt: { ref: commit-small, vars: { scope: api } }
+rules:
+  - id: lint
+    match:
+      contains: "error"
+    action:
+      pre: "Fix it."
+      post: { ref: lint-tests }
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        assert!(config_has_prompt_refs(&config));
+        let library = [
+            (
+                "commit-small",
+                "Commit {{ scope }} changes in {{scope}}; {{other}}",
+            ),
+            ("lint-tests", "Run lint/tests; fix failures.\n"),
+        ]
+        .into_iter()
+        .map(|(name, text)| {
+            (
+                name.to_string(),
+                PromptSnippet {
+                    text: text.trim_end().to_string(),
+                    source: PathBuf::from("prompts.yaml"),
+                },
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+        expand_config_prompt_refs(&mut config, &library).expect("expand");
+        assert_eq!(
+            build_prompt(config.default_action.as_ref().unwrap()),
+            "Commit api changes in api; {{other}}"
+        );
+        let rules = config.rules.as_ref().unwrap();
+        assert_eq!(
+            build_prompt(rules[0].action.as_ref().unwrap()),
+            "Fix it.\nRun lint/tests; fix failures."
         );
-        assert_eq!(hidden.len(), 1);
-        assert_eq!(hidden[0].record.id, "run-1");
 
-        let all = fleet_manager_visible_runs(
-            &vec![active, stale],
-            None,
-            true,
-            false,
-            FleetStateFilter::All,
-            "",
-            FleetSortMode::LastSeen,
-            FleetViewPreset::Default,
+        let mut typo: Config =
+            serde_yaml::from_str("default_action:\n  prompt: { ref: lint-test }\n").expect("yaml");
+        let err = expand_config_prompt_refs(&mut typo, &library).unwrap_err();
+        assert!(
+            format!("{err:#}")
+                .contains("unknown prompt ref `lint-test` (did you mean `lint-tests`?)")
         );
-        assert_eq!(all.len(), 2);
     }
 
     #[test]
-    fn version_mismatch_detection_uses_local_version() {
-        assert!(!is_version_mismatch(LOOPMUX_VERSION));
-        assert!(is_version_mismatch("0.0.1"));
-        assert!(is_version_mismatch(""));
+    fn prompt_refs_resolve_from_non_default_workspace_and_imports() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-prompt-workspace-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            root.join("shared.yaml"),
+            "prompts:\n  review: \"Shared review\"\n  ship: \"Ship it\"\n",
+        )
+        .unwrap();
+        let workspace = root.join("team.yaml");
+        std::fs::write(
+            &workspace,
+            r#"imports: [shared.yaml]
+prompts:
+  review: "Team review"
+target: "ai:1.0"
+iterations: 1
+default_action:
+  prompt: { ref: review }
+  post: { ref: ship }
+"#,
+        )
+        .unwrap();
+
+        let config = load_config(Some(&workspace)).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .unwrap();
+        assert_eq!(
+            build_prompt(&resolved.default_action),
+            "Team review\nShip it"
+        );
+        assert_eq!(
+            source_action_ref(&workspace, None).unwrap().as_deref(),
+            Some("review")
+        );
+
+        let _ = std::fs::remove_dir_all(root);
     }
 
     #[test]
-    fn fleet_manager_mismatch_filter_works() {
-        let run_match = fleet_listed(
-            fleet_test_record("run-1", "alpha", "waiting", 1, LOOPMUX_VERSION),
-            false,
-            false,
+    fn prompt_rotation_steps_through_items_per_send() {
+        let yaml = r#"
+iterations: 5
+default_action:
+  prompt:
+    rotate: once
+    items: [implement, test, commit]
+    fallback: "keep going"
+rules:
+  - id: review
+    match:
+      contains: "REVIEW"
+    action:
+      prompt:
+        rotate: weighted
+        items:
+          - { text: "never", weight: 0 }
+          - { text: "always", weight: 3 }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let mut tracker = TriggerTracker::with_seed(7);
+        let mut sent = Vec::new();
+        for _ in 0..5 {
+            let built = tracker
+                .build_send_prompt(&resolved.default_action, "default_action", "")
+                .expect("prompt");
+            assert_eq!(
+                built.rotation_keys,
+                vec!["default_action.prompt".to_string()]
+            );
+            sent.push(format!(
+                "{} ({})",
+                built.text,
+                built.rotation_label.unwrap_or_default()
+            ));
+            for key in built.rotation_keys {
+                *tracker.rotation.entry(key).or_default() += 1;
+            }
+        }
+        assert_eq!(
+            sent,
+            vec![
+                "implement (step 1/3)",
+                "test (step 2/3)",
+                "commit (step 3/3)",
+                "keep going (fallback)",
+                "keep going (fallback)"
+            ]
         );
-        let run_mismatch = fleet_listed(
-            fleet_test_record("run-2", "beta", "holding", 2, "0.0.1"),
-            false,
-            true,
+        let review = resolved.rules[0].action.as_ref().unwrap();
+        for _ in 0..4 {
+            let built = tracker
+                .build_send_prompt(review, "review", "")
+                .expect("prompt");
+            assert_eq!(built.text, "always");
+        }
+
+        let mut rng = DelayRng::new(1);
+        let rotation: PromptRotation =
+            serde_yaml::from_str("rotate: round_robin\nitems: [a, b]\n").expect("yaml");
+        let picks = (0..3)
+            .map(|position| rotation_pick(&rotation, position, &mut rng).unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(picks, vec!["a", "b", "a"]);
+
+        let missing: Config = serde_yaml::from_str(
+            "iterations: 1\ndefault_action:\n  prompt: { rotate: once, items: [a] }\n",
+        )
+        .expect("yaml");
+        let err = resolve_config(
+            missing, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("`rotate: once` needs a fallback"));
+
+        // Edits made at runtime go through the same checks and leave the config as it was.
+        let mut resolved = resolved;
+        let zero: Action =
+            serde_yaml::from_str("prompt: { rotate: weighted, items: [{ text: a, weight: 0 }] }\n")
+                .expect("yaml");
+        let err =
+            apply_edited_action(&mut resolved, Some(0), &zero, "rules[0].action").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("weighted rotation needs a positive weight")
         );
-        let filtered = fleet_manager_visible_runs(
-            &vec![run_match, run_mismatch.clone()],
-            None,
-            true,
-            true,
-            FleetStateFilter::All,
-            "",
-            FleetSortMode::LastSeen,
-            FleetViewPreset::Default,
+        let built = tracker
+            .build_send_prompt(resolved.rules[0].action.as_ref().unwrap(), "review", "")
+            .expect("prompt");
+        assert_eq!(built.text, "always");
+
+        // A rotation that cannot pick skips the send instead of ending the run.
+        let unchecked: Action =
+            serde_yaml::from_str("prompt: { rotate: once, items: [a] }\n").expect("yaml");
+        tracker.rotation.insert("edited.prompt".to_string(), 1);
+        let built = tracker
+            .build_send_prompt(&unchecked, "edited", "")
+            .expect("prompt");
+        assert_eq!(
+            built.skip.as_deref(),
+            Some("edited.prompt: rotate: once needs a fallback")
         );
-        assert_eq!(filtered.len(), 1);
-        assert_eq!(filtered[0].record.id, run_mismatch.record.id);
     }
 
     #[test]
-    fn fleet_manager_state_filter_holding_only() {
-        let waiting = fleet_listed(
-            fleet_test_record("run-1", "alpha", "waiting", 1, LOOPMUX_VERSION),
-            false,
-            false,
+    fn prompt_file_source_extracts_sections_and_skips_empty() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-test-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let next = root.join("NEXT.md");
+        std::fs::write(
+            &next,
+            "# Plan\n\n## Next\nWire the parser.\n### Detail\nKeep errors typed.\n## Later\nDocs.\n",
+        )
+        .unwrap();
+        let source = |yaml: String| -> PromptSource { serde_yaml::from_str(&yaml).expect("yaml") };
+        let file = next.display().to_string();
+
+        let section = source(format!("file: {file}\nsection: next"));
+        validate_prompt_source(&section).expect("valid");
+        assert_eq!(
+            read_prompt_source(&section).unwrap(),
+            "Wire the parser.\n### Detail\nKeep errors typed."
         );
-        let holding = fleet_listed(
-            fleet_test_record("run-2", "beta", "holding", 2, LOOPMUX_VERSION),
-            false,
-            false,
+        let lines = source(format!("file: {file}\nlines: 3-4\nmax_bytes: 12"));
+        assert_eq!(read_prompt_source(&lines).unwrap(), "## Next");
+
+        let action: Action =
+            serde_yaml::from_str(&format!("prompt: {{ file: {file}, section: Missing }}"))
+                .expect("yaml");
+        let built = TriggerTracker::default()
+            .build_send_prompt(&action, "default_action", "")
+            .expect("prompt");
+        assert_eq!(
+            built.pending.expect("pending").resolve().unwrap_err(),
+            format!("default_action.prompt: section `Missing` not found in {file}")
         );
-        let filtered = fleet_manager_visible_runs(
-            &vec![waiting, holding.clone()],
-            None,
-            true,
-            false,
-            FleetStateFilter::Holding,
-            "",
-            FleetSortMode::LastSeen,
-            FleetViewPreset::Default,
+
+        std::fs::write(&next, "  \n").unwrap();
+        assert!(read_prompt_source(&section).is_err());
+        assert_eq!(
+            read_prompt_source(&source(format!("file: {file}"))).unwrap_err(),
+            format!("prompt source empty: {file}")
         );
-        assert_eq!(filtered.len(), 1);
-        assert_eq!(filtered[0].record.id, holding.record.id);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(
+            validate_prompt_source(&source(format!("file: {file}")))
+                .unwrap_err()
+                .to_string()
+                .starts_with("prompt file not found")
+        );
+        assert!(read_prompt_source(&source(format!("file: {file}"))).is_err());
     }
 
     #[test]
-    fn fleet_manager_search_matches_name_or_target() {
-        let run = fleet_listed(
-            fleet_test_record("run-1", "planner-a", "waiting", 1, LOOPMUX_VERSION),
-            false,
-            false,
+    fn skipped_prompt_source_is_retried_on_unchanged_output() {
+        let root = std::env::temp_dir().join(format!(
+            "loopmux-retry-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let next = root.join("NEXT.md");
+        std::fs::write(&next, "placeholder").unwrap();
+        let yaml = format!(
+            "target: \"ai:1.0\"\niterations: 5\ntrigger_confirm_seconds: 0\ndefault_action:\n  prompt: {{ file: {} }}\nrules:\n  - id: idle\n    match: {{ contains: \"READY\" }}\n",
+            next.display()
         );
-        let by_name = fleet_manager_visible_runs(
-            &vec![run.clone()],
-            None,
-            true,
-            false,
-            FleetStateFilter::All,
-            "planner",
-            FleetSortMode::LastSeen,
-            FleetViewPreset::Default,
+        let config: Config = serde_yaml::from_str(&yaml).unwrap();
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .unwrap();
+        std::fs::remove_file(&next).unwrap();
+        let mut tracker = TriggerTracker::default();
+        let scanned = split_capture_styles("READY".to_string(), false);
+        let now = std::time::Instant::now();
+        let scan = |tracker: &mut TriggerTracker| {
+            tracker
+                .scan_target(
+                    &resolved,
+                    "ai:1.0",
+                    &scanned,
+                    now,
+                    &mut HashSet::new(),
+                    false,
+                )
+                .unwrap()
+        };
+        // The source is read at send time; a skipped send leaves the pane to be rescanned.
+        let mut first = scan(&mut tracker).plans.remove(0);
+        assert!(
+            first
+                .resolve_prompt()
+                .unwrap_err()
+                .contains("prompt file unreadable")
         );
-        assert_eq!(by_name.len(), 1);
+        tracker.retry_target("ai:1.0");
 
-        let by_target = fleet_manager_visible_runs(
-            &vec![run],
-            None,
-            true,
-            false,
-            FleetStateFilter::All,
-            "ai:1",
-            FleetSortMode::LastSeen,
-            FleetViewPreset::Default,
+        std::fs::write(&next, "Wire the parser.").unwrap();
+        let second = scan(&mut tracker);
+        assert!(!second.skipped_unchanged);
+        let mut plan = second.plans.into_iter().next().expect("plan");
+        plan.resolve_prompt().unwrap();
+        assert_eq!(plan.prompt, "Wire the parser.");
+
+        let source = |command: &str| -> PromptSource {
+            serde_yaml::from_str(&format!("command: \"{command}\"\ntimeout: 1s")).unwrap()
+        };
+        assert_eq!(read_prompt_source(&source("echo next")).unwrap(), "next");
+        let started = std::time::Instant::now();
+        assert_eq!(
+            read_prompt_source(&source("sleep 5")).unwrap_err(),
+            "prompt command timed out after 1s"
         );
-        assert_eq!(by_target.len(), 1);
+        assert!(started.elapsed() < Duration::from_secs(4));
+        let _ = std::fs::remove_dir_all(&root);
     }
 
     #[test]
-    fn fleet_profile_filter_matches_profile_or_name() {
-        let run = fleet_listed(
-            fleet_test_record("run-1", "planner-a", "waiting", 1, LOOPMUX_VERSION),
-            false,
-            false,
+    fn done_when_reports_evidence_and_requires_passing_exec() {
+        let yaml = r#"
+iterations: 3
+default_action:
+  prompt: "next"
+done_when:
+  match:
+    regex: "test result: ok\\. \\d+ passed"
+  exec: "true"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).expect("yaml");
+        let resolved = resolve_config(
+            config, None, None, true, None, None, false, false, false, None, None, None,
+        )
+        .expect("resolve");
+        let done = resolved.done_when.as_ref().expect("done_when");
+        let output = "running 4 tests\ntest result: ok. 4 passed; 0 failed\n$ ";
+        let evidence = done_when_match_evidence(done, "ai:1.0", output, &MatchContext::default())
+            .unwrap()
+            .expect("match");
+        assert_eq!(
+            evidence,
+            "target=ai:1.0 line=\"test result: ok. 4 passed; 0 failed\""
+        );
+        assert_eq!(
+            confirm_done_exec(done, evidence).unwrap().as_deref(),
+            Some("target=ai:1.0 line=\"test result: ok. 4 passed; 0 failed\" exec=\"true\" exit=0")
+        );
+        assert!(
+            done_when_match_evidence(
+                done,
+                "ai:1.0",
+                "test result: FAILED",
+                &MatchContext::default()
+            )
+            .unwrap()
+            .is_none()
         );
-        assert!(run_matches_profile_filter(&run, "planner-a"));
-        assert!(!run_matches_profile_filter(&run, "docs"));
-    }
 
-    #[test]
-    fn fleet_status_line_includes_profile_and_search() {
-        let line = fleet_status_line(
-            FleetViewPreset::Default,
-            FleetSortMode::LastSeen,
-            FleetStateFilter::All,
-            false,
-            true,
-            "needle",
-            Some("docs"),
+        let failing = DoneWhen {
+            match_: None,
+            exec: Some("false".to_string()),
+        };
+        assert!(
+            confirm_done_exec(&failing, String::new())
+                .unwrap()
+                .is_none()
         );
-        assert!(line.contains("search=needle"));
-        assert!(line.contains("profile=docs"));
-        assert!(line.contains("mismatch=on"));
+        let empty = DoneWhen {
+            match_: None,
+            exec: None,
+        };
+        assert!(validate_done_when(&empty).is_err());
+    }
+
+    fn history_fixture(target: &str, prompt: &str, pinned: bool) -> HistoryEntry {
+        serde_json::from_value(json!({
+            "last_run": "2026-02-17T00:00:00Z",
+            "target": target,
+            "prompt": prompt,
+            "trigger": "Done",
+            "once": false,
+            "pinned": pinned,
+        }))
+        .expect("history entry")
     }
 
     #[test]
-    fn fleet_run_list_line_includes_mismatch_tag_and_version() {
-        let run = fleet_listed(
-            fleet_test_record("run-2", "beta", "holding", 2, "0.0.1"),
-            false,
-            true,
+    fn history_picker_fuzzy_matches_and_keeps_pins() {
+        let mut entries = vec![
+            history_fixture("ai:1.0", "continue", false),
+            history_fixture("build:2.1", "run cargo test", true),
+            history_fixture("ai:3.0", "fix lint", false),
+            history_fixture("ai:4.0", "write docs", false),
+        ];
+        assert!(fuzzy_score("crgo", "run cargo test").is_some());
+        assert!(fuzzy_score("xyz", "run cargo test").is_none());
+        assert!(
+            fuzzy_score("cargo", "run cargo").unwrap() > fuzzy_score("cargo", "c a r g o").unwrap()
         );
-        let line = fleet_run_list_line(&run, true, true, true);
-        assert!(line.contains(">[x] beta"));
-        assert!(line.contains("[active"));
-        assert!(line.contains("mismatch"));
-        assert!(line.contains("ver=0.0.1"));
+
+        let candidates = history_candidates(&entries, 2);
+        assert_eq!(candidates, vec![1, 0, 2]);
+        assert_eq!(history_matches(&entries, &candidates, "lint"), vec![2]);
+        assert_eq!(history_matches(&entries, &candidates, "build"), vec![1]);
+
+        evict_history(&mut entries, 2);
+        let prompts = entries
+            .iter()
+            .map(|entry| entry.prompt.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(prompts, vec!["continue", "run cargo test"]);
     }
 
     #[test]
-    fn fleet_stop_snippet_uses_run_id() {
-        let snippet = fleet_stop_snippet("run-123");
-        assert_eq!(snippet, "loopmux runs stop run-123");
+    fn history_entry_exports_as_run_config() {
+        let mut entry = history_fixture("ai:1.0", "continue", false);
+        entry.iterations = Some(3);
+        let config = history_entry_config(&entry).expect("config");
+        assert_eq!(config.target.as_deref(), Some("ai:1.0"));
+        assert_eq!(config.iterations, Some(3));
+        assert!(
+            history_entry_preview_lines(&entry)
+                .iter()
+                .any(|line| line == "  continue")
+        );
     }
 }
 
@@ -8607,6 +17984,16 @@ fn collect_prompt_block_placeholders(block: Option<&PromptBlock>, vars: &mut Has
                 extract_placeholders(item, vars);
             }
         }
+        PromptBlock::Ref(_) => {}
+        PromptBlock::Rotate(rotation) => {
+            for item in &rotation.items {
+                extract_placeholders(item.text(), vars);
+            }
+            if let Some(fallback) = &rotation.fallback {
+                extract_placeholders(fallback, vars);
+            }
+        }
+        PromptBlock::Source(_) | PromptBlock::Forward(_) => {}
     }
 }
 